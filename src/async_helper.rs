@@ -1,22 +1,72 @@
-use std::fmt::Debug;
+use std::future::Future;
 
 use tokio::runtime::Handle;
 use tracing::trace;
 
 /// Run a future to completion on the current thread.
-/// This is useful when you want to run a future in a blocking context.
+/// This is the bridge the (sync) filesystem callbacks use to call into async code.
 /// This function will block the current thread until the provided future has run to completion.
 ///
-/// # Be careful with deadlocks
-pub fn run_async_blocking<T>(f: impl std::future::Future<Output = T> + Sized) -> T
-where
-    T: Debug,
-{
+/// The future is driven by [Handle::block_on] of the runtime the current thread
+/// belongs to, so the io and timers of that runtime work like in any other task.
+///
+/// # Panics
+///
+/// - when the current thread does not belong to a tokio runtime
+/// - when it is called from inside an async task. Blocking a worker thread can
+///   deadlock the runtime, so the filesystem session has to run on a blocking
+///   thread (see [tokio::task::spawn_blocking])
+pub fn run_async_blocking<F: Future>(f: F) -> F::Output {
     trace!("run_async");
-    let handle = Handle::current();
-    let _enter_guard = handle.enter();
-    trace!("run_async: entered handle");
-    let result = futures::executor::block_on(f);
-    trace!("run_async: got result: {:?}", result);
+    let handle = Handle::try_current()
+        .expect("run_async_blocking has to be called from a thread that belongs to a runtime");
+    let result = handle.block_on(f);
+    trace!("run_async: got result");
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::{mpsc, oneshot};
+
+    use super::*;
+
+    /// simulates a filesystem that gets many concurrent reads, which all wait
+    /// for an answer from the provider task on the same runtime
+    #[tokio::test(flavor = "current_thread")]
+    async fn concurrent_blocking_calls_do_not_deadlock() {
+        let (request_tx, mut request_rx) = mpsc::channel::<(u64, oneshot::Sender<u64>)>(1);
+        let provider = tokio::spawn(async move {
+            while let Some((offset, reply)) = request_rx.recv().await {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                let _ = reply.send(offset * 2);
+            }
+        });
+
+        let reads = (0..200u64)
+            .map(|offset| {
+                let request_tx = request_tx.clone();
+                tokio::task::spawn_blocking(move || {
+                    run_async_blocking(async {
+                        let (reply_tx, reply_rx) = oneshot::channel();
+                        request_tx.send((offset, reply_tx)).await.unwrap();
+                        reply_rx.await.unwrap()
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let results =
+            tokio::time::timeout(Duration::from_secs(30), futures::future::join_all(reads))
+                .await
+                .expect("the reads deadlocked");
+        for (offset, result) in results.into_iter().enumerate() {
+            assert_eq!(result.unwrap(), offset as u64 * 2);
+        }
+
+        drop(request_tx);
+        provider.await.unwrap();
+    }
+}
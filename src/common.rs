@@ -1,12 +1,51 @@
 use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
 use std::ops::Deref;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::bail;
+use tracing::warn;
+
+use crate::prelude::*;
 
 //region LocalPath
-#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+/// A path relative to the root of the mount, the default is the root itself.
+#[derive(Debug, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct LocalPath(PathBuf);
 
+impl LocalPath {
+    /// a path inside the mount, absolute paths and `..` are rejected so it
+    /// can't point outside of the folder it gets joined to
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut sanitized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => sanitized.push(name),
+                Component::CurDir => {}
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    bail!("{} is not a path inside the mount", path.display())
+                }
+            }
+        }
+        Ok(Self(sanitized))
+    }
+    /// the path of the child with this name, which is checked like in [LocalPath::new]
+    pub fn join(&self, name: impl AsRef<Path>) -> Result<Self> {
+        let name = Self::new(name)?;
+        Ok(Self(self.0.join(name.0)))
+    }
+    /// the path without the leading `base`, `None` if it is not inside of it
+    pub fn relative_to(&self, base: &LocalPath) -> Option<Self> {
+        self.0.strip_prefix(&base.0).ok().map(Self::from)
+    }
+    /// where the path is in `dir`, like the cache dir
+    pub fn in_dir(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.0)
+    }
+}
+
 impl From<PathBuf> for LocalPath {
     fn from(path: PathBuf) -> Self {
         Self(path)
@@ -85,12 +124,40 @@ impl<'a> From<&'a LocalPath> for &'a PathBuf {
         &value.0
     }
 }
+
+#[cfg(test)]
+mod local_path_tests {
+    use super::*;
+
+    #[test]
+    fn paths_can_not_leave_the_mount() {
+        assert!(LocalPath::new("folder/../../etc").is_err());
+        assert!(LocalPath::new("/etc").is_err());
+        assert!(LocalPath::default().join("..").is_err());
+
+        let path = LocalPath::default()
+            .join("folder")
+            .and_then(|folder| folder.join("./file.txt"))
+            .unwrap();
+        assert_eq!(
+            path.in_dir(Path::new("/cache")),
+            Path::new("/cache/folder/file.txt")
+        );
+        let folder = LocalPath::new("folder").unwrap();
+        assert_eq!(
+            path.relative_to(&folder),
+            Some(LocalPath::from(Path::new("file.txt")))
+        );
+    }
+}
 //endregion
 
 //region VecExtensions
 
 pub trait VecExtension<T> {
     fn remove_first_element(&mut self, target: &T) -> Option<T>;
+    /// removes every element that is equal to the target and returns how many were removed
+    fn remove_all_elements(&mut self, target: &T) -> usize;
 }
 
 impl<T> VecExtension<T> for Vec<T>
@@ -102,6 +169,12 @@ where
             .position(|x| x == target)
             .map(|x| self.remove(x))
     }
+
+    fn remove_all_elements(&mut self, target: &T) -> usize {
+        let len_before = self.len();
+        self.retain(|x| x != target);
+        len_before - self.len()
+    }
 }
 
 #[cfg(test)]
@@ -115,6 +188,159 @@ mod vec_extension_tests {
         assert_eq!(r, Some(20));
         assert_eq!(ve, vec![10, 30, 40])
     }
+
+    #[test]
+    fn test_remove_all_elements() {
+        let mut ve = vec![10, 20, 30, 20, 40];
+        let r = ve.remove_all_elements(&20);
+        assert_eq!(r, 2);
+        assert_eq!(ve, vec![10, 30, 40])
+    }
+}
+
+//endregion
+
+//region cache time
+/// checks if the cache time since the last check is over and it is time to check again.
+///
+/// If the clock went backwards since the last check this also counts as over,
+/// so a changed system time can't stop the checks for a long time.
+pub fn is_cache_time_over(last_checked: SystemTime, cache_time: Duration) -> bool {
+    match last_checked.elapsed() {
+        Ok(elapsed) => elapsed >= cache_time,
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod cache_time_tests {
+    use super::*;
+
+    #[test]
+    fn test_is_cache_time_over() {
+        let now = SystemTime::now();
+        assert!(is_cache_time_over(now, Duration::ZERO));
+        assert!(!is_cache_time_over(now, Duration::from_secs(10)));
+        assert!(is_cache_time_over(
+            now - Duration::from_secs(11),
+            Duration::from_secs(10)
+        ));
+        assert!(is_cache_time_over(
+            now + Duration::from_secs(60),
+            Duration::from_secs(10)
+        ));
+    }
+}
+//endregion
+
+//region child names
+/// the name an entry is shown with. `.` and `..` always mean the folder itself
+/// and its parent, so entries with those names on the drive get a `_` appended
+pub fn local_entry_name(name: String) -> String {
+    match name.as_str() {
+        "." | ".." => {
+            warn!("an entry is named '{}', it is shown as '{}_'", name, name);
+            format!("{}_", name)
+        }
+        _ => name,
+    }
+}
+
+/// the key names are compared with when a child gets looked up by its name.
+///
+/// Names are matched case insensitive (ascii only), like they always were.
+pub fn child_name_key(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
+
+/// finds the first child with the name, compared with [child_name_key].
+///
+/// If multiple children have the same name, the first one in the order of the
+/// children wins. Both filesystems resolve names with this, so the same tree
+/// always resolves to the same child.
+pub fn find_child_by_name<T, S: AsRef<str>>(
+    children: impl IntoIterator<Item = (T, Option<S>)>,
+    name: &str,
+) -> Option<T> {
+    let key = child_name_key(name);
+    children
+        .into_iter()
+        .find(|(_, child_name)| {
+            child_name
+                .as_ref()
+                .is_some_and(|child_name| child_name_key(child_name.as_ref()) == key)
+        })
+        .map(|(child, _)| child)
+}
+
+#[cfg(test)]
+mod child_name_tests {
+    use super::*;
+
+    #[test]
+    fn first_child_with_the_name_wins() {
+        let children = [(1, None), (2, Some("Report.txt")), (3, Some("report.TXT"))];
+        assert_eq!(find_child_by_name(children, "REPORT.txt"), Some(2));
+        assert_eq!(find_child_by_name(children, "other"), None);
+    }
+}
+//endregion
+
+//region md5
+/// the md5 checksum of the file in the form drive reports it, `None` if it can't be read
+pub fn compute_md5(path: &Path) -> Option<String> {
+    use md5::{Digest, Md5};
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut hasher = Md5::new();
+    std::io::copy(&mut file, &mut hasher).ok()?;
+    Some(format!("{:x}", hasher.finalize()))
 }
 
+/// [compute_md5] on a blocking thread, hashing a large file would stall every
+/// other task of the runtime thread otherwise
+pub async fn compute_md5_async(path: impl Into<PathBuf>) -> Option<String> {
+    let path = path.into();
+    tokio::task::spawn_blocking(move || compute_md5(&path))
+        .await
+        .ok()?
+}
+
+#[cfg(test)]
+mod md5_tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use md5::{Digest, Md5};
+
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn hashing_does_not_block_other_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large");
+        let content = vec![7u8; 64 * 1024 * 1024];
+        std::fs::write(&path, &content).unwrap();
+
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        let ticker = tokio::spawn({
+            let (ticks, done) = (ticks.clone(), done.clone());
+            async move {
+                while !done.load(Ordering::Relaxed) {
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+
+        let hash = compute_md5_async(&path).await;
+        done.store(true, Ordering::Relaxed);
+        ticker.await.unwrap();
+
+        assert_eq!(hash, Some(format!("{:x}", Md5::digest(&content))));
+        // the only thread of the runtime was free while the file got hashed
+        assert!(ticks.load(Ordering::Relaxed) > 0);
+        assert_eq!(compute_md5_async(dir.path().join("missing")).await, None);
+    }
+}
 //endregion
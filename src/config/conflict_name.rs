@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+use google_drive3::chrono::{DateTime, Utc};
+
+use crate::prelude::*;
+
+pub const DEFAULT_CONFLICT_NAME_TEMPLATE: &str = "{stem} (conflict {date}){ext}";
+
+/// The name of the second file a conflict produces, like `"{stem} (conflict {date}){ext}"`.
+///
+/// The placeholders are `stem` (the name without its extension), `ext` (the
+/// extension with its dot, empty if there is none), `date` (the day of the
+/// conflict) and `host` (the name of this computer).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictNameTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Stem,
+    Ext,
+    Date,
+    Host,
+}
+
+impl ConflictNameTemplate {
+    /// fails for unknown placeholders, unclosed braces and templates that
+    /// would not give every file its own name
+    pub fn new(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("the '{{' in '{}' is not closed", template))?;
+            parts.push(match &rest[start + 1..start + end] {
+                "stem" => Part::Stem,
+                "ext" => Part::Ext,
+                "date" => Part::Date,
+                "host" => Part::Host,
+                other => bail!("unknown placeholder '{{{}}}' in '{}'", other, template),
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if !parts.contains(&Part::Stem) {
+            bail!(
+                "'{}' needs '{{stem}}', the copies of all files would have the same name",
+                template
+            );
+        }
+        let text = parts.iter().filter_map(|part| match part {
+            Part::Text(text) => Some(text),
+            _ => None,
+        });
+        if text.clone().any(|text| text.contains('}')) {
+            bail!("the '}}' in '{}' has no '{{'", template);
+        }
+        if text.clone().any(|text| text.contains('/')) {
+            bail!("'{}' can not contain a '/'", template);
+        }
+        Ok(Self { parts })
+    }
+
+    /// the name of the copy of the file called `name`
+    pub fn render(&self, name: &str, time: DateTime<Utc>, host: &str) -> String {
+        let path = Path::new(name);
+        let stem = path
+            .file_stem()
+            .map_or(name.into(), |stem| stem.to_string_lossy());
+        let ext = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Stem => stem.to_string(),
+                Part::Ext => ext.clone(),
+                Part::Date => time.format("%Y-%m-%d").to_string(),
+                Part::Host => host.to_string(),
+            })
+            .collect()
+    }
+}
+
+impl Default for ConflictNameTemplate {
+    fn default() -> Self {
+        Self::new(DEFAULT_CONFLICT_NAME_TEMPLATE).expect("the default template is valid")
+    }
+}
+
+/// the name of this computer for `{host}`, empty if it can not be read
+pub fn host_name() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_names_with_and_without_extension() {
+        let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let template = ConflictNameTemplate::default();
+        assert_eq!(
+            template.render("report.pdf", time, "laptop"),
+            "report (conflict 2023-11-14).pdf"
+        );
+        assert_eq!(
+            template.render("Makefile", time, "laptop"),
+            "Makefile (conflict 2023-11-14)"
+        );
+        let template = ConflictNameTemplate::new("{stem}.{host}{ext}").unwrap();
+        assert_eq!(
+            template.render("archive.tar.gz", time, "laptop"),
+            "archive.tar.laptop.gz"
+        );
+
+        for invalid in [
+            "{stem} {day}{ext}",
+            "{stem",
+            "{date}{ext}",
+            "{stem}}",
+            "old/{stem}",
+        ] {
+            assert!(ConflictNameTemplate::new(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -1 +1,6 @@
 pub mod common_file_filter;
+pub mod conflict_name;
+pub mod no_cache;
+pub mod path_filter;
+pub mod ttl_overrides;
+pub mod upload_windows;
@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use globset::GlobSet;
+
+use crate::config::path_filter::build_glob_set;
+use crate::prelude::*;
+
+/// The files that are read straight from the drive instead of being downloaded
+/// to the cache dir, by their path relative to the root of the drive.
+///
+/// This is for files that are larger than the disk or only get streamed once,
+/// it trades bandwidth for disk space: every read that is not in the read
+/// ahead of its handle is a request for that range. `**` matches every file
+/// of the mount.
+///
+/// Only the handles that are opened for reading skip the cache, writing needs
+/// the local file. Files that are cached already are read from the cache.
+#[derive(Debug, Default, Clone)]
+pub struct NoCachePaths {
+    globs: GlobSet,
+}
+
+impl NoCachePaths {
+    pub fn new(patterns: &[&str]) -> Result<Self> {
+        Ok(Self {
+            globs: build_glob_set(patterns)?,
+        })
+    }
+
+    /// `path` is relative to the root of the drive
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.globs.is_match(path)
+    }
+}
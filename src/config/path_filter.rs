@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use globset::{GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+
+use crate::prelude::*;
+
+/// Decides which entries of the drive get synced, by their path relative to the root of the drive.
+///
+/// - an entry is excluded if it or one of its parent folders matches an exclude glob
+/// - if there are include globs, an entry has to match one of them or be inside a folder
+///   that does. Folders on the way to an included entry are included as well, otherwise
+///   the included entries could not be reached
+/// - without include globs, everything that is not excluded is included
+#[derive(Debug, Default, Clone)]
+pub struct PathFilter {
+    include: GlobSet,
+    /// the include globs split into their path components, `None` stands for `**`
+    include_components: Vec<Vec<Option<GlobMatcher>>>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    pub fn new(include: &[&str], exclude: &[&str]) -> Result<Self> {
+        let mut include_components = Vec::with_capacity(include.len());
+        for pattern in include {
+            let components = pattern
+                .split('/')
+                .filter(|component| !component.is_empty())
+                .map(|component| match component {
+                    "**" => Ok(None),
+                    component => Ok(Some(build_glob(component)?.compile_matcher())),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            include_components.push(components);
+        }
+        Ok(Self {
+            include: build_glob_set(include)?,
+            include_components,
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    /// `path` is relative to the root of the drive
+    pub fn is_included(&self, path: &Path, is_dir: bool) -> bool {
+        if path.ancestors().any(|p| self.exclude.is_match(p)) {
+            return false;
+        }
+        if self.include.is_empty() || path.as_os_str().is_empty() {
+            return true;
+        }
+        if path.ancestors().any(|p| self.include.is_match(p)) {
+            return true;
+        }
+        is_dir && self.leads_to_include(path)
+    }
+
+    /// checks if there could be an included entry somewhere inside this folder
+    fn leads_to_include(&self, dir: &Path) -> bool {
+        let dir_components: Vec<_> = dir.iter().collect();
+        self.include_components.iter().any(|pattern| {
+            for (i, dir_component) in dir_components.iter().enumerate() {
+                match pattern.get(i) {
+                    // everything below a ** can match
+                    Some(None) => return true,
+                    Some(Some(matcher)) if matcher.is_match(dir_component) => {}
+                    _ => return false,
+                }
+            }
+            true
+        })
+    }
+}
+
+pub(crate) fn build_glob(pattern: &str) -> Result<globset::Glob> {
+    Ok(GlobBuilder::new(pattern).literal_separator(true).build()?)
+}
+
+pub(crate) fn build_glob_set(patterns: &[&str]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(build_glob(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_included(filter: &PathFilter, path: &str, is_dir: bool) -> bool {
+        filter.is_included(Path::new(path), is_dir)
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let filter = PathFilter::new(&["Work/**", "Docs/*.pdf"], &["Work/tmp"]).unwrap();
+
+        assert!(is_included(&filter, "Work", true));
+        assert!(is_included(&filter, "Work/report.txt", false));
+        assert!(is_included(&filter, "Work/projects/a/b.txt", false));
+        assert!(!is_included(&filter, "Work/tmp", true));
+        assert!(!is_included(&filter, "Work/tmp/cache.bin", false));
+
+        assert!(is_included(&filter, "Docs", true));
+        assert!(is_included(&filter, "Docs/manual.pdf", false));
+        assert!(!is_included(&filter, "Docs/notes.txt", false));
+        assert!(!is_included(&filter, "Docs/old", true));
+
+        assert!(!is_included(&filter, "Music", true));
+        assert!(!is_included(&filter, "Music/song.mp3", false));
+    }
+
+    #[test]
+    fn without_includes_everything_not_excluded_is_included() {
+        let filter = PathFilter::new(&[], &["*.tmp", "Trash"]).unwrap();
+        assert!(is_included(&filter, "a.txt", false));
+        assert!(!is_included(&filter, "a.tmp", false));
+        assert!(!is_included(&filter, "Trash/a.txt", false));
+        assert!(is_included(&filter, "Folder/a.tmp", false));
+
+        let filter = PathFilter::default();
+        assert!(is_included(&filter, "anything/at/all", false));
+    }
+}
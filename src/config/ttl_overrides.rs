@@ -0,0 +1,57 @@
+use std::path::Path;
+use std::time::Duration;
+
+use globset::GlobMatcher;
+
+use crate::config::path_filter::build_glob;
+use crate::prelude::*;
+
+/// How long the kernel may keep the attributes of entries, by their path relative
+/// to the root of the drive.
+///
+/// Files that change often (like shared documents) can get a short TTL, so changes
+/// show up sooner. Files that never change can get a long one, which saves requests.
+/// The first glob that matches decides, entries without a match use the default TTL.
+#[derive(Debug, Default, Clone)]
+pub struct TtlOverrides {
+    rules: Vec<(GlobMatcher, Duration)>,
+}
+
+impl TtlOverrides {
+    pub fn new(rules: &[(&str, Duration)]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|(pattern, ttl)| Ok((build_glob(pattern)?.compile_matcher(), *ttl)))
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// `path` is relative to the root of the drive
+    pub fn ttl_for(&self, path: &Path) -> Option<Duration> {
+        self.rules
+            .iter()
+            .find(|(glob, _)| glob.is_match(path))
+            .map(|(_, ttl)| *ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_glob_decides() {
+        let overrides = TtlOverrides::new(&[
+            ("Shared/*.gdoc", Duration::from_secs(0)),
+            ("Shared/**", Duration::from_secs(5)),
+            ("**/*.png", Duration::from_secs(3600)),
+        ])
+        .unwrap();
+        let ttl_for = |path: &str| overrides.ttl_for(Path::new(path));
+        assert_eq!(ttl_for("Shared/notes.gdoc"), Some(Duration::from_secs(0)));
+        assert_eq!(ttl_for("Shared/a/notes.gdoc"), Some(Duration::from_secs(5)));
+        assert_eq!(ttl_for("Shared/logo.png"), Some(Duration::from_secs(5)));
+        assert_eq!(ttl_for("Assets/logo.png"), Some(Duration::from_secs(3600)));
+        assert_eq!(ttl_for("notes.txt"), None);
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+/// How long the upload of a changed file waits for more changes, by the size
+/// of the file.
+///
+/// Small files are cheap to upload again, so they should be on the drive right
+/// away. A large file that is written in bursts would be uploaded after each
+/// of them, waiting lets the writes of a burst end up in one upload. Every
+/// change within the window starts it again.
+///
+/// Files below the smallest threshold are uploaded right away.
+#[derive(Debug, Default, Clone)]
+pub struct UploadWindows {
+    /// the minimum size and the window, sorted by the size
+    thresholds: Vec<(u64, Duration)>,
+}
+
+impl UploadWindows {
+    /// `thresholds` are pairs of a minimum size in bytes and the window of the
+    /// files with at least that size, the largest matching size decides
+    pub fn new(thresholds: &[(u64, Duration)]) -> Self {
+        let mut thresholds = thresholds.to_vec();
+        thresholds.sort_by_key(|(min_size, _)| *min_size);
+        Self { thresholds }
+    }
+
+    pub fn window_for(&self, size: u64) -> Duration {
+        self.thresholds
+            .iter()
+            .rev()
+            .find(|(min_size, _)| size >= *min_size)
+            .map(|(_, window)| *window)
+            .unwrap_or(Duration::ZERO)
+    }
+}
@@ -0,0 +1,143 @@
+//! Downloads a single file from the drive, without mounting anything.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use google_drive3::api::File;
+use tracing::{debug, instrument};
+
+use crate::fs::drive_file_provider::placeholder_content;
+use crate::google_drive::{DriveId, DriveMetadata, GoogleDrive};
+use crate::prelude::*;
+
+/// downloads the file at `source` to `target`.
+///
+/// `source` is the id of the file or its path from the root of the drive, like
+/// `Documents/notes.txt`. Google docs can't be downloaded, for them the same
+/// placeholder as in the mount is written, with the link to open them.
+#[instrument(skip(drive))]
+pub async fn download(drive: &GoogleDrive, source: &str, target: &Path) -> Result<File> {
+    let metadata = resolve(drive, source).await?;
+    let drive_metadata = DriveMetadata::new(&metadata);
+    let id = drive_metadata.id()?;
+    if drive_metadata.is_folder() {
+        return Err(anyhow!(
+            "{} is a folder, only files can be downloaded",
+            source
+        ));
+    }
+    if drive_metadata.is_native() {
+        debug!("{} is a google doc, writing its placeholder", id);
+        std::fs::write(target, placeholder_content(&metadata))?;
+        return Ok(metadata);
+    }
+    drive.download_file(id, &target.to_path_buf()).await
+}
+
+/// the metadata of the file with `source` as its id, or at `source` as a path
+async fn resolve(drive: &GoogleDrive, source: &str) -> Result<File> {
+    if !source.contains('/') {
+        if let Ok(metadata) = drive.get_metadata_for_file(DriveId::from(source)).await {
+            return Ok(metadata);
+        }
+    }
+    let mut id = DriveId::root();
+    for name in PathBuf::from(source).iter() {
+        id = drive
+            .get_id(name, Some(id))
+            .await
+            .map_err(|e| anyhow!("{} was not found: {}", source, e))?;
+    }
+    debug!("{} has the id {}", source, id);
+    drive.get_metadata_for_file(id).await
+}
+
+/// reads `download <path or id> <target>` from the arguments after the program
+/// name, `None` if they are not a download. The log options can come before
+/// and after it
+pub fn parse_download_args(args: &[String]) -> Option<Result<(String, PathBuf)>> {
+    let mut args = args.iter();
+    let subcommand = loop {
+        let arg = args.next()?;
+        if arg == "--log-format" {
+            args.next();
+        } else if !arg.starts_with("--log-format=") && arg != "--tokio-console" {
+            break arg;
+        }
+    };
+    if subcommand != "download" {
+        return None;
+    }
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--log-format" {
+            args.next();
+        } else if !arg.starts_with("--") {
+            positional.push(arg);
+        }
+    }
+    Some(match positional[..] {
+        [source, target] => Ok((source.clone(), PathBuf::from(target))),
+        _ => Err(anyhow!("usage: download <path or id> <target>")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::google_drive::mock::MockDrive;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Result<(String, PathBuf)>> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_download_args(&args)
+    }
+
+    #[test]
+    fn download_args_parsing() {
+        let expected = ("Documents/a.txt".to_string(), PathBuf::from("a.txt"));
+        let parsed = parse(&["download", "Documents/a.txt", "a.txt"]);
+        assert_eq!(parsed.unwrap().unwrap(), expected);
+        let parsed = parse(&[
+            "--log-format",
+            "json",
+            "--tokio-console",
+            "download",
+            "Documents/a.txt",
+            "--log-format=human",
+            "a.txt",
+        ]);
+        assert_eq!(parsed.unwrap().unwrap(), expected);
+
+        assert!(parse(&["download", "Documents/a.txt"]).unwrap().is_err());
+        assert!(parse(&["download", "a", "b", "c"]).unwrap().is_err());
+        assert!(parse(&[]).is_none());
+        assert!(parse(&["--offline"]).is_none());
+        assert!(parse(&["--log-format", "json"]).is_none());
+        // only the log options can come before it
+        assert!(parse(&["--prefetch", "download", "a", "b"]).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn files_are_downloaded_by_path_and_id() {
+        let mock = MockDrive::start().await.unwrap();
+        let folder = mock.add_folder("Documents", &mock.root_id());
+        let id = mock.add_file("notes.txt", &folder, b"content");
+        let drive = mock.drive();
+        let dir = tempfile::tempdir().unwrap();
+
+        let by_path = dir.path().join("by_path.txt");
+        download(&drive, "Documents/notes.txt", &by_path)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&by_path).unwrap(), b"content");
+
+        let by_id = dir.path().join("by_id.txt");
+        download(&drive, id.as_str(), &by_id).await.unwrap();
+        assert_eq!(std::fs::read(&by_id).unwrap(), b"content");
+
+        let missing = download(&drive, "Documents/missing.txt", &by_id).await;
+        assert!(missing.is_err());
+        assert!(download(&drive, "Documents", &by_id).await.is_err());
+    }
+}
@@ -1,8 +1,7 @@
 use std::ffi::OsString;
-use std::path::PathBuf;
 
 use fuser::FileAttr;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 use crate::common::LocalPath;
 use crate::fs::Inode;
@@ -56,13 +55,18 @@ impl DriveEntry {
             local_md5_checksum: None,
         }
     }
+    /// an entry whose name can't be part of a path gets no local path
     pub fn build_local_path(&mut self, parent: Option<LocalPath>) {
-        if let Some(parent_path) = parent {
-            let path = parent_path.join(&self.name);
-            self.local_path = Some(LocalPath::from(path));
-        } else {
-            self.local_path = Some(LocalPath::from(PathBuf::from("")));
-        }
+        self.local_path = match parent {
+            Some(parent_path) => match parent_path.join(&self.name) {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    warn!("{} gets no local path: {:?}", self.drive_id, e);
+                    None
+                }
+            },
+            None => Some(LocalPath::default()),
+        };
     }
 }
 // impl CommonEntry for DriveEntry {
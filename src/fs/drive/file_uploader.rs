@@ -74,6 +74,10 @@ impl<'a> DriveFileUploader {
             running_uploads: HashMap::new(),
         }
     }
+    /// see [GoogleDrive::set_upload_chunk_size]
+    pub fn set_upload_chunk_size(&mut self, upload_chunk_size: u64) {
+        self.drive.set_upload_chunk_size(upload_chunk_size);
+    }
     #[instrument(skip(self), fields(self.upload_queue = self.upload_queue.len(),
     self.upload_filter = self.upload_filter.filter.num_ignores()))]
     pub async fn listen(&mut self) {
@@ -144,9 +148,36 @@ impl<'a> DriveFileUploader {
             }
         }
 
+        self.finish_running_uploads().await;
         info!("file upload listener stopped");
     }
 
+    /// waits for all running uploads, so no change gets lost when the listener
+    /// stops. The ones that still wait for more changes upload after their wait
+    async fn finish_running_uploads(&mut self) {
+        if self.running_uploads.is_empty() {
+            return;
+        }
+        info!(
+            "waiting for {} running uploads before stopping",
+            self.running_uploads.len()
+        );
+        for (drive_id, running_upload) in self.running_uploads.drain() {
+            let RunningUpload {
+                join_handle,
+                stop_sender,
+            } = running_upload;
+            // dropping the sender would cancel the upload
+            let result = join_handle.await;
+            drop(stop_sender);
+            match result {
+                Ok(Ok(())) => debug!("finished the upload of {} before stopping", drive_id),
+                Ok(Err(e)) => error!("the upload of {} failed: {:?}", drive_id, e),
+                Err(e) => error!("the upload task of {} failed: {:?}", drive_id, e),
+            }
+        }
+    }
+
     /// this function checks if there are any running uploads for the given drive_id
     /// and if there are, it sends a stop command to all of them and then awaits for them to finish
     async fn cancel_and_wait_for_running_upload_for_id(&mut self, drive_id: &String) {
@@ -231,7 +262,7 @@ impl<'a> DriveFileUploader {
         debug!("uploading file: {:?}", local_path);
         let path = local_path.as_path();
         drive
-            .upload_file_content_from_path(file_metadata, path)
+            .upload_file_content_from_path(file_metadata, path, None)
             .await?;
         // let result = drive.list_files(DriveId::from("root")).await.with_context(|| format!("could not do it"))?;
         debug!("upload_file_: done");
@@ -239,3 +270,40 @@ impl<'a> DriveFileUploader {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::google_drive::mock::MockDrive;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stopping_finishes_the_running_uploads() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("notes.txt", &mock.root_id(), b"old");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, b"new").unwrap();
+        let mut metadata = mock.metadata(&id).unwrap();
+        // the legacy filesystem keeps the id of the file there
+        metadata.drive_id = Some(id.to_string());
+
+        let (sender, receiver) = channel(2);
+        let mut uploader = DriveFileUploader::new(
+            mock.drive(),
+            CommonFileFilter::from_path(dir.path()).unwrap(),
+            receiver,
+            Duration::from_millis(100),
+        );
+        let upload = FileCommand::new(path, metadata);
+        sender
+            .send(FileUploaderCommand::UploadChange(upload))
+            .await
+            .unwrap();
+        sender.send(FileUploaderCommand::Stop).await.unwrap();
+        uploader.listen().await;
+
+        assert_eq!(mock.content(&id).unwrap(), b"new");
+        assert!(uploader.running_uploads.is_empty());
+    }
+}
@@ -1,7 +1,7 @@
 use std::fmt::{Debug, Formatter};
 use std::io::{stdout, Seek, SeekFrom, Write};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ffi::{OsStr, OsString},
     fmt::Display,
     fs::OpenOptions,
@@ -13,9 +13,9 @@ use std::{
 use anyhow::{anyhow, Context};
 use bimap::BiMap;
 use fuser::{
-    FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyData, ReplyDirectory,
-    ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow,
-    FUSE_ROOT_ID,
+    consts::FUSE_DO_READDIRPLUS, FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr,
+    ReplyData, ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite,
+    Request, TimeOrNow, FUSE_ROOT_ID,
 };
 use google_drive3::api::{File, StartPageToken};
 use libc::c_int;
@@ -25,10 +25,13 @@ use tracing::{debug, error, instrument, warn};
 use crate::fs::drive::{Change, ChangeType, FileCommand, FileUploaderCommand, SyncSettings};
 use crate::{
     async_helper::run_async_blocking,
-    common::LocalPath,
+    common::{
+        compute_md5_async, find_child_by_name, is_cache_time_over, local_entry_name, LocalPath,
+    },
     fs::drive::DriveEntry,
     fs::inode::Inode,
-    google_drive::{DriveId, GoogleDrive},
+    fs::next_mount_generation,
+    google_drive::{DriveId, DriveMetadata, FileTimes, GoogleDrive, ListOptions},
     prelude::*,
 };
 
@@ -68,6 +71,8 @@ pub struct DriveFilesystem {
     entries: HashMap<DriveId, DriveEntry>,
     ino_drive_id: BiMap<Inode, DriveId>,
     children: HashMap<DriveId, Vec<DriveId>>,
+    /// the real id of the root folder, changes report it as the parent instead of `root`
+    root_id: Option<DriveId>,
 
     /// with this we can send a path to the file uploader
     /// to tell it to upload certain files.
@@ -153,6 +158,45 @@ impl DriveFilesystem {
             .get_by_right(&parent.into())
             .context("could not get drive id for ino")
     }
+    /// the children of the folder after `offset`, each with the offset of the
+    /// entry after it. The offsets are the positions in `children`, so they
+    /// stay the same when a child in between can't be listed
+    fn list_dir(
+        &mut self,
+        ino: u64,
+        offset: i64,
+    ) -> std::result::Result<Vec<(Inode, i64, &DriveEntry)>, c_int> {
+        let update_res = run_async_blocking(self.update_entry_metadata_cache_if_needed());
+        if let Err(e) = update_res {
+            error!("read: could not update metadata cache: {}", e);
+            return Err(libc::EIO);
+        }
+        let Ok(dir_drive_id) = self.get_drive_id_from_ino(ino) else {
+            warn!("readdir: could not get drive id for ino: {}", ino);
+            return Err(libc::ENOENT);
+        };
+        if let Some(attr) = self.entries.get(dir_drive_id).map(|entry| entry.attr) {
+            if attr.kind != FileType::Directory {
+                return Err(libc::ENOTDIR);
+            }
+        }
+        let Some(children) = self.children.get(dir_drive_id) else {
+            return Err(libc::ENOENT);
+        };
+        debug!("children ({}): {:?}", children.len(), children);
+        let listing = children
+            .iter()
+            .enumerate()
+            .skip(offset.max(0) as usize)
+            .filter_map(|(index, child_id)| {
+                let entry = self.entries.get(child_id)?;
+                entry.local_path.as_ref()?;
+                let inode = self.get_ino_from_drive_id(child_id).ok()?;
+                Some((*inode, index as i64 + 1, entry))
+            })
+            .collect();
+        Ok(listing)
+    }
 }
 
 // region general
@@ -172,18 +216,19 @@ impl DriveFilesystem {
         );
         // let upload_filter = CommonFileFilter::from_path(config_path)?;
         let mut entries = HashMap::new();
-        Self::add_root_entry(&mut entries);
+        Self::add_root_entry(&mut entries, settings.blksize());
 
         let changes_start_token = drive.get_start_page_token().await?;
+        let generation = next_mount_generation(&cache_dir);
 
         let mut s = Self {
             source: drive,
             cache_dir: Some(cache_dir),
             entries,
             file_uploader_sender,
-            /*TODO: implement a way to increase this if necessary*/
-            generation: 0,
+            generation,
             children: HashMap::new(),
+            root_id: None,
             settings,
             changes_start_token,
             last_checked_changes: UNIX_EPOCH,
@@ -193,7 +238,7 @@ impl DriveFilesystem {
         Ok(s)
     }
 
-    fn add_root_entry(entries: &mut HashMap<DriveId, DriveEntry>) {
+    fn add_root_entry(entries: &mut HashMap<DriveId, DriveEntry>, blksize: u32) {
         let now = SystemTime::now();
         // Add root directory with inode number 1
         let root_attr = FileAttr {
@@ -210,7 +255,7 @@ impl DriveFilesystem {
             uid: 0,
             gid: 0,
             rdev: 0,
-            blksize: 4096,
+            blksize,
             flags: 0,
         };
         let inode = Inode::from(FUSE_ROOT_ID);
@@ -243,14 +288,10 @@ impl DriveFilesystem {
 
     #[instrument]
     fn construct_cache_folder_path(cache_dir: &Path, entry: &DriveEntry) -> PathBuf {
-        let path = cache_dir.to_path_buf();
-        path.join(match entry.local_path.as_ref() {
-            Some(x) => match x.parent() {
-                Some(parent) => parent.to_path_buf(),
-                None => PathBuf::new(),
-            },
-            None => PathBuf::new(),
-        })
+        match entry.local_path.as_ref().and_then(|path| path.parent()) {
+            Some(parent) => cache_dir.join(parent),
+            None => cache_dir.to_path_buf(),
+        }
     }
     #[instrument(fields(% self))]
     async fn add_all_file_entries(&mut self) -> anyhow::Result<()> {
@@ -268,8 +309,9 @@ impl DriveFilesystem {
             .id
             .context("the root id is not available")?;
 
-        Self::add_root_entry(&mut entries);
-        let drive_entries = self.source.list_all_files().await?;
+        self.root_id = Some(DriveId::from(&alternative_rood_id));
+        Self::add_root_entry(&mut entries, self.settings.blksize());
+        let drive_entries = self.source.list_all_files(ListOptions::default()).await?;
         for metadata in drive_entries {
             let inode = self.generate_ino_with_offset(entries.len());
             let entry = self.create_entry_from_drive_metadata(&metadata, inode);
@@ -322,11 +364,13 @@ impl DriveFilesystem {
         self.get_entry_mut(&DriveId::root())
             .expect("The root entry has to exist by now")
             .build_local_path(None);
-        self.build_path_for_children(&DriveId::root());
+        self.build_path_for_children(&DriveId::root(), &mut HashSet::new());
         Ok(())
     }
-    #[instrument(skip(self))]
-    fn build_path_for_children(&mut self, parent_id: &DriveId) {
+    /// `ancestors` are the folders above the parent, a child that is one of
+    /// them would make this recurse forever
+    #[instrument(skip(self, ancestors))]
+    fn build_path_for_children(&mut self, parent_id: &DriveId, ancestors: &mut HashSet<DriveId>) {
         let parent = self
             .entries
             .get(parent_id)
@@ -342,7 +386,15 @@ impl DriveFilesystem {
                 child_list.len(),
                 child_list
             );
+            ancestors.insert(parent_id.clone());
             for child_id in child_list.clone() {
+                if ancestors.contains(&child_id) {
+                    warn!(
+                        "build_path_for_children: {} is its own ancestor, skipping it under {}",
+                        child_id, parent_id
+                    );
+                    continue;
+                }
                 let parent: Option<LocalPath> = match self.entries.get(parent_id) {
                     Some(e) => e.local_path.clone(),
                     None => None,
@@ -357,9 +409,90 @@ impl DriveFilesystem {
                     "build_path_for_children: child: {:?} parent: {:?}",
                     child_id, parent_id
                 );
-                self.build_path_for_children(&child_id);
+                self.build_path_for_children(&child_id, ancestors);
+            }
+            ancestors.remove(parent_id);
+        }
+    }
+
+    /// the folders the entry is listed in
+    fn get_parents(&self, id: &DriveId) -> Vec<DriveId> {
+        self.children
+            .iter()
+            .filter(|(_, children)| children.contains(id))
+            .map(|(parent, _)| parent.clone())
+            .collect()
+    }
+    /// lists the entry in the `parents` instead of its current folders,
+    /// `false` if it already was in exactly those
+    fn set_parents(&mut self, id: &DriveId, parents: &[String]) -> bool {
+        let mut parents: Vec<DriveId> = parents
+            .iter()
+            .map(|parent| match &self.root_id {
+                Some(root_id) if root_id.as_str() == parent => DriveId::root(),
+                _ => DriveId::from(parent),
+            })
+            .collect();
+        if parents.is_empty() {
+            parents.push(DriveId::root());
+        }
+        let old_parents: HashSet<DriveId> = self.get_parents(id).into_iter().collect();
+        if old_parents == parents.iter().cloned().collect() {
+            return false;
+        }
+        for parent in old_parents {
+            if let Some(children) = self.children.get_mut(&parent) {
+                children.retain(|child| child != id);
+            }
+        }
+        for parent in parents {
+            self.add_child(id.clone(), &parent);
+        }
+        true
+    }
+    /// builds the local path of a moved or renamed entry again, together with
+    /// the paths of everything below it.
+    ///
+    /// The cached content is moved to the new path, so it does not have to be
+    /// downloaded again.
+    #[instrument(skip(self))]
+    fn rebuild_local_paths(&mut self, id: &DriveId) {
+        let parent_path = self
+            .get_parents(id)
+            .first()
+            .and_then(|parent| self.entries.get(parent))
+            .and_then(|parent| parent.local_path.clone());
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        let old_cache_path = self
+            .cache_dir
+            .as_ref()
+            .map(|cache_dir| Self::construct_cache_path_for_entry(cache_dir, entry));
+        match parent_path {
+            Some(parent_path) => entry.build_local_path(Some(parent_path)),
+            // without a parent path it would become the root
+            None => entry.local_path = None,
+        }
+        if let (Some(old_cache_path), Some(cache_dir)) = (old_cache_path, self.cache_dir.as_ref()) {
+            let new_cache_path = Self::construct_cache_path_for_entry(cache_dir, entry);
+            if old_cache_path != new_cache_path && old_cache_path.exists() {
+                debug!(
+                    "moving the cache of {} from {} to {}",
+                    id,
+                    old_cache_path.display(),
+                    new_cache_path.display()
+                );
+                let moved = new_cache_path
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|_| std::fs::rename(&old_cache_path, &new_cache_path));
+                if let Err(e) = moved {
+                    warn!("could not move the cache of {}: {:?}", id, e);
+                }
             }
         }
+        self.build_path_for_children(id, &mut HashSet::new());
     }
 
     #[instrument(skip(self), fields(self.children.len = % self.children.len()))]
@@ -381,21 +514,6 @@ impl DriveFilesystem {
         }
     }
 
-    #[instrument]
-    fn get_size_from_drive_metadata(entry: &File) -> Option<u64> {
-        let size = entry.size.ok_or_else(|| 0);
-        if let Err(e) = size {
-            warn!("could not get size: {}", e);
-            return None;
-        }
-        let size = size.unwrap();
-        if size < 0 {
-            warn!("invalid size: {}", size);
-            return None;
-        }
-        let size = size as u64;
-        Some(size)
-    }
     #[instrument(fields(% self, ino))]
     fn get_drive_id(&self, ino: impl Into<Inode>) -> Option<&DriveId> {
         self.ino_drive_id.get_by_left(&ino.into())
@@ -407,22 +525,20 @@ impl DriveFilesystem {
         metadata: &File,
         inode: Inode,
     ) -> anyhow::Result<DriveEntry> {
-        let name = metadata.name.as_ref().ok_or_else(|| "no name");
-        if let Err(e) = name {
-            warn!("could not get name: {}", e);
-            return Err(anyhow!("could not get name: {}", e));
-        }
-        let name = name.as_ref().unwrap();
+        let drive_metadata = DriveMetadata::new(metadata);
+        let name = drive_metadata.name().inspect_err(|e| warn!("{}", e))?;
         if name.contains("/") || name.contains("\\") || name.contains(":") || name.contains("'") {
             warn!("invalid name: {}", name);
             return Err(anyhow!("invalid name"));
         }
+        let name = local_entry_name(name.to_string());
         let ino = inode;
-        let id = DriveId::from(metadata.id.as_ref().context("could not get id")?);
-        let mime_type = metadata.mime_type.as_ref().context(
-            "could not determine if this is a file or a folder since the mime type was empty",
-        )?;
-        let kind = match mime_type.as_str() {
+        let id = drive_metadata.id()?;
+        let mime_type = drive_metadata.mime();
+        if mime_type.is_empty() {
+            warn!("{} has no mime type, it is shown as a file", name);
+        }
+        let kind = match mime_type {
             "application/vnd.google-apps.document"
             | "application/vnd.google-apps.spreadsheet"
             | "application/vnd.google-apps.drawing"
@@ -439,30 +555,22 @@ impl DriveFilesystem {
         debug!("created time: {:?}", metadata.created_time);
         debug!("modified time: {:?}", metadata.modified_time);
         debug!("viewed by me time: {:?}", metadata.viewed_by_me_time);
+        let times = FileTimes::from_metadata(metadata);
         let attributes = FileAttr {
             ino: ino.into(),
-            size: Self::get_size_from_drive_metadata(metadata).unwrap_or(0),
+            size: drive_metadata.size(),
             blocks: 0,
-            atime: metadata
-                .viewed_by_me_time
-                .map(SystemTime::from)
-                .unwrap_or(UNIX_EPOCH),
-            mtime: metadata
-                .modified_time
-                .map(SystemTime::from)
-                .unwrap_or(UNIX_EPOCH),
-            ctime: SystemTime::now(),
-            crtime: metadata
-                .created_time
-                .map(SystemTime::from)
-                .unwrap_or(UNIX_EPOCH),
+            atime: times.atime,
+            mtime: times.mtime,
+            ctime: times.ctime,
+            crtime: times.crtime,
             kind,
             perm: permissions,
             nlink: 1,
             uid: 0,
             gid: 0,
             rdev: 0,
-            blksize: 4096,
+            blksize: self.settings.blksize(),
             flags: 0,
         };
 
@@ -523,19 +631,36 @@ impl DriveFilesystem {
                 ChangeType::File(file) => {
                     debug!("file change: {:?}", file);
                     let drive_id = &change.id;
+                    let new_parents = file.parents.clone();
 
                     let entry = self.entries.get_mut(drive_id);
+                    let mut moved = false;
                     if let Some(entry) = entry {
+                        let old_name = entry.name.clone();
                         debug!(
                             "updating entry metadata: {}, {:?} entry: {:?}",
                             entry.ino, entry.md5_checksum, entry
                         );
+                        if entry.local_md5_checksum.is_none() {
+                            // a cached file that was never hashed yet can still match the remote
+                            if let Some(cache_dir) = self.cache_dir.as_ref() {
+                                let path = Self::construct_cache_path_for_entry(cache_dir, entry);
+                                entry.local_md5_checksum = compute_md5_async(path).await;
+                            }
+                        }
                         let change_successful = Self::update_entry_metadata(file, entry);
+                        moved = entry.name != old_name;
                         if let Err(e) = change_successful {
                             warn!("got an err while update entry metadata: {}", e);
                             updated_entries.push(change.id);
                             continue;
                         }
+                        if let Some(new_parents) = new_parents {
+                            moved |= self.set_parents(drive_id, &new_parents);
+                        }
+                    }
+                    if moved {
+                        self.rebuild_local_paths(drive_id);
                     }
 
                     updated_entries.push(change.id);
@@ -594,7 +719,11 @@ impl DriveFilesystem {
             entry.ino,
             cache_dir.display()
         );
-        let path = Self::construct_cache_folder_path(cache_dir, entry).join(&entry.name);
+        // without a local path the id is used, the name could point anywhere
+        let path = match entry.local_path.as_ref() {
+            Some(local_path) => local_path.in_dir(cache_dir),
+            None => cache_dir.join(entry.drive_id.as_str()),
+        };
         debug!(
             "get_cache_path_for_entry: {}: {}",
             entry.ino,
@@ -615,22 +744,30 @@ impl DriveFilesystem {
 
     #[instrument]
     fn update_entry_metadata(drive_metadata: File, entry: &mut DriveEntry) -> anyhow::Result<()> {
+        // drive always reports the modifiedTime with the full metadata,
+        // without it the other times are not known either
+        if drive_metadata.modified_time.is_some() {
+            FileTimes::from_metadata(&drive_metadata).apply(&mut entry.attr);
+        }
         if let Some(name) = drive_metadata.name {
-            entry.name = OsString::from(name);
+            entry.name = OsString::from(local_entry_name(name));
         }
         if let Some(size) = drive_metadata.size {
             entry.attr.size = size as u64;
         }
-        if let Some(modified_time) = drive_metadata.modified_time {
-            entry.attr.mtime = modified_time.into();
-        }
-        if let Some(created_time) = drive_metadata.created_time {
-            entry.attr.ctime = created_time.into();
-        }
-        if let Some(viewed_by_me) = drive_metadata.viewed_by_me_time {
-            entry.attr.atime = viewed_by_me.into();
-        }
 
+        if drive_metadata.md5_checksum.is_some()
+            && drive_metadata.md5_checksum == entry.local_md5_checksum
+        {
+            // only the metadata changed (like the viewedByMeTime), the content is already here
+            debug!(
+                "the local content of {} matches the remote, not downloading it",
+                entry.ino
+            );
+            entry.set_md5_checksum(drive_metadata.md5_checksum);
+            entry.has_upstream_content_changes = false;
+            return Ok(());
+        }
         let checksum_mismatch = Self::compare_checksums(&drive_metadata.md5_checksum, &entry);
         match checksum_mismatch {
             ChecksumMatch::Missing | ChecksumMatch::Unknown | ChecksumMatch::RemoteMismatch => {
@@ -737,21 +874,8 @@ impl DriveFilesystem {
         return ChecksumMatch::Unknown;
     }
 
-    #[instrument]
-    fn compute_md5_checksum(path: &PathBuf) -> Option<String> {
-        use md5::{Digest, Md5};
-        use std::{fs, io};
-        debug!("computing md5_checksum for {}", path.display());
-        let mut file = fs::File::open(&path).ok()?;
-        let mut hasher = Md5::new();
-        let _n = io::copy(&mut file, &mut hasher).ok()?;
-        let hash = hasher.finalize();
-        let hash = format!("{:x}", hash);
-        debug!("computed md5_checksum for {}: {}", path.display(), hash);
-        Some(hash)
-    }
     async fn get_changes(&mut self) -> anyhow::Result<Vec<Change>> {
-        if self.last_checked_changes + self.settings.cache_time() > SystemTime::now() {
+        if !is_cache_time_over(self.last_checked_changes, self.settings.cache_time()) {
             debug!("not checking for changes since we already checked recently");
             return Ok(vec![]);
         }
@@ -824,16 +948,28 @@ impl DriveFilesystem {
 
 // endregion
 
+/// asks the kernel to use [readdirplus](DriveFilesystem::readdirplus) for listings,
+/// without it the kernel only ever sends `readdir`
+fn request_readdirplus(add_capabilities: impl FnOnce(u32) -> std::result::Result<(), u32>) {
+    if let Err(unsupported) = add_capabilities(FUSE_DO_READDIRPLUS) {
+        warn!(
+            "the kernel does not support readdirplus (unsupported capabilities: {:#x})",
+            unsupported
+        );
+    }
+}
+
 //region filesystem
 impl Filesystem for DriveFilesystem {
     //region init
-    #[instrument(skip(_req, _config), fields(% self))]
+    #[instrument(skip(_req, config), fields(% self))]
     fn init(
         &mut self,
         _req: &Request<'_>,
-        _config: &mut KernelConfig,
+        config: &mut KernelConfig,
     ) -> std::result::Result<(), c_int> {
         debug!("init");
+        request_readdirplus(|capabilities| config.add_capabilities(capabilities));
 
         // let root = self.root.to_path_buf();
         // let x = run_async_blocking(self.add_dir_entry(&root, Inode::from(FUSE_ROOT_ID), true));
@@ -895,24 +1031,24 @@ impl Filesystem for DriveFilesystem {
         }
         let children = children.unwrap();
         debug!("lookup: children: {:?}", children);
-        for child_inode in children {
-            let entry = self.entries.get(&child_inode);
+        let children = children.iter().map(|child_inode| {
+            let entry = self.entries.get(child_inode);
             if entry.is_none() {
                 warn!("lookup: could not find entry for {}", child_inode);
-                continue;
             }
-            let entry = entry.unwrap();
-
-            let path: PathBuf = entry.name.clone().into();
-            let accepted = name.eq_ignore_ascii_case(&path);
+            (entry, entry.and_then(|e| e.name.to_str()))
+        });
+        let found = name
+            .to_str()
+            .and_then(|name| find_child_by_name(children, name))
+            .flatten();
+        if let Some(entry) = found {
             debug!(
-                "entry: {}:(accepted={}),{:?}; {:?}; {:?}",
-                child_inode, accepted, entry.md5_checksum, path, entry.attr
+                "lookup: found entry: {:?}; {:?}; {:?}",
+                entry.name, entry.md5_checksum, entry.attr
             );
-            if accepted {
-                reply.entry(&self.settings.time_to_live(), &entry.attr, self.generation);
-                return;
-            }
+            reply.entry(&self.settings.time_to_live(), &entry.attr, self.generation);
+            return;
         }
         warn!("lookup: could not find entry for {:?}", name);
 
@@ -1206,7 +1342,7 @@ impl Filesystem for DriveFilesystem {
                 "updated  size to {} for entry: {:?}",
                 entry.attr.size, entry
             );
-            entry.local_md5_checksum = Self::compute_md5_checksum(&path);
+            entry.local_md5_checksum = run_async_blocking(compute_md5_async(&path));
             debug!(
                 "updated local md5 to {:?} for entry: {:?}",
                 entry.local_md5_checksum, entry
@@ -1237,68 +1373,66 @@ impl Filesystem for DriveFilesystem {
         _req: &Request<'_>,
         ino: u64,
         fh: u64,
-        mut offset: i64,
+        offset: i64,
         mut reply: ReplyDirectory,
     ) {
         debug!("readdir: {}:{}:{:?}", ino, fh, offset);
-        let update_res = run_async_blocking(self.update_entry_metadata_cache_if_needed());
-        if let Err(e) = update_res {
-            error!("read: could not update metadata cache: {}", e);
-            reply.error(libc::EIO);
-            return;
-        }
-        let drive_id = self.get_drive_id_from_ino(&ino.into());
-        if drive_id.is_err() {
-            warn!("readdir: could not get drive id for ino: {}", ino);
-            reply.error(libc::ENOENT);
-            return;
-        }
-        let drive_id = drive_id.unwrap();
-        if let Some(attr) = self.entries.get(drive_id).map(|entry| entry.attr) {
-            if attr.kind != FileType::Directory {
-                reply.error(libc::ENOTDIR);
+        let listing = match self.list_dir(ino, offset) {
+            Ok(listing) => listing,
+            Err(errno) => {
+                reply.error(errno);
                 return;
             }
-        }
-        let dir_drive_id = self.get_drive_id_from_ino(&ino.into());
-        if dir_drive_id.is_err() {
-            warn!("readdir: could not get drive id for ino: {}", ino);
-            reply.error(libc::ENOENT);
-            return;
-        }
-        let dir_drive_id = dir_drive_id.unwrap();
-        let children = self.children.get(&dir_drive_id);
-        if children.is_none() {
-            reply.error(libc::ENOENT);
-            return;
-        }
-        let children = children.unwrap();
-        debug(children);
-        debug!("children ({}): {:?}", children.len(), children);
-        for child_id in children.iter().skip(offset as usize) {
-            let entry = self.entries.get(child_id);
-            if let Some(entry) = entry {
-                if let Some(local_path) = entry.local_path.as_ref() {
-                    let path: PathBuf = local_path.clone().into();
-                    let attr = entry.attr;
-                    let inode = self.get_ino_from_drive_id(child_id);
-                    if let Ok(inode) = inode {
-                        // Increment the offset for each processed entry
-                        offset += 1;
-                        debug!("entry: {}:{:?}; {:?}", inode, path, attr);
-                        if reply.add((*inode).into(), offset, attr.kind, &entry.name) {
-                            // If the buffer is full, we need to stop
-                            debug!("readdir: buffer full");
-                            break;
-                        }
-                    }
-                }
+        };
+        for (inode, offset, entry) in listing {
+            debug!("entry: {}:{:?}; {:?}", inode, entry.name, entry.attr);
+            if reply.add(inode.into(), offset, entry.attr.kind, &entry.name) {
+                // If the buffer is full, we need to stop
+                debug!("readdir: buffer full");
+                break;
             }
         }
         debug!("readdir: ok");
         reply.ok();
     }
     //endregion
+    //region readdirplus
+    /// like [readdir](Self::readdir), but with the attributes of every entry so
+    /// `ls -l` does not have to ask for each of them with a `getattr`
+    #[instrument(skip(_req, reply), fields(% self))]
+    fn readdirplus(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectoryPlus,
+    ) {
+        debug!("readdirplus: {}:{}:{:?}", ino, fh, offset);
+        let (ttl, generation) = (self.settings.time_to_live(), self.generation);
+        let listing = match self.list_dir(ino, offset) {
+            Ok(listing) => listing,
+            Err(errno) => {
+                reply.error(errno);
+                return;
+            }
+        };
+        for (inode, offset, entry) in listing {
+            if reply.add(
+                inode.into(),
+                offset,
+                &entry.name,
+                &ttl,
+                &entry.attr,
+                generation,
+            ) {
+                debug!("readdirplus: buffer full");
+                break;
+            }
+        }
+        reply.ok();
+    }
+    //endregion
     //region access
     #[instrument(fields(% self, ino, mask))]
     fn access(&mut self, _req: &Request<'_>, _ino: u64, _mask: i32, reply: ReplyEmpty) {
@@ -1312,3 +1446,20 @@ impl Filesystem for DriveFilesystem {
 // TODO: implement rename/move
 // TODO: implement create
 // TODO: implement delete
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_requests_readdirplus() {
+        let mut requested = 0;
+        request_readdirplus(|capabilities| {
+            requested |= capabilities;
+            Ok(())
+        });
+        assert_eq!(requested, FUSE_DO_READDIRPLUS);
+        // a kernel without readdirplus must not fail the mount
+        request_readdirplus(Err);
+    }
+}
@@ -1,6 +1,9 @@
 use std::fmt::{Display, Formatter};
 use std::time::Duration;
 
+/// the block size the files report, like most local filesystems
+pub const DEFAULT_BLKSIZE: u32 = 4096;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SyncSettings {
     /// How long the responses can/should be cached
@@ -12,6 +15,8 @@ pub struct SyncSettings {
     /// be downloaded again, it just checks the modified time
     /// on the remote against the local file
     cache_time: Duration,
+    /// see [SyncSettings::set_blksize]
+    blksize: u32,
 }
 
 impl SyncSettings {
@@ -19,8 +24,21 @@ impl SyncSettings {
         Self {
             time_to_live,
             cache_time,
+            blksize: DEFAULT_BLKSIZE,
         }
     }
+    /// the preferred size for reads and writes the files report in `st_blksize`.
+    ///
+    /// Programs like `cp` and the buffered io of libc use it as their buffer
+    /// size, so a larger one (like 128 KiB) means fewer and larger reads and
+    /// writes for large files. It has to be a power of two, other values are
+    /// rounded up to the next one and 0 means [DEFAULT_BLKSIZE].
+    pub fn set_blksize(&mut self, blksize: u32) {
+        self.blksize = match blksize {
+            0 => DEFAULT_BLKSIZE,
+            blksize => blksize.checked_next_power_of_two().unwrap_or(1 << 31),
+        };
+    }
     // pub fn from_path(path: &Path)-> Self{
     //     let s = Self{
     //         time_to_live: Duration::from_secs(60),
@@ -38,6 +56,9 @@ impl SyncSettings {
     pub fn cache_time(&self) -> Duration {
         self.cache_time
     }
+    pub fn blksize(&self) -> u32 {
+        self.blksize
+    }
 }
 
 // endregion
@@ -45,9 +66,30 @@ impl Display for SyncSettings {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "SyncSettings {{ ttl: {}s, cache_time: {}s }}",
+            "SyncSettings {{ ttl: {}s, cache_time: {}s, blksize: {} }}",
             self.time_to_live.as_secs(),
-            self.cache_time.as_secs()
+            self.cache_time.as_secs(),
+            self.blksize
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blksize_is_always_a_power_of_two() {
+        let mut settings = SyncSettings::new(Duration::from_secs(2), Duration::from_secs(10));
+        for (blksize, expected) in [
+            (0, DEFAULT_BLKSIZE),
+            (1, 1),
+            (4096, 4096),
+            (100_000, 131_072),
+            (u32::MAX, 1 << 31),
+        ] {
+            settings.set_blksize(blksize);
+            assert_eq!(settings.blksize(), expected, "{}", blksize);
+        }
+    }
+}
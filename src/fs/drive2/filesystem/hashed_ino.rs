@@ -0,0 +1,29 @@
+use fuser::FUSE_ROOT_ID;
+
+use crate::google_drive::DriveId;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+/// the hashed inos stay in the positive range of an `i64`, some tools don't
+/// expect anything larger
+const MAX_HASHED_INO: u64 = i64::MAX as u64;
+
+/// The ino of the id, derived from its FNV-1a hash.
+///
+/// The std hasher may change between rust versions, this one gives the same
+/// ino for the id on every mount. The root ino is never returned.
+pub(super) fn hashed_ino(id: &DriveId) -> u64 {
+    let hash = id.as_str().bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    });
+    (hash & MAX_HASHED_INO).max(FUSE_ROOT_ID + 1)
+}
+
+/// the ino to try after `ino` when it is taken
+pub(super) fn next_hashed_ino(ino: u64) -> u64 {
+    if ino >= MAX_HASHED_INO {
+        FUSE_ROOT_ID + 1
+    } else {
+        ino + 1
+    }
+}
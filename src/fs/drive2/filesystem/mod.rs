@@ -1,28 +1,34 @@
 use std::{
-    collections::HashMap,
-    ffi::OsStr,
+    collections::{BTreeSet, HashMap},
+    ffi::{OsStr, OsString},
     fmt::{Display, Formatter},
+    future::Future,
     sync::mpsc::{channel, Receiver, Sender},
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{anyhow, Context};
 use bimap::BiMap;
 use fuser::{
-    FileAttr, Filesystem, KernelConfig, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
-    ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow,
+    FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyLseek, ReplyOpen, ReplyWrite, ReplyXattr, Request, TimeOrNow, FUSE_ROOT_ID,
 };
 use libc::c_int;
 use tokio::fs::File;
 use tracing::{debug, error, field::debug, instrument, trace};
 
 pub use handle_flags::HandleFlags;
+use hashed_ino::{hashed_ino, next_hashed_ino};
 
+use crate::async_helper::run_async_blocking;
 use crate::fs::drive_file_provider::{
-    ProviderLookupRequest, ProviderMetadataRequest, ProviderOpenFileRequest,
-    ProviderReadContentRequest, ProviderReadDirRequest, ProviderReleaseFileRequest,
-    ProviderRenameRequest, ProviderRequest, ProviderResponse, ProviderSetAttrRequest,
-    ProviderWriteContentRequest,
+    ProviderFlushRequest, ProviderGetXattrRequest, ProviderListXattrRequest, ProviderLookupRequest,
+    ProviderLseekRequest, ProviderMetadataRequest, ProviderMkdirRequest, ProviderOpenFileRequest,
+    ProviderReadContentRequest, ProviderReadDirRequest, ProviderReadDirResponse,
+    ProviderReleaseFileRequest, ProviderRemoveRequest, ProviderRenameRequest, ProviderRequest,
+    ProviderResponse, ProviderRootIdRequest, ProviderSetAttrRequest, ProviderSetXattrRequest,
+    ProviderStatus, ProviderStatusRequest, ProviderWriteContentRequest, DRIVE_XATTR_PREFIX,
+    STATUS_XATTR,
 };
 use crate::google_drive::DriveId;
 use crate::{
@@ -31,9 +37,18 @@ use crate::{
 };
 
 //TODO2: decide if 1 second is a good TTL for all cases
+/// the TTL for entries without an override, see [TtlOverrides](crate::config::ttl_overrides::TtlOverrides)
 const TTL: Duration = Duration::from_secs(2);
 
+/// `.` and `..` come before the children of every folder
+const DOT_ENTRIES: u64 = 2;
+
+/// how many children are asked for per readdir. The kernel's buffer fills up
+/// long before that, the rest gets asked for again with the next offset
+const READ_DIR_PAGE_SIZE: usize = 256;
+
 mod handle_flags;
+mod hashed_ino;
 
 #[derive(Debug)]
 struct FileHandleData {
@@ -43,15 +58,38 @@ struct FileHandleData {
 #[derive(Debug)]
 struct Entry {
     attr: FileAttr,
+    ttl: Duration,
+    cached_at: Instant,
 }
 
 #[derive(Debug)]
 pub struct DriveFilesystem {
     file_provider_sender: tokio::sync::mpsc::Sender<ProviderRequest>,
+    /// receives the ids of entries that were changed on the remote
+    changed_ids_receiver: Receiver<DriveId>,
 
     entry_ids: BiMap<u64, DriveId>,
+    /// the real id of the root folder, it maps to the root ino just like the
+    /// `root` alias. `None` until the provider told it
+    root_id: Option<DriveId>,
     ino_to_file_handles: HashMap<u64, Vec<u64>>,
     next_ino: u64,
+    /// how many lookups the kernel has not forgotten yet for each ino
+    lookup_counts: HashMap<u64, u64>,
+    /// inos that are not in use anymore and can be handed out again. Only
+    /// used for counted inos, hashed ones are found by [DriveFilesystem::is_ino_free]
+    free_inos: BTreeSet<u64>,
+    /// the generation of every ino that has been recycled at least once,
+    /// so the kernel can tell the old and the new entry apart
+    generations: HashMap<u64, u64>,
+    /// see [DriveFilesystem::set_mount_generation]
+    mount_generation: u64,
+    /// see [DriveFilesystem::set_hashed_inos]
+    hashed_inos: bool,
+    /// the inos of the ids whose hashed ino was taken by another id
+    hash_collisions: HashMap<DriveId, u64>,
+    /// the last known attributes for each ino, valid for the duration of their TTL
+    attr_cache: HashMap<u64, Entry>,
 }
 //region DriveFilesystem ino_to_file_handle
 impl DriveFilesystem {
@@ -106,27 +144,232 @@ impl DriveFilesystem {
         self.entry_ids.get_by_left(&ino)
     }
     fn get_ino_from_id(&mut self, id: DriveId) -> u64 {
+        if self.is_root_id(&id) {
+            return FUSE_ROOT_ID;
+        }
         let x = self.entry_ids.get_by_right(&id);
         if let Some(ino) = x {
             return *ino;
         }
         self.add_id(id)
     }
+    fn is_root_id(&self, id: &DriveId) -> bool {
+        *id == DriveId::root() || self.root_id.as_ref() == Some(id)
+    }
+    /// asks the provider for the real id of the root folder
+    fn fetch_root_id(&self) -> Result<DriveId> {
+        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderRequest::RootId(ProviderRootIdRequest::new(provider_res_tx));
+        run_async_blocking(self.file_provider_sender.send(request))
+            .map_err(|_| anyhow!("the provider is not listening for requests"))?;
+        match run_async_blocking(provider_rx.recv()) {
+            Some(ProviderResponse::RootId(root_id)) => Ok(root_id),
+            response => Err(anyhow!(
+                "unexpected response for the root id: {:?}",
+                response
+            )),
+        }
+    }
     fn remove_id(&mut self, id: DriveId) -> Result<u64> {
         if let Some((ino, _)) = self.entry_ids.remove_by_right(&id) {
+            if self.lookup_counts.get(&ino).copied().unwrap_or(0) == 0 {
+                self.release_ino(ino);
+            } else {
+                trace!(
+                    "not recycling ino {} yet since the kernel still knows it",
+                    ino
+                );
+            }
             Ok(ino)
         } else {
             Err(anyhow!("could not find id {}", id))
         }
     }
     fn add_id(&mut self, id: DriveId) -> u64 {
-        let ino = self.generate_ino();
+        let ino = match self.hashed_inos {
+            true => self.find_hashed_ino(&id),
+            false => self.generate_ino(),
+        };
         trace!("adding new ino for drive id: {} => {}", id, ino);
         self.entry_ids.insert(ino, id);
         ino
     }
 }
 //endregion
+//region DriveFilesystem lookup counts
+impl DriveFilesystem {
+    fn increase_lookup_count(&mut self, ino: u64) {
+        *self.lookup_counts.entry(ino).or_insert(0) += 1;
+    }
+    /// decreases the lookup count of the ino and releases it for reuse once
+    /// the kernel does not reference it anymore
+    fn forget_ino(&mut self, ino: u64, nlookup: u64) {
+        if ino == FUSE_ROOT_ID {
+            return;
+        }
+        let count = self.lookup_counts.entry(ino).or_insert(0);
+        *count = count.saturating_sub(nlookup);
+        if *count > 0 {
+            return;
+        }
+        self.lookup_counts.remove(&ino);
+        if let Some((_, id)) = self.entry_ids.remove_by_left(&ino) {
+            trace!("kernel forgot ino {} for drive id: {}", ino, id);
+        }
+        self.release_ino(ino);
+    }
+    fn release_ino(&mut self, ino: u64) {
+        self.invalidate_attr(ino);
+        if self.hashed_inos || self.free_inos.insert(ino) {
+            *self.generations.entry(ino).or_insert(0) += 1;
+        }
+    }
+    /// the mount generation is in the upper half, so the inos of an earlier
+    /// mount never have the same generation, however often they were recycled
+    fn get_generation(&self, ino: u64) -> u64 {
+        (self.mount_generation << 32) + self.generations.get(&ino).copied().unwrap_or(0)
+    }
+}
+//endregion
+//region DriveFilesystem attr cache
+impl DriveFilesystem {
+    fn cache_attr(&mut self, attr: FileAttr, ttl: Duration) {
+        self.attr_cache.insert(
+            attr.ino,
+            Entry {
+                attr,
+                ttl,
+                cached_at: Instant::now(),
+            },
+        );
+    }
+    /// returns the cached entry of the ino if it is not older than its TTL
+    fn get_cached_entry(&self, ino: u64) -> Option<&Entry> {
+        self.attr_cache
+            .get(&ino)
+            .filter(|entry| entry.cached_at.elapsed() < entry.ttl)
+    }
+    #[cfg(test)]
+    fn get_cached_attr(&self, ino: u64) -> Option<FileAttr> {
+        self.get_cached_entry(ino).map(|entry| entry.attr)
+    }
+    fn invalidate_attr(&mut self, ino: u64) {
+        self.attr_cache.remove(&ino);
+    }
+    /// sends the request to the provider and waits for its response
+    fn ask_provider(
+        &self,
+        request: ProviderRequest,
+        mut response_receiver: tokio::sync::mpsc::Receiver<ProviderResponse>,
+    ) -> std::result::Result<ProviderResponse, c_int> {
+        if let Err(e) = run_async_blocking(self.file_provider_sender.send(request)) {
+            error!("Failed to send ProviderRequest; e:{}", e);
+            return Err(libc::EIO);
+        }
+        match run_async_blocking(response_receiver.recv()) {
+            Some(ProviderResponse::Error(e, code)) => {
+                error!("received ProviderResponse::Error: ({}) {}", code, e);
+                Err(code)
+            }
+            Some(response) => Ok(response),
+            None => {
+                error!("Failed to receive ProviderResponse");
+                Err(libc::EIO)
+            }
+        }
+    }
+    /// what [STATUS_XATTR] on the root shows
+    fn status(&self) -> std::result::Result<ProviderStatus, c_int> {
+        let (provider_res_tx, provider_rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderRequest::Status(ProviderStatusRequest::new(provider_res_tx));
+        match self.ask_provider(request, provider_rx)? {
+            ProviderResponse::Status(status) => Ok(status),
+            response => {
+                error!("Received unexpected ProviderResponse: {:?}", response);
+                Err(libc::EIO)
+            }
+        }
+    }
+    /// asks the provider for the entry `name` in the folder `parent` and caches
+    /// its attributes, so a `getattr` right after it does not have to ask again
+    fn lookup_entry(
+        &mut self,
+        parent: u64,
+        name: &OsStr,
+    ) -> std::result::Result<(Duration, FileAttr), c_int> {
+        let Some(parent_id) = self.entry_ids.get_by_left(&parent) else {
+            error!("Failed to find drive_id for parent ino: {}", parent);
+            return Err(libc::ENOENT);
+        };
+        let (provider_res_tx, provider_rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderRequest::Lookup(ProviderLookupRequest::new(
+            parent_id,
+            name.to_os_string(),
+            provider_res_tx,
+        ));
+        match self.ask_provider(request, provider_rx)? {
+            ProviderResponse::Lookup(Some(metadata)) => {
+                let mut attr = metadata.attr;
+                let ttl = metadata.ttl.unwrap_or(TTL);
+                attr.ino = self.get_ino_from_id(metadata.id);
+                self.increase_lookup_count(attr.ino);
+                self.cache_attr(attr, ttl);
+                Ok((ttl, attr))
+            }
+            ProviderResponse::Lookup(None) => Err(libc::ENOENT),
+            response => {
+                error!("Received unexpected ProviderResponse: {:?}", response);
+                Err(libc::EIO)
+            }
+        }
+    }
+    /// the attributes of the ino, only asks the provider when none are cached
+    fn get_attr(&mut self, ino: u64) -> std::result::Result<(Duration, FileAttr), c_int> {
+        self.apply_remote_changes();
+        if let Some(entry) = self.get_cached_entry(ino) {
+            trace!("responding with cached attr: {:?}", entry.attr);
+            return Ok((entry.ttl, entry.attr));
+        }
+        let Some(drive_id) = self.entry_ids.get_by_left(&ino) else {
+            error!("Failed to find drive_id for ino: {}", ino);
+            return Err(libc::ENOENT);
+        };
+        debug!("getting attributes");
+        let (provider_res_tx, provider_rx) = tokio::sync::mpsc::channel(1);
+        let request =
+            ProviderRequest::Metadata(ProviderMetadataRequest::new(drive_id, provider_res_tx));
+        match self.ask_provider(request, provider_rx)? {
+            ProviderResponse::Metadata(metadata) => {
+                trace!("Received ProviderResponse::Metadata({:?})", metadata);
+                let mut attr = metadata.attr;
+                let ttl = metadata.ttl.unwrap_or(TTL);
+                attr.ino = ino;
+                self.cache_attr(attr, ttl);
+                Ok((ttl, attr))
+            }
+            response => {
+                error!("Received unexpected ProviderResponse: {:?}", response);
+                Err(libc::EIO)
+            }
+        }
+    }
+    /// invalidates the attributes this filesystem cached for entries that were
+    /// changed on the remote.
+    ///
+    /// The kernel keeps its own copy of the attributes until their TTL is over,
+    /// fuser 0.12 has no way to tell it otherwise. The page cache of a changed
+    /// file gets dropped on its next open through
+    /// [OpenCacheMode::Auto](crate::fs::drive_file_provider::OpenCacheMode::Auto)
+    fn apply_remote_changes(&mut self) {
+        while let Ok(id) = self.changed_ids_receiver.try_recv() {
+            if let Some(ino) = self.entry_ids.get_by_right(&id).copied() {
+                trace!("invalidating ino {} because {} changed remotely", ino, id);
+                self.invalidate_attr(ino);
+            }
+        }
+    }
+}
+//endregion
 impl Display for DriveFilesystem {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "DriveFilesystem(entry ids: {})", self.entry_ids.len())
@@ -134,19 +377,94 @@ impl Display for DriveFilesystem {
 }
 
 impl DriveFilesystem {
-    pub fn new(file_provider_sender: tokio::sync::mpsc::Sender<ProviderRequest>) -> Self {
+    pub fn new(
+        file_provider_sender: tokio::sync::mpsc::Sender<ProviderRequest>,
+        changed_ids_receiver: Receiver<DriveId>,
+    ) -> Self {
         Self {
             file_provider_sender,
+            changed_ids_receiver,
             entry_ids: BiMap::new(),
+            root_id: None,
             ino_to_file_handles: HashMap::new(),
             next_ino: 222,
+            lookup_counts: HashMap::new(),
+            free_inos: BTreeSet::new(),
+            generations: HashMap::new(),
+            mount_generation: 0,
+            hashed_inos: false,
+            hash_collisions: HashMap::new(),
+            attr_cache: HashMap::new(),
         }
     }
+    /// sets which mount this is, see [next_mount_generation](crate::fs::next_mount_generation).
+    /// This has to be set before the filesystem gets mounted
+    pub fn set_mount_generation(&mut self, mount_generation: u64) {
+        self.mount_generation = mount_generation;
+    }
+    /// derives the inos from the hashes of the ids instead of counting them up,
+    /// so a file keeps its ino across mounts. Has to be set before the
+    /// filesystem gets mounted
+    pub fn set_hashed_inos(&mut self, hashed_inos: bool) {
+        self.hashed_inos = hashed_inos;
+    }
+    /// the hashed ino of the id, or the next free one after it if it is taken.
+    /// The id keeps the ino it got on a collision for the rest of the mount
+    fn find_hashed_ino(&mut self, id: &DriveId) -> u64 {
+        if let Some(ino) = self.hash_collisions.get(id).copied() {
+            if self.is_ino_free(ino) {
+                return ino;
+            }
+        }
+        let hashed = hashed_ino(id);
+        let mut ino = hashed;
+        while !self.is_ino_free(ino) {
+            ino = next_hashed_ino(ino);
+        }
+        if ino != hashed {
+            debug!(
+                "the hashed ino {} of {} is taken, using {}",
+                hashed, id, ino
+            );
+            self.hash_collisions.insert(id.clone(), ino);
+        }
+        ino
+    }
+    /// not used by an id and not known to the kernel anymore
+    fn is_ino_free(&self, ino: u64) -> bool {
+        !self.entry_ids.contains_left(&ino) && !self.lookup_counts.contains_key(&ino)
+    }
     fn generate_ino(&mut self) -> u64 {
+        if let Some(ino) = self.free_inos.pop_first() {
+            return ino;
+        }
         let ino = self.next_ino;
         self.next_ino += 1;
         ino
     }
+    /// resolves once the provider stopped listening, for example because it
+    /// panicked. Every request fails from then on, so the mount has to go.
+    pub fn provider_closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let file_provider_sender = self.file_provider_sender.clone();
+        async move {
+            file_provider_sender.closed().await;
+            error!("the provider is gone, the filesystem can not answer any request anymore");
+        }
+    }
+}
+
+/// calls `unmount` once `provider_closed` (see [DriveFilesystem::provider_closed])
+/// resolves. Ends without it when the session ended first, `provider_closed`
+/// holds a sender to the provider that would keep it waiting for requests
+pub async fn unmount_when_provider_closed(
+    provider_closed: impl Future<Output = ()>,
+    session_ended: impl Future<Output = ()>,
+    unmount: impl FnOnce(),
+) {
+    tokio::select! {
+        _ = provider_closed => unmount(),
+        _ = session_ended => debug!("the session ended, not waiting for the provider anymore"),
+    }
 }
 
 impl Filesystem for DriveFilesystem {
@@ -156,67 +474,39 @@ impl Filesystem for DriveFilesystem {
         _req: &Request<'_>,
         _config: &mut KernelConfig,
     ) -> std::result::Result<(), c_int> {
-        self.entry_ids.insert(1, DriveId::from("root"));
+        self.entry_ids.insert(FUSE_ROOT_ID, DriveId::root());
+        match self.fetch_root_id() {
+            Ok(root_id) => {
+                debug!("the root alias is {}", root_id);
+                self.root_id = Some(root_id);
+            }
+            // only the alias maps to the root then, that still works for most things
+            Err(e) => error!("could not get the real id of the root: {:?}", e),
+        }
         Ok(())
     }
     //endregion
     //region lookup
     fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
-
-        let parent_id = self.entry_ids.get_by_left(&parent);
-        reply_error_o!(
-            parent_id,
-            reply,
-            libc::ENOENT,
-            "Failed to find drive_id for parent ino: {}",
-            parent
-        );
-
-        let v = ProviderRequest::Lookup(ProviderLookupRequest::new(
-            parent_id,
-            name.to_os_string(),
-            provider_res_tx,
-        ));
-        send_request!(self.file_provider_sender, v, reply);
-
-        receive_response!(provider_rx, response, reply);
-        match_provider_response!(response, reply, ProviderResponse::Lookup(metadata), {
-            if let Some(metadata) = metadata {
-                let mut attr = metadata.attr;
-                attr.ino = self.get_ino_from_id(metadata.id);
-                reply.entry(&TTL, &attr, 0); //TODO3: generation
-            } else {
-                reply.error(libc::ENOENT);
-            }
-        });
+        match self.lookup_entry(parent, name) {
+            Ok((ttl, attr)) => reply.entry(&ttl, &attr, self.get_generation(attr.ino)),
+            Err(code) => reply.error(code),
+        }
         debug!("done with lookup!");
     }
     //endregion
+    //region forget
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        self.forget_ino(ino, nlookup);
+    }
+    //endregion
     //region getattr
     #[instrument(skip(_req), fields(% self))]
     fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
-        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
-        let drive_id = self.entry_ids.get_by_left(&ino);
-        reply_error_o!(
-            drive_id,
-            reply,
-            libc::ENOENT,
-            "Failed to find drive_id for ino: {}",
-            ino
-        );
-        debug!("getting attributes");
-
-        let v = ProviderRequest::Metadata(ProviderMetadataRequest::new(drive_id, provider_res_tx));
-        send_request!(self.file_provider_sender, v, reply);
-        receive_response!(provider_rx, response, reply);
-        match_provider_response!(response, reply, ProviderResponse::Metadata(metadata), {
-            trace!("Received ProviderResponse::Metadata({:?})", metadata);
-            let mut attr = metadata.attr;
-            attr.ino = ino;
-            trace!("responding with attr: {:?}", attr);
-            reply.attr(&TTL, &attr);
-        });
+        match self.get_attr(ino) {
+            Ok((ttl, attr)) => reply.attr(&ttl, &attr),
+            Err(code) => reply.error(code),
+        }
     }
     //endregion
     //region setattr
@@ -229,8 +519,8 @@ impl Filesystem for DriveFilesystem {
         uid: Option<u32>,
         gid: Option<u32>,
         size: Option<u64>,
-        _atime: Option<TimeOrNow>,
-        _mtime: Option<TimeOrNow>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
         _ctime: Option<SystemTime>,
         fh: Option<u64>,
         _crtime: Option<SystemTime>,
@@ -239,6 +529,7 @@ impl Filesystem for DriveFilesystem {
         flags: Option<u32>,
         reply: ReplyAttr,
     ) {
+        self.invalidate_attr(ino);
         let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
         let drive_id = self.entry_ids.get_by_left(&ino);
         reply_error_o!(
@@ -249,16 +540,11 @@ impl Filesystem for DriveFilesystem {
             ino
         );
         debug!("getting attributes");
-        let v = ProviderRequest::SetAttr(ProviderSetAttrRequest::new(
-            drive_id,
-            mode,
-            uid,
-            gid,
-            size,
-            flags,
-            fh,
-            provider_res_tx,
-        ));
+        let mut request =
+            ProviderSetAttrRequest::new(drive_id, mode, uid, gid, size, flags, fh, provider_res_tx);
+        request.atime = atime.map(time_or_now_to_system_time);
+        request.mtime = mtime.map(time_or_now_to_system_time);
+        let v = ProviderRequest::SetAttr(Box::new(request));
         send_request!(self.file_provider_sender, v, reply);
         receive_response!(provider_rx, response, reply);
         match_provider_response!(response, reply, ProviderResponse::SetAttr(metadata), {
@@ -266,13 +552,14 @@ impl Filesystem for DriveFilesystem {
             let mut attr = metadata.attr;
             attr.ino = ino;
             trace!("responding with attr: {:?}", attr);
-            reply.attr(&TTL, &attr);
+            reply.attr(&metadata.ttl.unwrap_or(TTL), &attr);
         });
     }
     //endregion
     //region open
     #[instrument(skip(_req), fields(%self))]
     fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        self.apply_remote_changes();
         let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
         // let fh_id = self.generate_fh();
         // // let flags = HandleFlags::from(flags);
@@ -294,16 +581,27 @@ impl Filesystem for DriveFilesystem {
         ));
         send_request!(self.file_provider_sender, v, reply);
         receive_response!(provider_rx, response, reply);
-        match_provider_response!(response, reply, ProviderResponse::OpenFile(fh, flags), {
-            trace!("got OpenFile result: fh: {}, flags: {:?}", fh, flags);
-            let x = self.ino_to_file_handles.get_mut(&ino);
-            if let Some(x) = x {
-                x.push(fh);
-            } else {
-                self.ino_to_file_handles.insert(ino, vec![fh]);
+        match_provider_response!(
+            response,
+            reply,
+            ProviderResponse::OpenFile(fh, flags, cache_mode),
+            {
+                trace!(
+                    "got OpenFile result: fh: {}, flags: {:?}, cache mode: {:?}",
+                    fh,
+                    flags,
+                    cache_mode
+                );
+                let x = self.ino_to_file_handles.get_mut(&ino);
+                if let Some(x) = x {
+                    x.push(fh);
+                } else {
+                    self.ino_to_file_handles.insert(ino, vec![fh]);
+                }
+                // the reply takes FOPEN_* flags, not the flags the file was opened with
+                reply.opened(fh, cache_mode.fopen_flags());
             }
-            reply.opened(fh, flags.into());
-        });
+        );
     }
     //endregion
     //region read
@@ -344,6 +642,156 @@ impl Filesystem for DriveFilesystem {
         });
     }
     //endregion
+    //region lseek
+    /// the kernel only asks for `SEEK_DATA` and `SEEK_HOLE`, it handles the other seeks itself
+    #[instrument(skip(_req), fields(% self))]
+    fn lseek(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: ReplyLseek,
+    ) {
+        let Ok(offset) = u64::try_from(offset) else {
+            reply.error(libc::ENXIO);
+            return;
+        };
+        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let drive_id = self.entry_ids.get_by_left(&ino);
+        reply_error_o!(
+            drive_id,
+            reply,
+            libc::ENOENT,
+            "Failed to find drive_id for ino: {}",
+            ino
+        );
+        let v = ProviderRequest::Lseek(ProviderLseekRequest::new(
+            drive_id,
+            fh,
+            offset,
+            whence,
+            provider_res_tx,
+        ));
+        send_request!(self.file_provider_sender, v, reply);
+        receive_response!(provider_rx, response, reply);
+        match_provider_response!(response, reply, ProviderResponse::Lseek(offset), {
+            reply.offset(offset as i64);
+        });
+    }
+    //endregion
+    //region xattr
+    /// only the attributes of the mount are known, see [DRIVE_XATTRS](crate::fs::drive_file_provider::DRIVE_XATTRS)
+    #[instrument(skip(_req, reply), fields(% self))]
+    fn getxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        // the kernel asks for things like `security.capability` all the time
+        let Some(name) = name
+            .to_str()
+            .filter(|name| name.starts_with(DRIVE_XATTR_PREFIX))
+        else {
+            reply.error(libc::ENODATA);
+            return;
+        };
+        if ino == FUSE_ROOT_ID && name == STATUS_XATTR {
+            match self.status() {
+                Ok(status) => reply_xattr(status.to_string().as_bytes(), size, reply),
+                Err(code) => reply.error(code),
+            }
+            return;
+        }
+        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let drive_id = self.entry_ids.get_by_left(&ino);
+        reply_error_o!(
+            drive_id,
+            reply,
+            libc::ENOENT,
+            "Failed to find drive_id for ino: {}",
+            ino
+        );
+        let v = ProviderRequest::GetXattr(ProviderGetXattrRequest::new(
+            drive_id,
+            name.to_string(),
+            provider_res_tx,
+        ));
+        send_request!(self.file_provider_sender, v, reply);
+        receive_response!(provider_rx, response, reply);
+        match_provider_response!(response, reply, ProviderResponse::GetXattr(value), {
+            reply_xattr(&value, size, reply);
+        });
+    }
+    #[instrument(skip(_req, value, reply), fields(% self))]
+    fn setxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        _flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        let Some(name) = name
+            .to_str()
+            .filter(|name| name.starts_with(DRIVE_XATTR_PREFIX))
+        else {
+            reply.error(libc::ENOTSUP);
+            return;
+        };
+        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let drive_id = self.entry_ids.get_by_left(&ino);
+        reply_error_o!(
+            drive_id,
+            reply,
+            libc::ENOENT,
+            "Failed to find drive_id for ino: {}",
+            ino
+        );
+        let v = ProviderRequest::SetXattr(ProviderSetXattrRequest::new(
+            drive_id,
+            name.to_string(),
+            value.to_vec(),
+            provider_res_tx,
+        ));
+        send_request!(self.file_provider_sender, v, reply);
+        receive_response!(provider_rx, response, reply);
+        match_provider_response!(response, reply, ProviderResponse::SetXattr, {
+            reply.ok();
+        });
+    }
+    #[instrument(skip(_req, reply), fields(% self))]
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let drive_id = self.entry_ids.get_by_left(&ino);
+        reply_error_o!(
+            drive_id,
+            reply,
+            libc::ENOENT,
+            "Failed to find drive_id for ino: {}",
+            ino
+        );
+        let v =
+            ProviderRequest::ListXattr(ProviderListXattrRequest::new(drive_id, provider_res_tx));
+        send_request!(self.file_provider_sender, v, reply);
+        receive_response!(provider_rx, response, reply);
+        match_provider_response!(response, reply, ProviderResponse::ListXattr(names), {
+            // every name ends with a nul byte
+            let mut list = vec![];
+            for name in names {
+                list.extend_from_slice(name.as_bytes());
+                list.push(0);
+            }
+            reply_xattr(&list, size, reply);
+        });
+    }
+    //endregion
     //region write
     #[instrument(skip(_req), fields(% self, data = data.len()))]
     fn write(
@@ -358,6 +806,7 @@ impl Filesystem for DriveFilesystem {
         lock_owner: Option<u64>,
         reply: ReplyWrite,
     ) {
+        self.invalidate_attr(ino);
         let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
         let drive_id = self.entry_ids.get_by_left(&ino);
         reply_error_o!(
@@ -382,6 +831,40 @@ impl Filesystem for DriveFilesystem {
         });
     }
     //endregion
+    //region flush
+    /// gets called on every close, uploads the changes so close returns only once they are safe
+    #[instrument(skip(_req), fields(%self))]
+    fn flush(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        _lock_owner: u64,
+        reply: ReplyEmpty,
+    ) {
+        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let drive_id = self.get_id_from_ino(ino);
+        reply_error_o!(
+            drive_id,
+            reply,
+            libc::ENOENT,
+            "Failed to find drive_id for ino: {}",
+            ino
+        );
+
+        let v = ProviderRequest::Flush(ProviderFlushRequest::new(
+            drive_id.clone(),
+            fh,
+            provider_res_tx,
+        ));
+        send_request!(self.file_provider_sender, v, reply);
+        receive_response!(provider_rx, response, reply);
+        match_provider_response!(response, reply, ProviderResponse::Flush, {
+            reply.ok();
+            debug!("Flushed fh: {}", fh);
+        });
+    }
+    //endregion
     //region release
     #[instrument(skip(_req), fields(%self))]
     fn release(
@@ -445,30 +928,30 @@ impl Filesystem for DriveFilesystem {
             ino
         );
 
+        // the provider only knows the children, not `.` and `..`
+        let children_offset = (offset as u64).saturating_sub(DOT_ENTRIES);
         let v = ProviderRequest::ReadDir(ProviderReadDirRequest::new(
             drive_id,
-            offset as u64,
+            children_offset,
+            READ_DIR_PAGE_SIZE,
             provider_res_tx,
         ));
         send_request!(self.file_provider_sender, v, reply);
         receive_response!(provider_rx, response, reply);
 
         match_provider_response!(response, reply, ProviderResponse::ReadDir(response), {
-            let mut counter = 0;
             debug!(
                 "received ProviderReadDirResponse with {} entries",
                 response.entries.len()
             );
-            for entry in response.entries {
-                let entry_ino = self.get_ino_from_id(entry.id.clone());
-                counter += 1;
+            for (entry_ino, entry_offset, kind, name) in self.dir_entries(ino, offset, response) {
                 debug!(
-                    "adding entry to output: ino:{}, counter:{}, entry: {:?}",
-                    entry_ino, counter, entry
+                    "adding entry to output: ino:{}, offset:{}, name: {:?}",
+                    entry_ino, entry_offset, name
                 );
-                let buffer_full = reply.add(entry_ino, counter, entry.attr.kind, &entry.name);
+                let buffer_full = reply.add(entry_ino, entry_offset, kind, &name);
                 if buffer_full {
-                    debug!("buffer full after {}", counter);
+                    debug!("buffer full at {}", entry_offset);
                     break;
                 }
             }
@@ -479,7 +962,7 @@ impl Filesystem for DriveFilesystem {
 
     //endregion
     //region rename
-    #[instrument(skip(_req, reply, _flags), fields(% self))]
+    #[instrument(skip(_req, reply), fields(% self))]
     fn rename(
         &mut self,
         _req: &Request<'_>,
@@ -487,7 +970,7 @@ impl Filesystem for DriveFilesystem {
         name: &OsStr,
         new_parent: u64,
         new_name: &OsStr,
-        _flags: u32,
+        flags: u32,
         reply: ReplyEmpty,
     ) {
         let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
@@ -513,6 +996,7 @@ impl Filesystem for DriveFilesystem {
             parent_id.clone(),
             new_name.to_os_string(),
             new_parent_id.clone(),
+            flags,
             provider_res_tx,
         ));
         send_request!(self.file_provider_sender, v, reply);
@@ -525,4 +1009,488 @@ impl Filesystem for DriveFilesystem {
         });
     }
     //endregion
+    //region mkdir
+    #[instrument(skip(_req, reply), fields(% self))]
+    fn mkdir(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let parent_id = self.get_id_from_ino(parent);
+        reply_error_o!(
+            parent_id,
+            reply,
+            libc::ENOENT,
+            "Failed to find drive_id for ino: {}",
+            parent
+        );
+
+        let v = ProviderRequest::Mkdir(ProviderMkdirRequest::new(
+            parent_id.clone(),
+            name.to_os_string(),
+            provider_res_tx,
+        ));
+        send_request!(self.file_provider_sender, v, reply);
+        receive_response!(provider_rx, response, reply);
+        match_provider_response!(response, reply, ProviderResponse::Mkdir(metadata), {
+            // the ino has to map to the new id before the reply, since the kernel
+            // might create the next folder inside this one right after it
+            let mut attr = metadata.attr;
+            let ttl = metadata.ttl.unwrap_or(TTL);
+            attr.ino = self.get_ino_from_id(metadata.id);
+            self.increase_lookup_count(attr.ino);
+            self.cache_attr(attr, ttl);
+            self.invalidate_attr(parent);
+            reply.entry(&ttl, &attr, self.get_generation(attr.ino));
+        });
+    }
+    //endregion
+    //region unlink & rmdir
+    #[instrument(skip(_req, reply), fields(% self))]
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        self.remove(parent, name, false, reply);
+    }
+    #[instrument(skip(_req, reply), fields(% self))]
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        self.remove(parent, name, true, reply);
+    }
+    //endregion
+}
+
+/// a `size` of 0 asks for the size the value needs, a smaller buffer than that
+/// gets `ERANGE`
+fn reply_xattr(value: &[u8], size: u32, reply: ReplyXattr) {
+    if size == 0 {
+        reply.size(value.len() as u32);
+    } else if value.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(value);
+    }
+}
+
+impl DriveFilesystem {
+    /// removes the entry through the provider, which decides if it goes to the trash
+    fn remove(&mut self, parent: u64, name: &OsStr, is_dir: bool, reply: ReplyEmpty) {
+        let (provider_res_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let parent_id = self.get_id_from_ino(parent);
+        reply_error_o!(
+            parent_id,
+            reply,
+            libc::ENOENT,
+            "Failed to find drive_id for ino: {}",
+            parent
+        );
+
+        let v = ProviderRequest::Remove(ProviderRemoveRequest::new(
+            parent_id.clone(),
+            name.to_os_string(),
+            is_dir,
+            provider_res_tx,
+        ));
+        send_request!(self.file_provider_sender, v, reply);
+        receive_response!(provider_rx, response, reply);
+        match_provider_response!(response, reply, ProviderResponse::Remove(id), {
+            if let Err(e) = self.remove_id(id) {
+                trace!("removed entry had no ino: {}", e);
+            }
+            self.invalidate_attr(parent);
+            reply.ok();
+        });
+    }
+    /// the entries of the folder from `offset` on, with `.` and `..` in front,
+    /// and the offset of the entry after each one
+    fn dir_entries(
+        &mut self,
+        ino: u64,
+        offset: i64,
+        response: ProviderReadDirResponse,
+    ) -> Vec<(u64, i64, FileType, OsString)> {
+        let offset = offset.max(0) as u64;
+        let parent_ino = match response.parent_id {
+            Some(parent_id) => self.get_ino_from_id(parent_id),
+            None => FUSE_ROOT_ID,
+        };
+        let mut entries = vec![];
+        let dots = [(ino, "."), (parent_ino, "..")];
+        for (index, (dot_ino, name)) in dots.into_iter().enumerate().skip(offset as usize) {
+            entries.push((
+                dot_ino,
+                index as i64 + 1,
+                FileType::Directory,
+                OsString::from(name),
+            ));
+        }
+        let first_child = DOT_ENTRIES + offset.saturating_sub(DOT_ENTRIES);
+        for (index, entry) in response.entries.into_iter().enumerate() {
+            let entry_ino = self.get_ino_from_id(entry.id);
+            let mut attr = entry.attr;
+            attr.ino = entry_ino;
+            self.cache_attr(attr, entry.ttl.unwrap_or(TTL));
+            let entry_offset = (first_child + index as u64 + 1) as i64;
+            entries.push((
+                entry_ino,
+                entry_offset,
+                attr.kind,
+                OsString::from(entry.name),
+            ));
+        }
+        entries
+    }
+}
+
+fn time_or_now_to_system_time(time: TimeOrNow) -> SystemTime {
+    match time {
+        TimeOrNow::SpecificTime(time) => time,
+        TimeOrNow::Now => SystemTime::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::drive_file_provider::{FileMetadata, HealthStatus, ProviderStats};
+    use crate::google_drive::UploadProgress;
+
+    use super::*;
+
+    fn create_filesystem() -> DriveFilesystem {
+        create_filesystem_with_changes().0
+    }
+
+    fn create_filesystem_with_changes() -> (DriveFilesystem, Sender<DriveId>) {
+        let (provider_tx, _provider_rx) = tokio::sync::mpsc::channel(1);
+        let (changed_ids_tx, changed_ids_rx) = channel();
+        (
+            DriveFilesystem::new(provider_tx, changed_ids_rx),
+            changed_ids_tx,
+        )
+    }
+
+    fn create_file_attr(ino: u64) -> FileAttr {
+        FileAttr {
+            ino,
+            size: 42,
+            blocks: 1,
+            atime: SystemTime::UNIX_EPOCH,
+            mtime: SystemTime::UNIX_EPOCH,
+            ctime: SystemTime::UNIX_EPOCH,
+            crtime: SystemTime::UNIX_EPOCH,
+            kind: fuser::FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn removed_ino_gets_reused() {
+        let mut fs = create_filesystem();
+        let ino = fs.get_ino_from_id(DriveId::from("a"));
+        fs.remove_id(DriveId::from("a")).unwrap();
+        let new_ino = fs.get_ino_from_id(DriveId::from("b"));
+        assert_eq!(ino, new_ino);
+    }
+
+    #[test]
+    fn looked_up_ino_is_only_reused_after_forget() {
+        let mut fs = create_filesystem();
+        let ino = fs.get_ino_from_id(DriveId::from("a"));
+        fs.increase_lookup_count(ino);
+        fs.remove_id(DriveId::from("a")).unwrap();
+        let other_ino = fs.get_ino_from_id(DriveId::from("b"));
+        assert_ne!(ino, other_ino);
+
+        fs.forget_ino(ino, 1);
+        let new_ino = fs.get_ino_from_id(DriveId::from("c"));
+        assert_eq!(ino, new_ino);
+    }
+
+    #[test]
+    fn recycled_ino_gets_new_generation() {
+        let mut fs = create_filesystem();
+        let ino = fs.get_ino_from_id(DriveId::from("a"));
+        let generation = fs.get_generation(ino);
+        fs.increase_lookup_count(ino);
+        fs.remove_id(DriveId::from("a")).unwrap();
+        assert_eq!(fs.get_generation(ino), generation);
+
+        fs.forget_ino(ino, 1);
+        let new_ino = fs.get_ino_from_id(DriveId::from("b"));
+        assert_eq!(ino, new_ino);
+        assert_ne!(fs.get_generation(new_ino), generation);
+    }
+
+    #[test]
+    fn hashed_inos_are_the_same_on_every_mount() {
+        let mut first = create_filesystem();
+        first.set_hashed_inos(true);
+        first.get_ino_from_id(DriveId::from("b"));
+        let ino = first.get_ino_from_id(DriveId::from("a"));
+
+        let mut second = create_filesystem();
+        second.set_hashed_inos(true);
+        assert_eq!(second.get_ino_from_id(DriveId::from("a")), ino);
+
+        // another id that already has the ino pushes this one to the next free ino
+        let mut third = create_filesystem();
+        third.set_hashed_inos(true);
+        third.entry_ids.insert(ino, DriveId::from("other"));
+        let probed = third.get_ino_from_id(DriveId::from("a"));
+        assert_ne!(probed, ino);
+        third.remove_id(DriveId::from("a")).unwrap();
+        assert_eq!(third.get_ino_from_id(DriveId::from("a")), probed);
+    }
+
+    #[test]
+    fn released_hashed_inos_only_get_a_new_generation() {
+        let mut fs = create_filesystem();
+        fs.set_hashed_inos(true);
+        let ino = fs.get_ino_from_id(DriveId::from("a"));
+        let generation = fs.get_generation(ino);
+        fs.remove_id(DriveId::from("a")).unwrap();
+        assert!(fs.free_inos.is_empty());
+        assert_ne!(fs.get_generation(ino), generation);
+        assert_eq!(fs.get_ino_from_id(DriveId::from("a")), ino);
+    }
+
+    #[test]
+    fn remounts_get_new_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut generations = Vec::new();
+        for _ in 0..2 {
+            let mut fs = create_filesystem();
+            fs.set_mount_generation(crate::fs::next_mount_generation(dir.path()));
+            generations.push(fs.get_generation(FUSE_ROOT_ID));
+        }
+        assert_ne!(generations[0], generations[1]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn getattr_after_lookup_uses_cached_attr() {
+        let (provider_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let (_changed_ids_tx, changed_ids_rx) = channel();
+        let mut fs = DriveFilesystem::new(provider_tx, changed_ids_rx);
+        // answers every request like the provider would and counts them by kind
+        let provider = tokio::spawn(async move {
+            let (mut lookups, mut metadata_requests) = (0, 0);
+            while let Some(request) = provider_rx.recv().await {
+                let (sender, response) = match request {
+                    ProviderRequest::Lookup(request) => {
+                        lookups += 1;
+                        let metadata = FileMetadata {
+                            id: DriveId::from("a"),
+                            name: "a".to_string(),
+                            attr: create_file_attr(0),
+                            ttl: None,
+                        };
+                        (
+                            request.response_sender,
+                            ProviderResponse::Lookup(Some(metadata)),
+                        )
+                    }
+                    ProviderRequest::Metadata(request) => {
+                        metadata_requests += 1;
+                        let metadata = FileMetadata {
+                            id: request.file_id,
+                            name: "a".to_string(),
+                            attr: create_file_attr(0),
+                            ttl: None,
+                        };
+                        (
+                            request.response_sender,
+                            ProviderResponse::Metadata(metadata),
+                        )
+                    }
+                    request => panic!("unexpected request: {:?}", request),
+                };
+                sender.send(response).await.unwrap();
+            }
+            (lookups, metadata_requests)
+        });
+
+        let fs = tokio::task::spawn_blocking(move || {
+            // init adds the root like this
+            fs.entry_ids.insert(FUSE_ROOT_ID, DriveId::root());
+            let (_, looked_up) = fs.lookup_entry(FUSE_ROOT_ID, OsStr::new("a")).unwrap();
+            let (_, attr) = fs.get_attr(looked_up.ino).unwrap();
+            assert_eq!(attr.ino, looked_up.ino);
+            assert_eq!(attr.size, looked_up.size);
+
+            fs.invalidate_attr(looked_up.ino);
+            fs.get_attr(looked_up.ino).unwrap();
+            fs
+        })
+        .await
+        .unwrap();
+        drop(fs);
+        // only the getattr after the invalidation had to ask the provider
+        assert_eq!(provider.await.unwrap(), (1, 1));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn status_comes_from_the_provider() {
+        let (provider_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let (_changed_ids_tx, changed_ids_rx) = channel();
+        let fs = DriveFilesystem::new(provider_tx, changed_ids_rx);
+        let provider = tokio::spawn(async move {
+            let Some(ProviderRequest::Status(request)) = provider_rx.recv().await else {
+                panic!("expected a status request");
+            };
+            let status = ProviderStatus {
+                uploads: vec![(DriveId::from("a"), UploadProgress::new(4))],
+                health: HealthStatus::default(),
+                quota: None,
+                stats: ProviderStats::default(),
+                prefetch: None,
+            };
+            request
+                .response_sender
+                .send(ProviderResponse::Status(status))
+                .await
+                .unwrap();
+        });
+
+        let status = tokio::task::spawn_blocking(move || fs.status().unwrap())
+            .await
+            .unwrap();
+        provider.await.unwrap();
+        let shown = status.to_string();
+        assert!(shown.contains("healthy: true"), "{}", shown);
+        assert!(shown.contains("quota: unknown"), "{}", shown);
+        assert!(shown.contains("upload a: 0%"), "{}", shown);
+    }
+
+    #[test]
+    fn cached_attr_expires_after_its_own_ttl() {
+        let mut fs = create_filesystem();
+        let volatile = fs.get_ino_from_id(DriveId::from("volatile"));
+        let stable = fs.get_ino_from_id(DriveId::from("stable"));
+        fs.cache_attr(create_file_attr(volatile), Duration::ZERO);
+        fs.cache_attr(create_file_attr(stable), Duration::from_secs(3600));
+        assert!(fs.get_cached_attr(volatile).is_none());
+        assert_eq!(
+            fs.get_cached_entry(stable).map(|entry| entry.ttl),
+            Some(Duration::from_secs(3600))
+        );
+    }
+
+    #[test]
+    fn remote_change_invalidates_cached_attr() {
+        let (mut fs, changed_ids_tx) = create_filesystem_with_changes();
+        let ino = fs.get_ino_from_id(DriveId::from("a"));
+        let other_ino = fs.get_ino_from_id(DriveId::from("b"));
+        fs.cache_attr(create_file_attr(ino), TTL);
+        fs.cache_attr(create_file_attr(other_ino), TTL);
+
+        changed_ids_tx.send(DriveId::from("a")).unwrap();
+        fs.apply_remote_changes();
+        assert!(fs.get_cached_attr(ino).is_none());
+        assert!(fs.get_cached_attr(other_ino).is_some());
+    }
+
+    fn read_dir_response(parent_id: Option<&str>, children: &[&str]) -> ProviderReadDirResponse {
+        ProviderReadDirResponse {
+            entries: children
+                .iter()
+                .map(|id| FileMetadata {
+                    id: DriveId::from(*id),
+                    name: id.to_string(),
+                    attr: create_file_attr(0),
+                    ttl: None,
+                })
+                .collect(),
+            parent_id: parent_id.map(DriveId::from),
+        }
+    }
+
+    #[test]
+    fn real_root_id_and_alias_map_to_the_root_ino() {
+        let mut fs = create_filesystem();
+        fs.root_id = Some(DriveId::from("real_root"));
+        assert_eq!(fs.get_ino_from_id(DriveId::root()), FUSE_ROOT_ID);
+        assert_eq!(fs.get_ino_from_id(DriveId::from("real_root")), FUSE_ROOT_ID);
+
+        // `..` of the root and of a folder in the root
+        let entries = fs.dir_entries(FUSE_ROOT_ID, 0, read_dir_response(None, &["folder"]));
+        let names: Vec<_> = entries.iter().map(|e| e.3.to_str().unwrap()).collect();
+        assert_eq!(names, [".", "..", "folder"]);
+        assert_eq!(entries[1].0, FUSE_ROOT_ID);
+        let folder_ino = entries[2].0;
+        let entries = fs.dir_entries(folder_ino, 0, read_dir_response(Some("real_root"), &[]));
+        assert_eq!(entries[0].0, folder_ino);
+        assert_eq!(entries[1].0, FUSE_ROOT_ID);
+    }
+
+    #[test]
+    fn dir_entry_offsets_continue_where_the_last_read_stopped() {
+        let mut fs = create_filesystem();
+        let entries = fs.dir_entries(FUSE_ROOT_ID, 0, read_dir_response(None, &["a", "b"]));
+        let offsets: Vec<_> = entries.iter().map(|e| e.1).collect();
+        assert_eq!(offsets, [1, 2, 3, 4]);
+        // the provider already skipped `a`, since the kernel asked from offset 3 on
+        let entries = fs.dir_entries(FUSE_ROOT_ID, 3, read_dir_response(None, &["b"]));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].1, 4);
+        assert_eq!(entries[0].3, "b");
+        let entries = fs.dir_entries(FUSE_ROOT_ID, 1, read_dir_response(None, &["a"]));
+        assert_eq!(entries[0].3, "..");
+    }
+
+    #[test]
+    fn recycled_ino_does_not_keep_cached_attr() {
+        let mut fs = create_filesystem();
+        let ino = fs.get_ino_from_id(DriveId::from("a"));
+        let attr = create_file_attr(ino);
+        fs.cache_attr(attr, TTL);
+        fs.remove_id(DriveId::from("a")).unwrap();
+        assert!(fs.get_cached_attr(ino).is_none());
+    }
+
+    #[tokio::test]
+    async fn dropped_provider_shuts_the_filesystem_down() {
+        let (provider_tx, provider_rx) = tokio::sync::mpsc::channel(1);
+        let (_changed_ids_tx, changed_ids_rx) = channel();
+        let fs = DriveFilesystem::new(provider_tx, changed_ids_rx);
+        let (unmount_tx, unmount_rx) = tokio::sync::oneshot::channel();
+        let watcher = tokio::spawn(unmount_when_provider_closed(
+            fs.provider_closed(),
+            std::future::pending(),
+            move || unmount_tx.send(()).unwrap(),
+        ));
+        drop(provider_rx);
+        tokio::time::timeout(Duration::from_secs(1), unmount_rx)
+            .await
+            .expect("the filesystem did not notice that the provider is gone")
+            .expect("the filesystem was not unmounted");
+        watcher.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn ended_session_lets_the_provider_stop() {
+        let (provider_tx, mut provider_rx) = tokio::sync::mpsc::channel(1);
+        let (_changed_ids_tx, changed_ids_rx) = channel();
+        let fs = DriveFilesystem::new(provider_tx, changed_ids_rx);
+        let (session_ended_tx, session_ended_rx) = tokio::sync::oneshot::channel::<()>();
+        let watcher = tokio::spawn(unmount_when_provider_closed(
+            fs.provider_closed(),
+            async {
+                let _ = session_ended_rx.await;
+            },
+            || panic!("the provider is still there"),
+        ));
+        drop(fs);
+        drop(session_ended_tx);
+        watcher.await.unwrap();
+        // all senders are gone, so the provider stops listening
+        assert!(provider_rx.recv().await.is_none());
+    }
 }
@@ -0,0 +1,85 @@
+use std::str::FromStr;
+
+use fuser::consts::{FOPEN_DIRECT_IO, FOPEN_KEEP_CACHE};
+
+/// How the kernel page cache is used for the content of opened files.
+///
+/// - `Auto` fits most workloads, it keeps the cache as long as nothing changed.
+/// - `KeepCache` fits files that are read again and again (libraries, media
+///   that is seeked around in) and only ever change through this mount.
+/// - `DirectIo` fits files that change on the remote while they are open, or
+///   huge files that are read once and would only push everything else out
+///   of the cache. Every read goes through the filesystem, so `mmap` does not work.
+/// - `Invalidate` drops the cache on every open, the kernel's default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OpenCacheMode {
+    /// `DirectIo` while the file is being downloaded, `KeepCache` if it was
+    /// already local and did not change on the remote since it was last opened,
+    /// `Invalidate` otherwise
+    #[default]
+    Auto,
+    KeepCache,
+    DirectIo,
+    Invalidate,
+}
+
+impl OpenCacheMode {
+    /// the flags for the open reply, `Auto` has to be resolved before
+    pub fn fopen_flags(&self) -> u32 {
+        match self {
+            OpenCacheMode::KeepCache => FOPEN_KEEP_CACHE,
+            OpenCacheMode::DirectIo => FOPEN_DIRECT_IO,
+            OpenCacheMode::Auto | OpenCacheMode::Invalidate => 0,
+        }
+    }
+    /// the mode for a single open.
+    ///
+    /// A running download means the content is not complete yet, so it must not
+    /// end up in the cache. Without a remote change the cached content is still
+    /// what the local file holds, otherwise the cache has to be dropped.
+    pub(crate) fn resolve(&self, downloading: bool, changed_on_remote: bool) -> OpenCacheMode {
+        match self {
+            OpenCacheMode::Auto if downloading => OpenCacheMode::DirectIo,
+            OpenCacheMode::Auto if changed_on_remote => OpenCacheMode::Invalidate,
+            OpenCacheMode::Auto => OpenCacheMode::KeepCache,
+            mode => *mode,
+        }
+    }
+}
+
+impl FromStr for OpenCacheMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(OpenCacheMode::Auto),
+            "keep-cache" => Ok(OpenCacheMode::KeepCache),
+            "direct-io" => Ok(OpenCacheMode::DirectIo),
+            "invalidate" => Ok(OpenCacheMode::Invalidate),
+            _ => Err(format!(
+                "invalid open cache mode '{}', expected 'auto', 'keep-cache', \
+                 'direct-io' or 'invalidate'",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_keeps_the_cache_only_for_unchanged_local_files() {
+        let auto = OpenCacheMode::Auto;
+        assert_eq!(auto.resolve(true, false), OpenCacheMode::DirectIo);
+        assert_eq!(auto.resolve(false, true), OpenCacheMode::Invalidate);
+        assert_eq!(auto.resolve(false, false), OpenCacheMode::KeepCache);
+        assert_eq!(
+            OpenCacheMode::KeepCache.resolve(true, true),
+            OpenCacheMode::KeepCache
+        );
+        assert_eq!(OpenCacheMode::Auto.fopen_flags(), 0);
+        assert_eq!(OpenCacheMode::DirectIo.fopen_flags(), FOPEN_DIRECT_IO);
+    }
+}
@@ -0,0 +1,20 @@
+use std::fmt::{Display, Formatter};
+
+/// what [crate::fs::drive_file_provider::DriveFileProvider::verify_cache] found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheVerification {
+    /// the cached files whose md5 was compared with the one of the drive
+    pub verified: usize,
+    /// the ones that did not match and were removed
+    pub evicted: usize,
+}
+
+impl Display for CacheVerification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "verified {} cached files, evicted {}",
+            self.verified, self.evicted
+        )
+    }
+}
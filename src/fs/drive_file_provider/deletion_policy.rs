@@ -0,0 +1,26 @@
+use std::str::FromStr;
+
+/// What happens on google drive when a file or folder gets removed through the mount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeletionPolicy {
+    /// moves it to the trash, where it can be restored for 30 days
+    #[default]
+    Trash,
+    /// deletes it for good, skipping the trash
+    Hard,
+}
+
+impl FromStr for DeletionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trash" => Ok(DeletionPolicy::Trash),
+            "hard" => Ok(DeletionPolicy::Hard),
+            _ => Err(format!(
+                "invalid deletion policy '{}', expected 'trash' or 'hard'",
+                s
+            )),
+        }
+    }
+}
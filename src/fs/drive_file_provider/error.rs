@@ -0,0 +1,181 @@
+use std::fmt::{Display, Formatter};
+use std::result::Result as StdResult;
+
+use libc::c_int;
+
+use crate::google_drive::{ApiError, NotAuthenticatedError, RequestTimeoutError};
+
+pub type ProviderResult<T> = StdResult<T, ProviderError>;
+
+/// The errors a provider request handler can produce.
+///
+/// Every variant knows which errno it should be reported to the kernel with,
+/// so the handlers don't have to decide that at every call site.
+#[derive(Debug)]
+pub enum ProviderError {
+    /// the requested entry (or a part of its path) does not exist
+    NotFound(String),
+    /// the target of an operation already exists
+    AlreadyExists(String),
+    /// the operation conflicts with another running operation or with remote state
+    Conflict(String),
+    /// the operation is not allowed for this entry or handle
+    PermissionDenied(String),
+    /// a file was expected, but the entry is a folder
+    IsDirectory(String),
+    /// a folder was expected, but the entry is a file
+    NotDirectory(String),
+    /// the folder still has children
+    NotEmpty(String),
+    /// the request itself was malformed (invalid name, wrong flags, etc.)
+    InvalidArgument(String),
+    /// the file handle is not (or no longer) known to the provider
+    BadFileHandle(u64),
+    /// the google drive storage quota is exhausted
+    QuotaExceeded,
+    /// google drive returned an error or could not be reached, the errno
+    /// depends on the [ApiError] if there is one
+    Remote(anyhow::Error),
+    /// google drive does not accept the credentials anymore, the user has to log in again
+    NotAuthenticated(anyhow::Error),
+    /// the operation needs google drive, but the provider is in offline mode
+    Offline(String),
+    /// the operation was cancelled before it could finish
+    Interrupted(String),
+    /// anything unexpected, this gets reported as a generic IO error
+    Other(anyhow::Error),
+}
+
+impl ProviderError {
+    /// the errno this error should be reported to the kernel with
+    pub fn errno(&self) -> c_int {
+        match self {
+            ProviderError::NotFound(_) => libc::ENOENT,
+            ProviderError::AlreadyExists(_) => libc::EEXIST,
+            ProviderError::Conflict(_) => libc::EBUSY,
+            ProviderError::PermissionDenied(_) => libc::EACCES,
+            ProviderError::IsDirectory(_) => libc::EISDIR,
+            ProviderError::NotDirectory(_) => libc::ENOTDIR,
+            ProviderError::NotEmpty(_) => libc::ENOTEMPTY,
+            ProviderError::InvalidArgument(_) => libc::EINVAL,
+            ProviderError::BadFileHandle(_) => libc::EBADF,
+            ProviderError::QuotaExceeded => libc::EDQUOT,
+            ProviderError::Remote(e) if e.downcast_ref::<RequestTimeoutError>().is_some() => {
+                libc::EAGAIN
+            }
+            ProviderError::Remote(e) => ApiError::find(e)
+                .and_then(ApiError::errno)
+                .unwrap_or(libc::EREMOTEIO),
+            ProviderError::NotAuthenticated(_) => libc::ENETDOWN,
+            ProviderError::Offline(_) => libc::ENETDOWN,
+            ProviderError::Interrupted(_) => libc::EINTR,
+            ProviderError::Other(_) => libc::EIO,
+        }
+    }
+}
+
+impl Display for ProviderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderError::NotFound(msg) => write!(f, "not found: {}", msg),
+            ProviderError::AlreadyExists(msg) => write!(f, "already exists: {}", msg),
+            ProviderError::Conflict(msg) => write!(f, "conflict: {}", msg),
+            ProviderError::PermissionDenied(msg) => write!(f, "permission denied: {}", msg),
+            ProviderError::IsDirectory(msg) => write!(f, "is a directory: {}", msg),
+            ProviderError::NotDirectory(msg) => write!(f, "not a directory: {}", msg),
+            ProviderError::NotEmpty(msg) => write!(f, "directory not empty: {}", msg),
+            ProviderError::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            ProviderError::BadFileHandle(fh) => write!(f, "unknown file handle: {}", fh),
+            ProviderError::QuotaExceeded => write!(f, "the storage quota has been exceeded"),
+            ProviderError::Remote(e) => write!(f, "remote error: {:?}", e),
+            ProviderError::NotAuthenticated(e) => write!(f, "not authenticated: {:?}", e),
+            ProviderError::Offline(msg) => write!(f, "offline: {}", msg),
+            ProviderError::Interrupted(msg) => write!(f, "interrupted: {}", msg),
+            ProviderError::Other(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl ProviderError {
+    /// an error that happened while talking to google drive
+    pub fn remote(value: anyhow::Error) -> Self {
+        if value.downcast_ref::<NotAuthenticatedError>().is_some() {
+            return ProviderError::NotAuthenticated(value);
+        }
+        if ApiError::find(&value).is_some_and(ApiError::is_quota_exceeded) {
+            return ProviderError::QuotaExceeded;
+        }
+        ProviderError::Remote(value)
+    }
+}
+
+impl From<anyhow::Error> for ProviderError {
+    fn from(value: anyhow::Error) -> Self {
+        let value = match value.downcast::<ProviderError>() {
+            Ok(e) => return e,
+            Err(value) => value,
+        };
+        if value.downcast_ref::<NotAuthenticatedError>().is_some() {
+            return ProviderError::NotAuthenticated(value);
+        }
+        if ApiError::find(&value).is_some() || value.downcast_ref::<RequestTimeoutError>().is_some()
+        {
+            return ProviderError::remote(value);
+        }
+        ProviderError::Other(value)
+    }
+}
+
+impl From<std::io::Error> for ProviderError {
+    fn from(value: std::io::Error) -> Self {
+        ProviderError::Other(value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn errno_mapping() {
+        assert_eq!(ProviderError::NotFound("x".into()).errno(), libc::ENOENT);
+        assert_eq!(
+            ProviderError::AlreadyExists("x".into()).errno(),
+            libc::EEXIST
+        );
+        assert_eq!(ProviderError::QuotaExceeded.errno(), libc::EDQUOT);
+        assert_eq!(ProviderError::from(anyhow!("x")).errno(), libc::EIO);
+        assert_eq!(
+            ProviderError::from(anyhow!("x").context(NotAuthenticatedError)).errno(),
+            libc::ENETDOWN
+        );
+        let api_error = ApiError {
+            status: Some(hyper::StatusCode::NOT_FOUND),
+            reason: Some("notFound".to_string()),
+            location: None,
+            message: None,
+        };
+        assert_eq!(
+            ProviderError::from(anyhow!("x").context(api_error)).errno(),
+            libc::ENOENT
+        );
+        let full = ApiError {
+            status: Some(hyper::StatusCode::FORBIDDEN),
+            reason: Some("storageQuotaExceeded".to_string()),
+            location: None,
+            message: None,
+        };
+        assert!(matches!(
+            ProviderError::remote(anyhow!("x").context(full)),
+            ProviderError::QuotaExceeded
+        ));
+        assert_eq!(
+            ProviderError::remote(anyhow!("connection refused")).errno(),
+            libc::EREMOTEIO
+        );
+    }
+}
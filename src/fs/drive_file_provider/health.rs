@@ -0,0 +1,60 @@
+use std::time::{Duration, SystemTime};
+
+/// what the provider knows about its own state and its connection to google drive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HealthStatus {
+    /// if the last poll for changes succeeded, `None` if there was none yet
+    pub last_changes_poll_ok: Option<bool>,
+    /// if the last call to google drive succeeded, `None` if there was none yet
+    pub last_drive_call_ok: Option<bool>,
+    /// when the changes were last polled successfully
+    pub last_successful_changes_poll: Option<SystemTime>,
+}
+
+impl HealthStatus {
+    pub(crate) fn record_changes_poll(&mut self, ok: bool) {
+        self.last_changes_poll_ok = Some(ok);
+        if ok {
+            self.last_successful_changes_poll = Some(SystemTime::now());
+        }
+        self.record_drive_call(ok);
+    }
+    pub(crate) fn record_drive_call(&mut self, ok: bool) {
+        self.last_drive_call_ok = Some(ok);
+    }
+    /// how long ago the cached state was last synced with the remote,
+    /// `None` if it never was
+    pub fn cache_age(&self) -> Option<Duration> {
+        self.last_successful_changes_poll
+            .map(|t| t.elapsed().unwrap_or(Duration::ZERO))
+    }
+    /// false if the last poll or the last drive call failed
+    pub fn is_healthy(&self) -> bool {
+        self.last_changes_poll_ok != Some(false) && self.last_drive_call_ok != Some(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn health_follows_the_last_results() {
+        let mut status = HealthStatus::default();
+        assert!(status.is_healthy());
+        assert_eq!(status.cache_age(), None);
+
+        status.record_changes_poll(true);
+        assert!(status.is_healthy());
+        assert!(status.cache_age().is_some());
+
+        status.record_drive_call(false);
+        assert!(!status.is_healthy());
+        status.record_changes_poll(true);
+        assert!(status.is_healthy());
+
+        status.record_changes_poll(false);
+        assert!(!status.is_healthy());
+        assert!(status.last_successful_changes_poll.is_some());
+    }
+}
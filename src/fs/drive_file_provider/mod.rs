@@ -1,5 +1,30 @@
 mod provider;
+pub use cache_mode::*;
+pub use cache_verification::*;
+pub use deletion_policy::*;
+pub use error::*;
+pub use health::*;
+pub use native_files::*;
+pub use prefetch::*;
 pub use provider::*;
+pub use push::*;
+pub use quota::*;
 pub use request::*;
+pub use stats::*;
+pub use transfer::*;
+pub use xattr::*;
+mod cache_mode;
+mod cache_verification;
+mod deletion_policy;
 mod entry;
+mod error;
+mod health;
+mod native_files;
+mod notification;
+mod prefetch;
+mod push;
+mod quota;
 mod request;
+mod stats;
+mod transfer;
+mod xattr;
@@ -0,0 +1,62 @@
+use google_drive3::api::File;
+use serde_json::json;
+
+use crate::google_drive::DriveMetadata;
+
+/// How the google docs, sheets, slides etc. show up in the mount. They have
+/// no content that could be downloaded like that of other files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NativeFilePolicy {
+    /// they are left out of the listings
+    Hide,
+    /// they show up as small read only files with the link to open them in the
+    /// browser, like `Report.gdoc`
+    #[default]
+    Placeholder,
+}
+
+/// the extensions of the placeholders, the other native files get `.glink`
+const PLACEHOLDER_EXTENSIONS: &[(&str, &str)] = &[
+    ("application/vnd.google-apps.document", "gdoc"),
+    ("application/vnd.google-apps.spreadsheet", "gsheet"),
+    ("application/vnd.google-apps.presentation", "gslides"),
+    ("application/vnd.google-apps.drawing", "gdraw"),
+    ("application/vnd.google-apps.form", "gform"),
+];
+
+/// see [DriveMetadata::is_native]
+pub(crate) fn is_native_file(metadata: &File) -> bool {
+    DriveMetadata::new(metadata).is_native()
+}
+
+fn placeholder_extension(metadata: &File) -> &'static str {
+    PLACEHOLDER_EXTENSIONS
+        .iter()
+        .find(|(mime_type, _)| DriveMetadata::new(metadata).mime() == *mime_type)
+        .map_or("glink", |(_, extension)| extension)
+}
+
+/// the name of the placeholder, the name on the drive with the extension for its type
+pub(crate) fn placeholder_name(name: &str, metadata: &File) -> String {
+    format!("{}.{}", name, placeholder_extension(metadata))
+}
+
+/// the name on the drive for the name of a placeholder, the extension only
+/// exists in the mount. Names without it are kept as they are
+pub(crate) fn name_without_placeholder_extension<'a>(name: &'a str, metadata: &File) -> &'a str {
+    name.strip_suffix(placeholder_extension(metadata))
+        .and_then(|name| name.strip_suffix('.'))
+        .unwrap_or(name)
+}
+
+/// the content of the placeholder, a json object with the link to the file
+pub(crate) fn placeholder_content(metadata: &File) -> Vec<u8> {
+    let id = metadata.id.as_deref().unwrap_or_default();
+    let url = metadata
+        .web_view_link
+        .clone()
+        .unwrap_or_else(|| format!("https://drive.google.com/open?id={}", id));
+    let mut content = json!({ "url": url, "doc_id": id }).to_string().into_bytes();
+    content.push(b'\n');
+    content
+}
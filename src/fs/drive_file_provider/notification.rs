@@ -0,0 +1,25 @@
+use tracing::debug;
+
+/// shows a desktop notification to the user.
+///
+/// This does nothing without the `notify` feature, so headless builds don't
+/// need a notification daemon.
+pub(crate) fn notify_user(summary: &str, body: &str) {
+    debug!("notifying the user: {}: {}", summary, body);
+    #[cfg(feature = "notify")]
+    {
+        let summary = summary.to_string();
+        let body = body.to_string();
+        // showing the notification blocks until the notification daemon answered
+        tokio::task::spawn_blocking(move || {
+            let result = notify_rust::Notification::new()
+                .appname("drive_syncer")
+                .summary(&summary)
+                .body(&body)
+                .show();
+            if let Err(e) = result {
+                tracing::warn!("could not show the notification: {:?}", e);
+            }
+        });
+    }
+}
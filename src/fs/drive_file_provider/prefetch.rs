@@ -0,0 +1,27 @@
+use std::fmt::{Display, Formatter};
+
+/// how far the prefetch of folders got, see [crate::fs::drive_file_provider::DriveFileProvider::prefetch]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PrefetchProgress {
+    /// the files that get pinned, of all folders that were prefetched
+    pub total: usize,
+    pub done: usize,
+    /// the files that could not be downloaded, they are not pinned
+    pub failed: usize,
+}
+
+impl PrefetchProgress {
+    pub fn is_finished(&self) -> bool {
+        self.done + self.failed >= self.total
+    }
+}
+
+impl Display for PrefetchProgress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{} files prefetched, {} failed",
+            self.done, self.total, self.failed
+        )
+    }
+}
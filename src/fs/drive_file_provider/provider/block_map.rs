@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+
+/// the granularity in which the content of partial cache files is tracked
+pub(crate) const BLOCK_SIZE: u64 = 4096;
+
+/// Which blocks of a cache file hold the content of the drive file.
+///
+/// With partial downloads a cache file starts out sparse, only the blocks that
+/// get read are downloaded. The map is stored next to the cache file, so the
+/// downloaded blocks are still there after a restart.
+///
+/// Everything after `size` (the size of the file on the drive) is present,
+/// there is nothing to download for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BlockMap {
+    size: u64,
+    bits: Vec<u8>,
+}
+
+impl BlockMap {
+    /// a map for a file of `size` bytes that has none of its blocks yet
+    pub(crate) fn new(size: u64) -> Self {
+        let blocks = size.div_ceil(BLOCK_SIZE);
+        Self {
+            size,
+            bits: vec![0; blocks.div_ceil(8) as usize],
+        }
+    }
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+    fn is_present(&self, block: u64) -> bool {
+        self.bits[(block / 8) as usize] & (1 << (block % 8)) != 0
+    }
+    fn set_present(&mut self, block: u64) {
+        self.bits[(block / 8) as usize] |= 1 << (block % 8);
+    }
+    /// the ranges (offset and length) that have to be downloaded before
+    /// `offset..offset + len` can be read, neighbouring blocks are merged
+    pub(crate) fn missing_ranges(&self, offset: u64, len: u64) -> Vec<(u64, u64)> {
+        let end = offset.saturating_add(len).min(self.size);
+        if offset >= end {
+            return Vec::new();
+        }
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for block in offset / BLOCK_SIZE..end.div_ceil(BLOCK_SIZE) {
+            if self.is_present(block) {
+                continue;
+            }
+            let start = block * BLOCK_SIZE;
+            let block_len = BLOCK_SIZE.min(self.size - start);
+            match ranges.last_mut() {
+                Some((last_start, last_len)) if *last_start + *last_len == start => {
+                    *last_len += block_len
+                }
+                _ => ranges.push((start, block_len)),
+            }
+        }
+        ranges
+    }
+    /// the missing parts of the blocks a write of `offset..offset + len` only
+    /// partly covers, those have to be downloaded first or the rest of the
+    /// block would be lost
+    pub(crate) fn partly_covered_ranges(&self, offset: u64, len: u64) -> Vec<(u64, u64)> {
+        let end = offset.saturating_add(len);
+        let mut ranges = Vec::new();
+        for edge in [offset, end.saturating_sub(1)] {
+            let block_start = edge - edge % BLOCK_SIZE;
+            if self.is_covered(block_start, offset, end) {
+                continue;
+            }
+            for range in self.missing_ranges(block_start, BLOCK_SIZE) {
+                if !ranges.contains(&range) {
+                    ranges.push(range);
+                }
+            }
+        }
+        ranges
+    }
+    fn is_covered(&self, block_start: u64, offset: u64, end: u64) -> bool {
+        block_start >= offset && (block_start + BLOCK_SIZE).min(self.size) <= end
+    }
+    /// marks the blocks that are completely inside `offset..offset + len`
+    pub(crate) fn mark_present(&mut self, offset: u64, len: u64) {
+        let end = offset.saturating_add(len);
+        for block in offset.div_ceil(BLOCK_SIZE)..end.min(self.size).div_ceil(BLOCK_SIZE) {
+            if self.is_covered(block * BLOCK_SIZE, offset, end) {
+                self.set_present(block);
+            }
+        }
+    }
+    /// the file got truncated, what comes after the new size is no longer on the drive
+    pub(crate) fn truncate(&mut self, size: u64) {
+        self.size = self.size.min(size);
+    }
+    pub(crate) fn is_complete(&self) -> bool {
+        self.missing_ranges(0, self.size).is_empty()
+    }
+    /// where the map of the cache file at `cache_path` is stored
+    pub(crate) fn path_for(cache_path: &Path) -> PathBuf {
+        let mut path = cache_path.as_os_str().to_owned();
+        path.push(".blocks");
+        PathBuf::from(path)
+    }
+    /// `None` if there is no (valid) map stored at `path`
+    pub(crate) fn load(path: &Path) -> Option<Self> {
+        let data = std::fs::read(path).ok()?;
+        let (size, bits) = data.split_first_chunk::<8>()?;
+        let map = Self {
+            size: u64::from_le_bytes(*size),
+            bits: bits.to_vec(),
+        };
+        (map.bits.len() == Self::new(map.size).bits.len()).then_some(map)
+    }
+    pub(crate) fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut data = self.size.to_le_bytes().to_vec();
+        data.extend_from_slice(&self.bits);
+        std::fs::write(path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_only_need_the_blocks_they_partly_cover() {
+        let map = BlockMap::new(5 * BLOCK_SIZE + 10);
+        // the first block is only partly written, the second and third completely
+        let ranges = map.partly_covered_ranges(10, 3 * BLOCK_SIZE - 10);
+        assert_eq!(ranges, [(0, BLOCK_SIZE)]);
+        // the last block of the file is covered by a write up to the end
+        let ranges = map.partly_covered_ranges(5 * BLOCK_SIZE, 20);
+        assert!(ranges.is_empty());
+
+        let mut map = map;
+        map.mark_present(10, 3 * BLOCK_SIZE);
+        assert_eq!(
+            map.missing_ranges(0, 6 * BLOCK_SIZE),
+            [(0, BLOCK_SIZE), (3 * BLOCK_SIZE, 2 * BLOCK_SIZE + 10)]
+        );
+    }
+}
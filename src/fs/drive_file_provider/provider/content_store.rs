@@ -0,0 +1,87 @@
+use std::{
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+/// Keeps one copy of every downloaded content, by its md5 checksum.
+///
+/// The cache files are hard links to these copies, so files with the same
+/// content only take up the space once. Before a cache file gets written to it
+/// has to get its own copy again, see [unshare].
+#[derive(Debug, Clone)]
+pub(crate) struct ContentStore {
+    dir: PathBuf,
+}
+
+impl ContentStore {
+    pub(crate) fn new(cache_dir: &Path) -> Self {
+        Self {
+            dir: cache_dir.join(".content"),
+        }
+    }
+    fn path(&self, md5: &str) -> PathBuf {
+        self.dir.join(md5)
+    }
+    /// makes the cache file at `target` a link to the stored copy, `false` if
+    /// there is no copy with this checksum
+    pub(crate) fn link(&self, md5: &str, target: &Path) -> io::Result<bool> {
+        let stored = self.path(md5);
+        if !stored.exists() {
+            return Ok(false);
+        }
+        replace_with_link(&stored, target)?;
+        Ok(true)
+    }
+    /// adds the downloaded cache file to the store, or links it to the copy
+    /// that is already stored
+    pub(crate) fn add(&self, md5: &str, path: &Path) -> io::Result<()> {
+        if self.link(md5, path)? {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::hard_link(path, self.path(md5))
+    }
+    /// removes the copies no cache file links to anymore
+    pub(crate) fn remove_unused(&self) -> io::Result<()> {
+        let Ok(stored) = std::fs::read_dir(&self.dir) else {
+            return Ok(());
+        };
+        for stored in stored {
+            let stored = stored?;
+            if stored.metadata()?.nlink() <= 1 {
+                std::fs::remove_file(stored.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// links `target` to `original`, the old file at `target` gets replaced at once
+fn replace_with_link(original: &Path, target: &Path) -> io::Result<()> {
+    let link_path = with_suffix(target, ".link");
+    let _ = std::fs::remove_file(&link_path);
+    std::fs::hard_link(original, &link_path)?;
+    std::fs::rename(&link_path, target)
+}
+
+/// gives the cache file its own copy if it shares one, so writing to it does
+/// not change the other files with the same content
+pub(crate) fn unshare(path: &Path) -> io::Result<()> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.nlink() > 1 => {}
+        _ => return Ok(()),
+    }
+    debug!("copying {} before it gets written to", path.display());
+    let copy_path = with_suffix(path, ".copy");
+    std::fs::copy(path, &copy_path)?;
+    std::fs::rename(&copy_path, path)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
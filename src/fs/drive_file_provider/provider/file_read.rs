@@ -0,0 +1,86 @@
+use std::{
+    collections::HashMap,
+    os::unix::fs::FileExt,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{OwnedRwLockWriteGuard, RwLock};
+
+use crate::{fs::drive_file_provider::ProviderResult, google_drive::DriveId};
+
+use super::{lock_read_ahead, read_ahead::ReadAhead};
+
+/// how many reads of the cache files run at the same time by default
+pub(crate) const MAX_CONCURRENT_READS: usize = 8;
+
+/// A lock for every file. The reads that run in their own task hold it for
+/// reading, everything that changes the file takes it for writing first.
+///
+/// Only the request loop spawns reads, so once it holds the write lock all
+/// running reads of the file are done and no new one starts before the loop
+/// gets to the next request.
+#[derive(Debug, Default)]
+pub(crate) struct FileLocks {
+    locks: HashMap<DriveId, Arc<RwLock<()>>>,
+}
+
+impl FileLocks {
+    pub(crate) fn get(&mut self, id: &DriveId) -> Arc<RwLock<()>> {
+        self.locks.entry(id.clone()).or_default().clone()
+    }
+    pub(crate) async fn write(&mut self, id: &DriveId) -> OwnedRwLockWriteGuard<()> {
+        self.get(id).write_owned().await
+    }
+    /// forgets the lock of a file that is gone, if no read holds it anymore
+    pub(crate) fn remove(&mut self, id: &DriveId) {
+        if self
+            .locks
+            .get(id)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            self.locks.remove(id);
+        }
+    }
+}
+
+/// A read that was not served from the read ahead buffer and has to go to the
+/// cache file, it does not need the provider so it can run in its own task.
+#[derive(Debug)]
+pub(crate) struct FileRead {
+    pub(crate) file: std::fs::File,
+    pub(crate) offset: u64,
+    pub(crate) size: usize,
+    /// how much to read ahead, 0 if the read is not sequential
+    pub(crate) read_ahead_size: usize,
+    pub(crate) read_ahead: Arc<Mutex<ReadAhead>>,
+}
+
+impl FileRead {
+    pub(crate) async fn run(self) -> ProviderResult<Vec<u8>> {
+        let read_size = self.size.max(self.read_ahead_size);
+        let data = read_up_to_at(self.file, self.offset, read_size).await?;
+        let mut read_ahead = lock_read_ahead(&self.read_ahead);
+        Ok(read_ahead.serve(self.offset, self.size, data, read_size))
+    }
+}
+
+/// reads until `size` bytes are read or the end of the file is reached.
+///
+/// Leaves the position of the file alone, so reads of the same handle can run
+/// at the same time.
+async fn read_up_to_at(file: std::fs::File, offset: u64, size: usize) -> std::io::Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || {
+        let mut buf = vec![0; size];
+        let mut filled = 0;
+        while filled < size {
+            let read = file.read_at(&mut buf[filled..], offset + filled as u64)?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        buf.truncate(filled);
+        Ok(buf)
+    })
+    .await?
+}
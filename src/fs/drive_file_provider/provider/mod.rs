@@ -1,47 +1,151 @@
 use std::mem::swap;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{Debug, Formatter},
     io::SeekFrom,
-    os::unix::prelude::MetadataExt,
-    path::PathBuf,
-    result::Result as StdResult,
+    os::unix::{fs::FileExt, prelude::MetadataExt},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{anyhow, Context};
 use fuser::{FileAttr, FileType};
+use futures::StreamExt;
 use google_drive3::api::StartPageToken;
-use libc::c_int;
+use google_drive3::chrono::Utc;
+use md5::{Digest, Md5};
+use serde_json::json;
 use tokio::{
     fs,
     fs::{File, OpenOptions},
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    io::{AsyncSeekExt, AsyncWrite, AsyncWriteExt},
     sync::mpsc::{Receiver, Sender},
-    task::JoinHandle,
+    sync::{OwnedRwLockWriteGuard, Semaphore},
+    task::{AbortHandle, JoinHandle},
 };
-use tracing::{debug, error, instrument, trace, warn};
+use tracing::{debug, error, info, instrument, level_filters::LevelFilter, trace, warn};
 
 use crate::{
-    common::VecExtension,
-    fs::drive::{Change, ChangeType},
+    common::{
+        child_name_key, compute_md5_async, find_child_by_name, is_cache_time_over,
+        local_entry_name, VecExtension,
+    },
+    config::conflict_name::{host_name, ConflictNameTemplate},
+    config::no_cache::NoCachePaths,
+    config::path_filter::PathFilter,
+    config::ttl_overrides::TtlOverrides,
+    config::upload_windows::UploadWindows,
+    fs::drive::{Change, ChangeType, DEFAULT_BLKSIZE},
     fs::drive2::HandleFlags,
+    fs::drive_file_provider::native_files::{
+        is_native_file, name_without_placeholder_extension, placeholder_content, placeholder_name,
+    },
+    fs::drive_file_provider::notification::notify_user,
     fs::drive_file_provider::ProviderRenameRequest,
     fs::drive_file_provider::{
-        FileMetadata, ProviderLookupRequest, ProviderMetadataRequest, ProviderOpenFileRequest,
-        ProviderReadContentRequest, ProviderReadDirRequest, ProviderReadDirResponse,
-        ProviderReleaseFileRequest, ProviderRequest, ProviderResponse, ProviderSetAttrRequest,
-        ProviderWriteContentRequest,
+        flag_value, parse_flag, CacheVerification, CachedQuota, DeletionPolicy, FileMetadata,
+        HealthStatus, NativeFilePolicy, OpenCacheMode, ProviderError, ProviderFlushRequest,
+        ProviderGetXattrRequest, ProviderListXattrRequest, ProviderLookupRequest,
+        ProviderLseekRequest, ProviderMetadataRequest, ProviderMkdirRequest,
+        ProviderOpenFileRequest, ProviderReadContentRequest, ProviderReadDirRequest,
+        ProviderReadDirResponse, ProviderReleaseFileRequest, ProviderRemoveRequest,
+        ProviderRequest, ProviderResponse, ProviderResult, ProviderRootIdRequest,
+        ProviderSetAttrRequest, ProviderSetXattrRequest, ProviderStats, ProviderStatus,
+        ProviderStatusRequest, ProviderWriteContentRequest, TransferDirection, TransferInfo,
+        DEFAULT_QUOTA_CACHE_TIME, DRIVE_XATTRS, PINNED_XATTR, STARRED_XATTR,
     },
-    google_drive::{DriveId, GoogleDrive},
+    google_drive::{
+        ApiError, DriveId, DriveMetadata, FileTimes, GoogleDrive, ListOptions,
+        NotAuthenticatedError, ProgressCallback, TransferCounter, UploadProgress, FOLDER_MIME_TYPE,
+    },
+    log_level,
     prelude::*,
     send_error_response, send_response,
 };
 
+use block_map::BlockMap;
+use content_store::{unshare, ContentStore};
+use file_read::{FileLocks, FileRead, MAX_CONCURRENT_READS};
+use pinned::{load_pinned, move_file, save_pinned};
+use prefetch::{PrefetchDone, Prefetches, MAX_CONCURRENT_PREFETCHES};
+use read_ahead::{ReadAhead, READ_AHEAD_SIZE};
+use revisions::{
+    file_of_revisions_folder, parse_revision_entry_id, revision_entry_id, revision_name,
+    revisions_folder_id, REVISIONS_SUFFIX,
+};
+use seek::seek_data_or_hole;
+use snapshot::{
+    has_snapshot, load_snapshot, remove_record, save_record, save_snapshot, save_state, Snapshot,
+};
+
+mod block_map;
+mod content_store;
+mod file_read;
+mod pinned;
+mod prefetch;
+mod read_ahead;
+mod revisions;
+mod seek;
+mod snapshot;
+
+/// how many cached files [DriveFileProvider::verify_cache] hashes at the same time
+const MAX_CONCURRENT_VERIFICATIONS: usize = 4;
+/// how many entries the full listing did not have are fetched at the same time,
+/// see [DriveFileProvider::reconcile_with_full_listing]
+const MAX_CONCURRENT_UNLISTED_CHECKS: usize = 8;
+
 #[derive(Debug)]
 pub enum ProviderCommand {
     Stop,
     PauseSync,
+    /// asks the provider if it is alive and if its connection to google drive works
+    HealthCheck {
+        reply: tokio::sync::oneshot::Sender<HealthStatus>,
+    },
+    /// asks the provider for a summary of its internal state as json, for debugging.
+    /// See [DriveFileProvider::dump_state]
+    DumpState {
+        redact_ids: bool,
+        reply: tokio::sync::oneshot::Sender<serde_json::Value>,
+    },
+    /// asks the provider how many entries and handles it holds
+    Stats {
+        reply: tokio::sync::oneshot::Sender<ProviderStats>,
+    },
+    /// google drive notified about a change, so the changes should be polled now
+    CheckForChanges,
+    /// changes how much gets logged, for the whole process. See [crate::log_level]
+    SetLogLevel(LevelFilter),
+    /// turns the offline mode on or off, see [DriveFileProvider::set_offline]
+    SetOffline(bool),
+    /// keeps the file available offline, see [DriveFileProvider::pin]
+    Pin {
+        file_id: DriveId,
+    },
+    Unpin {
+        file_id: DriveId,
+    },
+    /// pins the files of a folder, see [DriveFileProvider::prefetch]
+    Prefetch {
+        folder: PathBuf,
+    },
+    /// removes the cached content that is not needed, see [DriveFileProvider::evict_cache]
+    EvictCache,
+    /// asks the provider which downloads and uploads are running
+    ListTransfers {
+        reply: tokio::sync::oneshot::Sender<Vec<TransferInfo>>,
+    },
+    /// stops the download or upload of the file if one is running. The requests
+    /// that wait for a download fail with `EINTR`, a cancelled upload is tried
+    /// again at the next flush
+    CancelTransfer {
+        file_id: DriveId,
+    },
+    /// replaces the drive, see [DriveFileProvider::reload_auth]
+    ReloadAuth {
+        drive: Box<GoogleDrive>,
+    },
 }
 #[derive(Debug)]
 pub struct FileRequest {
@@ -61,6 +165,10 @@ pub struct FileData {
     pub perma: bool,
     pub attr: FileAttr,
     pub is_local: bool,
+    /// the file is in the trash on the drive
+    pub trashed: bool,
+    /// the file belongs to a backed up computer, those can only be browsed
+    pub read_only: bool,
 }
 impl FileData {
     fn get_id(&self) -> Option<DriveId> {
@@ -76,6 +184,10 @@ pub struct FileHandleData {
     creating: bool,
     marked_for_open: bool,
     has_content_changed: bool,
+    /// shared with the reads that run in their own task
+    read_ahead: Arc<Mutex<ReadAhead>>,
+    /// the reads go straight to the drive, see [DriveFileProvider::set_no_cache]
+    no_cache: bool,
 }
 
 pub struct DriveFileProvider {
@@ -85,26 +197,116 @@ pub struct DriveFileProvider {
 
     // file_request_receiver: std::sync::mpsc::Receiver<ProviderRequest>,
     running_requests: HashMap<DriveId, JoinHandle<Result<()>>>,
+    /// the progress of all uploads that are currently running
+    ///
+    /// this gets updated from inside the upload tasks
+    upload_progress: Arc<Mutex<HashMap<DriveId, UploadProgress>>>,
+    /// the md5 checksum of the local content as of the last download or upload
+    /// that finished, local changes drop it.
+    ///
+    /// this gets updated from inside the transfer tasks
+    local_md5s: Arc<Mutex<HashMap<DriveId, String>>>,
+    /// shared with the command listener, so it can answer health checks
+    /// while a request is being processed
+    health: Arc<Mutex<HealthStatus>>,
+    /// shared with the command listener, so it can list and cancel the transfers
+    /// while a request waits for one of them
+    running_transfers: RunningTransfers,
     alt_root_id: DriveId,
     entries: HashMap<DriveId, FileData>,
     parents: HashMap<DriveId, Vec<DriveId>>,
     children: HashMap<DriveId, Vec<DriveId>>,
+    /// the children of each parent by their name, so lookups don't have to
+    /// go through all children. See [child_name_key]
+    child_names: HashMap<DriveId, HashMap<String, DriveId>>,
+    /// see [DriveFileProvider::resolve_path]
+    path_cache: Mutex<HashMap<DriveId, Option<PathBuf>>>,
 
     file_handles: HashMap<u64, FileHandleData>,
     next_fh: u64,
 
     changes_start_token: StartPageToken,
     last_checked_for_changes: SystemTime,
+    /// how long the known state is trusted before the changes get polled again.
+    ///
+    /// A shorter time means remote edits show up sooner, but every poll is a
+    /// request to the drive api that counts towards the quota and delays the
+    /// request that triggered it.
     allowed_cache_time: Duration,
+    /// gets the id of every entry that was changed on the remote, so the
+    /// filesystem can invalidate what it has cached for it
+    changed_ids_sender: Option<std::sync::mpsc::Sender<DriveId>>,
+    /// the quota rarely changes, so it only gets fetched again after the quota cache time
+    quota: Option<CachedQuota>,
+    quota_cache_time: Duration,
+    /// only the entries that pass this filter are kept
+    path_filter: PathFilter,
+    ttl_overrides: TtlOverrides,
+    /// if a folder gets refreshed from the remote when it is read and was not
+    /// refreshed within the cache time
+    refresh_folders_on_read_dir: bool,
+    folder_refreshed_at: HashMap<DriveId, SystemTime>,
+    /// how much is read at once when a handle is read sequentially, 0 turns it off
+    read_ahead_size: usize,
+    /// bounds how many reads of the cache files run at the same time, see
+    /// [DriveFileProvider::read_content]
+    read_permits: Arc<Semaphore>,
+    file_locks: FileLocks,
+    open_cache_mode: OpenCacheMode,
+    /// the files that changed on the remote since they were last opened,
+    /// their content in the kernel cache might be outdated
+    changed_on_remote: HashSet<DriveId>,
+    deletion_policy: DeletionPolicy,
+    native_file_policy: NativeFilePolicy,
+    /// if files in the trash are listed and can be looked up
+    include_trashed: bool,
+    /// if the backed up computers show up as top level folders
+    include_computers: bool,
+    /// if changes to files other users own are applied, see
+    /// [DriveFileProvider::set_follow_changes_of_others]
+    follow_changes_of_others: bool,
+    /// the folder that is shown as the root, see [DriveFileProvider::set_root_folder]
+    root_folder: DriveId,
+    /// the name the root is shown with instead of the name of the root folder
+    root_label: Option<String>,
+    /// see [DriveFileProvider::set_offline]
+    offline: bool,
+    /// the files that were changed while offline, in the order of their first change
+    queued_uploads: Vec<DriveId>,
+    /// the files whose upload failed because the credentials were not accepted,
+    /// see [DriveFileProvider::reload_auth]
+    auth_failed_uploads: Vec<DriveId>,
+    /// see [DriveFileProvider::set_partial_downloads]
+    partial_downloads: bool,
+    /// see [DriveFileProvider::set_verify_cache]
+    verify_cache: bool,
+    /// see [SyncSettings::set_blksize](crate::fs::drive::SyncSettings::set_blksize)
+    blksize: u32,
+    /// the cache files that are not completely downloaded yet
+    partial_files: HashMap<DriveId, BlockMap>,
+    /// `None` if every cache file has its own copy, see [DriveFileProvider::set_dedupe_cache]
+    content_store: Option<ContentStore>,
+    /// the files that are kept in the perma dir, see [DriveFileProvider::pin]
+    pinned: HashSet<DriveId>,
+    /// see [DriveFileProvider::set_show_revisions]
+    show_revisions: bool,
+    upload_windows: UploadWindows,
+    /// the changed files that wait for more changes, with when they get uploaded
+    scheduled_uploads: HashMap<DriveId, tokio::time::Instant>,
+    prefetches: Prefetches,
+    no_cache: NoCachePaths,
+    /// names the copy of the local content when a file changed locally and on the remote
+    conflict_name_template: ConflictNameTemplate,
 }
 impl Debug for DriveFileProvider {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let stats = self.stats();
         f.debug_struct("DriveFileProvider")
-            // .field("running_requests", &self.running_requests.len())
-            .field("entries", &self.entries.len())
-            .field("children", &self.children.len())
-            .field("parents", &self.parents.len())
-            .field("file_handles", &self.file_handles.len())
+            .field("running_requests", &stats.running_requests)
+            .field("entries", &stats.entries)
+            .field("children", &stats.children)
+            .field("parents", &stats.parents)
+            .field("file_handles", &stats.file_handles)
             .field("next_fh", &self.next_fh)
             // .field("cache_dir", &self.cache_dir)
             // .field("perma_dir", &self.perma_dir)
@@ -117,27 +319,184 @@ impl DriveFileProvider {
         cache_dir: PathBuf,
         perma_dir: PathBuf,
         changes_start_token: StartPageToken,
+        allowed_cache_time: Duration,
+        changed_ids_sender: Option<std::sync::mpsc::Sender<DriveId>>,
         // file_request_receiver: std::sync::mpsc::Receiver<ProviderRequest>,
     ) -> Self {
+        let pinned = load_pinned(&perma_dir);
         Self {
             drive,
             cache_dir,
             perma_dir,
             // file_request_receiver,
             running_requests: HashMap::new(),
+            upload_progress: Arc::new(Mutex::new(HashMap::new())),
+            local_md5s: Arc::new(Mutex::new(HashMap::new())),
+            health: Arc::new(Mutex::new(HealthStatus::default())),
+            running_transfers: RunningTransfers::default(),
             alt_root_id: DriveId::root(),
             entries: HashMap::new(),
             parents: HashMap::new(),
             children: HashMap::new(),
+            child_names: HashMap::new(),
+            path_cache: Mutex::new(HashMap::new()),
             file_handles: HashMap::new(),
             next_fh: 111,
 
             changes_start_token,
             last_checked_for_changes: SystemTime::UNIX_EPOCH,
-            allowed_cache_time: Duration::from_secs(10),
+            allowed_cache_time,
+            changed_ids_sender,
+            quota: None,
+            quota_cache_time: DEFAULT_QUOTA_CACHE_TIME,
+            path_filter: PathFilter::default(),
+            ttl_overrides: TtlOverrides::default(),
+            refresh_folders_on_read_dir: false,
+            folder_refreshed_at: HashMap::new(),
+            read_ahead_size: READ_AHEAD_SIZE,
+            read_permits: Arc::new(Semaphore::new(MAX_CONCURRENT_READS)),
+            file_locks: FileLocks::default(),
+            open_cache_mode: OpenCacheMode::default(),
+            changed_on_remote: HashSet::new(),
+            deletion_policy: DeletionPolicy::default(),
+            native_file_policy: NativeFilePolicy::default(),
+            include_trashed: false,
+            include_computers: false,
+            follow_changes_of_others: false,
+            root_folder: DriveId::root(),
+            root_label: None,
+            offline: false,
+            queued_uploads: Vec::new(),
+            auth_failed_uploads: Vec::new(),
+            partial_downloads: false,
+            verify_cache: false,
+            blksize: DEFAULT_BLKSIZE,
+            partial_files: HashMap::new(),
+            content_store: None,
+            pinned,
+            show_revisions: false,
+            upload_windows: UploadWindows::default(),
+            scheduled_uploads: HashMap::new(),
+            prefetches: Prefetches::default(),
+            no_cache: NoCachePaths::default(),
+            conflict_name_template: ConflictNameTemplate::default(),
         }
     }
+    /// sets which entries of the drive are synced, this has to be set before
+    /// the provider starts listening
+    pub fn set_path_filter(&mut self, path_filter: PathFilter) {
+        self.path_filter = path_filter;
+    }
+    /// sets how long the kernel may cache the attributes of matching entries
+    pub fn set_ttl_overrides(&mut self, ttl_overrides: TtlOverrides) {
+        self.ttl_overrides = ttl_overrides;
+    }
+    /// makes reading a folder poll the changes and list the folder again, if that
+    /// did not happen within the cache time, so navigating shows remote changes sooner
+    pub fn set_refresh_folders_on_read_dir(&mut self, refresh: bool) {
+        self.refresh_folders_on_read_dir = refresh;
+    }
+    /// sets how much is read from the cache file at once when a handle is read
+    /// sequentially, 0 turns the read ahead off
+    pub fn set_read_ahead_size(&mut self, read_ahead_size: usize) {
+        self.read_ahead_size = read_ahead_size;
+    }
+    /// sets how the kernel caches the content of opened files, see [OpenCacheMode]
+    pub fn set_open_cache_mode(&mut self, open_cache_mode: OpenCacheMode) {
+        self.open_cache_mode = open_cache_mode;
+    }
+    /// sets if removed files and folders go to the trash or get deleted, see [DeletionPolicy]
+    pub fn set_deletion_policy(&mut self, deletion_policy: DeletionPolicy) {
+        self.deletion_policy = deletion_policy;
+    }
+    /// sets how the copy of the local content is named when a file changed
+    /// locally and on the remote, see [ConflictNameTemplate]
+    pub fn set_conflict_name_template(&mut self, conflict_name_template: ConflictNameTemplate) {
+        self.conflict_name_template = conflict_name_template;
+    }
+    /// sets how the google docs, sheets etc. show up, see [NativeFilePolicy].
+    /// This has to be set before the provider starts listening
+    pub fn set_native_file_policy(&mut self, native_file_policy: NativeFilePolicy) {
+        self.native_file_policy = native_file_policy;
+    }
+    /// makes the files in the trash show up in their folders, this has to be
+    /// set before the provider starts listening
+    pub fn set_include_trashed(&mut self, include_trashed: bool) {
+        self.include_trashed = include_trashed;
+    }
+    /// makes the backed up computers show up as read only top level folders, this
+    /// has to be set before the provider starts listening
+    pub fn set_include_computers(&mut self, include_computers: bool) {
+        self.include_computers = include_computers;
+    }
+    /// applies the changes to files other users own too. Only the own files are
+    /// listed at the start, so by default their changes are skipped
+    pub fn set_follow_changes_of_others(&mut self, follow_changes_of_others: bool) {
+        self.follow_changes_of_others = follow_changes_of_others;
+    }
+    /// shows only this folder and what is in it, with the name and the times of
+    /// the folder on the root. This has to be set before the provider starts listening
+    pub fn set_root_folder(&mut self, root_folder: DriveId) {
+        self.root_folder = root_folder;
+    }
+    /// shows the root with this name instead of the name of the root folder
+    pub fn set_root_label(&mut self, root_label: Option<String>) {
+        self.root_label = root_label;
+    }
+    /// lets `<name>@revisions` be looked up next to every file, a read only folder
+    /// with the older contents of the file. Listing it costs a request to the drive
+    pub fn set_show_revisions(&mut self, show_revisions: bool) {
+        self.show_revisions = show_revisions;
+    }
+    /// lets the uploads of larger files wait for more changes, see [UploadWindows].
+    /// By default every change is uploaded when the file is closed
+    pub fn set_upload_windows(&mut self, upload_windows: UploadWindows) {
+        self.upload_windows = upload_windows;
+    }
+    /// only downloads the blocks of a file that get read, instead of the whole
+    /// file when it is opened. See [BlockMap]
+    pub fn set_partial_downloads(&mut self, partial_downloads: bool) {
+        self.partial_downloads = partial_downloads;
+    }
+    /// the block size the entries report, see [SyncSettings::set_blksize](crate::fs::drive::SyncSettings::set_blksize)
+    pub fn set_blksize(&mut self, blksize: u32) {
+        self.blksize = blksize;
+    }
+    /// checks the files a previous run left in the cache when the entries are
+    /// initialized, see [DriveFileProvider::verify_cache]
+    pub fn set_verify_cache(&mut self, verify_cache: bool) {
+        self.verify_cache = verify_cache;
+    }
+    /// reads the matching files straight from the drive instead of caching them, see [NoCachePaths]
+    pub fn set_no_cache(&mut self, no_cache: NoCachePaths) {
+        self.no_cache = no_cache;
+    }
+    /// lets cache files with the same content share one copy on the disk, see [ContentStore]
+    pub fn set_dedupe_cache(&mut self, dedupe_cache: bool) {
+        self.content_store = dedupe_cache.then(|| ContentStore::new(&self.cache_dir));
+    }
+    /// sets how many reads of different files can run at the same time, at least 1
+    pub fn set_max_concurrent_reads(&mut self, max_concurrent_reads: usize) {
+        self.read_permits = Arc::new(Semaphore::new(max_concurrent_reads.max(1)));
+    }
+    /// sets how long the storage quota is cached before it is fetched again
+    pub fn set_quota_cache_time(&mut self, quota_cache_time: Duration) {
+        self.quota_cache_time = quota_cache_time;
+    }
+    /// both ids go through [DriveFileProvider::get_correct_id], so the children
+    /// of the root don't get split between the alias and the actual id.
+    ///
+    /// A relation that would make the child its own ancestor is refused.
     fn add_parent_child_relation(&mut self, parent_id: DriveId, child_id: DriveId) {
+        let parent_id = self.get_correct_id(parent_id);
+        let child_id = self.get_correct_id(child_id);
+        if self.is_ancestor_or_self(&child_id, &parent_id) {
+            warn!(
+                "not adding {} as a child of {}, that would form a cycle",
+                child_id, parent_id
+            );
+            return;
+        }
         trace!(
             "adding child-parent relation for child: {:<50} and parent: {:<50}",
             child_id,
@@ -150,26 +509,239 @@ impl DriveFileProvider {
                 .insert(child_id.clone(), vec![parent_id.clone()]);
         }
         if let Some(children) = self.children.get_mut(&parent_id) {
-            children.push(child_id);
+            children.push(child_id.clone());
         } else {
-            self.children.insert(parent_id, vec![child_id]);
+            self.children
+                .insert(parent_id.clone(), vec![child_id.clone()]);
+        }
+        self.index_child_name(&parent_id, &child_id);
+        self.invalidate_paths();
+    }
+
+    /// if `ancestor` is `id` or can be reached from it through the parents
+    fn is_ancestor_or_self(&self, ancestor: &DriveId, id: &DriveId) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![id.clone()];
+        while let Some(current) = pending.pop() {
+            if &current == ancestor {
+                return true;
+            }
+            if visited.insert(current.clone()) {
+                pending.extend(self.parents.get(&current).into_iter().flatten().cloned());
+            }
         }
+        false
     }
 
     fn remove_parent_child_relation(&mut self, parent_id: DriveId, child_id: DriveId) {
+        let parent_id = self.get_correct_id(parent_id);
+        let child_id = self.get_correct_id(child_id);
         trace!(
             "removing child-parent relation for child: {:<50} and parent: {:<50}",
             child_id,
             parent_id
         );
         if let Some(parents) = self.parents.get_mut(&child_id) {
-            parents.remove_first_element(&parent_id);
+            parents.remove_all_elements(&parent_id);
         }
+        self.unindex_child_name(&parent_id, &child_id);
+        self.invalidate_paths();
         if let Some(children) = self.children.get_mut(&parent_id) {
-            children.remove_first_element(&child_id);
+            let removed = children.remove_all_elements(&child_id);
+            if removed > 1 {
+                warn!(
+                    "child {} was listed {} times under parent {}",
+                    child_id, removed, parent_id
+                );
+            }
+        }
+    }
+
+    //region child names
+    fn get_entry_name_key(&self, id: &DriveId) -> Option<String> {
+        self.entries
+            .get(id)
+            .and_then(|e| e.metadata.name.as_deref())
+            .map(child_name_key)
+    }
+
+    /// adds the child to the name index of the parent, if there is no other
+    /// child with the same name already
+    fn index_child_name(&mut self, parent_id: &DriveId, child_id: &DriveId) {
+        if let Some(key) = self.get_entry_name_key(child_id) {
+            self.child_names
+                .entry(parent_id.clone())
+                .or_default()
+                .entry(key)
+                .or_insert_with(|| child_id.clone());
         }
     }
 
+    /// removes the child from the name index of the parent.
+    ///
+    /// If another child has the same name, that one takes its place, which
+    /// needs a scan over the children of the parent.
+    fn unindex_child_name(&mut self, parent_id: &DriveId, child_id: &DriveId) {
+        let Some(key) = self.get_entry_name_key(child_id) else {
+            return;
+        };
+        let Some(names) = self.child_names.get_mut(parent_id) else {
+            return;
+        };
+        if names.get(&key) != Some(child_id) {
+            return;
+        }
+        names.remove(&key);
+
+        let replacement = self.children.get(parent_id).and_then(|children| {
+            let others = children.iter().filter(|id| *id != child_id).map(|id| {
+                (
+                    id,
+                    self.entries.get(id).and_then(|e| e.metadata.name.as_ref()),
+                )
+            });
+            find_child_by_name(others, &key).cloned()
+        });
+        if let Some(replacement) = replacement {
+            if let Some(names) = self.child_names.get_mut(parent_id) {
+                names.insert(key, replacement);
+            }
+        }
+    }
+
+    fn index_entry_name(&mut self, id: &DriveId) {
+        self.invalidate_paths();
+        for parent_id in self.parents.get(id).cloned().unwrap_or_default() {
+            self.index_child_name(&parent_id, id);
+        }
+    }
+
+    /// has to be called before the name of an entry changes, and
+    /// [DriveFileProvider::index_entry_name] after that
+    fn unindex_entry_name(&mut self, id: &DriveId) {
+        for parent_id in self.parents.get(id).cloned().unwrap_or_default() {
+            self.unindex_child_name(&parent_id, id);
+        }
+    }
+    //endregion
+
+    //region paths
+    /// reconstructs the path of the entry relative to the root of the drive.
+    ///
+    /// Entries with multiple parents get the path through their first parent.
+    /// Returns `None` if the entry can not be reached from the root, for
+    /// example because it has no parents or its parents form a cycle.
+    ///
+    /// The paths are cached until any name or parent changes.
+    pub fn resolve_path(&self, id: &DriveId) -> Option<PathBuf> {
+        let mut path_cache = self.path_cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(path) = path_cache.get(id) {
+            return path.clone();
+        }
+        let mut names = vec![];
+        let mut current = id.clone();
+        let mut base = None;
+        // the depth limit protects against parent cycles
+        for _ in 0..=self.entries.len() {
+            if current == self.alt_root_id {
+                base = Some(PathBuf::new());
+                break;
+            }
+            if let Some(cached) = path_cache.get(&current) {
+                base = cached.clone();
+                break;
+            }
+            let Some(name) = self.get_entry_name(&current) else {
+                break;
+            };
+            names.push(name);
+            let Some(parent) = self.parents.get(&current).and_then(|p| p.first()) else {
+                break;
+            };
+            current = parent.clone();
+        }
+        if base.is_none() && names.len() > self.entries.len() {
+            warn!("found a cycle in the parents of {}", id);
+        }
+        let path = base.map(|mut path| {
+            path.extend(names.iter().rev());
+            path
+        });
+        path_cache.insert(id.clone(), path.clone());
+        path
+    }
+
+    fn get_entry_name(&self, id: &DriveId) -> Option<String> {
+        self.entries.get(id)?.metadata.name.clone()
+    }
+
+    /// has to be called whenever a name or a parent changes, since that can
+    /// change the path of every entry below it
+    fn invalidate_paths(&self) {
+        self.path_cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+    //endregion
+
+    //region path filter
+    /// entries that can not be reached from the root are kept, since their path is unknown
+    fn is_entry_included(&self, id: &DriveId) -> bool {
+        let Some(path) = self.resolve_path(id) else {
+            return true;
+        };
+        let is_dir = self
+            .entries
+            .get(id)
+            .is_some_and(|e| e.attr.kind == FileType::Directory);
+        self.path_filter.is_included(&path, is_dir)
+    }
+
+    fn get_ttl(&self, id: &DriveId) -> Option<Duration> {
+        self.ttl_overrides.ttl_for(&self.resolve_path(id)?)
+    }
+
+    fn remove_filtered_entries(&mut self) {
+        let filtered: Vec<DriveId> = self
+            .entries
+            .keys()
+            .filter(|id| !self.is_entry_included(id))
+            .cloned()
+            .collect();
+        debug!("removing {} entries that are filtered out", filtered.len());
+        for id in filtered {
+            self.remove_entry(&id);
+        }
+    }
+
+    /// removes the entry and all of its children that don't have another parent
+    fn remove_entry(&mut self, id: &DriveId) {
+        trace!("removing entry {}", id);
+        self.file_locks.remove(id);
+        self.forget_snapshot_record(id);
+        self.auth_failed_uploads.retain(|failed| failed != id);
+        for child_id in self.children.get(id).cloned().unwrap_or_default() {
+            self.remove_parent_child_relation(id.clone(), child_id.clone());
+            if self.parents.get(&child_id).is_none_or(|p| p.is_empty()) {
+                self.remove_entry(&child_id);
+            }
+        }
+        for parent_id in self.parents.get(id).cloned().unwrap_or_default() {
+            self.remove_parent_child_relation(parent_id, id.clone());
+        }
+        self.parents.remove(id);
+        self.children.remove(id);
+        self.child_names.remove(id);
+        self.entries.remove(id);
+        self.partial_files.remove(id);
+        let revisions_folder = revisions_folder_id(id);
+        if self.entries.contains_key(&revisions_folder) {
+            self.remove_entry(&revisions_folder);
+        }
+    }
+    //endregion
+
     //region listeners
     #[instrument(skip(self, request_reciever, command_receiver))]
     pub async fn listen(
@@ -178,25 +750,101 @@ impl DriveFileProvider {
         command_receiver: Receiver<ProviderCommand>,
     ) {
         debug!("listen");
+        let health = self.health.clone();
+        let running_transfers = self.running_transfers.clone();
+        let transferred = self.drive.transfer_counter();
+        let (state_command_tx, state_command_rx) = tokio::sync::mpsc::channel(1);
         tokio::select! {
-            _ = Self::listen_for_stop(command_receiver) => {
-                trace!("DriveFileProvider::listen_for_stop() finished");
+            _ = Self::listen_for_commands(command_receiver, health, running_transfers, transferred, state_command_tx) => {
+                trace!("DriveFileProvider::listen_for_commands() finished");
             },
-            _ = self.listen_for_file_requests(request_reciever) => {
+            _ = self.listen_for_file_requests(request_reciever, state_command_rx) => {
                 trace!("DriveFileProvider::listen_for_file_requests() finished");
             },
         }
+        // a stop command ends the file request listener as well, the uploads
+        // that are still waiting for their window would get lost otherwise
+        self.finish_uploads().await;
+        self.save_snapshot_state();
     }
-    pub async fn listen_for_stop(mut command_receiver: Receiver<ProviderCommand>) {
-        let signal = command_receiver.recv().await;
-        if let Some(signal) = signal {
+    /// answers commands until it receives a stop command or all senders are dropped.
+    ///
+    /// commands that need the state of the provider are forwarded to the
+    /// file request listener with `state_commands`
+    pub(crate) async fn listen_for_commands(
+        mut command_receiver: Receiver<ProviderCommand>,
+        health: Arc<Mutex<HealthStatus>>,
+        running_transfers: RunningTransfers,
+        transferred: TransferCounter,
+        state_commands: Sender<ProviderCommand>,
+    ) {
+        while let Some(signal) = command_receiver.recv().await {
             match signal {
                 ProviderCommand::Stop => {
                     debug!("provider received stop command");
+                    break;
+                }
+                ProviderCommand::HealthCheck { reply } => {
+                    let status = health
+                        .lock()
+                        .map(|health| *health)
+                        .unwrap_or_else(|e| *e.into_inner());
+                    if reply.send(status).is_err() {
+                        warn!("could not send the health status, the receiver is gone");
+                    }
+                }
+                ProviderCommand::ListTransfers { reply } => {
+                    let mut transfers: Vec<TransferInfo> =
+                        lock_running_transfers(&running_transfers)
+                            .iter()
+                            .map(|(file_id, transfer)| TransferInfo {
+                                file_id: file_id.clone(),
+                                direction: transfer.direction,
+                                transferred: transferred.of_file(file_id),
+                            })
+                            .collect();
+                    transfers.sort_by(|a, b| a.file_id.as_str().cmp(b.file_id.as_str()));
+                    if reply.send(transfers).is_err() {
+                        warn!("could not send the transfers, the receiver is gone");
+                    }
+                }
+                ProviderCommand::CancelTransfer { file_id } => {
+                    match lock_running_transfers(&running_transfers).get(&file_id) {
+                        Some(transfer) => {
+                            info!("cancelling the {:?} of {}", transfer.direction, file_id);
+                            transfer.abort.abort();
+                        }
+                        None => {
+                            debug!("there is no running transfer of {} to cancel", file_id);
+                            continue;
+                        }
+                    }
+                    // the file request listener cleans up after the cancelled transfer
+                    let command = ProviderCommand::CancelTransfer { file_id };
+                    if state_commands.send(command).await.is_err() {
+                        warn!("could not forward the command, the file request listener is gone");
+                    }
+                }
+                ProviderCommand::SetLogLevel(level) => match log_level::set_log_level(level) {
+                    Ok(()) => info!("changed the log level to {}", level),
+                    Err(e) => warn!("could not change the log level: {:?}", e),
+                },
+                command @ (ProviderCommand::DumpState { .. }
+                | ProviderCommand::Stats { .. }
+                | ProviderCommand::CheckForChanges
+                | ProviderCommand::SetOffline(_)
+                | ProviderCommand::Pin { .. }
+                | ProviderCommand::Unpin { .. }
+                | ProviderCommand::Prefetch { .. }
+                | ProviderCommand::EvictCache
+                | ProviderCommand::ReloadAuth { .. }) => {
+                    if state_commands.send(command).await.is_err() {
+                        warn!("could not forward the command, the file request listener is gone");
+                    }
                 }
-                _ => {
-                    error!("unknown signal");
-                    todo!()
+                command => {
+                    // keeps the provider running, the mount would be stuck otherwise
+                    error!("the provider can not handle the command: {:?}", command);
                 }
             }
         }
@@ -204,11 +852,15 @@ impl DriveFileProvider {
         //     10 * 60 * 60 * 24, /*10 days*/
         // ))
         // .await;
-        debug!("listen for stop finished");
+        debug!("listen for commands finished");
         // //TODO: implement waiting for the stop signal instead of just waiting for 10 days
     }
-    #[instrument(skip(self, rx))]
-    pub async fn listen_for_file_requests(&mut self, rx: Receiver<ProviderRequest>) {
+    #[instrument(skip(self, rx, state_commands))]
+    pub async fn listen_for_file_requests(
+        &mut self,
+        rx: Receiver<ProviderRequest>,
+        mut state_commands: Receiver<ProviderCommand>,
+    ) {
         debug!("initializing entries");
         let init_res = self.initialize_entries().await;
         if let Err(e) = init_res {
@@ -217,1083 +869,6337 @@ impl DriveFileProvider {
         }
         debug!("listening for file requests");
         let mut rx = rx;
-        while let Some(file_request) = rx.recv().await {
-            debug!("got file request: {:?}", file_request);
-            self.check_and_apply_changes().await;
-            let result = match file_request {
-                ProviderRequest::OpenFile(r) => self.open_file(r).await,
-                ProviderRequest::ReleaseFile(r) => self.release_file(r).await,
-                ProviderRequest::Metadata(r) => self.metadata(r).await,
-                ProviderRequest::ReadContent(r) => self.read_content(r).await,
-                ProviderRequest::WriteContent(r) => self.write_content(r).await,
-                ProviderRequest::ReadDir(r) => self.read_dir(r).await,
-                ProviderRequest::Rename(r) => self.rename(r).await,
-                ProviderRequest::Lookup(r) => self.lookup(r).await,
-                ProviderRequest::SetAttr(r) => self.set_attr(r).await,
-                _ => {
-                    error!(
-                    "DriveFileProvider::listen_for_file_requests() received unknown request: {:?}",
-                    file_request
-                );
-                    todo!("handle this unknown request")
+        loop {
+            let next_upload = self.scheduled_uploads.values().min().copied();
+            tokio::select! {
+                file_request = rx.recv() => {
+                    let Some(file_request) = file_request else {
+                        break;
+                    };
+                    self.handle_file_request(file_request).await;
+                    debug!("processed file request, waiting for more...");
+                },
+                Some(command) = state_commands.recv() => self.handle_state_command(command).await,
+                _ = tokio::time::sleep_until(next_upload.unwrap_or_else(tokio::time::Instant::now)),
+                    if next_upload.is_some() => self.start_scheduled_uploads(false).await,
+                Some((id, ok)) = self.prefetches.done_rx.recv(), if self.prefetches.is_running() => {
+                    self.finish_prefetch(id, ok).await
                 }
-            };
-            if let Err(e) = result {
-                error!("file request handler returned an error: {}", e);
             }
-            debug!("processed file request, waiting for more...");
         }
         debug!("Received None from file request receiver, that means all senders have been dropped. Ending listener");
     }
-
-    async fn check_and_apply_changes(&mut self) {
-        let changes = self.get_changes().await;
-        if let Ok(changes) = changes {
-            for change in changes {
-                let change_applied_successful = self.process_change(change).await;
-                if let Err(e) = change_applied_successful {
-                    error!("got an error while applying change: {:?}", e);
+    async fn handle_file_request(&mut self, file_request: ProviderRequest) {
+        debug!("got file request: {:?}", file_request);
+        self.check_and_apply_changes().await;
+        let result = match file_request {
+            ProviderRequest::OpenFile(r) => self.open_file(r).await,
+            ProviderRequest::Flush(r) => self.flush(r).await,
+            ProviderRequest::ReleaseFile(r) => self.release_file(r).await,
+            ProviderRequest::Metadata(r) => self.metadata(r).await,
+            ProviderRequest::ReadContent(r) => self.read_content(r).await,
+            ProviderRequest::Lseek(r) => self.lseek(r).await,
+            ProviderRequest::GetXattr(r) => self.get_xattr(r).await,
+            ProviderRequest::SetXattr(r) => self.set_xattr(r).await,
+            ProviderRequest::ListXattr(r) => self.list_xattr(r).await,
+            ProviderRequest::WriteContent(r) => self.write_content(r).await,
+            ProviderRequest::ReadDir(r) => self.read_dir(r).await,
+            ProviderRequest::Rename(r) => self.rename(r).await,
+            ProviderRequest::Remove(r) => self.remove(r).await,
+            ProviderRequest::Mkdir(r) => self.mkdir(r).await,
+            ProviderRequest::Lookup(r) => self.lookup(r).await,
+            ProviderRequest::SetAttr(r) => self.set_attr(*r).await,
+            ProviderRequest::Status(r) => self.status(r).await,
+            ProviderRequest::RootId(r) => self.root_id(r).await,
+            _ => self.reply_unsupported(file_request).await,
+        };
+        if let Err(e) = result {
+            error!("file request handler returned an error: {}", e);
+        }
+    }
+    /// answers requests the provider can't handle with `ENOSYS`, if they have
+    /// somewhere to send the answer to
+    async fn reply_unsupported(&self, request: ProviderRequest) -> Result<()> {
+        error!("received a request that can not be handled: {:?}", request);
+        let Some(request) = request.responder() else {
+            return Ok(());
+        };
+        let response = ProviderResponse::Error(anyhow!("unsupported request"), libc::ENOSYS);
+        if request.get_response_sender().send(response).await.is_err() {
+            warn!("could not answer the unsupported request, the receiver is gone");
+        }
+        Ok(())
+    }
+    /// answers the commands [DriveFileProvider::listen_for_commands] forwarded
+    async fn handle_state_command(&mut self, command: ProviderCommand) {
+        match command {
+            ProviderCommand::DumpState { redact_ids, reply } => {
+                if reply.send(self.dump_state(redact_ids)).is_err() {
+                    warn!("could not send the state dump, the receiver is gone");
+                }
+            }
+            ProviderCommand::Stats { reply } => {
+                if reply.send(self.stats()).is_err() {
+                    warn!("could not send the stats, the receiver is gone");
+                }
+            }
+            ProviderCommand::CheckForChanges => {
+                self.last_checked_for_changes = UNIX_EPOCH;
+                self.check_and_apply_changes().await;
+            }
+            ProviderCommand::SetOffline(offline) => self.set_offline(offline).await,
+            ProviderCommand::Pin { file_id } => {
+                if let Err(e) = self.pin(&file_id).await {
+                    error!("could not pin {}: {:?}", file_id, e);
+                }
+            }
+            ProviderCommand::Unpin { file_id } => {
+                if let Err(e) = self.unpin(&file_id).await {
+                    error!("could not unpin {}: {:?}", file_id, e);
+                }
+            }
+            ProviderCommand::Prefetch { folder } => {
+                if let Err(e) = self.prefetch(&folder).await {
+                    error!("could not prefetch {}: {:?}", folder.display(), e);
+                }
+            }
+            ProviderCommand::EvictCache => {
+                self.evict_cache().await;
+            }
+            ProviderCommand::ReloadAuth { drive } => self.reload_auth(*drive).await,
+            ProviderCommand::CancelTransfer { file_id } => {
+                // only the cleanup is left, the command listener aborted it already
+                if let Err(e) = self.wait_for_drive_request_if_exists(&file_id).await {
+                    debug!("the cancelled transfer of {} ended with: {}", file_id, e);
                 }
             }
+            command => error!("got a command that does not need the state: {:?}", command),
         }
     }
-    //endregion
 
-    //region request handlers
-    //region lookup
-    #[instrument(skip(request))]
-    async fn lookup(&self, request: ProviderLookupRequest) -> Result<()> {
-        let name = request.name.into_string();
-        if name.is_err() {
-            return send_error_response!(request, anyhow!("invalid name"), libc::EINVAL);
+    /// talks to google drive with this drive from now on, so a new login does
+    /// not need a remount. The uploads that failed because the old credentials
+    /// were not accepted are started again.
+    pub async fn reload_auth(&mut self, mut drive: GoogleDrive) {
+        info!("switching to the reloaded credentials");
+        // the uploads that failed in the background are only known once they are waited for
+        let finished: Vec<DriveId> = self
+            .running_requests
+            .iter()
+            .filter(|(_, handle)| handle.is_finished())
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in finished {
+            if let Err(e) = self.wait_for_drive_request_if_exists(&id).await {
+                debug!("the transfer of {} ended with: {}", id, e);
+            }
         }
-        let name = name.unwrap();
-        let parent_id = self.get_correct_id(request.parent);
-        debug!("looking up {} under id {}", name, parent_id);
-
-        let result = self.find_first_child_by_name(&name, &parent_id);
-
-        if let Some(result) = result {
-            let result = Self::create_file_metadata_from_entry(result);
-            let response = ProviderResponse::Lookup(Some(result));
-            return send_response!(request, response);
+        // the command listener keeps counting with the old counter
+        drive.set_transfer_counter(self.drive.transfer_counter());
+        self.drive = drive;
+        let failed_uploads = std::mem::take(&mut self.auth_failed_uploads);
+        debug!(
+            "uploading {} files that failed because of the credentials",
+            failed_uploads.len()
+        );
+        for id in failed_uploads {
+            let drive = self.drive.clone();
+            if let Err(e) = self.start_upload_call(id.clone(), drive).await {
+                error!("could not upload {} with the new credentials: {:?}", id, e);
+            }
         }
-
-        debug!("could not find file: {} in {}", name, parent_id);
-        let response = ProviderResponse::Lookup(None);
-        return send_response!(request, response);
     }
 
-    //endregion
-    //region read dir
-    #[instrument(skip(request))]
-    async fn read_dir(&mut self, request: ProviderReadDirRequest) -> Result<()> {
-        let parent_id = self.get_correct_id(request.file_id.clone());
+    /// In offline mode the provider does not talk to google drive at all. Only
+    /// the files that are already local can be opened, their changes get
+    /// uploaded once the provider is back online.
+    ///
+    /// Everything else that needs the drive fails with [ProviderError::Offline].
+    /// A provider that is offline before it listens starts with the entries
+    /// the last run saved, see [Self::can_start_offline].
+    pub async fn set_offline(&mut self, offline: bool) {
+        info!("offline mode: {}", offline);
+        self.offline = offline;
+        if offline {
+            return;
+        }
+        let queued_uploads = std::mem::take(&mut self.queued_uploads);
         debug!(
-            "got read dir request for id: {} with offset: {}",
-            parent_id, request.offset
+            "uploading {} files changed while offline",
+            queued_uploads.len()
         );
-        if let Some(children) = self.children.get(&parent_id) {
-            let response = children
-                .iter()
-                .map(|id| (id, self.entries.get(id)))
-                .filter(|(_id, e)| e.is_some())
-                .map(|(id, e)| (id, e.unwrap()))
-                .map(|(id, e)| FileMetadata {
-                    id: id.clone(),
-                    name: e
-                        .metadata
-                        .name
-                        .as_ref()
-                        .unwrap_or(&"NO_NAME".to_string())
-                        .clone(),
-                    attr: e.attr.clone(),
-                })
-                .skip(request.offset as usize)
-                .collect::<Vec<FileMetadata>>();
-            debug!("returning {} entries", response.len());
-            let response = ProviderReadDirResponse { entries: response };
-            return send_response!(request, ProviderResponse::ReadDir(response));
-        }
-        debug!("found no entries to return");
-        for e in self.entries.iter() {
-            debug!("entry: {}: {:?}", e.0, e.1);
-            debug!("children: {:?}", self.children.get(e.0));
-            debug!("parents: {:?}", self.parents.get(e.0));
+        for id in queued_uploads {
+            if let Err(e) = self.wait_for_running_drive_request_if_exists(&id).await {
+                warn!("could not wait for the running request of {}: {:?}", id, e);
+            }
+            let drive = self.drive.clone();
+            if let Err(e) = self.start_upload_call(id.clone(), drive).await {
+                error!("could not upload {} after going online: {:?}", id, e);
+            }
         }
-        return send_response!(
-            request,
-            ProviderResponse::ReadDir(ProviderReadDirResponse { entries: vec![] })
-        );
     }
-    //endregion
-    //region open file
-    #[instrument(skip(request))]
-    async fn open_file(&mut self, request: ProviderOpenFileRequest) -> Result<()> {
-        let file_id = &self.get_correct_id(request.file_id.clone());
-        let wait_res = self
-            .wait_for_running_drive_request_if_exists(&file_id)
-            .await;
-        if let Err(e) = wait_res {
-            return send_error_response!(request, e, libc::EIO);
+    /// keeps the file in the perma dir, so it is available offline and never
+    /// gets evicted. If it is not local yet, it gets downloaded in the background.
+    ///
+    /// The pins are stored in the perma dir, so they survive restarts.
+    pub async fn pin(&mut self, id: &DriveId) -> ProviderResult<()> {
+        let id = self.get_correct_id(id.clone());
+        let entry = self
+            .entries
+            .get(&id)
+            .ok_or_else(|| ProviderError::NotFound(format!("{} is not known", id)))?;
+        if entry.attr.kind == FileType::Directory {
+            return Err(ProviderError::IsDirectory(format!(
+                "can't pin folder {}",
+                id
+            )));
         }
-        let target_path = self.construct_path(&file_id);
-        if let Err(e) = target_path {
-            return send_error_response!(request, e, libc::EIO);
+        if parse_revision_entry_id(&id).is_some() {
+            return Err(ProviderError::PermissionDenied(format!(
+                "can't pin revision {}",
+                id
+            )));
+        }
+        self.wait_for_drive_request_if_exists(&id).await?;
+        self.start_pin(&id, None).await
+    }
+    /// moves the file to the perma dir and starts the download if it is not
+    /// complete. `done` is told once the file is complete
+    async fn start_pin(&mut self, id: &DriveId, done: Option<PrefetchDone>) -> ProviderResult<()> {
+        // a partial file gets downloaded as a whole
+        let complete = self.entries.get(id).is_some_and(|e| e.is_local)
+            && !self.partial_files.contains_key(id);
+        if !complete {
+            self.check_online()?;
         }
-        let target_path = target_path.unwrap();
-        if !self
-            .entries
-            .get(file_id)
-            .map(|e| e.is_local)
-            .unwrap_or(false)
         {
-            debug!("file not local, downloading...");
-            let drive = self.drive.clone();
-            self.start_download_call(&request, drive, &target_path)
-                .await?;
+            let _lock = self.file_locks.write(id).await;
+            self.move_to_perma_dir(id, true)?;
         }
-        let handle_flags = HandleFlags::from(request.flags);
-        let fh = self.create_fh(handle_flags, target_path, false, true);
-        send_response!(request, ProviderResponse::OpenFile(fh, handle_flags))
-    }
-    //endregion
-    //region release file
-    #[instrument(skip(request))]
-    async fn release_file(&mut self, request: ProviderReleaseFileRequest) -> Result<()> {
-        let file_id = &self.get_correct_id(request.file_id.clone());
-        let wait_res = self
-            .wait_for_running_drive_request_if_exists(&file_id)
-            .await;
-        if let Err(e) = wait_res {
-            return send_error_response!(request, e, libc::EIO);
+        self.pinned.insert(id.clone());
+        save_pinned(&self.perma_dir, &self.pinned)?;
+        if complete {
+            if let Some(done) = done {
+                done.succeeded();
+            }
+            return Ok(());
         }
-        // let entry = self.entries.get(file_id).context("could not get entry");
-        // if let Err(e) = entry {
-        //     return send_error_response!(request, e, libc::EIO);
-        // }
-        // let entry = entry.unwrap();
-        let file_handle = self
-            .file_handles
-            .remove(&request.fh)
-            .context("could not get entry");
-        if let Err(e) = file_handle {
-            return send_error_response!(request, e, libc::EIO);
+        let path = self.construct_path(id)?;
+        if self.partial_files.remove(id).is_some() {
+            let _ = std::fs::remove_file(BlockMap::path_for(&path));
         }
-        let file_handle = file_handle.unwrap();
-        if file_handle.has_content_changed {
-            debug!("uploading changes to google drive for file: {}", file_id);
-            let drive = self.drive.clone();
-            let start_result = self.start_upload_call(file_id.clone(), drive).await;
-            if let Err(e) = start_result {
-                error!("got error from starting the upload: {:?}", e);
-                return send_error_response!(request, e, libc::EIO);
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.is_local = true;
+        }
+        debug!("downloading pinned file {}", id);
+        let drive = self.drive.clone();
+        let file_id = id.clone();
+        let local_md5s = self.local_md5s.clone();
+        let handle: JoinHandle<Result<()>> = tokio::spawn(async move {
+            let metadata = drive.download_file(file_id.clone(), &path).await?;
+            set_local_md5(&local_md5s, &file_id, metadata.md5_checksum);
+            if let Some(done) = done {
+                done.succeeded();
             }
+            Ok(())
+        });
+        self.track_transfer(id.clone(), TransferDirection::Download, handle);
+        Ok(())
+    }
+    /// moves the file back into the cache dir, where it can get evicted again
+    pub async fn unpin(&mut self, id: &DriveId) -> ProviderResult<()> {
+        let id = self.get_correct_id(id.clone());
+        self.wait_for_drive_request_if_exists(&id).await?;
+        let _lock = self.file_locks.write(&id).await;
+        if self.entries.contains_key(&id) {
+            self.move_to_perma_dir(&id, false)?;
         }
-        return send_response!(request, ProviderResponse::ReleaseFile);
+        self.pinned.remove(&id);
+        Ok(save_pinned(&self.perma_dir, &self.pinned)?)
     }
-    //endregion
-    //region metadata
-    #[instrument(skip(request))]
-    async fn metadata(&self, request: ProviderMetadataRequest) -> Result<()> {
-        let file_id = &self.get_correct_id(request.file_id.clone());
-        debug!("metadata got called");
-        let entry = self.entries.get(file_id);
-        if entry.is_none() {
-            return send_error_response!(
-                request,
-                anyhow!("could not find entry with id"),
-                libc::ENOENT
-            );
+    //region prefetch
+    /// pins every file in the folder and its subfolders, so the whole folder is
+    /// available offline. `folder` is the path from the root of the mount.
+    ///
+    /// The files get downloaded in the background, at most
+    /// [MAX_CONCURRENT_PREFETCHES] at the same time, the progress is part of the
+    /// status. Files the path filter excludes and native files are skipped.
+    pub async fn prefetch(&mut self, folder: &Path) -> ProviderResult<()> {
+        let folder_id = self.find_by_path(folder).ok_or_else(|| {
+            ProviderError::NotFound(format!("{} does not exist", folder.display()))
+        })?;
+        if !self
+            .entries
+            .get(&folder_id)
+            .is_some_and(|e| e.attr.kind == FileType::Directory)
+        {
+            return Err(ProviderError::NotDirectory(format!(
+                "{} is not a folder",
+                folder.display()
+            )));
         }
-        let entry = entry.unwrap();
-        let response = ProviderResponse::Metadata(Self::create_file_metadata_from_entry(entry));
-
-        send_response!(request, response)
+        let files = self.files_to_prefetch(&folder_id);
+        info!("prefetching {} files in {}", files.len(), folder.display());
+        let progress = self
+            .prefetches
+            .progress
+            .get_or_insert_with(Default::default);
+        progress.total += files.len();
+        self.prefetches.queue.extend(files);
+        self.start_prefetches().await;
+        Ok(())
     }
-
-    //endregion
-    //region set_attr
-    async fn set_attr(&mut self, request: ProviderSetAttrRequest) -> Result<()> {
-        let file_id = &self.get_correct_id(request.file_id.clone());
-        let wait_res = self
-            .wait_for_running_drive_request_if_exists(&file_id)
-            .await;
-        if let Err(e) = wait_res {
-            return send_error_response!(request, e, libc::EIO);
+    /// the entry at the path from the root of the mount
+    fn find_by_path(&self, path: &Path) -> Option<DriveId> {
+        let mut id = self.alt_root_id.clone();
+        for component in path.components() {
+            let Component::Normal(name) = component else {
+                continue;
+            };
+            id = self
+                .find_first_child_by_name(&name.to_string_lossy(), &id)
+                .filter(|entry| self.is_visible(entry))?
+                .get_id()?;
         }
-        debug!("set_attr got called");
-        let entry = self.entries.get(file_id);
-        if entry.is_none() {
-            return send_error_response!(
-                request,
-                anyhow!("could not find entry with id"),
-                libc::ENOENT
-            );
+        Some(id)
+    }
+    /// the files in the folder and its subfolders that are not pinned yet
+    fn files_to_prefetch(&self, folder_id: &DriveId) -> Vec<DriveId> {
+        let mut files = Vec::new();
+        let mut folders = vec![folder_id.clone()];
+        let mut seen = HashSet::new();
+        while let Some(folder) = folders.pop() {
+            if !seen.insert(folder.clone()) {
+                continue;
+            }
+            for child in self.children.get(&folder).into_iter().flatten() {
+                let Some(entry) = self.entries.get(child) else {
+                    continue;
+                };
+                if !self.is_visible(entry) || !self.is_entry_included(child) {
+                    continue;
+                }
+                match entry.attr.kind {
+                    FileType::Directory => folders.push(child.clone()),
+                    FileType::RegularFile
+                        if !is_native_file(&entry.metadata) && !self.pinned.contains(child) =>
+                    {
+                        files.push(child.clone())
+                    }
+                    _ => {}
+                }
+            }
         }
-        let entry = entry.unwrap();
-        let mut attr = entry.attr.clone();
-
-        if let Some(size) = request.size {
-            attr.size = size;
-            let x = self
-                .set_underlying_file_size(&file_id, request.fh, size)
-                .await;
-            if let Err(e) = x {
-                error!(
-                    "got an error while setting the underlying file size: {:?}",
-                    e
-                );
-                return send_error_response!(request, e, libc::EIO);
+        files
+    }
+    /// starts the downloads of the queued files, until the limit is reached
+    async fn start_prefetches(&mut self) {
+        while self.prefetches.running.len() < MAX_CONCURRENT_PREFETCHES {
+            let Some(id) = self.prefetches.queue.pop_front() else {
+                break;
+            };
+            if let Err(e) = self.start_prefetch(&id).await {
+                warn!("could not prefetch {}: {:?}", id, e);
+                self.count_prefetch(false);
             }
         }
-        if let Some(flags) = request.flags {
-            attr.flags = flags;
+    }
+    /// pins the file, if it is not complete it gets downloaded in the background
+    async fn start_prefetch(&mut self, id: &DriveId) -> ProviderResult<()> {
+        if !self.entries.contains_key(id) {
+            return Err(ProviderError::NotFound(format!("{} is not known", id)));
         }
-        if let Some(mode) = request.mode {
-            //TODO2: check if setting attr.perm to mode in setattr is correct (probably)
-            // and if i can just cast it to u16 (from u32) (i have no Idea)
-            attr.perm = mode as u16;
-            // TODO3: check if the file below even needs me to set the permissions
-            //  on the underlying file or if this is not needed at all since
-            //  permissions don't get transferred to gdrive and locally i
-            //  have the info in the entries
-
-            // if let Some(fh) = request.fh {
-            //     let handle = self.file_handles.get_mut(&fh);
-            //     if let Some(handle) = handle {
-            //         if let Some(file) = &mut handle.file {
-            //             let perms = Permissions::from_mode(mode);
-            //             let x = file.set_permissions(perms).await;
-            //             if x.is_err() {
-            //                 warn!("got an error result while setting len of file: {:?}", x);
-            //             }
-            //         }
-            //     }
-            // }
+        self.wait_for_drive_request_if_exists(id).await?;
+        debug!("prefetching {}", id);
+        // a pin that can't start drops the guard, that counts it as failed
+        let done = self.prefetches.done_guard(id.clone());
+        self.prefetches.running.insert(id.clone());
+        if let Err(e) = self.start_pin(id, Some(done)).await {
+            warn!("could not pin the prefetched file {}: {:?}", id, e);
         }
-        // if let Some(fh) = request.fh {
-        //     //TODO2: implement something for fh in setattr (if needed)
-        //     warn!(
-        //         "fh was set in setattr but I don't know what to do with this: {:?}",
-        //         request
-        //     );
-        // }
-        if let Some(gid) = request.gid {
-            //TODO2: implement something for gid in setattr
-            warn!(
-                "gid was set in setattr but I don't know what to do with this: {:?}",
-                request
-            );
+        Ok(())
+    }
+    /// cleans up after the download of a prefetched file and starts the next ones.
+    /// A file that could not be downloaded is not pinned
+    async fn finish_prefetch(&mut self, id: DriveId, ok: bool) {
+        self.prefetches.running.remove(&id);
+        if let Err(e) = self.wait_for_drive_request_if_exists(&id).await {
+            debug!("the prefetch of {} ended with: {}", id, e);
         }
-        if let Some(uid) = request.uid {
-            //TODO2: implement something for uid in setattr
-            warn!(
-                "uid was set in setattr but I don't know what to do with this: {:?}",
-                request
-            );
+        if !ok {
+            if let Some(entry) = self.entries.get_mut(&id) {
+                entry.is_local = false;
+            }
+            self.pinned.remove(&id);
+            if let Err(e) = self.move_to_perma_dir(&id, false) {
+                warn!("could not unpin the failed prefetch {}: {:?}", id, e);
+            }
         }
-
-        let entry = self
-            .entries
-            .get_mut(file_id)
-            .expect("got it in here before");
-        entry.attr = attr;
-
-        let response = ProviderResponse::SetAttr(Self::create_file_metadata_from_entry(entry));
-
-        send_response!(request, response)
+        self.count_prefetch(ok);
+        if let Err(e) = save_pinned(&self.perma_dir, &self.pinned) {
+            warn!("could not save the pinned files: {:?}", e);
+        }
+        self.start_prefetches().await;
     }
-
-    async fn set_underlying_file_size(
-        &mut self,
-        file_id: &&DriveId,
-        fh: Option<u64>,
-        size: u64,
-    ) -> Result<()> {
-        let mut was_applied = false;
-        if let Some(fh) = fh {
-            let handle = self.file_handles.get_mut(&fh);
-            if let Some(handle) = handle {
-                if let Some(file) = &mut handle.file {
-                    let x = file
-                        .set_len(size)
-                        .await
-                        .context("could not set the len of the file");
-                    if x.is_err() {
-                        warn!("got an error result while setting len of file: {:?}", x);
-                    } else {
-                        was_applied = true;
-                    }
+    fn count_prefetch(&mut self, ok: bool) {
+        let Some(progress) = self.prefetches.progress.as_mut() else {
+            return;
+        };
+        match ok {
+            true => progress.done += 1,
+            false => progress.failed += 1,
+        }
+        if progress.is_finished() {
+            info!("the prefetch finished: {}", progress);
+        }
+    }
+    //endregion
+    /// moves the local content of the file between the cache and the perma
+    /// dir, the handles of the file follow it
+    fn move_to_perma_dir(&mut self, id: &DriveId, perma: bool) -> ProviderResult<()> {
+        let from = self.construct_path(id)?;
+        let Some(entry) = self.entries.get_mut(id).filter(|e| e.perma != perma) else {
+            return Ok(());
+        };
+        entry.perma = perma;
+        let is_local = entry.is_local;
+        let to = self.construct_path(id)?;
+        if !is_local {
+            return Ok(());
+        }
+        move_file(&from, &to)?;
+        let _ = std::fs::remove_file(BlockMap::path_for(&from));
+        for file_handle in self.file_handles.values_mut() {
+            if file_handle.path == from {
+                file_handle.path = to.clone();
+                file_handle.file = None;
+                file_handle.marked_for_open = true;
+            }
+        }
+        Ok(())
+    }
+    /// removes the cached content of the files that are not pinned, not open
+    /// and have no changes that still have to be uploaded. They get downloaded
+    /// again when they are opened the next time.
+    pub async fn evict_cache(&mut self) -> usize {
+        let ids: Vec<DriveId> = self
+            .entries
+            .iter()
+            .filter(|(id, e)| {
+                e.is_local
+                    && !e.perma
+                    && !self.running_requests.contains_key(*id)
+                    && !self.queued_uploads.contains(*id)
+                    && !self.scheduled_uploads.contains_key(*id)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut evicted = 0;
+        for id in ids {
+            let Ok(path) = self.construct_path(&id) else {
+                continue;
+            };
+            if self.file_handles.values().any(|h| h.path == path) {
+                continue;
+            }
+            let _lock = self.file_locks.write(&id).await;
+            if let Err(e) = fs::remove_file(&path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("could not evict {}: {:?}", id, e);
+                    continue;
                 }
             }
+            let _ = std::fs::remove_file(BlockMap::path_for(&path));
+            self.partial_files.remove(&id);
+            if let Some(entry) = self.entries.get_mut(&id) {
+                entry.is_local = false;
+            }
+            evicted += 1;
         }
-        if !was_applied {
-            let target_path = self.construct_path(&file_id)?;
-            OpenOptions::new()
-                .write(true)
-                .open(target_path)
-                .await
-                .context("could not open file to set the size")?
-                .set_len(size)
-                .await
-                .context("could not set the size of the file")?;
+        if let Some(content_store) = &self.content_store {
+            if let Err(e) = content_store.remove_unused() {
+                warn!("could not clean up the stored content: {:?}", e);
+            }
         }
-        Ok(())
+        info!("evicted {} files from the cache", evicted);
+        evicted
     }
-    //endregion
-    //region read content
-    #[instrument(skip(request))]
-    async fn read_content(&mut self, request: ProviderReadContentRequest) -> Result<()> {
-        let file_id = &self.get_correct_id(request.file_id.clone());
-        let wait_res = self
-            .wait_for_running_drive_request_if_exists(&file_id)
-            .await;
-        if let Err(e) = wait_res {
-            return send_error_response!(request, e, libc::EIO);
+    fn check_online(&self) -> ProviderResult<()> {
+        if self.offline {
+            return Err(ProviderError::Offline(
+                "this needs google drive".to_string(),
+            ));
         }
+        Ok(())
+    }
 
-        let data = self.read_content_from_file(&request).await;
-        if let Err(e) = data {
-            return send_error_response!(request, e, libc::EIO);
+    async fn check_and_apply_changes(&mut self) {
+        let changes = self.get_changes().await;
+        if let Ok(changes) = changes {
+            for change in changes {
+                let change_applied_successful = self.process_change(change).await;
+                if let Err(e) = change_applied_successful {
+                    error!("got an error while applying change: {:?}", e);
+                }
+            }
+            // the changes are in the records already, the next run continues after them
+            self.save_snapshot_state();
         }
-        let data = data.unwrap();
-        send_response!(request, ProviderResponse::ReadContent(data))
     }
     //endregion
-    //region rename
 
+    //region state dump
+    /// a summary of the internal state as json, meant to be attached to bug reports.
+    ///
+    /// With `redact_ids` every id is replaced by a hash of it and the names are
+    /// left out, so the dump does not reveal which files are on the drive but
+    /// the relations between the entries can still be followed.
+    pub fn dump_state(&self, redact_ids: bool) -> serde_json::Value {
+        let id = |id: &DriveId| redact_id(id, redact_ids);
+        let relations = |relations: &HashMap<DriveId, Vec<DriveId>>| {
+            relations
+                .iter()
+                .map(|(key, ids)| (id(key), json!(ids.iter().map(id).collect::<Vec<_>>())))
+                .collect::<serde_json::Map<_, _>>()
+        };
+
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(entry_id, entry)| {
+                json!({
+                    "id": id(entry_id),
+                    "name": entry.metadata.name.as_ref().filter(|_| !redact_ids),
+                    "kind": format!("{:?}", entry.attr.kind),
+                    "size": entry.attr.size,
+                    "is_local": entry.is_local,
+                    "perma": entry.perma,
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+        let mut file_handles: Vec<_> = self
+            .file_handles
+            .iter()
+            .map(|(fh, data)| {
+                json!({
+                    "fh": fh,
+                    "flags": format!("{:?}", data.flags),
+                    "is_open": data.file.is_some(),
+                    "creating": data.creating,
+                    "marked_for_open": data.marked_for_open,
+                    "has_content_changed": data.has_content_changed,
+                })
+            })
+            .collect();
+        file_handles.sort_by_key(|handle| handle["fh"].as_u64());
+        let mut running_requests: Vec<_> = self
+            .running_requests
+            .iter()
+            .map(|(request_id, handle)| {
+                json!({
+                    "id": id(request_id),
+                    "finished": handle.is_finished(),
+                })
+            })
+            .collect();
+        running_requests.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+
+        json!({
+            "entries": entries,
+            "parents": relations(&self.parents),
+            "children": relations(&self.children),
+            "file_handles": file_handles,
+            "running_requests": running_requests,
+            "changes_start_token": self.changes_start_token.start_page_token,
+            "last_checked_for_changes": self
+                .last_checked_for_changes
+                .duration_since(UNIX_EPOCH)
+                .map(|t| t.as_secs())
+                .ok(),
+        })
+    }
+    //endregion
+
+    //region request handlers
+    //region lookup
     #[instrument(skip(request))]
-    async fn rename(&mut self, request: ProviderRenameRequest) -> Result<()> {
-        let original_parent = self.get_correct_id(request.original_parent.clone());
-        let original_name = request.original_name.into_string();
-        if let Err(e) = original_name {
+    async fn lookup(&mut self, request: ProviderLookupRequest) -> Result<()> {
+        let name = request.name.into_string();
+        if name.is_err() {
             return send_error_response!(
                 request,
-                anyhow!("Could not convert original name into string: {:?}", e),
-                libc::EIO
+                ProviderError::InvalidArgument("invalid name".to_string())
             );
         }
-        let original_name = original_name.unwrap();
-        let new_parent = self.get_correct_id(request.new_parent.clone());
-        let new_name = request.new_name.into_string();
-        if let Err(e) = new_name {
-            return send_error_response!(
-                request,
-                anyhow!("Could not convert new name into string: {:?}", e),
-                libc::EIO
-            );
+        let name = name.unwrap();
+        let parent_id = self.get_correct_id(request.parent);
+        debug!("looking up {} under id {}", name, parent_id);
+        if let Err(e) = self.load_revisions_if_outdated(&parent_id).await {
+            return send_error_response!(request, e);
         }
-        let new_name = new_name.unwrap();
 
-        let rename_result = self
-            .rename_inner(&original_parent, &original_name, &new_parent, &new_name)
-            .await;
-        if let Err((msg, code)) = rename_result {
-            return send_error_response!(request, anyhow!("{}", msg), code);
+        let result = self
+            .find_first_child_by_name(&name, &parent_id)
+            .filter(|entry| self.is_visible(entry));
+
+        if let Some(result) = result {
+            let result = self.create_file_metadata_from_entry(result);
+            let response = ProviderResponse::Lookup(Some(result));
+            return send_response!(request, response);
+        }
+        if let Some(folder_id) = self.add_revisions_folder(&name, &parent_id) {
+            let result = self.create_file_metadata_from_entry(&self.entries[&folder_id]);
+            return send_response!(request, ProviderResponse::Lookup(Some(result)));
         }
 
-        send_response!(request, ProviderResponse::Rename)
+        debug!("could not find file: {} in {}", name, parent_id);
+        let response = ProviderResponse::Lookup(None);
+        return send_response!(request, response);
     }
 
-    #[instrument(skip(self))]
-    async fn rename_inner(
-        &mut self,
-        original_parent: &DriveId,
-        original_name: &String,
-        new_parent: &DriveId,
-        new_name: &String,
-    ) -> StdResult<(), (String, c_int)> {
-        let file_entry = self.find_first_child_by_name(&original_name, &original_parent);
-        if file_entry.is_none() {
-            return Err((format!("Could not find rename source"), libc::ENOENT));
+    //endregion
+    //region revisions
+    /// adds the folder with the revisions of the file the name belongs to.
+    ///
+    /// It is not a child of the folder the file is in, so it does not show up
+    /// when that gets listed, it can only be looked up by its name.
+    fn add_revisions_folder(&mut self, name: &str, parent_id: &DriveId) -> Option<DriveId> {
+        if !self.show_revisions {
+            return None;
         }
-        let file_entry = file_entry.unwrap();
-
-        let file_id = file_entry.get_id();
-        if file_id.is_none() {
-            return Err((format!("Could not get id from entry"), libc::EINVAL));
+        let file =
+            self.find_first_child_by_name(name.strip_suffix(REVISIONS_SUFFIX)?, parent_id)?;
+        if file.attr.kind != FileType::RegularFile || is_native_file(&file.metadata) {
+            return None;
+        }
+        let folder_id = revisions_folder_id(&file.get_id()?);
+        if self.entries.contains_key(&folder_id) {
+            return Some(folder_id);
+        }
+        let metadata = DriveFileMetadata {
+            id: Some(folder_id.to_string()),
+            name: Some(name.to_string()),
+            mime_type: Some(FOLDER_MIME_TYPE.to_string()),
+            created_time: file.metadata.created_time,
+            modified_time: file.metadata.modified_time,
+            ..Default::default()
+        };
+        let mut attr = self.create_file_attr_from_metadata(&metadata).ok()?;
+        attr.perm = 0o555;
+        debug!("adding the revisions folder {}", folder_id);
+        self.entries.insert(
+            folder_id.clone(),
+            FileData {
+                metadata,
+                changed_metadata: Default::default(),
+                perma: false,
+                attr,
+                is_local: false,
+                trashed: false,
+                read_only: true,
+            },
+        );
+        self.parents
+            .insert(folder_id.clone(), vec![parent_id.clone()]);
+        self.invalidate_paths();
+        Some(folder_id)
+    }
+    /// lists the revisions of the file into its revisions folder, at most once per
+    /// cache time. Nothing happens for other folders
+    async fn load_revisions_if_outdated(&mut self, folder_id: &DriveId) -> ProviderResult<()> {
+        let Some(file_id) = file_of_revisions_folder(folder_id) else {
+            return Ok(());
+        };
+        if self.offline
+            || !self.entries.contains_key(folder_id)
+            || !self.is_time_to_refresh_folder(folder_id)
+        {
+            return Ok(());
+        }
+        debug!("listing the revisions of {}", file_id);
+        let result = self.drive.list_revisions(file_id.clone()).await;
+        self.record_health(|health| health.record_drive_call(result.is_ok()));
+        let revisions =
+            result.map_err(|e| ProviderError::remote(e.context("could not list the revisions")))?;
+        self.folder_refreshed_at
+            .insert(folder_id.clone(), SystemTime::now());
+        let (file_name, mime_type) = self
+            .entries
+            .get(&file_id)
+            .map(|e| (e.metadata.name.clone(), e.metadata.mime_type.clone()))
+            .unwrap_or_default();
+        let file_name = file_name.unwrap_or_default();
+        for revision in revisions {
+            let Some(revision_id) = revision.id.as_deref() else {
+                continue;
+            };
+            let id = revision_entry_id(&file_id, revision_id);
+            if self.entries.contains_key(&id) {
+                continue;
+            }
+            let metadata = DriveFileMetadata {
+                id: Some(id.to_string()),
+                name: Some(revision_name(&revision, revision_id, &file_name)),
+                size: revision.size,
+                md5_checksum: revision.md5_checksum.clone(),
+                mime_type: revision.mime_type.clone().or_else(|| mime_type.clone()),
+                created_time: revision.modified_time,
+                modified_time: revision.modified_time,
+                ..Default::default()
+            };
+            let Ok(mut attr) = self.create_file_attr_from_metadata(&metadata) else {
+                continue;
+            };
+            attr.perm = 0o444;
+            self.entries.insert(
+                id.clone(),
+                FileData {
+                    metadata,
+                    changed_metadata: Default::default(),
+                    perma: false,
+                    attr,
+                    is_local: false,
+                    trashed: false,
+                    read_only: true,
+                },
+            );
+            self.add_parent_child_relation(folder_id.clone(), id);
+        }
+        Ok(())
+    }
+    /// revisions are always downloaded as a whole, they can't be read in parts.
+    /// The download runs in the background like the one of a file, see
+    /// [Self::start_download_call]
+    async fn download_revision(&mut self, id: &DriveId, target_path: &Path) -> ProviderResult<()> {
+        let (file_id, revision_id) = parse_revision_entry_id(id)
+            .ok_or_else(|| ProviderError::NotFound(format!("{} is no revision", id)))?;
+        self.check_online()?;
+        if self.running_requests.contains_key(id) {
+            return Err(ProviderError::Conflict(
+                "Id already has a request running".to_string(),
+            ));
+        }
+        debug!("downloading the revision {} of {}", revision_id, file_id);
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.is_local = true;
+        }
+        let drive = self.drive.clone();
+        let target_path = target_path.to_path_buf();
+        let handle: JoinHandle<Result<()>> = tokio::spawn(async move {
+            drive
+                .download_revision(file_id, &revision_id, &target_path)
+                .await
+                .context("could not download the revision")
+        });
+        self.track_transfer(id.clone(), TransferDirection::Download, handle);
+        Ok(())
+    }
+    //endregion
+    //region read dir
+    #[instrument(skip(request))]
+    /// answers with up to `limit` children from `offset` on.
+    ///
+    /// New children get added at the end, so a listing that goes through the
+    /// pages while the folder changes still sees every child that was there
+    /// before it started, as long as none of them gets removed meanwhile.
+    async fn read_dir(&mut self, request: ProviderReadDirRequest) -> Result<()> {
+        let parent_id = self.get_correct_id(request.file_id.clone());
+        debug!(
+            "got read dir request for id: {} with offset: {}",
+            parent_id, request.offset
+        );
+        let is_file = self
+            .entries
+            .get(&parent_id)
+            .is_some_and(|e| e.attr.kind != FileType::Directory);
+        if is_file {
+            return send_error_response!(
+                request,
+                ProviderError::NotDirectory(format!("{} is not a folder", parent_id))
+            );
+        }
+        if file_of_revisions_folder(&parent_id).is_some() {
+            if let Err(e) = self.load_revisions_if_outdated(&parent_id).await {
+                return send_error_response!(request, e);
+            }
+        } else {
+            self.refresh_folder_if_outdated(&parent_id).await;
+        }
+        let folder_parent_id = self
+            .parents
+            .get(&parent_id)
+            .and_then(|parents| parents.first())
+            .cloned();
+        if let Some(children) = self.children.get(&parent_id) {
+            let response = children
+                .iter()
+                .map(|id| (id, self.entries.get(id)))
+                .filter(|(_id, e)| e.is_some_and(|e| self.is_visible(e)))
+                .map(|(id, e)| (id, e.unwrap()))
+                .skip(request.offset as usize)
+                .take(request.limit)
+                .map(|(id, e)| FileMetadata {
+                    id: id.clone(),
+                    name: e
+                        .metadata
+                        .name
+                        .as_ref()
+                        .unwrap_or(&"NO_NAME".to_string())
+                        .clone(),
+                    attr: e.attr.clone(),
+                    ttl: self.get_ttl(id),
+                })
+                .collect::<Vec<FileMetadata>>();
+            debug!("returning {} entries", response.len());
+            let response = ProviderReadDirResponse {
+                entries: response,
+                parent_id: folder_parent_id,
+            };
+            return send_response!(request, ProviderResponse::ReadDir(response));
+        }
+        debug!("found no entries to return");
+        for e in self.entries.iter() {
+            debug!("entry: {}: {:?}", e.0, e.1);
+            debug!("children: {:?}", self.children.get(e.0));
+            debug!("parents: {:?}", self.parents.get(e.0));
+        }
+        return send_response!(
+            request,
+            ProviderResponse::ReadDir(ProviderReadDirResponse {
+                entries: vec![],
+                parent_id: folder_parent_id,
+            })
+        );
+    }
+    fn is_time_to_refresh_folder(&self, folder_id: &DriveId) -> bool {
+        let last_refresh = self
+            .folder_refreshed_at
+            .get(folder_id)
+            .copied()
+            .unwrap_or(UNIX_EPOCH);
+        is_cache_time_over(last_refresh, self.allowed_cache_time)
+    }
+    /// polls the changes and adds the children of the folder that are not known yet,
+    /// at most once per cache time and only if folders should be refreshed on read dir.
+    ///
+    /// returns if the folder was refreshed
+    async fn refresh_folder_if_outdated(&mut self, folder_id: &DriveId) -> bool {
+        if self.offline
+            || !self.refresh_folders_on_read_dir
+            || !self.is_time_to_refresh_folder(folder_id)
+        {
+            return false;
         }
-        let file_id = file_id.unwrap();
+        debug!("refreshing folder {}", folder_id);
+        // a failed refresh counts too, otherwise every read dir would retry it
+        self.folder_refreshed_at
+            .insert(folder_id.clone(), SystemTime::now());
+        self.last_checked_for_changes = UNIX_EPOCH;
+        self.check_and_apply_changes().await;
 
+        let children = self.drive.list_files(folder_id.clone()).await;
+        self.record_health(|health| health.record_drive_call(children.is_ok()));
+        let children = match children {
+            Ok(children) => children,
+            Err(e) => {
+                warn!("could not list the children of {}: {:?}", folder_id, e);
+                return true;
+            }
+        };
+        let mut added_children = false;
+        for child in children {
+            let Some(id) = child.id.as_ref().map(DriveId::from) else {
+                continue;
+            };
+            if self.entries.contains_key(&id) {
+                continue;
+            }
+            self.add_drive_entry_to_entries(child);
+            if self.is_entry_included(&id) {
+                added_children = true;
+            } else {
+                self.remove_entry(&id);
+            }
+        }
+        if added_children {
+            self.notify_changed_id(folder_id.clone());
+        }
+        true
+    }
+    //endregion
+    //region open file
+    #[instrument(skip(request))]
+    async fn open_file(&mut self, request: ProviderOpenFileRequest) -> Result<()> {
+        let file_id = &self.get_correct_id(request.file_id.clone());
         let wait_res = self
             .wait_for_running_drive_request_if_exists(&file_id)
             .await;
         if let Err(e) = wait_res {
-            let msg = e.to_string();
-            error!("{}", msg);
-            return Err((msg, libc::EIO));
+            return send_error_response!(request, ProviderError::from(e));
         }
-
-        if !self.check_id_exists(new_parent) {
-            let msg = format!("Folder does not exist");
-            error!("{}", msg);
-            return Err((msg, libc::ENOENT));
+        if let Err(e) = self.load_entry_if_unknown(file_id).await {
+            return send_error_response!(request, e);
         }
-
-        if self.does_target_name_exist_under_parent(new_parent, new_name) {
-            let msg = format!("Target name is already used");
-            error!("{}", msg);
-            return Err((msg, libc::EADDRINUSE));
+        if let Err(e) = self.check_not_directory(file_id) {
+            return send_error_response!(request, e);
         }
-
-        let entry = self
+        if HandleFlags::from(request.flags).can_write() {
+            if let Err(e) = self.check_writable(file_id) {
+                return send_error_response!(request, e);
+            }
+        }
+        let target_path = self.construct_path(&file_id);
+        if let Err(e) = target_path {
+            return send_error_response!(request, ProviderError::NotFound(e.to_string()));
+        }
+        let target_path = target_path.unwrap();
+        let downloading = !self
             .entries
-            .get_mut(&file_id)
-            .expect("We checked shortly before if the entry exists");
-
-        if original_name != new_name {
-            trace!("Updating name");
-            //check if the filename has been changed and update it in the metadata and on google drive
-            entry.changed_metadata.name = Some(new_name.clone());
+            .get(file_id)
+            .map(|e| e.is_local)
+            .unwrap_or(false);
+        let handle_flags = HandleFlags::from(request.flags);
+        if downloading && !handle_flags.can_write() && self.is_no_cache(file_id) {
+            debug!("{} is not cached, it gets read from the drive", file_id);
+            if let Err(e) = self.check_online() {
+                return send_error_response!(request, e);
+            }
+            let fh = self.create_fh(handle_flags, target_path, false, false);
+            if let Some(file_handle) = self.file_handles.get_mut(&fh) {
+                file_handle.no_cache = true;
+            }
+            // nothing is local that the kernel could keep in sync with
+            let cache_mode = self.open_cache_mode.resolve(true, false);
+            let response = ProviderResponse::OpenFile(fh, handle_flags, cache_mode);
+            return send_response!(request, response);
         }
-        let now = SystemTime::now();
-        entry.attr.atime = now;
-        entry.attr.mtime = now;
-        //check if the path is changed (child-parent relationships) and modify them accordingly
-        if original_parent != new_parent {
-            trace!("Updating child-parent relations");
-            entry.changed_metadata.parents = Some(vec![new_parent.to_string()]);
-            self.remove_parent_child_relation(original_parent.clone(), file_id.clone());
-            self.add_parent_child_relation(new_parent.clone(), file_id.clone());
+        if downloading && self.write_placeholder(file_id, &target_path) {
+            debug!("{} is a native file, its placeholder was written", file_id);
+        } else if downloading && self.link_stored_content(file_id, &target_path) {
+            debug!("the content of {} is cached already", file_id);
+        } else if downloading && parse_revision_entry_id(file_id).is_some() {
+            if let Err(e) = self.download_revision(file_id, &target_path).await {
+                return send_error_response!(request, e);
+            }
+        } else if downloading {
+            if let Err(e) = self.check_online() {
+                return send_error_response!(request, e);
+            }
+            if self.partial_downloads {
+                debug!("file not local, only downloading what gets read");
+                if let Err(e) = self.start_partial_file(file_id, &target_path).await {
+                    return send_error_response!(request, e);
+                }
+            } else {
+                debug!("file not local, downloading...");
+                let drive = self.drive.clone();
+                self.start_download_call(&request, drive, &target_path)
+                    .await?;
+            }
         }
-
-        let upload_result = self.update_remote_metadata(file_id).await;
-        if let Err(e) = upload_result {
-            let msg = format!("Error while uploading Metadata: {:?}", e);
-            error!("{}", msg);
-            return Err((msg, libc::EREMOTEIO));
+        let changed_on_remote = self.changed_on_remote.remove(file_id);
+        let cache_mode = self.open_cache_mode.resolve(downloading, changed_on_remote);
+        let fh = self.create_fh(handle_flags, target_path, false, true);
+        send_response!(
+            request,
+            ProviderResponse::OpenFile(fh, handle_flags, cache_mode)
+        )
+    }
+    /// native files and revisions have no content that could be read in ranges
+    fn is_no_cache(&self, id: &DriveId) -> bool {
+        let Some(entry) = self.entries.get(id) else {
+            return false;
+        };
+        if is_native_file(&entry.metadata) || parse_revision_entry_id(id).is_some() {
+            return false;
+        }
+        self.resolve_path(id)
+            .is_some_and(|path| self.no_cache.is_match(&path))
+    }
+    /// fetches the metadata of a file the provider does not know yet, like one
+    /// whose folder was not listed when it got added on the remote
+    async fn load_entry_if_unknown(&mut self, id: &DriveId) -> ProviderResult<()> {
+        if self.entries.contains_key(id) {
+            return Ok(());
+        }
+        if self.offline {
+            return Err(ProviderError::NotFound(format!(
+                "{} is not known and can't be loaded while offline",
+                id
+            )));
+        }
+        debug!("{} is not known yet, loading its metadata", id);
+        let result = self.drive.get_metadata_for_file(id.clone()).await;
+        self.record_health(|health| health.record_drive_call(result.is_ok()));
+        let metadata = result.map_err(|e| {
+            error!("could not load the metadata of {}: {:?}", id, e);
+            ProviderError::remote(e.context("could not load the metadata"))
+        })?;
+        self.add_drive_entry_to_entries(metadata);
+        if !self.entries.contains_key(id) {
+            return Err(ProviderError::NotFound(format!(
+                "could not add an entry for {}",
+                id
+            )));
         }
-
         Ok(())
     }
-
+    /// writes the content of the placeholder, if the entry is a native file
+    fn write_placeholder(&mut self, id: &DriveId, target_path: &Path) -> bool {
+        let Some(entry) = self.entries.get_mut(id) else {
+            return false;
+        };
+        if !is_native_file(&entry.metadata) {
+            return false;
+        }
+        let content = placeholder_content(&entry.metadata);
+        let written = target_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(target_path, content));
+        match written {
+            Ok(()) => {
+                entry.is_local = true;
+                true
+            }
+            Err(e) => {
+                warn!("could not write the placeholder of {}: {:?}", id, e);
+                false
+            }
+        }
+    }
+    /// links the cache file to a stored copy with the same content, if there is one
+    fn link_stored_content(&mut self, id: &DriveId, target_path: &Path) -> bool {
+        let (Some(content_store), Some(entry)) = (&self.content_store, self.entries.get_mut(id))
+        else {
+            return false;
+        };
+        let Some(md5) = entry
+            .metadata
+            .md5_checksum
+            .as_deref()
+            .filter(|_| !entry.perma)
+        else {
+            return false;
+        };
+        match content_store.link(md5, target_path) {
+            Ok(linked) => {
+                entry.is_local = linked;
+                linked
+            }
+            Err(e) => {
+                warn!("could not link {} to the stored content: {:?}", id, e);
+                false
+            }
+        }
+    }
     //endregion
-    //region write content
+    //region flush
+    /// uploads the changes of the file, so they are on the drive once `close` returns.
+    /// Files with an upload window only get their upload scheduled, see [UploadWindows].
+    ///
+    /// The kernel flushes on every close of a descriptor, also of dup'd ones that
+    /// share the handle. Only the first flush after a write uploads something.
     #[instrument(skip(request))]
-    async fn write_content(&mut self, request: ProviderWriteContentRequest) -> Result<()> {
-        let file_id = &self.get_correct_id(request.file_id.clone());
-        let wait_res = self.wait_for_running_drive_request_if_exists(file_id).await;
+    async fn flush(&mut self, request: ProviderFlushRequest) -> Result<()> {
+        let file_id = self.get_correct_id(request.file_id.clone());
+        let wait_res = self
+            .wait_for_running_drive_request_if_exists(&file_id)
+            .await;
         if let Err(e) = wait_res {
-            return send_error_response!(request, e, libc::EIO);
+            return send_error_response!(request, ProviderError::from(e));
         }
-
-        let size_written = self
-            .write_content_from_file(file_id.clone(), &request)
-            .await;
-        if let Err(e) = size_written {
-            return send_error_response!(request, e, libc::EIO);
+        let Some(file_handle) = self.file_handles.get(&request.fh) else {
+            return send_error_response!(request, ProviderError::BadFileHandle(request.fh));
+        };
+        let path = file_handle.path.clone();
+        if !self.is_dirty(&path) {
+            trace!("nothing to flush for fh {}", request.fh);
+            return send_response!(request, ProviderResponse::Flush);
         }
-        let size_written = size_written.unwrap();
-        return send_response!(request, ProviderResponse::WriteSize(size_written));
-    }
-    //endregion
-
-    //endregion
-    //region request helpers
-
-    fn does_target_name_exist_under_parent(&self, new_parent: &DriveId, new_name: &String) -> bool {
-        let new_file_entry = self.find_first_child_by_name(&new_name, &new_parent);
-        return new_file_entry.is_some();
+        // the upload takes the whole file, so it covers the writes of every handle
+        self.set_dirty(&path, false);
+        if !self.upload_window(&file_id).is_zero() {
+            if let Err(e) = self.schedule_upload(&file_id).await {
+                return send_error_response!(request, ProviderError::from(e));
+            }
+            return send_response!(request, ProviderResponse::Flush);
+        }
+        if let Err(e) = self.upload_and_wait(file_id).await {
+            error!("could not upload on flush: {:?}", e);
+            // the release tries again
+            self.set_dirty(&path, true);
+            let e = ProviderError::remote(e);
+            if matches!(e, ProviderError::QuotaExceeded) {
+                // so statfs reports the full storage like the failed write does
+                self.quota = None;
+            }
+            return send_error_response!(request, e);
+        }
+        send_response!(request, ProviderResponse::Flush)
     }
-    fn check_id_exists(&self, id: &DriveId) -> bool {
-        self.entries.contains_key(id)
+    fn is_dirty(&self, path: &PathBuf) -> bool {
+        self.file_handles
+            .values()
+            .any(|handle| &handle.path == path && handle.has_content_changed)
     }
-
-    /// returns the first entry it finds with the specified name that is a child of the parent_id
-    ///
-    /// returns ```Option::None``` if none match/the parent does not have any children  
-    fn find_first_child_by_name(&self, name: &String, parent_id: &DriveId) -> Option<&FileData> {
-        let mut result = None;
-        let children = self.children.get(&parent_id);
-        for child in children.unwrap_or(&vec![]) {
-            if let Some(child) = self.entries.get(child) {
-                if child
-                    .metadata
-                    .name
-                    .as_ref()
-                    .unwrap_or(&"$'\\NO_NAME".to_string())
-                    .eq_ignore_ascii_case(&name)
-                {
-                    result = Some(child);
-                    break;
-                }
+    fn set_dirty(&mut self, path: &PathBuf, dirty: bool) {
+        for handle in self.file_handles.values_mut() {
+            if &handle.path == path {
+                handle.has_content_changed = dirty;
             }
         }
-        result
     }
-
-    /// gets the file-handle and opens the file if it is marked for open.
-    ///
-    /// If it is not marked for open but the file is None this returns an error
-    #[instrument]
-    async fn get_and_open_file_handle(&mut self, fh: u64) -> Result<&mut FileHandleData> {
-        let file_handle = self.file_handles.get_mut(&fh);
+    //endregion
+    //region release file
+    /// only uploads if something was written since the last flush, which is
+    /// rare since the kernel flushes before it releases
+    #[instrument(skip(request))]
+    async fn release_file(&mut self, request: ProviderReleaseFileRequest) -> Result<()> {
+        let file_id = &self.get_correct_id(request.file_id.clone());
+        let wait_res = self
+            .wait_for_running_drive_request_if_exists(&file_id)
+            .await;
+        if let Err(e) = wait_res {
+            return send_error_response!(request, ProviderError::from(e));
+        }
+        // let entry = self.entries.get(file_id).context("could not get entry");
+        // if let Err(e) = entry {
+        //     return send_error_response!(request, e, libc::EIO);
+        // }
+        // let entry = entry.unwrap();
+        let file_handle = self.file_handles.remove(&request.fh);
         if file_handle.is_none() {
-            error!("Failed to find file_handle for fh: {}", fh);
-            return Err(anyhow!("Failed to find file_handle for fh: {}", fh));
+            return send_error_response!(request, ProviderError::BadFileHandle(request.fh));
         }
         let file_handle = file_handle.unwrap();
-        if file_handle.file.is_none() {
-            debug!("file is none, opening...");
-            let flags = file_handle.flags;
-            let opened_file = OpenOptions::new()
-                .write(flags.can_write())
-                .read(flags.can_read())
-                .open(&file_handle.path)
-                .await;
-            if let Err(e) = &opened_file {
-                let e = anyhow!("error opening the file{}", e);
-                error!("{}", e);
-                return Err(e);
+        if file_handle.has_content_changed {
+            debug!("uploading changes to google drive for file: {}", file_id);
+            let start_result = self.schedule_upload(file_id).await;
+            if let Err(e) = start_result {
+                error!("got error from starting the upload: {:?}", e);
+                return send_error_response!(request, ProviderError::from(e));
             }
-            let opened_file = opened_file.unwrap();
-            file_handle.file = Some(opened_file);
-            file_handle.marked_for_open = false;
-            // } else {
-            //     error!("File handle does not have a file");
-            //     return Err(anyhow!("File handle does not have a file"));
         }
-        Ok(file_handle)
+        return send_response!(request, ProviderResponse::ReleaseFile);
     }
-
-    async fn write_content_from_file(
+    //endregion
+    //region metadata
+    #[instrument(skip(request))]
+    async fn metadata(&self, request: ProviderMetadataRequest) -> Result<()> {
+        let file_id = &self.get_correct_id(request.file_id.clone());
+        debug!("metadata got called");
+        let entry = self.entries.get(file_id);
+        if entry.is_none() {
+            return send_error_response!(
+                request,
+                ProviderError::NotFound(format!("could not find entry with id {}", file_id))
+            );
+        }
+        let entry = entry.unwrap();
+        let response = ProviderResponse::Metadata(self.create_file_metadata_from_entry(entry));
+
+        send_response!(request, response)
+    }
+
+    //endregion
+    //region root id
+    #[instrument(skip(request))]
+    async fn root_id(&self, request: ProviderRootIdRequest) -> Result<()> {
+        let root_id = self.get_correct_id(DriveId::root());
+        send_response!(request, ProviderResponse::RootId(root_id))
+    }
+    //endregion
+    //region status
+    #[instrument(skip(request))]
+    async fn status(&mut self, request: ProviderStatusRequest) -> Result<()> {
+        let uploads: Option<Vec<_>> = self.upload_progress.lock().ok().map(|upload_progress| {
+            upload_progress
+                .iter()
+                .map(|(id, progress)| (id.clone(), *progress))
+                .collect()
+        });
+        let Some(uploads) = uploads else {
+            return send_error_response!(
+                request,
+                ProviderError::Other(anyhow!("the upload progress lock is poisoned"))
+            );
+        };
+        let health = self
+            .health
+            .lock()
+            .map(|health| *health)
+            .unwrap_or_else(|e| *e.into_inner());
+        let quota = match self.get_quota().await {
+            Ok(quota) => Some(quota),
+            Err(e) => {
+                warn!("could not get the quota for the status: {:?}", e);
+                None
+            }
+        };
+        let response = ProviderResponse::Status(ProviderStatus {
+            uploads,
+            health,
+            quota,
+            stats: self.stats(),
+            prefetch: self.prefetches.progress,
+        });
+        send_response!(request, response)
+    }
+    pub fn stats(&self) -> ProviderStats {
+        let transferred = self.drive.transferred_bytes();
+        ProviderStats {
+            entries: self.entries.len(),
+            children: self.children.len(),
+            parents: self.parents.len(),
+            file_handles: self.file_handles.len(),
+            running_requests: self.running_requests.len(),
+            downloaded_bytes: transferred.downloaded,
+            uploaded_bytes: transferred.uploaded,
+        }
+    }
+    //endregion
+    //region quota
+    /// returns the cached quota and only fetches it from the drive when it is outdated.
+    ///
+    /// If the fetch fails the outdated quota is returned, if there is one.
+    pub(crate) async fn get_quota(&mut self) -> Result<CachedQuota> {
+        if let Some(quota) = self.quota {
+            if !quota.is_outdated(self.quota_cache_time) || self.offline {
+                return Ok(quota);
+            }
+        }
+        self.check_online()?;
+        let result = self.drive.get_storage_quota().await;
+        self.record_health(|health| health.record_drive_call(result.is_ok()));
+        match result {
+            Ok(quota) => {
+                let quota = CachedQuota::from_storage_quota(&quota, SystemTime::now());
+                self.quota = Some(quota);
+                Ok(quota)
+            }
+            Err(e) => match self.quota {
+                Some(quota) => {
+                    warn!(
+                        "could not refresh the quota, using the outdated one: {:?}",
+                        e
+                    );
+                    Ok(quota)
+                }
+                None => Err(e.context("could not get the quota")),
+            },
+        }
+    }
+    //endregion
+    //region set_attr
+    async fn set_attr(&mut self, request: ProviderSetAttrRequest) -> Result<()> {
+        let file_id = &self.get_correct_id(request.file_id.clone());
+        let _lock = match self.lock_for_change(file_id).await {
+            Ok(lock) => lock,
+            Err(e) => return send_error_response!(request, e),
+        };
+        debug!("set_attr got called");
+        if let Err(e) = self.check_writable(file_id) {
+            return send_error_response!(request, e);
+        }
+        let entry = self.entries.get(file_id);
+        if entry.is_none() {
+            return send_error_response!(
+                request,
+                ProviderError::NotFound(format!("could not find entry with id {}", file_id))
+            );
+        }
+        let entry = entry.unwrap();
+        let mut attr = entry.attr.clone();
+
+        if let Some(size) = request.size {
+            let x = self
+                .set_underlying_file_size(&file_id, request.fh, size)
+                .await;
+            if let Err(e) = x {
+                error!(
+                    "got an error while setting the underlying file size: {:?}",
+                    e
+                );
+                return send_error_response!(request, ProviderError::from(e));
+            }
+            attr.size = size;
+        }
+        if let Some(flags) = request.flags {
+            attr.flags = flags;
+        }
+        if let Some(atime) = request.atime {
+            attr.atime = atime;
+        }
+        if let Some(mtime) = request.mtime {
+            attr.mtime = mtime;
+        }
+        if let Some(mode) = request.mode {
+            //TODO2: check if setting attr.perm to mode in setattr is correct (probably)
+            // and if i can just cast it to u16 (from u32) (i have no Idea)
+            attr.perm = mode as u16;
+            // TODO3: check if the file below even needs me to set the permissions
+            //  on the underlying file or if this is not needed at all since
+            //  permissions don't get transferred to gdrive and locally i
+            //  have the info in the entries
+
+            // if let Some(fh) = request.fh {
+            //     let handle = self.file_handles.get_mut(&fh);
+            //     if let Some(handle) = handle {
+            //         if let Some(file) = &mut handle.file {
+            //             let perms = Permissions::from_mode(mode);
+            //             let x = file.set_permissions(perms).await;
+            //             if x.is_err() {
+            //                 warn!("got an error result while setting len of file: {:?}", x);
+            //             }
+            //         }
+            //     }
+            // }
+        }
+        // if let Some(fh) = request.fh {
+        //     //TODO2: implement something for fh in setattr (if needed)
+        //     warn!(
+        //         "fh was set in setattr but I don't know what to do with this: {:?}",
+        //         request
+        //     );
+        // }
+        if let Some(gid) = request.gid {
+            //TODO2: implement something for gid in setattr
+            warn!(
+                "gid was set in setattr but I don't know what to do with this: {:?}",
+                request
+            );
+        }
+        if let Some(uid) = request.uid {
+            //TODO2: implement something for uid in setattr
+            warn!(
+                "uid was set in setattr but I don't know what to do with this: {:?}",
+                request
+            );
+        }
+
+        let entry = self
+            .entries
+            .get_mut(file_id)
+            .expect("got it in here before");
+        entry.attr = attr;
+        let entry = &self.entries[file_id];
+
+        let response = ProviderResponse::SetAttr(self.create_file_metadata_from_entry(entry));
+
+        send_response!(request, response)
+    }
+
+    async fn set_underlying_file_size(
         &mut self,
-        file_id: DriveId,
-        request: &ProviderWriteContentRequest,
-    ) -> Result<u32> {
-        let file_handle = self.get_and_open_file_handle(request.fh).await?;
-        let file = file_handle.file.as_mut().unwrap();
-        if !file_handle.flags.can_write() {
-            error!("File handle does not have read permissions");
-            return Err(anyhow!("File handle does not have read permissions"));
+        file_id: &&DriveId,
+        fh: Option<u64>,
+        size: u64,
+    ) -> Result<()> {
+        set_local_md5(&self.local_md5s, file_id, None);
+        let mut was_applied = false;
+        if let Some(fh) = fh {
+            let handle = self.file_handles.get_mut(&fh);
+            if let Some(handle) = handle {
+                // the new size has to be uploaded on release, even if nothing gets written after
+                handle.has_content_changed = true;
+                if let Some(file) = &mut handle.file {
+                    let x = file
+                        .set_len(size)
+                        .await
+                        .context("could not set the len of the file");
+                    if x.is_err() {
+                        warn!("got an error result while setting len of file: {:?}", x);
+                    } else {
+                        was_applied = true;
+                    }
+                }
+            }
+        }
+        if !was_applied {
+            let is_local = self
+                .entries
+                .get(*file_id)
+                .map(|e| e.is_local)
+                .unwrap_or(false);
+            let target_path = self.construct_path(&file_id)?;
+            if is_local && self.content_store.is_some() {
+                unshare(&target_path)?;
+                self.reopen_file_handles_for_path(&target_path);
+            }
+            if !is_local && size > 0 {
+                self.check_online()?;
+                // the content up to the new size has to be there before it can be resized
+                debug!("file is not local, downloading before resizing it");
+                self.drive
+                    .download_file((*file_id).clone(), &target_path)
+                    .await
+                    .context("could not download the file to set the size")?;
+            }
+            // set_len zero-fills when growing, like a truncate to a larger size should
+            OpenOptions::new()
+                .write(true)
+                .create(!is_local)
+                .open(target_path)
+                .await
+                .context("could not open file to set the size")?
+                .set_len(size)
+                .await
+                .context("could not set the size of the file")?;
+            if let Some(entry) = self.entries.get_mut(*file_id) {
+                entry.is_local = true;
+            }
+        }
+        let path = self.construct_path(file_id)?;
+        self.clear_read_ahead_for_path(&path);
+        if let Some(block_map) = self.partial_files.get_mut(*file_id) {
+            block_map.truncate(size);
+            self.save_block_map(file_id, &path)?;
+        }
+        Ok(())
+    }
+    //endregion
+    //region read content
+    #[instrument(skip(request))]
+    /// answers reads that the read ahead buffer can serve right away, all
+    /// others read the cache file in their own task.
+    ///
+    /// Reading a big file from a slow disk would hold up every other request
+    /// otherwise. Metadata and lookups are answered from memory, so they don't
+    /// need this. Everything that changes a file waits for its reads first,
+    /// see [FileLocks].
+    async fn read_content(&mut self, request: ProviderReadContentRequest) -> Result<()> {
+        let file_id = &self.get_correct_id(request.file_id.clone());
+        let wait_res = self.wait_for_drive_request_if_exists(&file_id).await;
+        if let Err(e) = wait_res {
+            return send_error_response!(request, ProviderError::from(e));
+        }
+        if let Err(e) = self.check_not_directory(file_id) {
+            return send_error_response!(request, e);
+        }
+        if self.reads_without_cache(file_id, request.fh) {
+            return self.read_without_cache(file_id, request).await;
+        }
+        // the read ahead reads more than asked for, that has to be there as well
+        let read_len = request.size.max(self.read_ahead_size) as u64;
+        let fetched = self
+            .fetch_missing_blocks(file_id, request.offset, read_len)
+            .await;
+        if let Err(e) = fetched {
+            return send_error_response!(request, e);
+        }
+
+        let read = match self.prepare_read(&request).await {
+            Ok(PreparedRead::Buffered(data)) => {
+                return send_response!(request, ProviderResponse::ReadContent(data));
+            }
+            Ok(PreparedRead::File(read)) => read,
+            Err(e) => return send_error_response!(request, e),
+        };
+        let read_permits = self.read_permits.clone();
+        // taken before the task starts, so a change that comes after this read
+        // can't get in before it
+        let guard = self.file_locks.get(file_id).read_owned().await;
+        tokio::spawn(async move {
+            let _permit = read_permits.acquire_owned().await;
+            let _guard = guard;
+            match read.run().await {
+                Ok(data) => send_response!(request, ProviderResponse::ReadContent(data)),
+                Err(e) => send_error_response!(request, e),
+            }
+        });
+        Ok(())
+    }
+    /// once the file got cached by another handle, the cache is read instead
+    fn reads_without_cache(&self, file_id: &DriveId, fh: u64) -> bool {
+        self.file_handles.get(&fh).is_some_and(|h| h.no_cache)
+            && !self.entries.get(file_id).is_some_and(|e| e.is_local)
+    }
+    /// downloads the range of the read into memory in its own task, sequential
+    /// reads download the read ahead size and keep the rest in the read ahead
+    /// of the handle
+    async fn read_without_cache(
+        &mut self,
+        file_id: &DriveId,
+        request: ProviderReadContentRequest,
+    ) -> Result<()> {
+        let read_ahead = match self.file_handles.get(&request.fh) {
+            Some(file_handle) => file_handle.read_ahead.clone(),
+            None => return send_error_response!(request, ProviderError::BadFileHandle(request.fh)),
+        };
+        let (buffered, sequential) = {
+            let mut read_ahead = lock_read_ahead(&read_ahead);
+            let buffered = read_ahead
+                .get(request.offset, request.size)
+                .map(|data| data.to_vec());
+            if let Some(data) = &buffered {
+                read_ahead.record_read(request.offset, data.len());
+            }
+            (buffered, read_ahead.is_sequential(request.offset))
+        };
+        if let Some(data) = buffered {
+            return send_response!(request, ProviderResponse::ReadContent(data));
+        }
+        let read_size = match sequential {
+            true => request.size.max(self.read_ahead_size),
+            false => request.size,
+        };
+        let size = self.entries.get(file_id).map_or(0, |e| e.attr.size);
+        let length = (read_size as u64).min(size.saturating_sub(request.offset));
+        if length > 0 {
+            if let Err(e) = self.check_online() {
+                return send_error_response!(request, e);
+            }
+            debug!(
+                "reading {} bytes of {} at {} from the drive",
+                length, file_id, request.offset
+            );
+        }
+        let drive = self.drive.clone();
+        let health = self.health.clone();
+        let read_permits = self.read_permits.clone();
+        let file_id = file_id.clone();
+        tokio::spawn(async move {
+            let _permit = read_permits.acquire_owned().await;
+            let data = if length == 0 {
+                Ok(Vec::new())
+            } else {
+                let result = drive.download_range(file_id, request.offset, length).await;
+                record_health(&health, |health| health.record_drive_call(result.is_ok()));
+                result.map_err(ProviderError::remote)
+            };
+            match data {
+                Ok(data) => {
+                    let data = lock_read_ahead(&read_ahead).serve(
+                        request.offset,
+                        request.size,
+                        data,
+                        read_size,
+                    );
+                    send_response!(request, ProviderResponse::ReadContent(data))
+                }
+                Err(e) => send_error_response!(request, e),
+            }
+        });
+        Ok(())
+    }
+    //endregion
+    //region lseek
+    /// answers `SEEK_DATA` and `SEEK_HOLE` from the cache file, see [seek_data_or_hole]
+    #[instrument(skip(request))]
+    async fn lseek(&mut self, request: ProviderLseekRequest) -> Result<()> {
+        let file_id = self.get_correct_id(request.file_id.clone());
+        if let Err(e) = self.wait_for_drive_request_if_exists(&file_id).await {
+            return send_error_response!(request, ProviderError::from(e));
+        }
+        match self.seek_data_or_hole(&file_id, &request) {
+            Ok(Some(offset)) => send_response!(request, ProviderResponse::Lseek(offset)),
+            Ok(None) => send_error_response!(
+                request,
+                anyhow!("there is no data after {}", request.offset),
+                libc::ENXIO
+            ),
+            Err(e) => send_error_response!(request, e),
+        }
+    }
+    fn seek_data_or_hole(
+        &self,
+        file_id: &DriveId,
+        request: &ProviderLseekRequest,
+    ) -> ProviderResult<Option<u64>> {
+        if request.whence != libc::SEEK_DATA && request.whence != libc::SEEK_HOLE {
+            return Err(ProviderError::InvalidArgument(format!(
+                "can not seek with whence {}",
+                request.whence
+            )));
+        }
+        self.check_not_directory(file_id)?;
+        let entry = self
+            .entries
+            .get(file_id)
+            .ok_or_else(|| ProviderError::NotFound(format!("{} is not known", file_id)))?;
+        let size = entry.attr.size;
+        let file_handle = self
+            .file_handles
+            .get(&request.fh)
+            .ok_or(ProviderError::BadFileHandle(request.fh))?;
+        if !entry.is_local {
+            // nothing is cached, all of the content is data
+            let found = match request.whence {
+                libc::SEEK_DATA => request.offset,
+                _ => size,
+            };
+            return Ok((request.offset < size).then_some(found));
+        }
+        // its own descriptor, the offset of the one of the handle stays as it is
+        let file = std::fs::File::open(&file_handle.path)?;
+        let block_map = self.partial_files.get(file_id);
+        Ok(seek_data_or_hole(
+            &file,
+            size,
+            block_map,
+            request.offset,
+            request.whence,
+        )?)
+    }
+    //endregion
+    //region xattr
+    async fn get_xattr(&mut self, request: ProviderGetXattrRequest) -> Result<()> {
+        let file_id = self.get_correct_id(request.file_id.clone());
+        match self.xattr_value(&file_id, &request.name) {
+            Ok(Some(value)) => send_response!(request, ProviderResponse::GetXattr(value)),
+            Ok(None) => send_error_response!(
+                request,
+                anyhow!("{} has no attribute {}", file_id, request.name),
+                libc::ENODATA
+            ),
+            Err(e) => send_error_response!(request, e),
+        }
+    }
+    /// `None` if the entry does not have the attribute
+    fn xattr_value(&self, file_id: &DriveId, name: &str) -> ProviderResult<Option<Vec<u8>>> {
+        if name == PINNED_XATTR && self.entries.contains_key(file_id) {
+            return Ok(Some(flag_value(self.pinned.contains(file_id))));
+        }
+        let entry = self
+            .entries
+            .get(file_id)
+            .ok_or_else(|| ProviderError::NotFound(format!("{} is not known", file_id)))?;
+        Ok(match name {
+            STARRED_XATTR => {
+                // a change that was not uploaded yet is what the user expects to see
+                let starred = entry
+                    .changed_metadata
+                    .starred
+                    .or(entry.metadata.starred)
+                    .unwrap_or(false);
+                Some(flag_value(starred))
+            }
+            _ => None,
+        })
+    }
+    async fn set_xattr(&mut self, request: ProviderSetXattrRequest) -> Result<()> {
+        let file_id = self.get_correct_id(request.file_id.clone());
+        if !DRIVE_XATTRS.contains(&request.name.as_str()) {
+            return send_error_response!(
+                request,
+                anyhow!("the attribute {} can't be set", request.name),
+                libc::ENOTSUP
+            );
+        }
+        match self
+            .set_xattr_inner(&file_id, &request.name, &request.value)
+            .await
+        {
+            Ok(()) => send_response!(request, ProviderResponse::SetXattr),
+            Err(e) => send_error_response!(request, e),
+        }
+    }
+    async fn set_xattr_inner(
+        &mut self,
+        file_id: &DriveId,
+        name: &str,
+        value: &[u8],
+    ) -> ProviderResult<()> {
+        let flag = parse_flag(value).ok_or_else(|| {
+            ProviderError::InvalidArgument(format!("{} can only be set to 1 or 0", name))
+        })?;
+        match (name, flag) {
+            (PINNED_XATTR, true) => self.pin(file_id).await,
+            (PINNED_XATTR, false) => self.unpin(file_id).await,
+            (_, starred) => self.set_starred(file_id, starred).await,
+        }
+    }
+    /// stages the change in the changed metadata and uploads it right away,
+    /// while offline it goes along with the next upload of the file
+    async fn set_starred(&mut self, file_id: &DriveId, starred: bool) -> ProviderResult<()> {
+        self.check_writable(file_id)?;
+        let entry = self
+            .entries
+            .get_mut(file_id)
+            .ok_or_else(|| ProviderError::NotFound(format!("{} is not known", file_id)))?;
+        entry.changed_metadata.starred = Some(starred);
+        if self.offline {
+            debug!("{} gets starred={} with its next upload", file_id, starred);
+            return Ok(());
+        }
+        let _lock = self.lock_for_change(file_id).await?;
+        self.update_remote_metadata(file_id.clone())
+            .await
+            .map_err(|e| ProviderError::remote(e.context("could not update the starred flag")))
+    }
+    async fn list_xattr(&mut self, request: ProviderListXattrRequest) -> Result<()> {
+        let file_id = self.get_correct_id(request.file_id.clone());
+        if !self.entries.contains_key(&file_id) {
+            return send_error_response!(
+                request,
+                ProviderError::NotFound(format!("{} is not known", file_id))
+            );
+        }
+        let names = DRIVE_XATTRS.iter().map(|name| name.to_string()).collect();
+        send_response!(request, ProviderResponse::ListXattr(names))
+    }
+    //endregion
+    //region rename
+
+    #[instrument(skip(request))]
+    async fn rename(&mut self, request: ProviderRenameRequest) -> Result<()> {
+        let original_parent = self.get_correct_id(request.original_parent.clone());
+        let original_name = request.original_name.into_string();
+        if let Err(e) = original_name {
+            return send_error_response!(
+                request,
+                ProviderError::InvalidArgument(format!(
+                    "Could not convert original name into string: {:?}",
+                    e
+                ))
+            );
+        }
+        let original_name = original_name.unwrap();
+        let new_parent = self.get_correct_id(request.new_parent.clone());
+        let new_name = request.new_name.into_string();
+        if let Err(e) = new_name {
+            return send_error_response!(
+                request,
+                ProviderError::InvalidArgument(format!(
+                    "Could not convert new name into string: {:?}",
+                    e
+                ))
+            );
+        }
+        let new_name = new_name.unwrap();
+
+        let rename_result = self
+            .rename_inner(
+                &original_parent,
+                &original_name,
+                &new_parent,
+                &new_name,
+                request.flags,
+            )
+            .await;
+        if let Err(e) = rename_result {
+            return send_error_response!(request, e);
+        }
+
+        send_response!(request, ProviderResponse::Rename)
+    }
+
+    /// moves the entry to the new name and parent, the flags are the ones of `renameat2`.
+    ///
+    /// The target is never replaced, so `RENAME_NOREPLACE` is what every rename
+    /// does. `RENAME_EXCHANGE` swaps the two entries instead.
+    #[instrument(skip(self))]
+    async fn rename_inner(
+        &mut self,
+        original_parent: &DriveId,
+        original_name: &String,
+        new_parent: &DriveId,
+        new_name: &String,
+        flags: u32,
+    ) -> ProviderResult<()> {
+        if flags & libc::RENAME_WHITEOUT != 0 {
+            return Err(ProviderError::InvalidArgument(
+                "whiteouts are not supported".to_string(),
+            ));
+        }
+        let exchange = flags & libc::RENAME_EXCHANGE != 0;
+        let known_flags = libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE;
+        if flags & !known_flags != 0 || flags == known_flags {
+            return Err(ProviderError::InvalidArgument(format!(
+                "invalid rename flags: {:#x}",
+                flags
+            )));
+        }
+        self.check_writable(original_parent)?;
+        self.check_writable(new_parent)?;
+        self.check_online()?;
+        let file_id = self.find_rename_entry(original_parent, original_name, "source")?;
+        // a content upload of the file that is still running would reach the
+        // drive after the metadata and could undo the rename
+        let _file_lock = self.lock_for_change(&file_id).await?;
+
+        if !self.check_id_exists(new_parent) {
+            let msg = format!("Folder does not exist");
+            error!("{}", msg);
+            return Err(ProviderError::NotFound(msg));
+        }
+
+        if exchange {
+            let target_id = self.find_rename_entry(new_parent, new_name, "target")?;
+            let _target_lock = self.lock_for_change(&target_id).await?;
+            self.move_entry(
+                &file_id,
+                original_parent,
+                original_name,
+                new_parent,
+                new_name,
+            );
+            self.move_entry(
+                &target_id,
+                new_parent,
+                new_name,
+                original_parent,
+                original_name,
+            );
+            self.upload_renamed_metadata(file_id).await?;
+            return self.upload_renamed_metadata(target_id).await;
+        }
+
+        if self.does_target_name_exist_under_parent(new_parent, new_name) {
+            let msg = format!("Target name is already used");
+            error!("{}", msg);
+            return Err(ProviderError::AlreadyExists(msg));
+        }
+
+        self.move_entry(
+            &file_id,
+            original_parent,
+            original_name,
+            new_parent,
+            new_name,
+        );
+        self.upload_renamed_metadata(file_id).await
+    }
+
+    fn find_rename_entry(
+        &self,
+        parent: &DriveId,
+        name: &str,
+        what: &str,
+    ) -> ProviderResult<DriveId> {
+        let file_entry = self.find_first_child_by_name(name, parent);
+        let Some(file_entry) = file_entry else {
+            return Err(ProviderError::NotFound(format!(
+                "Could not find rename {}",
+                what
+            )));
+        };
+        file_entry.get_id().ok_or_else(|| {
+            ProviderError::InvalidArgument("Could not get id from entry".to_string())
+        })
+    }
+
+    /// changes the name and parent locally, the changes are uploaded with
+    /// [DriveFileProvider::upload_renamed_metadata]
+    fn move_entry(
+        &mut self,
+        file_id: &DriveId,
+        original_parent: &DriveId,
+        original_name: &String,
+        new_parent: &DriveId,
+        new_name: &String,
+    ) {
+        let entry = self
+            .entries
+            .get_mut(file_id)
+            .expect("We checked shortly before if the entry exists");
+
+        if original_name != new_name {
+            trace!("Updating name");
+            //check if the filename has been changed and update it in the metadata and on google drive
+            // the placeholders of native files have an extension the drive doesn't know
+            let new_name = match is_native_file(&entry.metadata) {
+                true => name_without_placeholder_extension(new_name, &entry.metadata),
+                false => new_name.as_str(),
+            };
+            entry.changed_metadata.name = Some(new_name.to_string());
+        }
+        let now = SystemTime::now();
+        entry.attr.atime = now;
+        entry.attr.mtime = now;
+        //check if the path is changed (child-parent relationships) and modify them accordingly
+        if original_parent != new_parent {
+            trace!("Updating child-parent relations");
+            let parents = entry.metadata.parents.clone().unwrap_or_default();
+            entry.changed_metadata.parents =
+                Some(move_to_parent(&parents, original_parent, new_parent));
+            let is_already_child = self
+                .parents
+                .get(file_id)
+                .is_some_and(|parents| parents.contains(new_parent));
+            self.remove_parent_child_relation(original_parent.clone(), file_id.clone());
+            if !is_already_child {
+                self.add_parent_child_relation(new_parent.clone(), file_id.clone());
+            }
+        }
+    }
+
+    async fn upload_renamed_metadata(&mut self, file_id: DriveId) -> ProviderResult<()> {
+        let upload_result = self.update_remote_metadata(file_id).await;
+        if let Err(e) = upload_result {
+            error!("Error while uploading Metadata: {:?}", e);
+            return Err(ProviderError::remote(
+                e.context("Error while uploading Metadata"),
+            ));
+        }
+        Ok(())
+    }
+
+    //endregion
+    //region mkdir
+    /// creates the folder on google drive first, so the entry has its real id
+    /// before the next request (like the next `mkdir` of a `mkdir -p`) uses it
+    #[instrument(skip(request))]
+    async fn mkdir(&mut self, request: ProviderMkdirRequest) -> Result<()> {
+        let parent = self.get_correct_id(request.parent.clone());
+        let name = request.name.clone().into_string();
+        if let Err(e) = name {
+            return send_error_response!(
+                request,
+                ProviderError::InvalidArgument(format!(
+                    "Could not convert name into string: {:?}",
+                    e
+                ))
+            );
+        }
+        let name = name.unwrap();
+
+        match self.mkdir_inner(&parent, &name).await {
+            Ok(metadata) => send_response!(request, ProviderResponse::Mkdir(metadata)),
+            Err(e) => send_error_response!(request, e),
+        }
+    }
+
+    async fn mkdir_inner(&mut self, parent: &DriveId, name: &str) -> ProviderResult<FileMetadata> {
+        if !self.check_id_exists(parent) {
+            return Err(ProviderError::NotFound(format!(
+                "the parent {} does not exist",
+                parent
+            )));
+        }
+        self.check_writable(parent)?;
+        self.check_online()?;
+        if self.find_first_child_by_name(name, parent).is_some() {
+            return Err(ProviderError::AlreadyExists(name.to_string()));
+        }
+
+        let result = self.drive.create_folder(name, parent.clone()).await;
+        self.record_health(|health| health.record_drive_call(result.is_ok()));
+        let folder = result.map_err(|e| {
+            error!(
+                "could not create the folder {} in {}: {:?}",
+                name, parent, e
+            );
+            ProviderError::remote(e.context("could not create the folder"))
+        })?;
+        let Some(id) = folder.id.as_ref().map(DriveId::from) else {
+            return Err(ProviderError::Other(anyhow!(
+                "google drive did not return an id for the folder"
+            )));
+        };
+        self.add_drive_entry_to_entries(folder);
+        let entry = self.entries.get(&id).ok_or_else(|| {
+            ProviderError::Other(anyhow!("the created folder {} is not an entry", id))
+        })?;
+        Ok(self.create_file_metadata_from_entry(entry))
+    }
+    //endregion
+    //region remove
+    #[instrument(skip(request))]
+    async fn remove(&mut self, request: ProviderRemoveRequest) -> Result<()> {
+        let parent = self.get_correct_id(request.parent.clone());
+        let name = request.name.clone().into_string();
+        if let Err(e) = name {
+            return send_error_response!(
+                request,
+                ProviderError::InvalidArgument(format!(
+                    "Could not convert name into string: {:?}",
+                    e
+                ))
+            );
+        }
+        let name = name.unwrap();
+
+        match self.remove_inner(&parent, &name, request.is_dir).await {
+            Ok(id) => send_response!(request, ProviderResponse::Remove(id)),
+            Err(e) => send_error_response!(request, e),
+        }
+    }
+
+    /// removes the entry on google drive according to the [DeletionPolicy] and then locally
+    #[instrument(skip(self))]
+    async fn remove_inner(
+        &mut self,
+        parent: &DriveId,
+        name: &str,
+        is_dir: bool,
+    ) -> ProviderResult<DriveId> {
+        self.check_writable(parent)?;
+        self.check_online()?;
+        let Some(entry) = self.find_first_child_by_name(name, parent) else {
+            return Err(ProviderError::NotFound(format!(
+                "could not find {} in {}",
+                name, parent
+            )));
+        };
+        let Some(id) = entry.get_id() else {
+            return Err(ProviderError::InvalidArgument(
+                "Could not get id from entry".to_string(),
+            ));
+        };
+        let entry_is_dir = entry.attr.kind == FileType::Directory;
+        if is_dir && !entry_is_dir {
+            return Err(ProviderError::NotDirectory(name.to_string()));
+        }
+        if !is_dir && entry_is_dir {
+            return Err(ProviderError::IsDirectory(name.to_string()));
+        }
+        if self.children.get(&id).is_some_and(|c| !c.is_empty()) {
+            return Err(ProviderError::NotEmpty(name.to_string()));
+        }
+
+        self.wait_for_running_drive_request_if_exists(&id)
+            .await
+            .map_err(ProviderError::from)?;
+        let result = match self.deletion_policy {
+            DeletionPolicy::Trash => self.drive.trash_file(id.clone()).await,
+            DeletionPolicy::Hard => self.drive.delete_file(id.clone()).await,
+        };
+        self.record_health(|health| health.record_drive_call(result.is_ok()));
+        if let Err(e) = result {
+            error!("could not remove {} on google drive: {:?}", id, e);
+            return Err(ProviderError::remote(
+                e.context("could not remove the entry on google drive"),
+            ));
+        }
+
+        if let Ok(path) = self.construct_path(&id) {
+            // handles that are still open must not upload the removed file again
+            self.set_dirty(&path, false);
+            if !entry_is_dir && path.exists() {
+                if let Err(e) = fs::remove_file(&path).await {
+                    warn!("could not remove the cached file of {}: {:?}", id, e);
+                }
+            }
+        }
+        self.remove_entry(&id);
+        Ok(id)
+    }
+    //endregion
+    //region write content
+    #[instrument(skip(request))]
+    async fn write_content(&mut self, request: ProviderWriteContentRequest) -> Result<()> {
+        let file_id = &self.get_correct_id(request.file_id.clone());
+        let _lock = match self.lock_for_change(file_id).await {
+            Ok(lock) => lock,
+            Err(e) => return send_error_response!(request, e),
+        };
+        if let Err(e) = self.check_not_directory(file_id) {
+            return send_error_response!(request, e);
+        }
+
+        let size_written = self
+            .write_content_from_file(file_id.clone(), &request)
+            .await;
+        if let Err(e) = size_written {
+            return send_error_response!(request, e);
+        }
+        let size_written = size_written.unwrap();
+        return send_response!(request, ProviderResponse::WriteSize(size_written));
+    }
+    //endregion
+
+    //endregion
+    //region request helpers
+
+    fn does_target_name_exist_under_parent(&self, new_parent: &DriveId, new_name: &String) -> bool {
+        let new_file_entry = self.find_first_child_by_name(&new_name, &new_parent);
+        return new_file_entry.is_some();
+    }
+    fn check_id_exists(&self, id: &DriveId) -> bool {
+        self.entries.contains_key(id)
+    }
+    /// the entries of backed up computers and the revisions can only be read
+    fn check_writable(&self, id: &DriveId) -> ProviderResult<()> {
+        if self.entries.get(id).is_some_and(|e| e.read_only) {
+            return Err(ProviderError::PermissionDenied(format!(
+                "{} can only be read, it is a revision or belongs to a backed up computer",
+                id
+            )));
+        }
+        Ok(())
+    }
+    /// folders have no content, they can't be opened, read or written like files
+    fn check_not_directory(&self, id: &DriveId) -> ProviderResult<()> {
+        if self
+            .entries
+            .get(id)
+            .is_some_and(|e| e.attr.kind == FileType::Directory)
+        {
+            return Err(ProviderError::IsDirectory(format!(
+                "{} is a folder and has no content",
+                id
+            )));
+        }
+        Ok(())
+    }
+    /// trashed entries are only known to the provider unless they are included
+    fn is_visible(&self, entry: &FileData) -> bool {
+        self.include_trashed || !entry.trashed
+    }
+
+    /// returns the first entry it finds with the specified name that is a child of the parent_id
+    ///
+    /// returns ```Option::None``` if none match/the parent does not have any children  
+    fn find_first_child_by_name(&self, name: &str, parent_id: &DriveId) -> Option<&FileData> {
+        self.child_names
+            .get(parent_id)?
+            .get(&child_name_key(name))
+            .and_then(|id| self.entries.get(id))
+    }
+
+    /// gets the file-handle and opens the file if it is marked for open.
+    ///
+    /// If it is not marked for open but the file is None this returns an error
+    #[instrument]
+    async fn get_and_open_file_handle(&mut self, fh: u64) -> ProviderResult<&mut FileHandleData> {
+        if let Some(file_handle) = self.file_handles.get(&fh) {
+            if self.content_store.is_some()
+                && file_handle.file.is_none()
+                && file_handle.flags.can_write()
+            {
+                let path = file_handle.path.clone();
+                unshare(&path)?;
+                // the other handles still have the shared copy open
+                self.reopen_file_handles_for_path(&path);
+            }
+        }
+        let file_handle = self.file_handles.get_mut(&fh);
+        if file_handle.is_none() {
+            error!("Failed to find file_handle for fh: {}", fh);
+            return Err(ProviderError::BadFileHandle(fh));
+        }
+        let file_handle = file_handle.unwrap();
+        if file_handle.file.is_none() {
+            debug!("file is none, opening...");
+            let flags = file_handle.flags;
+            let opened_file = OpenOptions::new()
+                .write(flags.can_write())
+                .read(flags.can_read())
+                .open(&file_handle.path)
+                .await;
+            if let Err(e) = &opened_file {
+                let e = anyhow!("error opening the file{}", e);
+                error!("{}", e);
+                return Err(e.into());
+            }
+            let opened_file = opened_file.unwrap();
+            file_handle.file = Some(opened_file);
+            file_handle.marked_for_open = false;
+            // } else {
+            //     error!("File handle does not have a file");
+            //     return Err(anyhow!("File handle does not have a file"));
+        }
+        Ok(file_handle)
+    }
+
+    async fn write_content_from_file(
+        &mut self,
+        file_id: DriveId,
+        request: &ProviderWriteContentRequest,
+    ) -> ProviderResult<u32> {
+        let len = request.data.len() as u64;
+        if let Some(block_map) = self.partial_files.get(&file_id) {
+            let ranges = block_map.partly_covered_ranges(request.offset, len);
+            self.fetch_blocks(&file_id, ranges).await?;
+        }
+        let file_handle = self.get_and_open_file_handle(request.fh).await?;
+        let file = file_handle.file.as_mut().unwrap();
+        if !file_handle.flags.can_write() {
+            error!("File handle does not have write permissions");
+            return Err(ProviderError::PermissionDenied(
+                "File handle does not have write permissions".to_string(),
+            ));
+        }
+        debug!(
+            "writing to file at local path: {}",
+            file_handle.path.display()
+        );
+        let file: &mut File = file;
+        trace!("seeking position: {}", request.offset);
+        file.seek(SeekFrom::Start(request.offset)).await?;
+        trace!("writing data: {:?}", request.data);
+        let m = file.metadata().await.unwrap();
+        debug!(
+            "metadata before write: size: {}; modified: {:?}",
+            m.size(),
+            m.modified()
+        );
+        let size_written = write_fully(file, &request.data).await?;
+        file.sync_all().await?;
+        let m = file.metadata().await.unwrap();
+        debug!(
+            "metadata after  write: size: {}; modified: {:?}",
+            m.size(),
+            m.modified()
+        );
+        trace!("wrote data: size: {}", size_written);
+        file_handle.has_content_changed = true;
+        let path = file_handle.path.clone();
+        set_local_md5(&self.local_md5s, &file_id, None);
+        self.clear_read_ahead_for_path(&path);
+        if let Some(block_map) = self.partial_files.get_mut(&file_id) {
+            block_map.mark_present(request.offset, size_written as u64);
+            self.save_block_map(&file_id, &path)?;
+        }
+        let entry = self.entries.get_mut(&file_id);
+        if entry.is_none() {
+            error!("could not find entry");
+            return Err(ProviderError::NotFound(
+                "could not find entry to update metadata on".to_string(),
+            ));
+        }
+        let entry = entry.unwrap();
+        let now = SystemTime::now();
+        // the size has to be derived from the offset, since a truncate (like
+        // 'echo "x" > file' does) can reset it to 0 before the first write
+        entry.attr.size = entry.attr.size.max(request.offset + size_written as u64);
+        entry.attr.atime = now;
+        entry.attr.mtime = now;
+
+        Ok(size_written as u32)
+    }
+
+    /// serves the read from the read ahead buffer if it can, otherwise
+    /// prepares reading the cache file
+    async fn prepare_read(
+        &mut self,
+        request: &ProviderReadContentRequest,
+    ) -> ProviderResult<PreparedRead> {
+        let read_ahead_size = self.read_ahead_size;
+        let file_handle = self.get_and_open_file_handle(request.fh).await?;
+        if !file_handle.flags.can_read() {
+            error!("File handle does not have read permissions");
+            return Err(ProviderError::PermissionDenied(
+                "File handle does not have read permissions".to_string(),
+            ));
+        }
+        let read_ahead_size = {
+            let mut read_ahead = lock_read_ahead(&file_handle.read_ahead);
+            if let Some(data) = read_ahead.get(request.offset, request.size) {
+                trace!("serving read from the read ahead buffer");
+                let data = data.to_vec();
+                read_ahead.record_read(request.offset, data.len());
+                return Ok(PreparedRead::Buffered(data));
+            }
+            if read_ahead.is_sequential(request.offset) {
+                read_ahead_size
+            } else {
+                0
+            }
+        };
+        let file = file_handle.file.as_ref().expect("we just opened this...");
+        let file = file.try_clone().await?.into_std().await;
+        Ok(PreparedRead::File(FileRead {
+            file,
+            offset: request.offset,
+            size: request.size,
+            read_ahead_size,
+            read_ahead: file_handle.read_ahead.clone(),
+        }))
+    }
+    /// the read ahead buffers of the handles for this path are outdated once the file changes
+    fn clear_read_ahead_for_path(&mut self, path: &PathBuf) {
+        for file_handle in self.file_handles.values_mut() {
+            if &file_handle.path == path {
+                lock_read_ahead(&file_handle.read_ahead).clear();
+            }
+        }
+    }
+    fn create_file_metadata_from_entry(&self, entry: &FileData) -> FileMetadata {
+        let id = DriveMetadata::new(&entry.metadata)
+            .id()
+            .expect("only metadata with an id becomes an entry");
+        let name = match &self.root_label {
+            Some(root_label) if id == self.alt_root_id => root_label.clone(),
+            _ => entry
+                .changed_metadata
+                .name
+                .as_ref()
+                .unwrap_or(
+                    entry
+                        .metadata
+                        .name
+                        .as_ref()
+                        .unwrap_or(&"NO_NAME".to_string()),
+                )
+                .clone(),
+        };
+        FileMetadata {
+            ttl: self.get_ttl(&id),
+            attr: entry.attr.clone(),
+            name,
+            id,
+        }
+    }
+    //endregion
+
+    //region drive helpers
+    fn record_health(&self, record: impl FnOnce(&mut HealthStatus)) {
+        record_health(&self.health, record);
+    }
+    fn is_time_to_check_for_changes(&self) -> bool {
+        is_cache_time_over(self.last_checked_for_changes, self.allowed_cache_time)
+    }
+    #[instrument]
+    async fn get_changes(&mut self) -> Result<Vec<Change>> {
+        if self.offline {
+            trace!("not checking for changes while offline");
+            return Ok(vec![]);
+        }
+        if !self.is_time_to_check_for_changes() {
+            debug!("not checking for changes since we already checked recently");
+            return Ok(vec![]);
+        }
+        debug!("checking for changes...");
+        let drive_changes = self
+            .drive
+            .get_changes_since(&mut self.changes_start_token)
+            .await;
+        if let Err(e) = &drive_changes {
+            if ApiError::find(e).is_some_and(|e| e.is_invalid_value("pageToken")) {
+                warn!(
+                    "the changes token is not accepted anymore, comparing everything with the drive: {:?}",
+                    e
+                );
+                let recovered = self.recover_from_invalid_changes_token().await;
+                self.record_health(|health| health.record_changes_poll(recovered.is_ok()));
+                self.last_checked_for_changes = SystemTime::now();
+                return recovered.map(|_| vec![]);
+            }
+        }
+        self.record_health(|health| health.record_changes_poll(drive_changes.is_ok()));
+        let changes: Result<Vec<Change>> =
+            drive_changes?.into_iter().map(Change::try_from).collect();
+
+        self.last_checked_for_changes = SystemTime::now();
+        debug!(
+            "checked for changes, found {} changes",
+            changes.as_ref().unwrap_or(&Vec::<Change>::new()).len()
+        );
+        changes
+    }
+
+    /// The changes since the token can't be listed anymore, so some might have
+    /// been missed. Everything is compared with a full listing instead and the
+    /// changes are followed from a new token on.
+    ///
+    /// The new token is fetched first, so nothing that changes during the
+    /// listing is missed.
+    async fn recover_from_invalid_changes_token(&mut self) -> Result<()> {
+        let start_token = self.drive.get_start_page_token().await?;
+        self.reconcile_with_full_listing().await?;
+        info!(
+            "compared the entries with the drive, following the changes from {:?} on",
+            start_token.start_page_token
+        );
+        self.changes_start_token = start_token;
+        self.save_snapshot_state();
+        Ok(())
+    }
+
+    /// applies the difference between the entries and the listing of the drive
+    /// as changes, entries that are not listed anymore are removed
+    async fn reconcile_with_full_listing(&mut self) -> Result<()> {
+        let options = ListOptions {
+            include_trashed: self.include_trashed,
+            include_computers: self.include_computers,
+        };
+        let listed = self.drive.list_all_files(options).await?;
+        let listed_ids: HashSet<DriveId> = listed
+            .iter()
+            .filter_map(|entry| entry.id.as_deref().map(DriveId::from))
+            .collect();
+        // the listing only has the files of the user, the others are checked one by one
+        let unlisted: Vec<DriveId> = self
+            .entries
+            .keys()
+            .filter(|id| {
+                **id != self.alt_root_id
+                    && file_of_revisions_folder(id).is_none()
+                    && parse_revision_entry_id(id).is_none()
+                    && !listed_ids.contains(*id)
+            })
+            .cloned()
+            .collect();
+        let drive = self.drive.clone();
+        let checked: Vec<_> = futures::stream::iter(unlisted)
+            .map(|id| {
+                let drive = drive.clone();
+                async move {
+                    let result = drive.get_metadata_for_file(id.clone()).await;
+                    (id, result)
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT_UNLISTED_CHECKS)
+            .collect()
+            .await;
+        for (id, result) in checked {
+            self.record_health(|health| health.record_drive_call(result.is_ok()));
+            if !self.entries.contains_key(&id) {
+                // removed with its parent
+                continue;
+            }
+            match result {
+                Ok(metadata) => {
+                    let change = Change {
+                        id,
+                        kind: ChangeType::File(metadata),
+                        time: Utc::now(),
+                    };
+                    if let Err(e) = self.process_change(change).await {
+                        error!("got an error while applying an unlisted entry: {:?}", e);
+                    }
+                }
+                Err(e) if ApiError::find(&e).and_then(ApiError::errno) == Some(libc::ENOENT) => {
+                    debug!("{} is not on the drive anymore", id);
+                    self.remove_entry(&id);
+                    self.notify_changed_id(id);
+                }
+                Err(e) => warn!("could not check if {} is still on the drive: {:?}", id, e),
+            }
+        }
+        for entry in listed {
+            let Some(id) = entry.id.as_deref().map(DriveId::from) else {
+                continue;
+            };
+            if id == self.alt_root_id {
+                continue;
+            }
+            if self.entries.contains_key(&id) {
+                let change = Change {
+                    id,
+                    kind: ChangeType::File(entry),
+                    time: Utc::now(),
+                };
+                if let Err(e) = self.process_change(change).await {
+                    error!("got an error while applying a listed entry: {:?}", e);
+                }
+            } else {
+                self.add_drive_entry_to_entries(entry);
+                self.save_snapshot_record(&id);
+                self.notify_changed_id(id);
+            }
+        }
+        self.apply_computers();
+        if self.root_folder != DriveId::root() {
+            self.remove_entries_outside_root();
+        }
+        self.remove_filtered_entries();
+        Ok(())
+    }
+
+    #[instrument]
+    async fn update_remote_metadata(&mut self, id: DriveId) -> Result<()> {
+        trace!("Uploading changed metadata");
+        let mut file_data = self.entries.get_mut(&id);
+        if file_data.is_none() {
+            return Err(anyhow!("Could not get entry with id: {}", id));
+        }
+        let file_data = file_data.unwrap();
+        //extract changes from the file_data and replaces with new empty one
+        let mut metadata = DriveFileMetadata::default();
+        swap(&mut file_data.changed_metadata, &mut metadata);
+        Self::prepare_changed_metadata_for_upload(&id, &mut metadata);
+
+        let result = self
+            .drive
+            .update_file_metadata_on_drive(metadata, &file_data.metadata)
+            .await;
+        self.record_health(|health| health.record_drive_call(result.is_ok()));
+        result?;
+
+        self.reset_local_metadata_to_remote_version(&id).await?;
+
+        Ok(())
+    }
+
+    async fn reset_local_metadata_to_remote_version(&mut self, id: &DriveId) -> Result<()> {
+        let mut new_metadata = self.drive.get_metadata_for_file(id.clone()).await?;
+        new_metadata.name = new_metadata
+            .name
+            .clone()
+            .map(|name| shown_name(name, &new_metadata));
+        let file_data = self.entries.get_mut(id);
+        if file_data.is_none() {
+            return Err(anyhow!("Could not get entry with id: {}", id));
+        }
+        let file_data = file_data.unwrap();
+        let name_changed = file_data.metadata.name != new_metadata.name;
+        if name_changed {
+            self.unindex_entry_name(id);
+        }
+        let file_data = self.entries.get_mut(id).expect("we just got this entry");
+        file_data.metadata = new_metadata;
+        file_data.changed_metadata = DriveFileMetadata::default();
+        if name_changed {
+            self.index_entry_name(id);
+        }
+        Ok(())
+    }
+
+    /// starts a download of the specified file and puts it in the running_requests map
+    ///
+    /// - will return an Error if another request is already running for the same id, so all callers should make sure of that
+    async fn start_download_call(
+        &mut self,
+        request: &ProviderOpenFileRequest,
+        drive: GoogleDrive,
+        target_path: &PathBuf,
+    ) -> Result<()> {
+        let file_id = self.get_correct_id(request.file_id.clone());
+        let id = file_id.clone();
+        let entry = self.entries.get_mut(&id).context("could not find entry")?;
+        entry.is_local = true;
+
+        if let Some(_handle) = self.running_requests.get(&id) {
+            return send_error_response!(
+                request,
+                ProviderError::Conflict("Id already has a request running".to_string())
+            );
+        }
+        let target_path = target_path.clone();
+        let content_store = self.content_store.clone().filter(|_| !entry.perma);
+        let local_md5s = self.local_md5s.clone();
+        let handle: JoinHandle<Result<()>> = tokio::spawn(async move {
+            let metadata: DriveFileMetadata =
+                drive.download_file(file_id.clone(), &target_path).await?;
+            set_local_md5(&local_md5s, &file_id, metadata.md5_checksum.clone());
+            if let (Some(content_store), Some(md5)) = (content_store, metadata.md5_checksum) {
+                content_store.add(&md5, &target_path)?;
+            }
+            Ok(())
+        });
+
+        self.track_transfer(id, TransferDirection::Download, handle);
+        Ok(())
+    }
+    /// puts the transfer in the running_requests map and makes it visible to
+    /// the command listener, so it can be listed and cancelled
+    fn track_transfer(
+        &mut self,
+        id: DriveId,
+        direction: TransferDirection,
+        handle: JoinHandle<Result<()>>,
+    ) {
+        let transfer = RunningTransfer {
+            direction,
+            abort: handle.abort_handle(),
+        };
+        lock_running_transfers(&self.running_transfers).insert(id.clone(), transfer);
+        self.running_requests.insert(id, handle);
+    }
+
+    /// - will return an Error if another request is already running for the same id, so all callers should make sure of that
+    async fn start_upload_call(&mut self, id: DriveId, drive: GoogleDrive) -> Result<()> {
+        if self.running_requests.contains_key(&id) {
+            return Err(anyhow!("Id already has a request running"));
+        }
+        if self.entries.get(&id).is_some_and(|e| e.trashed) {
+            debug!("not uploading {}, it is in the trash", id);
+            return Ok(());
+        }
+        if self.offline {
+            debug!("queueing the upload of {} until the provider is online", id);
+            if !self.queued_uploads.contains(&id) {
+                self.queued_uploads.push(id);
+            }
+            return Ok(());
+        }
+        self.queued_uploads.retain(|queued| queued != &id);
+        // the upload takes the whole file
+        self.fetch_missing_blocks(&id, 0, u64::MAX).await?;
+
+        let metadata = self.create_upload_metadata(&id)?;
+
+        let target_path = self.construct_path(&id)?;
+        debug!(
+            "starting upload in the background for path: '{}' and metadata: {:?}",
+            target_path.display(),
+            metadata
+        );
+        let total = fs::metadata(&target_path).await?.len();
+        let progress = self.create_upload_progress_callback(id.clone(), total);
+        let upload_progress = self.upload_progress.clone();
+        let progress_id = id.clone();
+        let name = self
+            .entries
+            .get(&id)
+            .and_then(|e| e.metadata.name.clone())
+            .unwrap_or_else(|| id.to_string());
+        // nothing may change or move the file while it is read for the upload
+        let lock = self.file_locks.get(&id).read_owned().await;
+        let local_md5s = self.local_md5s.clone();
+        let md5_id = id.clone();
+        let handle: JoinHandle<Result<()>> = tokio::spawn(async move {
+            let _lock = lock;
+            //TODO1: only send the changed metadata over (+id), not all of it (currently only all data that could change and where changes should be written to the drive), since google drive only wants the changes
+            let result = drive
+                .upload_file_content_from_path(metadata, &target_path, Some(progress))
+                .await;
+            if result.is_ok() {
+                // the lock keeps the content as it was uploaded
+                let md5 = compute_md5_async(&target_path).await;
+                set_local_md5(&local_md5s, &md5_id, md5);
+            }
+            if let Ok(mut upload_progress) = upload_progress.lock() {
+                upload_progress.remove(&progress_id);
+            }
+            if let Err(e) = &result {
+                // uploads are not retried, so the local changes are not on the drive
+                notify_user(
+                    "Upload failed",
+                    &format!("'{}' could not be uploaded: {}", name, e),
+                );
+            }
+            result
+        });
+        self.track_transfer(id, TransferDirection::Upload, handle);
+        Ok(())
+    }
+
+    /// creates the sparse cache file of a partial download, or picks up the
+    /// blocks an earlier run already downloaded
+    async fn start_partial_file(&mut self, id: &DriveId, path: &Path) -> ProviderResult<()> {
+        let size = self.entries.get(id).map_or(0, |e| e.attr.size);
+        let block_map = match BlockMap::load(&BlockMap::path_for(path)) {
+            Some(block_map) if block_map.size() == size && path.exists() => {
+                debug!("continuing the partial download of {}", id);
+                block_map
+            }
+            _ => {
+                File::create(path).await?.set_len(size).await?;
+                BlockMap::new(size)
+            }
+        };
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.is_local = true;
+        }
+        self.partial_files.insert(id.clone(), block_map);
+        self.save_block_map(id, path)
+    }
+    /// downloads what is missing of `offset..offset + len`, if the file is partial
+    async fn fetch_missing_blocks(
+        &mut self,
+        id: &DriveId,
+        offset: u64,
+        len: u64,
+    ) -> ProviderResult<()> {
+        let Some(block_map) = self.partial_files.get(id) else {
+            return Ok(());
+        };
+        let ranges = block_map.missing_ranges(offset, len);
+        if ranges.is_empty() {
+            return Ok(());
+        }
+        // the reads of the file must not see a block that is only half written
+        let _lock = self.file_locks.write(id).await;
+        self.fetch_blocks(id, ranges).await
+    }
+    /// the caller has to hold the write lock of the file
+    async fn fetch_blocks(&mut self, id: &DriveId, ranges: Vec<(u64, u64)>) -> ProviderResult<()> {
+        if ranges.is_empty() {
+            return Ok(());
+        }
+        self.check_online()?;
+        let path = self.construct_path(id)?;
+        let file = std::fs::OpenOptions::new().write(true).open(&path)?;
+        for (offset, length) in ranges {
+            debug!("downloading {} bytes of {} at {}", length, id, offset);
+            let result = self.drive.download_range(id.clone(), offset, length).await;
+            self.record_health(|health| health.record_drive_call(result.is_ok()));
+            let data = result.map_err(ProviderError::remote)?;
+            file.write_all_at(&data, offset)?;
+            if let Some(block_map) = self.partial_files.get_mut(id) {
+                block_map.mark_present(offset, data.len() as u64);
+            }
+        }
+        self.save_block_map(id, &path)
+    }
+    /// stores the map next to the cache file, once the file is complete it is not needed anymore
+    fn save_block_map(&mut self, id: &DriveId, path: &Path) -> ProviderResult<()> {
+        let map_path = BlockMap::path_for(path);
+        let Some(block_map) = self.partial_files.get(id) else {
+            return Ok(());
+        };
+        if !block_map.is_complete() {
+            return Ok(block_map.save(&map_path)?);
+        }
+        debug!("all blocks of {} are downloaded", id);
+        self.partial_files.remove(id);
+        match std::fs::remove_file(map_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// starts the upload and waits for it, unlike [Self::start_upload_call]
+    async fn upload_and_wait(&mut self, id: DriveId) -> Result<()> {
+        let drive = self.drive.clone();
+        self.start_upload_call(id.clone(), drive).await?;
+        let Some(handle) = self.running_requests.remove(&id) else {
+            return Ok(());
+        };
+        lock_running_transfers(&self.running_transfers).remove(&id);
+        let result = handle.await?;
+        self.record_health(|health| health.record_drive_call(result.is_ok()));
+        self.record_upload_result(&id, &result);
+        result
+    }
+    /// remembers the uploads google drive refused because of the credentials,
+    /// [Self::reload_auth] starts them again. A later successful upload of the
+    /// file covers the content of the failed one
+    fn record_upload_result(&mut self, id: &DriveId, result: &Result<()>) {
+        match result {
+            Err(e) if e.downcast_ref::<NotAuthenticatedError>().is_some() => {
+                if !self.auth_failed_uploads.contains(id) {
+                    self.auth_failed_uploads.push(id.clone());
+                }
+            }
+            Err(_) => {}
+            Ok(()) => self.auth_failed_uploads.retain(|failed| failed != id),
+        }
+    }
+
+    /// starts the upload of the file, or schedules it for the end of its window
+    /// if it has one. See [UploadWindows]
+    async fn schedule_upload(&mut self, id: &DriveId) -> Result<()> {
+        let window = self.upload_window(id);
+        if window.is_zero() {
+            self.scheduled_uploads.remove(id);
+            let drive = self.drive.clone();
+            return self.start_upload_call(id.clone(), drive).await;
+        }
+        debug!("uploading {} in {:?}, unless it changes again", id, window);
+        self.scheduled_uploads
+            .insert(id.clone(), tokio::time::Instant::now() + window);
+        Ok(())
+    }
+    fn upload_window(&self, id: &DriveId) -> Duration {
+        let size = self
+            .construct_path(id)
+            .ok()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .or_else(|| self.entries.get(id).map(|e| e.attr.size))
+            .unwrap_or(0);
+        self.upload_windows.window_for(size)
+    }
+    /// starts the scheduled uploads whose window is over, or all of them
+    async fn start_scheduled_uploads(&mut self, all: bool) {
+        let now = tokio::time::Instant::now();
+        let due: Vec<DriveId> = self
+            .scheduled_uploads
+            .iter()
+            .filter(|(_, upload_at)| all || **upload_at <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in due {
+            self.scheduled_uploads.remove(&id);
+            if let Err(e) = self.wait_for_running_drive_request_if_exists(&id).await {
+                warn!("could not wait for the running request of {}: {:?}", id, e);
+            }
+            let drive = self.drive.clone();
+            if let Err(e) = self.start_upload_call(id.clone(), drive).await {
+                error!("could not start the scheduled upload of {}: {:?}", id, e);
+            }
+        }
+    }
+
+    /// starts all scheduled uploads and waits for the running ones, nothing can
+    /// change the files anymore when the provider stops
+    async fn finish_uploads(&mut self) {
+        self.start_scheduled_uploads(true).await;
+        let uploads: Vec<DriveId> = lock_running_transfers(&self.running_transfers)
+            .iter()
+            .filter(|(_, transfer)| transfer.direction == TransferDirection::Upload)
+            .map(|(id, _)| id.clone())
+            .collect();
+        debug!("waiting for {} uploads to finish", uploads.len());
+        for id in uploads {
+            if let Err(e) = self.wait_for_drive_request_if_exists(&id).await {
+                warn!("the upload of {} did not finish: {:?}", id, e);
+            }
+        }
+    }
+
+    /// registers the upload in the upload_progress map and creates a callback that
+    /// keeps it up to date and traces every 10% of progress
+    fn create_upload_progress_callback(&mut self, id: DriveId, total: u64) -> ProgressCallback {
+        let upload_progress = self.upload_progress.clone();
+        if let Ok(mut upload_progress) = upload_progress.lock() {
+            upload_progress.insert(id.clone(), UploadProgress::new(total));
+        }
+        Arc::new(move |bytes_sent, total| {
+            let Ok(mut upload_progress) = upload_progress.lock() else {
+                return;
+            };
+            let progress = upload_progress
+                .entry(id.clone())
+                .or_insert_with(|| UploadProgress::new(total));
+            if let Some(percent) = progress.update(bytes_sent) {
+                info!(
+                    "upload of {}: {}% ({}/{} bytes)",
+                    id, percent, bytes_sent, total
+                );
+            }
+        })
+    }
+
+    /// creates the metadata that gets sent with a content upload
+    fn create_upload_metadata(&self, id: &DriveId) -> Result<DriveFileMetadata> {
+        let file_data = self.entries.get(id).context("could not find data for id")?;
+
+        let mut metadata = file_data.changed_metadata.clone();
+        Self::prepare_changed_metadata_for_upload(id, &mut metadata);
+        metadata.mime_type = file_data.metadata.mime_type.clone();
+        // without this drive sets the modified time to the time of the upload
+        metadata.modified_time = Some(file_data.attr.mtime.into());
+        Ok(metadata)
+    }
+
+    fn prepare_changed_metadata_for_upload(id: &DriveId, mut metadata: &mut DriveFileMetadata) {
+        metadata.id = Some(id.clone().into());
+        remove_volatile_metadata(&mut metadata);
+    }
+
+    /// Checks if a drive request for this ID is running and if there is, waits for it
+    /// and removes it from the map. Waits for the reads of the file that run in
+    /// their own task as well, so the file can be changed
+    async fn wait_for_running_drive_request_if_exists(&mut self, file_id: &DriveId) -> Result<()> {
+        self.wait_for_drive_request_if_exists(file_id).await?;
+        drop(self.file_locks.write(file_id).await);
+        Ok(())
+    }
+    /// like [Self::wait_for_running_drive_request_if_exists], but keeps the
+    /// lock of the file until the returned guard is dropped
+    async fn lock_for_change(
+        &mut self,
+        file_id: &DriveId,
+    ) -> ProviderResult<OwnedRwLockWriteGuard<()>> {
+        if let Err(e) = self.wait_for_drive_request_if_exists(file_id).await {
+            error!("{}", e);
+            return Err(ProviderError::from(e));
+        }
+        Ok(self.file_locks.write(file_id).await)
+    }
+    async fn wait_for_drive_request_if_exists(&mut self, file_id: &DriveId) -> Result<()> {
+        if let Some(handle) = self.running_requests.get_mut(&file_id) {
+            debug!("DriveFileProvider::open_file() waiting for download/upload to finish");
+            let handle_result = handle.await;
+            self.running_requests.remove(&file_id);
+            let transfer = lock_running_transfers(&self.running_transfers).remove(file_id);
+            let handle_result = match handle_result {
+                Err(e) if e.is_cancelled() => {
+                    let direction = transfer.map(|t| t.direction);
+                    if direction == Some(TransferDirection::Upload) {
+                        self.discard_cancelled_upload(file_id);
+                        return Ok(());
+                    }
+                    self.discard_cancelled_download(file_id);
+                    return Err(ProviderError::Interrupted(format!(
+                        "the download of {} was cancelled",
+                        file_id
+                    ))
+                    .into());
+                }
+                handle_result => handle_result?,
+            };
+            self.record_health(|health| health.record_drive_call(handle_result.is_ok()));
+            if let Err(e) = &handle_result {
+                error!("async request had an error: {:?}", e);
+            }
+            if transfer.is_some_and(|t| t.direction == TransferDirection::Upload) {
+                self.record_upload_result(file_id, &handle_result);
+            }
+            // a download replaces the cached file, so handles that already
+            // opened it would keep reading and writing the old one
+            if let Ok(path) = self.construct_path(file_id) {
+                self.reopen_file_handles_for_path(&path);
+            }
+        }
+        Ok(())
+    }
+    /// the local content stays as it is, it gets uploaded with the next flush or
+    /// when the provider goes online. Until then it is queued, so the eviction
+    /// keeps it
+    fn discard_cancelled_upload(&mut self, file_id: &DriveId) {
+        info!("the upload of {} was cancelled", file_id);
+        if !self.queued_uploads.contains(file_id) {
+            self.queued_uploads.push(file_id.clone());
+        }
+        if let Ok(mut upload_progress) = self.upload_progress.lock() {
+            upload_progress.remove(file_id);
+        }
+    }
+    /// the cache file only has a part of the content, it gets downloaded again
+    /// when the file is opened the next time
+    fn discard_cancelled_download(&mut self, file_id: &DriveId) {
+        if let Some(entry) = self.entries.get_mut(file_id) {
+            entry.is_local = false;
+        }
+        if let Ok(path) = self.construct_path(file_id) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!(
+                    "could not remove the cancelled download {}: {:?}",
+                    path.display(),
+                    e
+                );
+            }
+            self.reopen_file_handles_for_path(&path);
+        }
+    }
+    /// closes the files of all handles for this path, they get opened again on their next use
+    fn reopen_file_handles_for_path(&mut self, path: &PathBuf) {
+        for file_handle in self.file_handles.values_mut() {
+            if &file_handle.path == path && file_handle.file.is_some() {
+                file_handle.file = None;
+                file_handle.marked_for_open = true;
+                lock_read_ahead(&file_handle.read_ahead).clear();
+            }
+        }
+    }
+    //endregion
+
+    fn create_fh(
+        &mut self,
+        flags: HandleFlags,
+        path: PathBuf,
+        create: bool,
+        mark_for_open: bool,
+    ) -> u64 {
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        let file_handle = FileHandleData {
+            creating: create,
+            flags,
+            file: None,
+            path,
+            marked_for_open: mark_for_open,
+            has_content_changed: false,
+            read_ahead: Arc::default(),
+            no_cache: false,
+        };
+        self.file_handles.insert(fh, file_handle);
+        fh
+    }
+    /// constructs the path where the file is stored locally. This is not necessarily a
+    /// path with the correct file ending or folder structure, it could just be a unique id.
+    fn construct_path(&self, id: &DriveId) -> Result<PathBuf> {
+        let metadata = self.entries.get(id).context("No data found for id")?;
+        //TODO: check if every drive_id is actually a valid filepath/does
+        //      not contain characters that cannot be used in a path
+        if metadata.perma {
+            Ok(self.perma_dir.join(id.as_str()))
+        } else {
+            Ok(self.cache_dir.join(id.as_str()))
+        }
+    }
+    async fn initialize_entries(&mut self) -> Result<()> {
+        if self.offline {
+            return self.initialize_entries_offline().await;
+        }
+        self.add_root_entry()
+            .await
+            .expect("adding the root entry has to work, otherwise nothing else works");
+        let options = ListOptions {
+            include_trashed: self.include_trashed,
+            include_computers: self.include_computers,
+        };
+        let entries = self.drive.list_all_files(options).await?;
+        self.add_listed_entries(entries).await;
+        if self.verify_cache {
+            self.verify_cache().await;
+        }
+        self.save_snapshot();
+        // for (i, (id, data)) in self.entries.iter().enumerate() {
+        //     info!("entry {:3} id: {:>40} data: {:?}", i, id, data);
+        // }
+        Ok(())
+    }
+    /// like [Self::initialize_entries], with the entries the last run saved
+    /// instead of the ones on the drive. The changes since then get applied
+    /// once the provider is online
+    async fn initialize_entries_offline(&mut self) -> Result<()> {
+        let snapshot = load_snapshot(&self.perma_dir)
+            .context("there are no saved entries to start offline with")?;
+        let root_id = snapshot.root.id.as_deref().map(DriveId::from);
+        if self.root_folder != DriveId::root() && root_id.as_ref() != Some(&self.root_folder) {
+            return Err(anyhow!(
+                "the saved entries are of another root folder: {:?}",
+                root_id
+            ));
+        }
+        info!(
+            "starting offline with {} saved entries",
+            snapshot.entries.len()
+        );
+        self.changes_start_token = snapshot.start_page_token;
+        self.insert_root_entry(snapshot.root)?;
+        self.add_listed_entries(snapshot.entries).await;
+        // only the cached files that still match the saved md5 can be used
+        self.verify_cache().await;
+        Ok(())
+    }
+    /// adds the entries of the listing, the root has to be there already
+    async fn add_listed_entries(&mut self, entries: Vec<DriveFileMetadata>) {
+        for entry in entries {
+            // a root folder other than the drive root is listed as well
+            if entry.id.as_deref() == Some(self.alt_root_id.as_str()) {
+                continue;
+            }
+            self.add_drive_entry_to_entries(entry);
+        }
+        self.apply_computers();
+        if self.root_folder != DriveId::root() {
+            self.remove_entries_outside_root();
+        }
+        // the paths are only complete after all entries have been added
+        self.remove_filtered_entries();
+        self.check_pinned_files().await;
+    }
+    /// if there are saved entries in `perma_dir` to start offline with, see
+    /// [DriveFileProvider::set_offline]
+    pub fn can_start_offline(perma_dir: &Path) -> bool {
+        has_snapshot(perma_dir)
+    }
+    /// saves all entries with the current page token of the changes api, the
+    /// next run can start offline with them. After that only the records of the
+    /// entries that change get written, see [Self::save_snapshot_record]
+    fn save_snapshot(&self) {
+        let Some(root) = self.entries.get(&self.alt_root_id) else {
+            return;
+        };
+        let entries = self
+            .entries
+            .keys()
+            .filter_map(|id| self.snapshot_record(id))
+            .collect();
+        let snapshot = Snapshot {
+            start_page_token: self.changes_start_token.clone(),
+            root: root.metadata.clone(),
+            entries,
+        };
+        if let Err(e) = save_snapshot(&self.perma_dir, &snapshot) {
+            warn!("could not save the entries for starting offline: {:?}", e);
+        }
+    }
+    /// what the snapshot keeps of the entry, `None` for the root and the
+    /// revisions, those are not saved
+    fn snapshot_record(&self, id: &DriveId) -> Option<DriveFileMetadata> {
+        if *id == self.alt_root_id
+            || parse_revision_entry_id(id).is_some()
+            || file_of_revisions_folder(id).is_some()
+        {
+            return None;
+        }
+        // the entries are added with the name they have on the drive
+        let mut metadata = self.entries.get(id)?.metadata.clone();
+        if is_native_file(&metadata) {
+            metadata.name = metadata
+                .name
+                .as_deref()
+                .map(|name| name_without_placeholder_extension(name, &metadata).to_string());
+        }
+        Some(metadata)
+    }
+    /// writes the record of the entry again, or removes it if the entry is gone.
+    /// Nothing happens before there is a snapshot to update
+    fn save_snapshot_record(&self, id: &DriveId) {
+        if !has_snapshot(&self.perma_dir) {
+            return;
+        }
+        if *id == self.alt_root_id {
+            return self.save_snapshot_state();
+        }
+        let result = match self.snapshot_record(id) {
+            Some(record) => save_record(&self.perma_dir, &record),
+            None => remove_record(&self.perma_dir, id),
+        };
+        if let Err(e) = result {
+            warn!(
+                "could not save the entry {} for starting offline: {:?}",
+                id, e
+            );
+        }
+    }
+    fn forget_snapshot_record(&self, id: &DriveId) {
+        if !has_snapshot(&self.perma_dir) {
+            return;
+        }
+        if let Err(e) = remove_record(&self.perma_dir, id) {
+            warn!("could not remove the saved entry {}: {:?}", id, e);
+        }
+    }
+    /// saves the page token the changes are followed from, and the root
+    fn save_snapshot_state(&self) {
+        if !has_snapshot(&self.perma_dir) {
+            return;
+        }
+        let Some(root) = self.entries.get(&self.alt_root_id) else {
+            return;
+        };
+        if let Err(e) = save_state(&self.perma_dir, &self.changes_start_token, &root.metadata) {
+            warn!(
+                "could not save the changes token for starting offline: {:?}",
+                e
+            );
+        }
+    }
+
+    /// the pinned files that did not change on the drive while the provider was
+    /// not running don't have to be downloaded again
+    async fn check_pinned_files(&mut self) {
+        let pinned: Vec<DriveId> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.perma)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in pinned {
+            let Ok(path) = self.construct_path(&id) else {
+                continue;
+            };
+            let remote = self
+                .entries
+                .get(&id)
+                .and_then(|e| e.metadata.md5_checksum.clone());
+            let unchanged = remote.is_some() && remote == compute_md5_async(path).await;
+            if let Some(entry) = self.entries.get_mut(&id) {
+                entry.is_local = unchanged;
+            }
+            if !unchanged {
+                debug!("the pinned file {} changed, it gets downloaded again", id);
+            }
+        }
+    }
+    /// Compares the files a previous run left in the cache with the md5 of
+    /// their entries, for example after a crash that left some truncated.
+    ///
+    /// The intact ones are used instead of downloading them again, the others
+    /// are removed and get downloaded when they are opened. Files without an
+    /// md5 on the drive and partially downloaded ones are left alone.
+    pub async fn verify_cache(&mut self) -> CacheVerification {
+        let cached: Vec<(DriveId, PathBuf, String)> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| !e.perma && !e.is_local)
+            .filter_map(|(id, e)| {
+                let md5_checksum = e.metadata.md5_checksum.clone()?;
+                let path = self.cache_dir.join(id.as_str());
+                let complete = path.is_file() && !BlockMap::path_for(&path).exists();
+                complete.then_some((id.clone(), path, md5_checksum))
+            })
+            .collect();
+        let checked: Vec<(DriveId, PathBuf, bool)> = futures::stream::iter(cached)
+            .map(|(id, path, md5_checksum)| async move {
+                let intact = compute_md5_async(path.clone()).await == Some(md5_checksum);
+                (id, path, intact)
+            })
+            .buffer_unordered(MAX_CONCURRENT_VERIFICATIONS)
+            .collect()
+            .await;
+        let mut verification = CacheVerification::default();
+        for (id, path, intact) in checked {
+            verification.verified += 1;
+            if intact {
+                if let Some(entry) = self.entries.get_mut(&id) {
+                    entry.is_local = true;
+                }
+                continue;
+            }
+            debug!(
+                "the cached file of {} does not match its md5, evicting it",
+                id
+            );
+            if let Err(e) = fs::remove_file(&path).await {
+                warn!("could not evict the cached file of {}: {:?}", id, e);
+                continue;
+            }
+            verification.evicted += 1;
+        }
+        info!("{}", verification);
+        verification
+    }
+    fn add_drive_entry_to_entries(&mut self, mut entry: DriveFileMetadata) -> bool {
+        let native = is_native_file(&entry);
+        if native && self.native_file_policy == NativeFilePolicy::Hide {
+            debug!("hiding the native file {:?}", entry.name);
+            return true;
+        }
+        entry.name = entry.name.clone().map(|name| shown_name(name, &entry));
+        if let Ok(id) = DriveMetadata::new(&entry).id() {
+            let attr = self.create_file_attr_from_metadata(&entry);
+            if attr.is_err() {
+                warn!(
+                    "error while creating FileAttr from metadata: {:?} entry: {:?}",
+                    attr, entry
+                );
+                return true;
+            }
+            let attr = attr.unwrap();
+            self.add_child_parent_relations(&entry, &id);
+            let trashed = DriveMetadata::new(&entry).is_trashed();
+            // children that get added later inherit it from the computer they are in
+            let read_only = native
+                || self
+                    .parents
+                    .get(&id)
+                    .is_some_and(|parents| parents.iter().any(|p| self.is_read_only(p)));
+            let mut attr = attr;
+            if read_only {
+                attr.perm &= !0o222;
+            }
+            let entry_data = FileData {
+                metadata: entry,
+                changed_metadata: Default::default(),
+                perma: self.pinned.contains(&id),
+                attr,
+                is_local: false,
+                trashed,
+                read_only,
+            };
+            self.entries.insert(id.clone(), entry_data);
+            // the name is only known after the entry has been inserted
+            self.index_entry_name(&id);
+        }
+        false
+    }
+
+    fn is_read_only(&self, id: &DriveId) -> bool {
+        self.entries.get(id).is_some_and(|e| e.read_only)
+    }
+
+    /// The folders of backed up computers have no parent, so they end up at the
+    /// top level. They and everything in them are made read only, or removed if
+    /// the computers are not included.
+    fn apply_computers(&mut self) {
+        let computers: Vec<DriveId> = self
+            .entries
+            .iter()
+            .filter(|(id, e)| **id != self.alt_root_id && is_computer_folder(&e.metadata))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in computers {
+            if !self.include_computers {
+                debug!("removing the backed up computer {}", id);
+                self.remove_entry(&id);
+                continue;
+            }
+            let mut pending = vec![id];
+            while let Some(id) = pending.pop() {
+                if let Some(entry) = self.entries.get_mut(&id) {
+                    entry.read_only = true;
+                    entry.attr.perm &= !0o222;
+                }
+                pending.extend(self.children.get(&id).cloned().unwrap_or_default());
+            }
+        }
+    }
+
+    /// removes everything that is not in the root folder, when only a folder is shown
+    fn remove_entries_outside_root(&mut self) {
+        let mut inside = HashSet::new();
+        let mut pending = vec![self.alt_root_id.clone()];
+        while let Some(id) = pending.pop() {
+            if inside.insert(id.clone()) {
+                pending.extend(self.children.get(&id).cloned().unwrap_or_default());
+            }
+        }
+        let outside: Vec<DriveId> = self
+            .entries
+            .keys()
+            .filter(|id| !inside.contains(*id))
+            .cloned()
+            .collect();
+        debug!(
+            "removing {} entries outside of the root folder",
+            outside.len()
+        );
+        for id in outside {
+            self.remove_entry(&id);
+        }
+    }
+
+    fn add_child_parent_relations(&mut self, entry: &DriveFileMetadata, id: &DriveId) {
+        if let Some(parents) = &entry.parents {
+            for parent in parents {
+                let parent_id = DriveId::from(parent);
+                self.add_parent_child_relation(parent_id, id.clone());
+            }
+        } else {
+            //file is at root level
+            self.add_parent_child_relation(DriveId::root(), id.clone());
+        }
+    }
+
+    fn create_file_attr_from_metadata(&self, metadata: &DriveFileMetadata) -> Result<FileAttr> {
+        if is_native_file(metadata) && self.native_file_policy == NativeFilePolicy::Placeholder {
+            let mut attr = self.create_file_attr_from_metadata(&DriveFileMetadata {
+                mime_type: None,
+                size: Some(placeholder_content(metadata).len() as i64),
+                ..metadata.clone()
+            })?;
+            attr.perm = 0o444;
+            return Ok(attr);
+        }
+        let mime = DriveMetadata::new(metadata).mime();
+        if mime.is_empty() {
+            warn!(
+                "{:?} has no mime type, it is shown as a file",
+                metadata.name
+            );
+        }
+        let kind = convert_mime_type_to_file_type(mime)?;
+        // let permissions= todo!("read default permissions from a file or read specific permissions for id from somewhere (if the permissions were set in a previous sessions and stuff like that should be carried over to the next session");
+        let permissions = match kind {
+            FileType::Directory => 0o755,
+            _ => 0o644,
+        };
+        let times = FileTimes::from_metadata(metadata);
+        let attributes = FileAttr {
+            ino: 0,
+            size: DriveMetadata::new(metadata).size(),
+            blocks: 0,
+            atime: times.atime,
+            mtime: times.mtime,
+            ctime: times.ctime,
+            crtime: times.crtime,
+            kind,
+            perm: permissions,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: self.blksize,
+            flags: 0,
+        };
+        Ok(attributes)
+    }
+    async fn add_root_entry(&mut self) -> Result<()> {
+        let metadata = self
+            .drive
+            .get_metadata_for_file(self.root_folder.clone())
+            .await?;
+        self.insert_root_entry(metadata)
+    }
+    fn insert_root_entry(&mut self, metadata: DriveFileMetadata) -> Result<()> {
+        let attr = self.create_file_attr_from_metadata(&metadata)?;
+        let root_id = DriveMetadata::new(&metadata).id()?;
+        let data = FileData {
+            metadata,
+            changed_metadata: Default::default(),
+            attr,
+            perma: false,
+            is_local: false,
+            trashed: false,
+            read_only: false,
+        };
+
+        // the relations that were added before the root was known use the alias
+        let alias_children = self.children.get(&self.alt_root_id).cloned();
+        for child_id in alias_children.iter().flatten() {
+            self.remove_parent_child_relation(self.alt_root_id.clone(), child_id.clone());
+        }
+        self.children.remove(&self.alt_root_id);
+        self.alt_root_id = root_id.clone();
+        for child_id in alias_children.into_iter().flatten() {
+            self.add_parent_child_relation(root_id.clone(), child_id);
+        }
+        self.invalidate_paths();
+        self.entries.insert(root_id, data);
+        Ok(())
+    }
+
+    /// changes alias ids like ```DriveId::root()``` into their actual IDs on the drive
+    fn get_correct_id(&self, id: DriveId) -> DriveId {
+        if id == DriveId::root() {
+            trace!("aliasing DriveId::root() to actual root: {}", id);
+            return self.alt_root_id.clone();
+        }
+        return id;
+    }
+
+    async fn process_change(&mut self, change: Change) -> Result<()> {
+        let id = change.id;
+        let id = self.get_correct_id(id);
+        match change.kind {
+            ChangeType::Drive(drive) => {
+                error!("drive changes are not supported yet: {:?}", drive);
+                // todo!("drive changes are not supported yet: {:?}", drive);
+            }
+            ChangeType::File(file_change) => {
+                if !self.entries.contains_key(&id) && !self.is_change_in_scope(&file_change) {
+                    trace!("{} is outside of the mount, skipping its change", id);
+                    return Ok(());
+                }
+                //TODO: check if local has changes that conflict (content)
+                //TODO: check if the content was changed (checksum) and schedule
+                // a download if it is a local/perm file or mark it for download on next open
+                trace!("file change: {:?}", file_change);
+                if file_change.trashed == Some(true) && !self.include_trashed {
+                    debug!("{} was moved to the trash", id);
+                    self.remove_entry(&id);
+                    self.notify_changed_id(id);
+                    return Ok(());
+                }
+
+                // changes like a new viewedByMeTime leave the content as it is
+                let content_unchanged = file_change.md5_checksum.is_some()
+                    && file_change.md5_checksum == self.local_md5_checksum(&id);
+                if content_unchanged {
+                    trace!("only the metadata of {} changed", id);
+                } else {
+                    self.changed_on_remote.insert(id.clone());
+                    if self.has_pending_upload(&id) {
+                        if let Err(e) = self.keep_local_copy(&id).await {
+                            error!("could not keep the local content of {}: {:?}", id, e);
+                        }
+                    }
+                }
+                self.process_remote_file_moved(&id, &file_change);
+                if self.entries.contains_key(&id) {
+                    let name_changed = file_change.name.is_some();
+                    if name_changed {
+                        self.unindex_entry_name(&id);
+                    }
+                    let entry = self.entries.get_mut(&id).expect("we just checked this");
+                    let result = process_file_change(entry, file_change);
+                    if name_changed {
+                        self.index_entry_name(&id);
+                    }
+                    result?;
+                } else {
+                    let entry = self.drive.get_metadata_for_file(id.clone()).await?;
+                    if is_computer_folder(&entry) && !self.include_computers {
+                        debug!("{} is a backed up computer, those are not included", id);
+                        return Ok(());
+                    }
+                    self.add_drive_entry_to_entries(entry);
+                    // todo!("there was a file/dir added on the remote since this ID is unknown")
+                    //TODO1: check if this is working
+                }
+                if self.entries.contains_key(&id) && !self.is_entry_included(&id) {
+                    debug!("{} is filtered out after the change", id);
+                    self.remove_entry(&id);
+                }
+                self.save_snapshot_record(&id);
+                if !content_unchanged {
+                    if let Err(e) = self.discard_partial_blocks(&id).await {
+                        warn!("could not drop the outdated blocks of {}: {:?}", id, e);
+                    }
+                }
+            }
+            ChangeType::Removed => {
+                //TODO: remove the cached content as well
+                debug!("{} was deleted", id);
+                self.remove_entry(&id);
+            }
+        }
+        self.notify_changed_id(id);
+        Ok(())
+    }
+
+    /// if a file that is not known yet belongs in the mount. The changes of the
+    /// others are not worth fetching the metadata for.
+    ///
+    /// New folders get their change before the files in them, so a file whose
+    /// parents are all unknown is outside of the (root) folder.
+    fn is_change_in_scope(&self, file_change: &DriveFileMetadata) -> bool {
+        if file_change.owned_by_me == Some(false) && !self.follow_changes_of_others {
+            return false;
+        }
+        // without parents it is a computer or the parents were not requested
+        let Some(parents) = &file_change.parents else {
+            return true;
+        };
+        parents.iter().any(|parent| {
+            let parent = self.get_correct_id(DriveId::from(parent));
+            parent == self.alt_root_id || self.entries.contains_key(&parent)
+        })
+    }
+
+    /// if the local content has changes that were not uploaded yet
+    fn has_pending_upload(&self, id: &DriveId) -> bool {
+        self.scheduled_uploads.contains_key(id)
+            || self.queued_uploads.contains(id)
+            || self.auth_failed_uploads.contains(id)
+    }
+    /// the file changed on the remote while its local changes still waited for
+    /// their upload. Both are kept: the local content moves to a new file next
+    /// to it, named by the [ConflictNameTemplate], and the file itself gets the
+    /// remote content on its next open
+    async fn keep_local_copy(&mut self, id: &DriveId) -> Result<DriveId> {
+        let entry = self
+            .entries
+            .get(id)
+            .context("the conflicting file is not an entry")?;
+        let name = entry
+            .metadata
+            .name
+            .clone()
+            .unwrap_or_else(|| id.to_string());
+        let mime_type = entry.metadata.mime_type.clone();
+        let parent = self
+            .parents
+            .get(id)
+            .and_then(|parents| parents.first())
+            .cloned()
+            .unwrap_or_else(|| self.alt_root_id.clone());
+        let copy_name = self
+            .conflict_name_template
+            .render(&name, Utc::now(), &host_name());
+        info!(
+            "{} changed locally and on the remote, keeping the local content as {}",
+            name, copy_name
+        );
+        let copy = self
+            .drive
+            .create_file(&copy_name, parent, mime_type.as_deref())
+            .await?;
+        let copy_id = copy
+            .id
+            .clone()
+            .map(DriveId::from)
+            .context("google drive did not return an id for the copy")?;
+        self.add_drive_entry_to_entries(copy);
+
+        let path = self.construct_path(id)?;
+        let copy_path = self.construct_path(&copy_id)?;
+        {
+            let _lock = self.file_locks.write(id).await;
+            fs::rename(&path, &copy_path).await?;
+        }
+        let size = fs::metadata(&copy_path).await?.len();
+        if let Some(copy) = self.entries.get_mut(&copy_id) {
+            copy.is_local = true;
+            copy.attr.size = size;
+            copy.metadata.size = Some(size as i64);
+        }
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.is_local = false;
+        }
+        self.scheduled_uploads.remove(id);
+        self.queued_uploads.retain(|queued| queued != id);
+        self.auth_failed_uploads.retain(|failed| failed != id);
+        self.schedule_upload(&copy_id).await?;
+        Ok(copy_id)
+    }
+    /// the md5 checksum of the local content, `None` if the file is not local or
+    /// was changed since it was last downloaded or uploaded
+    fn local_md5_checksum(&self, id: &DriveId) -> Option<String> {
+        let entry = self.entries.get(id)?;
+        if !entry.is_local {
+            return None;
+        }
+        if self.partial_files.contains_key(id) {
+            // the blocks were downloaded from the content the entry knows
+            return entry.metadata.md5_checksum.clone();
+        }
+        let local_md5s = self.local_md5s.lock().ok()?;
+        local_md5s.get(id).cloned()
+    }
+    /// the blocks of a partial download belong to the old content, together with
+    /// the blocks of the new content they would make up a file that never existed
+    async fn discard_partial_blocks(&mut self, id: &DriveId) -> ProviderResult<()> {
+        if self.partial_files.remove(id).is_none() {
+            return Ok(());
+        }
+        debug!(
+            "{} changed on the remote, dropping its downloaded blocks",
+            id
+        );
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.is_local = false;
+        }
+        let path = self.construct_path(id)?;
+        let _lock = self.file_locks.write(id).await;
+        let _ = std::fs::remove_file(BlockMap::path_for(&path));
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+        if self.file_handles.values().any(|h| h.path == path) {
+            // the open handles get the blocks of the new content
+            self.start_partial_file(id, &path).await?;
+            self.reopen_file_handles_for_path(&path);
+        }
+        Ok(())
+    }
+
+    fn notify_changed_id(&self, id: DriveId) {
+        if let Some(sender) = &self.changed_ids_sender {
+            if let Err(e) = sender.send(id) {
+                trace!("could not notify the filesystem about a change: {}", e);
+            }
+        }
+    }
+
+    #[instrument(skip(self, file_change))]
+    fn process_remote_file_moved(&mut self, id: &DriveId, file_change: &DriveFileMetadata) {
+        if let Some(changed_parents) = &file_change.parents {
+            trace!("parent was changed! {:?}", id);
+            let entry = self.entries.get(&id);
+            if let Some(entry) = entry {
+                trace!(
+                    "changed_parents: {:?} before change: {:?}",
+                    changed_parents,
+                    entry.metadata.parents
+                );
+                if Some(changed_parents) != entry.metadata.parents.as_ref() {
+                    if let Some(existing_parents) = entry.metadata.parents.clone() {
+                        for e in existing_parents {
+                            let old_parent_id = self.get_correct_id(DriveId::from(&e));
+                            trace!("(1) converted id from {} to {}", e, old_parent_id);
+                            self.remove_parent_child_relation(old_parent_id, id.clone());
+                        }
+                    }
+                    trace!("done removing old parents");
+                    for new_parent in changed_parents {
+                        let new_parent_id = self.get_correct_id(DriveId::from(new_parent));
+                        trace!("(2) converted id from {} to {} ", new_parent, new_parent_id);
+                        self.add_parent_child_relation(new_parent_id, id.clone());
+                    }
+                    trace!("done adding new parents");
+                    let entry_m = self.entries.get_mut(id);
+                    if let Some(entry_m) = entry_m {
+                        entry_m.metadata.parents = file_change.parents.clone();
+                    }
+                    trace!("done modifying metadata");
+                } else {
+                    trace!(
+                        "before and after are equal: {:?} == {:?}",
+                        Some(changed_parents),
+                        entry.metadata.parents
+                    );
+                }
+            } else {
+                warn!(
+                    "A remote file was moved but is unknown locally. is this right?: {:?}",
+                    id
+                );
+            }
+        }
+    }
+}
+/// what [DriveFileProvider::prepare_read] found for a read
+enum PreparedRead {
+    Buffered(Vec<u8>),
+    File(FileRead),
+}
+
+/// writes all of `data` and returns how much that is. A single write can
+/// accept less, the kernel would take that as a short write and the size of
+/// the entry would not match the file
+async fn write_fully(
+    writer: &mut (impl AsyncWrite + Unpin),
+    data: &[u8],
+) -> std::io::Result<usize> {
+    writer.write_all(data).await?;
+    Ok(data.len())
+}
+
+#[derive(Debug)]
+pub(crate) struct RunningTransfer {
+    direction: TransferDirection,
+    abort: AbortHandle,
+}
+
+/// the running downloads and uploads, by the id of their file
+type RunningTransfers = Arc<Mutex<HashMap<DriveId, RunningTransfer>>>;
+
+fn lock_running_transfers(
+    running_transfers: &RunningTransfers,
+) -> std::sync::MutexGuard<'_, HashMap<DriveId, RunningTransfer>> {
+    running_transfers.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn lock_read_ahead(read_ahead: &Mutex<ReadAhead>) -> std::sync::MutexGuard<'_, ReadAhead> {
+    read_ahead.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// like [DriveFileProvider::record_health], for the tasks that have a clone of the health
+fn record_health(health: &Mutex<HealthStatus>, record: impl FnOnce(&mut HealthStatus)) {
+    match health.lock() {
+        Ok(mut health) => record(&mut health),
+        Err(e) => record(&mut e.into_inner()),
+    }
+}
+
+/// remembers the md5 checksum of the local content, `None` forgets it
+fn set_local_md5(local_md5s: &Mutex<HashMap<DriveId, String>>, id: &DriveId, md5: Option<String>) {
+    let Ok(mut local_md5s) = local_md5s.lock() else {
+        return;
+    };
+    match md5 {
+        Some(md5) => local_md5s.insert(id.clone(), md5),
+        None => local_md5s.remove(id),
+    };
+}
+
+/// the folder of a backed up computer, the only folders other than the root
+/// that have no parent
+fn is_computer_folder(metadata: &DriveFileMetadata) -> bool {
+    metadata.parents.is_none() && DriveMetadata::new(metadata).is_folder()
+}
+
+/// the first characters of the md5 hash of the id, so the same id always
+/// gets the same replacement
+fn redact_id(id: &DriveId, redact: bool) -> String {
+    if !redact {
+        return id.to_string();
+    }
+    let hash = format!("{:x}", Md5::digest(id.as_bytes()));
+    format!("redacted-{}", &hash[..12])
+}
+#[instrument]
+fn process_file_change(entry: &mut FileData, change: DriveFileMetadata) -> Result<()> {
+    if let Some(size) = change.size {
+        entry.metadata.size = Some(size);
+        entry.attr.size = size as u64;
+        //TODO1: set the size of the cached file if necessary
+    }
+    if let Some(name) = change.name {
+        entry.metadata.name = Some(shown_name(name, &entry.metadata));
+    }
+    if let Some(parents) = change.parents {
+        if Some(&parents) != entry.metadata.parents.as_ref() {
+            //TODO1: change the parent child relations
+            warn!(
+                "parents changed from {:?}: {:?}",
+                entry.metadata.parents,
+                Some(parents)
+            )
+        }
+    }
+    if let Some(starred) = change.starred {
+        entry.metadata.starred = Some(starred);
+    }
+    if let Some(description) = change.description {
+        entry.metadata.description = Some(description);
+    }
+    if let Some(trashed) = change.trashed {
+        entry.metadata.trashed = Some(trashed);
+        entry.trashed = trashed;
+    }
+    if let Some(thumbnail_link) = change.thumbnail_link {
+        entry.metadata.thumbnail_link = Some(thumbnail_link);
+    }
+    let times_changed = change.modified_time.is_some()
+        || change.modified_by_me_time.is_some()
+        || change.viewed_by_me_time.is_some()
+        || change.created_time.is_some();
+    if times_changed {
+        let metadata = &mut entry.metadata;
+        metadata.modified_time = change.modified_time.or(metadata.modified_time);
+        metadata.modified_by_me_time = change.modified_by_me_time.or(metadata.modified_by_me_time);
+        metadata.viewed_by_me_time = change.viewed_by_me_time.or(metadata.viewed_by_me_time);
+        metadata.created_time = change.created_time.or(metadata.created_time);
+        FileTimes::from_metadata(metadata).apply(&mut entry.attr);
+    }
+    warn!("not all changes have been implemented");
+    //TODO2: implement all other needed changes!
+    // if let Some() = change.{
+    //      entry.metadata. = ;
+    // }
+    Ok(())
+}
+
+/// the name an entry with the name `name` on the drive is shown with. Native
+/// files are only known with the placeholder policy, they get its extension
+fn shown_name(name: String, metadata: &DriveFileMetadata) -> String {
+    let name = match is_native_file(metadata) {
+        true => placeholder_name(&name, metadata),
+        false => name,
+    };
+    local_entry_name(name)
+}
+
+/// the parents of an entry after it was moved from one of its parents to another.
+///
+/// A file can have multiple parents on drive, the other ones stay as they are.
+fn move_to_parent(parents: &[String], from: &DriveId, to: &DriveId) -> Vec<String> {
+    let mut new_parents: Vec<String> = parents
+        .iter()
+        .filter(|parent| DriveId::from(*parent) != *from)
+        .cloned()
+        .collect();
+    let to = to.to_string();
+    if !new_parents.contains(&to) {
+        new_parents.push(to);
+    }
+    new_parents
+}
+
+/// removes the fields google drive owns, so an upload does not overwrite them.
+///
+/// The `modifiedTime` gets set again from the local mtime for content uploads.
+fn remove_volatile_metadata(metadata: &mut DriveFileMetadata) {
+    metadata.size = None;
+    metadata.created_time = None;
+    metadata.trashed_time = None;
+    metadata.trashed = None;
+    metadata.modified_by_me_time = None;
+    metadata.modified_time = None;
+    metadata.shared_with_me_time = None;
+    metadata.viewed_by_me_time = None;
+    metadata.explicitly_trashed = None;
+    metadata.md5_checksum = None;
+    // metadata.parents = None;
+    // parents have to be set differently: "The parents field is not directly writable in update requests. Use the addParents and removeParents parameters instead."
+    metadata.kind = None;
+}
+
+fn convert_mime_type_to_file_type(mime_type: &str) -> Result<FileType> {
+    Ok(match mime_type {
+        "application/vnd.google-apps.folder" => FileType::Directory,
+        "application/vnd.google-apps.document"
+        | "application/vnd.google-apps.spreadsheet"
+        | "application/vnd.google-apps.drawing"
+        | "application/vnd.google-apps.form"
+        | "application/vnd.google-apps.presentation"
+        | "application/vnd.google-apps.drive-sdk"
+        | "application/vnd.google-apps.script"
+        | "application/vnd.google-apps.*"
+        //TODO: add all relevant mime types to ignore or match only the start or something
+        => return Err(anyhow!("google app files are not supported (docs, sheets, etc)")),
+        _ => FileType::RegularFile,
+    })
+}
+
+// TODOs:
+// TODO: actually upload the changes to google drive at release (start it in there, don't wait for it to finish)
+// TODO: implement the changes api again (maybe with periodic updates?)
+// TODO: decide what to do with a fsync
+// TODO: create a way to write to a file and read
+//      - read and write at least kind of work ('echo "hi" >> file' does work, opening editors like vim, nano or gui editors like kate dont, they hang up at write, open or just don't write something correct)
+//          probably truncate flags or something
+// TODO: conform to the flags passed with open like 'read-write' or 'readonly'
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::google_drive::mock::MockDrive;
+
+    use super::*;
+
+    /// creates a provider that works on a temporary cache dir and can not reach google drive
+    fn create_provider() -> (DriveFileProvider, TempDir) {
+        create_provider_with_drive(GoogleDrive::new_unauthenticated())
+    }
+
+    fn create_provider_with_drive(drive: GoogleDrive) -> (DriveFileProvider, TempDir) {
+        let dir = TempDir::new().unwrap();
+        let provider = DriveFileProvider::new(
+            drive,
+            dir.path().to_path_buf(),
+            dir.path().to_path_buf(),
+            StartPageToken::default(),
+            Duration::from_secs(10),
+            None,
+        );
+        (provider, dir)
+    }
+
+    /// adds an entry for a file that is already downloaded with the given content
+    async fn add_local_file(provider: &mut DriveFileProvider, id: &DriveId, content: &[u8]) {
+        let metadata = DriveFileMetadata {
+            id: Some(id.to_string()),
+            name: Some(id.to_string()),
+            size: Some(content.len() as i64),
+            mime_type: Some("text/plain".to_string()),
+            ..Default::default()
+        };
+        let attr = provider.create_file_attr_from_metadata(&metadata).unwrap();
+        provider.entries.insert(
+            id.clone(),
+            FileData {
+                metadata,
+                changed_metadata: Default::default(),
+                perma: false,
+                attr,
+                is_local: true,
+                trashed: false,
+                read_only: false,
+            },
+        );
+        fs::write(provider.construct_path(id).unwrap(), content)
+            .await
+            .unwrap();
+    }
+
+    async fn open(provider: &mut DriveFileProvider, id: &DriveId, flags: i32) -> u64 {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderOpenFileRequest::new(id.clone(), flags, tx);
+        provider.open_file(request).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::OpenFile(fh, _, _) => fh,
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    async fn open_cache_mode(provider: &mut DriveFileProvider, id: &DriveId) -> OpenCacheMode {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderOpenFileRequest::new(id.clone(), libc::O_RDONLY, tx);
+        provider.open_file(request).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::OpenFile(_, _, cache_mode) => cache_mode,
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    async fn flush(provider: &mut DriveFileProvider, id: &DriveId, fh: u64) {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        provider
+            .flush(ProviderFlushRequest::new(id.clone(), fh, tx))
+            .await
+            .unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::Flush => {}
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    async fn set_size(provider: &mut DriveFileProvider, id: &DriveId, size: u64) -> u64 {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request =
+            ProviderSetAttrRequest::new(id.clone(), None, None, None, Some(size), None, None, tx);
+        provider.set_attr(request).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::SetAttr(metadata) => metadata.attr.size,
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn set_attr_shrinks_file() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"0123456789").await;
+
+        assert_eq!(set_size(&mut provider, &id, 4).await, 4);
+        let content = fs::read(provider.construct_path(&id).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(content, b"0123");
+    }
+
+    #[tokio::test]
+    async fn set_attr_grows_file_with_zeros() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"0123").await;
+
+        assert_eq!(set_size(&mut provider, &id, 8).await, 8);
+        let content = fs::read(provider.construct_path(&id).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(content, b"0123\0\0\0\0");
+    }
+
+    #[tokio::test]
+    async fn set_attr_truncates_file_that_is_not_local() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"").await;
+        fs::remove_file(provider.construct_path(&id).unwrap())
+            .await
+            .unwrap();
+        provider.entries.get_mut(&id).unwrap().is_local = false;
+
+        assert_eq!(set_size(&mut provider, &id, 0).await, 0);
+        assert!(provider.entries.get(&id).unwrap().is_local);
+        assert!(provider.construct_path(&id).unwrap().exists());
+    }
+
+    #[tokio::test]
+    async fn health_check_command_reports_health() {
+        let health = Arc::new(Mutex::new(HealthStatus::default()));
+        health.lock().unwrap().record_changes_poll(false);
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(1);
+        let (state_tx, _state_rx) = tokio::sync::mpsc::channel(1);
+        let listener = tokio::spawn(DriveFileProvider::listen_for_commands(
+            command_rx,
+            health.clone(),
+            RunningTransfers::default(),
+            TransferCounter::default(),
+            state_tx,
+        ));
+
+        let (reply, status) = tokio::sync::oneshot::channel();
+        command_tx
+            .send(ProviderCommand::HealthCheck { reply })
+            .await
+            .unwrap();
+        let status = status.await.unwrap();
+        assert_eq!(status.last_changes_poll_ok, Some(false));
+        assert!(!status.is_healthy());
+
+        command_tx.send(ProviderCommand::Stop).await.unwrap();
+        listener.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancelled_downloads_interrupt_the_waiting_read() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"partial").await;
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        // a download that would never finish on its own
+        let download: JoinHandle<Result<()>> = tokio::spawn(std::future::pending());
+        provider.track_transfer(id.clone(), TransferDirection::Download, download);
+
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(1);
+        let (state_tx, _state_rx) = tokio::sync::mpsc::channel(1);
+        let listener = tokio::spawn(DriveFileProvider::listen_for_commands(
+            command_rx,
+            provider.health.clone(),
+            provider.running_transfers.clone(),
+            provider.drive.transfer_counter(),
+            state_tx,
+        ));
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReadContentRequest::new(id.clone(), 0, 7, fh, tx);
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            command_tx
+                .send(ProviderCommand::CancelTransfer {
+                    file_id: id.clone(),
+                })
+                .await
+                .unwrap();
+        };
+        let (read, ()) = tokio::join!(provider.read_content(request), cancel);
+        read.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::Error(_, errno) => assert_eq!(errno, libc::EINTR),
+            response => panic!("unexpected response: {:?}", response),
         }
-        debug!(
-            "writing to file at local path: {}",
-            file_handle.path.display()
+        assert!(provider.running_requests.is_empty());
+        assert!(!provider.entries[&id].is_local);
+        assert!(!provider.construct_path(&id).unwrap().exists());
+
+        command_tx.send(ProviderCommand::Stop).await.unwrap();
+        listener.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn transfers_can_be_listed_and_cancelled() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"changed").await;
+        // an upload that is stuck
+        let upload: JoinHandle<Result<()>> = tokio::spawn(std::future::pending());
+        provider.track_transfer(id.clone(), TransferDirection::Upload, upload);
+
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(1);
+        let (state_tx, mut state_rx) = tokio::sync::mpsc::channel(1);
+        let listener = tokio::spawn(DriveFileProvider::listen_for_commands(
+            command_rx,
+            provider.health.clone(),
+            provider.running_transfers.clone(),
+            provider.drive.transfer_counter(),
+            state_tx,
+        ));
+        let list_transfers = || async {
+            let (reply, transfers) = tokio::sync::oneshot::channel();
+            command_tx
+                .send(ProviderCommand::ListTransfers { reply })
+                .await
+                .unwrap();
+            transfers.await.unwrap()
+        };
+        assert_eq!(
+            list_transfers().await,
+            [TransferInfo {
+                file_id: id.clone(),
+                direction: TransferDirection::Upload,
+                transferred: Default::default(),
+            }]
         );
-        let file: &mut File = file;
-        trace!("seeking position: {}", request.offset);
-        file.seek(SeekFrom::Start(request.offset)).await?;
-        trace!("writing data: {:?}", request.data);
-        let m = file.metadata().await.unwrap();
-        debug!(
-            "metadata before write: size: {}; modified: {:?}",
-            m.size(),
-            m.modified()
+
+        command_tx
+            .send(ProviderCommand::CancelTransfer {
+                file_id: id.clone(),
+            })
+            .await
+            .unwrap();
+        // the cleanup happens in the file request listener
+        let command = state_rx.recv().await.unwrap();
+        provider.handle_state_command(command).await;
+        assert!(list_transfers().await.is_empty());
+        assert!(provider.running_requests.is_empty());
+        // the local changes are kept for the next upload
+        assert!(provider.entries[&id].is_local);
+        assert_eq!(
+            fs::read(provider.construct_path(&id).unwrap())
+                .await
+                .unwrap(),
+            b"changed"
         );
-        let size_written = file.write(&request.data).await?;
-        file.sync_all().await?;
-        let m = file.metadata().await.unwrap();
-        debug!(
-            "metadata after  write: size: {}; modified: {:?}",
-            m.size(),
-            m.modified()
+        // and they are not evicted before they are uploaded
+        assert_eq!(provider.evict_cache().await, 0);
+        assert!(provider.construct_path(&id).unwrap().exists());
+
+        command_tx.send(ProviderCommand::Stop).await.unwrap();
+        listener.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_after_a_download_go_to_the_downloaded_file() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"old").await;
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+        provider.get_and_open_file_handle(fh).await.unwrap();
+
+        // a download replaces the file while the handle has it open
+        let path = provider.construct_path(&id).unwrap();
+        let download_path = path.with_file_name("file.download");
+        let download: JoinHandle<Result<()>> = tokio::spawn(async move {
+            fs::write(&download_path, b"downloaded").await?;
+            fs::rename(&download_path, &path).await?;
+            Ok(())
+        });
+        provider.running_requests.insert(id.clone(), download);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 0, fh, b"D".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::WriteSize(1)
+        ));
+        assert_eq!(
+            fs::read(provider.construct_path(&id).unwrap())
+                .await
+                .unwrap(),
+            b"Downloaded"
         );
-        trace!("wrote data: size: {}", size_written);
-        file_handle.has_content_changed = true;
-        let entry = self.entries.get_mut(&file_id);
-        if entry.is_none() {
-            error!("could not find entry");
-            return Err(anyhow!("could not find entry to update metadata on"));
+    }
+
+    #[tokio::test]
+    async fn stats_command_counts_entries_and_handles() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"").await;
+        provider.add_parent_child_relation(DriveId::root(), id.clone());
+        open(&mut provider, &id, libc::O_RDONLY).await;
+
+        let (reply, stats) = tokio::sync::oneshot::channel();
+        provider
+            .handle_state_command(ProviderCommand::Stats { reply })
+            .await;
+        let stats = stats.await.unwrap();
+        assert_eq!(
+            stats,
+            ProviderStats {
+                entries: 1,
+                children: 1,
+                parents: 1,
+                file_handles: 1,
+                running_requests: 0,
+                downloaded_bytes: 0,
+                uploaded_bytes: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn folder_refresh_happens_once_per_cache_window() {
+        let (mut provider, _dir) = create_provider();
+        let folder = DriveId::from("folder");
+        assert!(!provider.refresh_folder_if_outdated(&folder).await);
+
+        provider.set_refresh_folders_on_read_dir(true);
+        assert!(provider.refresh_folder_if_outdated(&folder).await);
+        assert!(!provider.refresh_folder_if_outdated(&folder).await);
+        // other folders have their own window
+        assert!(
+            provider
+                .refresh_folder_if_outdated(&DriveId::from("other"))
+                .await
+        );
+
+        provider
+            .folder_refreshed_at
+            .insert(folder.clone(), SystemTime::now() - Duration::from_secs(11));
+        assert!(provider.refresh_folder_if_outdated(&folder).await);
+    }
+
+    #[tokio::test]
+    async fn read_dir_pages_through_large_folders() {
+        let (mut provider, _dir) = create_provider();
+        let folder = provider.alt_root_id.clone();
+        for i in 0..20_000 {
+            provider.add_drive_entry_to_entries(DriveFileMetadata {
+                id: Some(format!("file{}", i)),
+                name: Some(format!("file{}", i)),
+                mime_type: Some("text/plain".to_string()),
+                parents: Some(vec![folder.to_string()]),
+                ..Default::default()
+            });
         }
-        let entry = entry.unwrap();
-        let now = SystemTime::now();
-        entry.attr.size += size_written as u64;
-        entry.attr.atime = now;
-        entry.attr.mtime = now;
 
-        Ok(size_written as u32)
+        let mut ids = HashSet::new();
+        loop {
+            let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+            let request = ProviderReadDirRequest::new(folder.clone(), ids.len() as u64, 256, tx);
+            provider.read_dir(request).await.unwrap();
+            let ProviderResponse::ReadDir(response) = rx.recv().await.unwrap() else {
+                panic!("expected a read dir response");
+            };
+            if response.entries.is_empty() {
+                break;
+            }
+            assert!(response.entries.len() <= 256);
+            ids.extend(response.entries.into_iter().map(|entry| entry.id));
+        }
+        assert_eq!(ids.len(), 20_000);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn root_children_are_listed_under_the_alias_and_the_actual_id() {
+        let mock = MockDrive::start().await.unwrap();
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        let add_file = |provider: &mut DriveFileProvider, id: &str, parent: String| {
+            provider.add_drive_entry_to_entries(DriveFileMetadata {
+                id: Some(id.to_string()),
+                name: Some(id.to_string()),
+                mime_type: Some("text/plain".to_string()),
+                parents: Some(vec![parent]),
+                ..Default::default()
+            });
+        };
+        // added before the actual id of the root is known
+        add_file(&mut provider, "a", DriveId::root().to_string());
+        provider.add_root_entry().await.unwrap();
+        add_file(&mut provider, "b", mock.root_id().to_string());
+        add_file(&mut provider, "c", DriveId::root().to_string());
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReadDirRequest::new(DriveId::root(), 0, 256, tx);
+        provider.read_dir(request).await.unwrap();
+        let ProviderResponse::ReadDir(response) = rx.recv().await.unwrap() else {
+            panic!("expected a read dir response");
+        };
+        let mut names: Vec<_> = response.entries.into_iter().map(|e| e.name).collect();
+        names.sort();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(!provider.children.contains_key(&DriveId::root()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn native_files_follow_the_policy() {
+        let mock = MockDrive::start().await.unwrap();
+        let list_names = |policy: NativeFilePolicy| {
+            let drive = mock.drive();
+            async move {
+                let (mut provider, dir) = create_provider_with_drive(drive);
+                provider.set_native_file_policy(policy);
+                provider.add_root_entry().await.unwrap();
+                provider.add_drive_entry_to_entries(DriveFileMetadata {
+                    id: Some("doc".to_string()),
+                    name: Some("Report".to_string()),
+                    mime_type: Some("application/vnd.google-apps.document".to_string()),
+                    web_view_link: Some("https://docs.google.com/document/d/doc".to_string()),
+                    parents: Some(vec![DriveId::root().to_string()]),
+                    ..Default::default()
+                });
+                let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+                let request = ProviderReadDirRequest::new(DriveId::root(), 0, 256, tx);
+                provider.read_dir(request).await.unwrap();
+                let ProviderResponse::ReadDir(response) = rx.recv().await.unwrap() else {
+                    panic!("expected a read dir response");
+                };
+                let names: Vec<_> = response.entries.into_iter().map(|e| e.name).collect();
+                (provider, dir, names)
+            }
+        };
+
+        let (_, _dir, names) = list_names(NativeFilePolicy::Hide).await;
+        assert!(names.is_empty());
+
+        let (mut provider, _dir, names) = list_names(NativeFilePolicy::Placeholder).await;
+        assert_eq!(names, ["Report.gdoc"]);
+        let id = DriveId::from("doc");
+        assert!(provider.check_writable(&id).is_err());
+        let size = provider.entries[&id].attr.size;
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        let content = read(&mut provider, &id, fh, 0, size as usize).await;
+        let content: serde_json::Value = serde_json::from_slice(&content).unwrap();
+        assert_eq!(content["url"], "https://docs.google.com/document/d/doc");
+        assert_eq!(content["doc_id"], "doc");
+    }
+
+    /// the index of the provider has to resolve names like [find_child_by_name],
+    /// which the legacy filesystem uses
+    #[tokio::test]
+    async fn name_index_resolves_like_find_child_by_name() {
+        let (mut provider, _dir) = create_provider();
+        let parent = DriveId::root();
+        for (id, name) in [("a", "Report.txt"), ("b", "report.TXT"), ("c", "other")] {
+            let id = DriveId::from(id);
+            add_local_file(&mut provider, &id, b"").await;
+            provider.entries.get_mut(&id).unwrap().metadata.name = Some(name.to_string());
+            provider.add_parent_child_relation(parent.clone(), id);
+        }
+        let assert_same_resolution = |provider: &DriveFileProvider| {
+            let children: Vec<_> = provider.children[&parent]
+                .iter()
+                .map(|id| (id, provider.entries[id].metadata.name.as_ref()))
+                .collect();
+            for name in ["report.txt", "REPORT.TXT", "Other", "missing"] {
+                assert_eq!(
+                    provider
+                        .find_first_child_by_name(name, &parent)
+                        .and_then(|e| e.get_id()),
+                    find_child_by_name(children.clone(), name).cloned(),
+                    "{}",
+                    name
+                );
+            }
+        };
+        assert_same_resolution(&provider);
+
+        provider.remove_parent_child_relation(parent.clone(), DriveId::from("a"));
+        assert_same_resolution(&provider);
+    }
+
+    #[tokio::test]
+    async fn dump_state_redacts_ids() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("secret_file_id");
+        add_local_file(&mut provider, &id, b"content").await;
+        provider.add_parent_child_relation(DriveId::root(), id.clone());
+
+        let dump = provider.dump_state(false);
+        assert_eq!(dump["entries"][0]["id"], "secret_file_id");
+        assert_eq!(dump["entries"][0]["name"], "secret_file_id");
+        assert_eq!(dump["entries"][0]["size"], 7);
+
+        let dump = provider.dump_state(true);
+        assert!(!dump.to_string().contains("secret_file_id"));
+        let redacted_id = dump["entries"][0]["id"].as_str().unwrap();
+        assert_eq!(dump["parents"][redacted_id].as_array().unwrap().len(), 1);
+        assert_eq!(dump["entries"][0]["name"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn zero_cache_time_checks_for_changes_every_time() {
+        let (mut provider, _dir) = create_provider();
+        provider.last_checked_for_changes = SystemTime::now();
+        assert!(!provider.is_time_to_check_for_changes());
+
+        provider.allowed_cache_time = Duration::ZERO;
+        assert!(provider.is_time_to_check_for_changes());
+        provider.last_checked_for_changes = SystemTime::now();
+        assert!(provider.is_time_to_check_for_changes());
+    }
+
+    #[tokio::test]
+    async fn remote_change_notifies_filesystem() {
+        let (mut provider, _dir) = create_provider();
+        let (changed_ids_tx, changed_ids_rx) = std::sync::mpsc::channel();
+        provider.changed_ids_sender = Some(changed_ids_tx);
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"content").await;
+
+        let change = Change {
+            id: id.clone(),
+            kind: ChangeType::File(DriveFileMetadata {
+                size: Some(42),
+                ..Default::default()
+            }),
+            time: Default::default(),
+        };
+        provider.process_change(change).await.unwrap();
+        assert_eq!(provider.entries.get(&id).unwrap().attr.size, 42);
+        assert_eq!(changed_ids_rx.try_recv().unwrap(), id);
+    }
+
+    #[tokio::test]
+    async fn changes_outside_of_the_mount_are_skipped() {
+        // the drive can't be reached, fetching the metadata would fail
+        let (mut provider, _dir) = create_provider();
+        let (changed_ids_tx, changed_ids_rx) = std::sync::mpsc::channel();
+        provider.changed_ids_sender = Some(changed_ids_tx);
+        let change = |id: &str, owned_by_me, parent: &str| Change {
+            id: DriveId::from(id),
+            kind: ChangeType::File(DriveFileMetadata {
+                id: Some(id.to_string()),
+                name: Some(id.to_string()),
+                owned_by_me: Some(owned_by_me),
+                parents: Some(vec![parent.to_string()]),
+                ..Default::default()
+            }),
+            time: Default::default(),
+        };
+        let root = provider.alt_root_id.to_string();
+
+        provider
+            .process_change(change("shared", false, &root))
+            .await
+            .unwrap();
+        provider
+            .process_change(change("elsewhere", true, "unknown-folder"))
+            .await
+            .unwrap();
+        assert!(!provider.entries.contains_key(&DriveId::from("shared")));
+        assert!(!provider.entries.contains_key(&DriveId::from("elsewhere")));
+        assert!(changed_ids_rx.try_recv().is_err());
+
+        // followed changes fetch the metadata, which fails here
+        provider.set_follow_changes_of_others(true);
+        let e = provider
+            .process_change(change("shared", false, &root))
+            .await;
+        assert!(e.is_err());
+    }
+
+    #[tokio::test]
+    async fn open_keeps_the_cache_until_a_remote_change() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"content").await;
+
+        assert_eq!(
+            open_cache_mode(&mut provider, &id).await,
+            OpenCacheMode::KeepCache
+        );
+        let change = Change {
+            id: id.clone(),
+            kind: ChangeType::File(DriveFileMetadata {
+                size: Some(42),
+                ..Default::default()
+            }),
+            time: Default::default(),
+        };
+        provider.process_change(change).await.unwrap();
+        assert_eq!(
+            open_cache_mode(&mut provider, &id).await,
+            OpenCacheMode::Invalidate
+        );
+        assert_eq!(
+            open_cache_mode(&mut provider, &id).await,
+            OpenCacheMode::KeepCache
+        );
+
+        provider.set_open_cache_mode(OpenCacheMode::DirectIo);
+        assert_eq!(
+            open_cache_mode(&mut provider, &id).await,
+            OpenCacheMode::DirectIo
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn metadata_only_change_keeps_the_cache() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let id = mock.add_file("a.txt", &root, b"content");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+        provider
+            .wait_for_drive_request_if_exists(&id)
+            .await
+            .unwrap();
+
+        let change = |content: &[u8]| Change {
+            id: id.clone(),
+            kind: ChangeType::File(DriveFileMetadata {
+                md5_checksum: Some(format!("{:x}", Md5::digest(content))),
+                viewed_by_me_time: Some(Default::default()),
+                ..Default::default()
+            }),
+            time: Default::default(),
+        };
+        provider.process_change(change(b"content")).await.unwrap();
+        assert!(!provider.changed_on_remote.contains(&id));
+
+        // the local change is not what the drive has until it is uploaded
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 0, fh, b"changed".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
+        provider.process_change(change(b"content")).await.unwrap();
+        assert!(provider.changed_on_remote.remove(&id));
+
+        flush(&mut provider, &id, fh).await;
+        provider.process_change(change(b"changed")).await.unwrap();
+        assert!(!provider.changed_on_remote.contains(&id));
+        assert_eq!(
+            open_cache_mode(&mut provider, &id).await,
+            OpenCacheMode::KeepCache
+        );
+    }
+
+    #[tokio::test]
+    async fn attr_times_follow_the_drive_times() {
+        let (mut provider, _dir) = create_provider();
+        let time = |secs| google_drive3::chrono::DateTime::from_timestamp(secs, 0).unwrap();
+        let metadata = DriveFileMetadata {
+            id: Some("file".to_string()),
+            name: Some("file".to_string()),
+            mime_type: Some("text/plain".to_string()),
+            created_time: Some(time(100)),
+            modified_time: Some(time(200)),
+            modified_by_me_time: Some(time(300)),
+            viewed_by_me_time: Some(time(250)),
+            ..Default::default()
+        };
+        provider.add_drive_entry_to_entries(metadata);
+        let id = DriveId::from("file");
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let attr = provider.entries.get(&id).unwrap().attr;
+        assert_eq!(
+            (attr.crtime, attr.mtime, attr.ctime, attr.atime),
+            (at(100), at(200), at(200), at(300))
+        );
+
+        // someone else edits it and this user views it later, the created time stays
+        let change = Change {
+            id: id.clone(),
+            kind: ChangeType::File(DriveFileMetadata {
+                modified_time: Some(time(400)),
+                viewed_by_me_time: Some(time(500)),
+                ..Default::default()
+            }),
+            time: Default::default(),
+        };
+        provider.process_change(change).await.unwrap();
+        let attr = provider.entries.get(&id).unwrap().attr;
+        assert_eq!(
+            (attr.crtime, attr.mtime, attr.ctime, attr.atime),
+            (at(100), at(400), at(400), at(500))
+        );
+    }
+
+    #[tokio::test]
+    async fn truncate_then_write_results_in_written_content() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"some old content\n").await;
+
+        // echo "x" > file
+        let fh = open(&mut provider, &id, libc::O_WRONLY | libc::O_TRUNC).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request =
+            ProviderSetAttrRequest::new(id.clone(), None, None, None, Some(0), None, Some(fh), tx);
+        provider.set_attr(request).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::SetAttr(m) if m.attr.size == 0
+        ));
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 0, fh, b"x\n".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::WriteSize(2)
+        ));
+        assert_eq!(provider.entries.get(&id).unwrap().attr.size, 2);
+        assert!(provider.file_handles.get(&fh).unwrap().has_content_changed);
+
+        // cat file
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReadContentRequest::new(id.clone(), 0, 4096, fh, tx);
+        provider.read_content(request).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::ReadContent(content) => assert_eq!(content, b"x\n"),
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    async fn read(
+        provider: &mut DriveFileProvider,
+        id: &DriveId,
+        fh: u64,
+        offset: u64,
+        size: usize,
+    ) -> Vec<u8> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReadContentRequest::new(id.clone(), offset, size, fh, tx);
+        provider.read_content(request).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::ReadContent(content) => content,
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn sequential_reads_are_served_from_the_read_ahead() {
+        let (mut provider, _dir) = create_provider();
+        provider.set_read_ahead_size(1000);
+        let id = DriveId::from("file");
+        let content: Vec<u8> = (0..2500).map(|i| (i % 251) as u8).collect();
+        add_local_file(&mut provider, &id, &content).await;
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+
+        let mut offset = 0;
+        while offset < content.len() {
+            let data = read(&mut provider, &id, fh, offset as u64, 100).await;
+            assert_eq!(data, content[offset..(offset + 100).min(content.len())]);
+            offset += 100;
+        }
+        assert!(read(&mut provider, &id, fh, offset as u64, 100)
+            .await
+            .is_empty());
+
+        // a write has to show up in the next read, even if that was read ahead before
+        assert_eq!(read(&mut provider, &id, fh, 0, 10).await, content[..10]);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 10, fh, b"XX".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
+        assert_eq!(read(&mut provider, &id, fh, 10, 2).await, b"XX");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn opening_an_unknown_file_loads_it() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("new.txt", &mock.root_id(), b"hello");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        assert!(!provider.entries.contains_key(&id));
+
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        assert_eq!(read(&mut provider, &id, fh, 0, 5).await, b"hello");
+        assert!(provider.entries.contains_key(&id));
+    }
+
+    #[tokio::test]
+    async fn reads_of_different_files_overlap() {
+        let (mut provider, _dir) = create_provider();
+        let (a, b) = (DriveId::from("a"), DriveId::from("b"));
+        add_local_file(&mut provider, &a, b"aaa").await;
+        add_local_file(&mut provider, &b, b"bbb").await;
+        let fh_a = open(&mut provider, &a, libc::O_RDONLY).await;
+        let fh_b = open(&mut provider, &b, libc::O_RDONLY).await;
+        provider.set_max_concurrent_reads(2);
+
+        // a read of another file that is busy, like on a slow disk
+        let busy = provider.read_permits.clone().acquire_owned().await;
+        assert_eq!(read(&mut provider, &a, fh_a, 0, 3).await, b"aaa");
+
+        // the read of b waits for its turn in its own task
+        let busy_too = provider.read_permits.clone().acquire_owned().await;
+        let (tx, mut rx_b) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReadContentRequest::new(b.clone(), 0, 3, fh_b, tx);
+        provider.read_content(request).await.unwrap();
+        assert!(rx_b.try_recv().is_err());
+        drop(busy);
+        assert!(matches!(
+            rx_b.recv().await.unwrap(),
+            ProviderResponse::ReadContent(data) if data == b"bbb"
+        ));
+        drop(busy_too);
+    }
+
+    /// run with `cargo test --release -- --ignored --nocapture read_ahead_benchmark`
+    #[tokio::test]
+    #[ignore]
+    async fn read_ahead_benchmark() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, &vec![7u8; 64 << 20]).await;
+        for read_ahead_size in [0, READ_AHEAD_SIZE] {
+            provider.set_read_ahead_size(read_ahead_size);
+            let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+            let start = std::time::Instant::now();
+            let mut offset = 0;
+            loop {
+                let data = read(&mut provider, &id, fh, offset, 4096).await;
+                if data.is_empty() {
+                    break;
+                }
+                offset += data.len() as u64;
+            }
+            let elapsed = start.elapsed();
+            println!(
+                "read ahead size {}: read {} MiB in 4 KiB reads in {:?} ({:.0} MiB/s)",
+                read_ahead_size,
+                offset >> 20,
+                elapsed,
+                (offset >> 20) as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unsupported_requests_keep_the_provider_running() {
+        let mock = MockDrive::start().await.unwrap();
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        let (request_tx, request_rx) = tokio::sync::mpsc::channel(1);
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(1);
+        let listener = tokio::spawn(async move {
+            provider.listen(request_rx, command_rx).await;
+        });
+
+        request_tx.send(ProviderRequest::Unknown).await.unwrap();
+        command_tx.send(ProviderCommand::PauseSync).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderRequest::RootId(ProviderRootIdRequest::new(tx));
+        request_tx.send(request).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::RootId(id) if id == mock.root_id()
+        ));
+
+        command_tx.send(ProviderCommand::Stop).await.unwrap();
+        listener.await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn flush_uploads_once_for_a_dup_closed_twice() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("file.txt", &mock.root_id(), b"hello");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        add_local_file(&mut provider, &id, b"hello").await;
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 0, fh, b"HELLO".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
+
+        // the close of the first descriptor makes the change durable
+        flush(&mut provider, &id, fh).await;
+        assert_eq!(mock.upload_count(), 1);
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"HELLO"[..]));
+
+        // the close of the dup'd descriptor and the release have nothing left to do
+        flush(&mut provider, &id, fh).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        provider
+            .release_file(ProviderReleaseFileRequest::new(id.clone(), fh, tx))
+            .await
+            .unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::ReleaseFile
+        ));
+        assert!(provider.running_requests.is_empty());
+        assert_eq!(mock.upload_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn offline_reads_only_serve_local_files() {
+        let (mut provider, _dir) = create_provider();
+        let local = DriveId::from("local");
+        add_local_file(&mut provider, &local, b"cached").await;
+        let remote = DriveId::from("remote");
+        provider.add_drive_entry_to_entries(DriveFileMetadata {
+            id: Some(remote.to_string()),
+            name: Some(remote.to_string()),
+            mime_type: Some("text/plain".to_string()),
+            ..Default::default()
+        });
+        provider.set_offline(true).await;
+
+        let fh = open(&mut provider, &local, libc::O_RDONLY).await;
+        assert_eq!(read(&mut provider, &local, fh, 0, 100).await, b"cached");
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderOpenFileRequest::new(remote.clone(), libc::O_RDONLY, tx);
+        provider.open_file(request).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::Error(_, libc::ENETDOWN)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_change_updates_one_record_of_the_snapshot() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let folder = mock.add_folder("folder", &root);
+        let changed = mock.add_file("changed.txt", &folder, b"old");
+        let removed = mock.add_file("removed.txt", &folder, b"removed");
+        mock.add_file("unchanged.txt", &folder, b"unchanged");
+        let drive = mock.drive();
+        let (mut provider, dir) = create_provider_with_drive(drive.clone());
+        provider.changes_start_token = drive.get_start_page_token().await.unwrap();
+        provider.initialize_entries().await.unwrap();
+        let before = snapshot::tests::records(dir.path());
+        assert_eq!(before.len(), 4);
+
+        mock.set_content(&changed, b"new content");
+        provider.check_and_apply_changes().await;
+        let after = snapshot::tests::records(dir.path());
+        let updated: Vec<_> = after
+            .iter()
+            .filter(|(name, record)| before.get(*name) != Some(*record))
+            .map(|(name, _)| name.clone())
+            .collect();
+        assert_eq!(updated, vec![format!("{}.json", changed)]);
+        assert_eq!(after.len(), before.len());
+
+        provider.remove_entry(&removed);
+        let after_removal = snapshot::tests::records(dir.path());
+        assert_eq!(after_removal.len(), before.len() - 1);
+        assert!(!after_removal.contains_key(&format!("{}.json", removed)));
+        let loaded = load_snapshot(dir.path()).unwrap();
+        assert_eq!(
+            loaded.start_page_token.start_page_token,
+            provider.changes_start_token.start_page_token
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn offline_start_uses_the_entries_of_the_last_run() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let folder = mock.add_folder("folder", &root);
+        let cached = mock.add_file("cached.txt", &folder, b"cached");
+        let remote = mock.add_file("remote.txt", &folder, b"remote");
+        let drive = mock.drive();
+        let (mut provider, dir) = create_provider_with_drive(drive.clone());
+        assert!(!DriveFileProvider::can_start_offline(dir.path()));
+        provider.changes_start_token = drive.get_start_page_token().await.unwrap();
+        provider.initialize_entries().await.unwrap();
+        open(&mut provider, &cached, libc::O_RDONLY).await;
+        provider
+            .wait_for_drive_request_if_exists(&cached)
+            .await
+            .unwrap();
+        // like at the end of listen
+        provider.save_snapshot_state();
+        drop(provider);
+        assert!(DriveFileProvider::can_start_offline(dir.path()));
+
+        let added = mock.add_file("added.txt", &folder, b"added");
+        let mut provider = DriveFileProvider::new(
+            drive,
+            dir.path().to_path_buf(),
+            dir.path().to_path_buf(),
+            StartPageToken::default(),
+            Duration::from_secs(10),
+            None,
+        );
+        provider.set_offline(true).await;
+        provider.initialize_entries().await.unwrap();
+        let found = lookup(&mut provider, &DriveId::root(), "folder").await;
+        assert_eq!(found.map(|f| f.id), Some(folder.clone()));
+        let fh = open(&mut provider, &cached, libc::O_RDONLY).await;
+        assert_eq!(read(&mut provider, &cached, fh, 0, 100).await, b"cached");
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderOpenFileRequest::new(remote.clone(), libc::O_RDONLY, tx);
+        provider.open_file(request).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::Error(_, libc::ENETDOWN)
+        ));
+        assert!(lookup(&mut provider, &folder, "added.txt").await.is_none());
+
+        // online again, the changes since the last run are applied
+        provider.set_offline(false).await;
+        provider.check_and_apply_changes().await;
+        let found = lookup(&mut provider, &folder, "added.txt").await;
+        assert_eq!(found.map(|f| f.id), Some(added));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn offline_writes_are_uploaded_when_online_again() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("file.txt", &mock.root_id(), b"hello");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        add_local_file(&mut provider, &id, b"hello").await;
+        provider.set_offline(true).await;
+
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 0, fh, b"HELLO".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
+        flush(&mut provider, &id, fh).await;
+        assert_eq!(mock.upload_count(), 0);
+        assert_eq!(provider.queued_uploads, vec![id.clone()]);
+
+        provider.set_offline(false).await;
+        provider
+            .wait_for_running_drive_request_if_exists(&id)
+            .await
+            .unwrap();
+        assert!(provider.queued_uploads.is_empty());
+        assert_eq!(mock.upload_count(), 1);
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"HELLO"[..]));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn partial_reads_only_download_the_missing_blocks() {
+        const BLOCK: u64 = block_map::BLOCK_SIZE;
+        let mock = MockDrive::start().await.unwrap();
+        let content: Vec<u8> = (0..4 * BLOCK + 100).map(|i| (i / BLOCK) as u8).collect();
+        let id = mock.add_file("file.bin", &mock.root_id(), &content);
+        let (mut provider, dir) = create_provider_with_drive(mock.drive());
+        provider.set_partial_downloads(true);
+        provider.set_read_ahead_size(0);
+        provider.load_entry_if_unknown(&id).await.unwrap();
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        assert!(mock.downloaded_ranges(&id).is_empty());
+
+        let data = read(&mut provider, &id, fh, BLOCK, BLOCK as usize).await;
+        assert_eq!(data, &content[BLOCK as usize..2 * BLOCK as usize]);
+        assert_eq!(mock.downloaded_ranges(&id), [(BLOCK, BLOCK)]);
+
+        // the second block is there already, only the first and third are fetched
+        let data = read(&mut provider, &id, fh, 100, 2 * BLOCK as usize).await;
+        assert_eq!(data, &content[100..100 + 2 * BLOCK as usize]);
+        assert_eq!(
+            mock.downloaded_ranges(&id),
+            [(BLOCK, BLOCK), (0, BLOCK), (2 * BLOCK, BLOCK)]
+        );
+        let map_path = BlockMap::path_for(&dir.path().join(id.as_str()));
+        assert_eq!(
+            BlockMap::load(&map_path)
+                .unwrap()
+                .missing_ranges(0, u64::MAX),
+            [(3 * BLOCK, BLOCK + 100)]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn remote_changes_drop_the_downloaded_blocks() {
+        const BLOCK: u64 = block_map::BLOCK_SIZE;
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("file.bin", &mock.root_id(), &vec![1; 2 * BLOCK as usize]);
+        let drive = mock.drive();
+        let (mut provider, dir) = create_provider_with_drive(drive.clone());
+        provider.set_partial_downloads(true);
+        provider.set_read_ahead_size(0);
+        provider.changes_start_token = drive.get_start_page_token().await.unwrap();
+        provider.load_entry_if_unknown(&id).await.unwrap();
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        assert_eq!(read(&mut provider, &id, fh, 0, 1).await, [1]);
+
+        mock.set_content(&id, &vec![2; 2 * BLOCK as usize]);
+        provider.last_checked_for_changes = SystemTime::UNIX_EPOCH;
+        provider.check_and_apply_changes().await;
+        // the open handle reads the new content, not the old blocks
+        assert_eq!(read(&mut provider, &id, fh, 0, 1).await, [2]);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReleaseFileRequest::new(id.clone(), fh, tx);
+        provider.release_file(request).await.unwrap();
+        rx.recv().await.unwrap();
+        mock.set_content(&id, &vec![3; 2 * BLOCK as usize]);
+        provider.last_checked_for_changes = SystemTime::UNIX_EPOCH;
+        provider.check_and_apply_changes().await;
+        let path = dir.path().join(id.as_str());
+        assert!(!provider.partial_files.contains_key(&id));
+        assert!(!provider.entries[&id].is_local);
+        assert!(!path.exists());
+        assert!(!BlockMap::path_for(&path).exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn files_with_the_same_content_share_one_copy() {
+        let mock = MockDrive::start().await.unwrap();
+        let a = mock.add_file("a.txt", &mock.root_id(), b"same");
+        let b = mock.add_file("b.txt", &mock.root_id(), b"same");
+        let (mut provider, dir) = create_provider_with_drive(mock.drive());
+        provider.set_dedupe_cache(true);
+        for id in [&a, &b] {
+            provider.load_entry_if_unknown(id).await.unwrap();
+            let fh = open(&mut provider, id, libc::O_RDONLY).await;
+            assert_eq!(read(&mut provider, id, fh, 0, 100).await, b"same");
+        }
+        let (path_a, path_b) = (dir.path().join(a.as_str()), dir.path().join(b.as_str()));
+        let stored = std::fs::read_dir(dir.path().join(".content")).unwrap();
+        assert_eq!(stored.count(), 1);
+        let ino = |path: &PathBuf| std::fs::metadata(path).unwrap().ino();
+        assert_eq!(ino(&path_a), ino(&path_b));
+
+        // writing gives the file its own copy first
+        let fh = open(&mut provider, &b, libc::O_RDWR).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(b.clone(), 0, fh, b"diff".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
+        assert_ne!(ino(&path_a), ino(&path_b));
+        assert_eq!(std::fs::read(&path_a).unwrap(), b"same");
+        assert_eq!(std::fs::read(&path_b).unwrap(), b"diff");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pinned_files_survive_an_eviction() {
+        let mock = MockDrive::start().await.unwrap();
+        let pinned = mock.add_file("pinned.txt", &mock.root_id(), b"keep");
+        let cached = mock.add_file("cached.txt", &mock.root_id(), b"evict");
+        let dir = TempDir::new().unwrap();
+        let (cache_dir, perma_dir) = (dir.path().join("cache"), dir.path().join("perma"));
+        std::fs::create_dir(&cache_dir).unwrap();
+        std::fs::create_dir(&perma_dir).unwrap();
+        let create_provider = || {
+            DriveFileProvider::new(
+                mock.drive(),
+                cache_dir.clone(),
+                perma_dir.clone(),
+                StartPageToken::default(),
+                Duration::from_secs(10),
+                None,
+            )
+        };
+        let mut provider = create_provider();
+        for id in [&pinned, &cached] {
+            provider.load_entry_if_unknown(id).await.unwrap();
+        }
+        let fh = open(&mut provider, &cached, libc::O_RDONLY).await;
+        read(&mut provider, &cached, fh, 0, 100).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReleaseFileRequest::new(cached.clone(), fh, tx);
+        provider.release_file(request).await.unwrap();
+        rx.recv().await.unwrap();
+
+        provider.pin(&pinned).await.unwrap();
+        // the download runs in the background
+        provider
+            .wait_for_drive_request_if_exists(&pinned)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(perma_dir.join(pinned.as_str())).unwrap(),
+            b"keep"
+        );
+        assert_eq!(provider.evict_cache().await, 1);
+        assert!(!cache_dir.join(cached.as_str()).exists());
+        assert!(!provider.entries[&cached].is_local);
+        assert!(provider.entries[&pinned].is_local);
+
+        // the pin is still there after a restart
+        let mut provider = create_provider();
+        provider.load_entry_if_unknown(&pinned).await.unwrap();
+        assert!(provider.entries[&pinned].perma);
+    }
+
+    /// accepts at most 3 bytes per write
+    struct ShortWriter(Vec<u8>);
+
+    impl AsyncWrite for ShortWriter {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            let len = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..len]);
+            std::task::Poll::Ready(Ok(len))
+        }
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn short_writes_are_continued() {
+        let mut writer = ShortWriter(Vec::new());
+        let written = write_fully(&mut writer, b"hello world").await.unwrap();
+        assert_eq!(written, 11);
+        assert_eq!(writer.0, b"hello world");
+
+        // the size of the entry follows what was written
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"").await;
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+        let data = vec![7; 1 << 20];
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 5, fh, data.clone(), tx);
+        provider.write_content(request).await.unwrap();
+        let ProviderResponse::WriteSize(written) = rx.recv().await.unwrap() else {
+            panic!("expected a write size");
+        };
+        assert_eq!(written as usize, data.len());
+        assert_eq!(provider.entries[&id].attr.size, 5 + data.len() as u64);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn full_storage_fails_the_flush_with_edquot() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("file.txt", &mock.root_id(), b"hello");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        add_local_file(&mut provider, &id, b"hello").await;
+        assert_ne!(provider.get_quota().await.unwrap().available(), Some(0));
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 0, fh, b"HELLO".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
+
+        mock.set_storage_full(true);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        provider
+            .flush(ProviderFlushRequest::new(id.clone(), fh, tx))
+            .await
+            .unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::Error(_, libc::EDQUOT)
+        ));
+        // df agrees with the failed write
+        assert_eq!(provider.get_quota().await.unwrap().available(), Some(0));
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"hello"[..]));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mkdir_inside_a_just_created_folder() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+
+        let mut parent = root.clone();
+        for name in ["a", "b", "c"] {
+            let folder = provider.mkdir_inner(&parent, name).await.unwrap();
+            assert_eq!(folder.attr.kind, FileType::Directory);
+            assert_eq!(mock.find(name, &parent), Some(folder.id.clone()));
+            parent = folder.id;
+        }
+        assert!(matches!(
+            provider.mkdir_inner(&root, "a").await,
+            Err(ProviderError::AlreadyExists(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn removal_follows_the_deletion_policy() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let trashed = mock.add_file("trashed.txt", &root, b"a");
+        let folder = mock.add_folder("folder", &root);
+        let deleted = mock.add_file("deleted.txt", &folder, b"b");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+
+        // the default keeps the file restorable
+        provider
+            .remove_inner(&root, "trashed.txt", false)
+            .await
+            .unwrap();
+        assert_eq!(mock.metadata(&trashed).unwrap().trashed, Some(true));
+        assert!(provider
+            .find_first_child_by_name("trashed.txt", &root)
+            .is_none());
+
+        provider.set_deletion_policy(DeletionPolicy::Hard);
+        assert!(matches!(
+            provider.remove_inner(&root, "folder", false).await,
+            Err(ProviderError::IsDirectory(_))
+        ));
+        assert!(matches!(
+            provider.remove_inner(&root, "folder", true).await,
+            Err(ProviderError::NotEmpty(_))
+        ));
+        provider
+            .remove_inner(&folder, "deleted.txt", false)
+            .await
+            .unwrap();
+        provider.remove_inner(&root, "folder", true).await.unwrap();
+        assert!(mock.metadata(&deleted).is_none());
+        assert!(mock.metadata(&folder).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn trashed_files_are_only_listed_when_included() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        mock.add_file("kept.txt", &root, b"a");
+        let trashed = mock.add_file("trashed.txt", &root, b"b");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        provider
+            .remove_inner(&root, "trashed.txt", false)
+            .await
+            .unwrap();
+
+        for include_trashed in [false, true] {
+            let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+            provider.set_include_trashed(include_trashed);
+            provider.initialize_entries().await.unwrap();
+
+            let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+            let request = ProviderReadDirRequest::new(root.clone(), 0, 256, tx);
+            provider.read_dir(request).await.unwrap();
+            let ProviderResponse::ReadDir(response) = rx.recv().await.unwrap() else {
+                panic!("expected a read dir response");
+            };
+            let mut names: Vec<_> = response.entries.into_iter().map(|e| e.name).collect();
+            names.sort();
+            let expected = if include_trashed {
+                vec!["kept.txt", "trashed.txt"]
+            } else {
+                vec!["kept.txt"]
+            };
+            assert_eq!(names, expected);
+
+            let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+            let request = ProviderLookupRequest::new(root.clone(), "trashed.txt".into(), tx);
+            provider.lookup(request).await.unwrap();
+            let ProviderResponse::Lookup(found) = rx.recv().await.unwrap() else {
+                panic!("expected a lookup response");
+            };
+            assert_eq!(
+                found.map(|f| f.id),
+                include_trashed.then(|| trashed.clone())
+            );
+        }
     }
 
-    async fn read_content_from_file(
-        &mut self,
-        request: &ProviderReadContentRequest,
-    ) -> Result<Vec<u8>> {
-        let file_handle = self.get_and_open_file_handle(request.fh).await?;
-        let file = file_handle.file.as_mut().expect("we just opened this...");
-        if !file_handle.flags.can_read() {
-            error!("File handle does not have read permissions");
-            return Err(anyhow!("File handle does not have read permissions"));
+    #[tokio::test(flavor = "multi_thread")]
+    async fn root_folder_is_shown_as_the_root() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        mock.add_file("outside.txt", &root, b"a");
+        let folder = mock.add_folder("Photos", &root);
+        let inside = mock.add_file("inside.jpg", &folder, b"b");
+        let folder_mtime = mock.metadata(&folder).unwrap().modified_time.unwrap();
+
+        for root_label in [None, Some("Label".to_string())] {
+            let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+            provider.set_root_folder(folder.clone());
+            provider.set_root_label(root_label.clone());
+            provider.initialize_entries().await.unwrap();
+
+            // what stat on the mountpoint asks for
+            let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+            let request = ProviderMetadataRequest::new(DriveId::root(), tx);
+            provider.metadata(request).await.unwrap();
+            let ProviderResponse::Metadata(metadata) = rx.recv().await.unwrap() else {
+                panic!("expected a metadata response");
+            };
+            assert_eq!(metadata.id, folder);
+            assert_eq!(metadata.name, root_label.as_deref().unwrap_or("Photos"));
+            assert_eq!(metadata.attr.kind, FileType::Directory);
+            assert_eq!(metadata.attr.mtime, SystemTime::from(folder_mtime));
+
+            assert!(provider.entries.contains_key(&inside));
+            assert!(provider
+                .find_first_child_by_name("outside.txt", &root)
+                .is_none());
+            assert_eq!(provider.entries.len(), 2);
         }
-        trace!("seeking position in file: {}", request.offset);
-        file.seek(SeekFrom::Start(request.offset)).await?;
-        let mut buf = vec![0; request.size as usize];
-        trace!("reading to buffer: size: {}", request.size);
-        let size_read = file.read(&mut buf).await?;
-        if size_read != request.size {
-            debug!(
-                "did not read the targeted size: target size: {}, actual size: {}",
-                request.size, size_read
-            );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn computers_are_read_only_top_level_folders() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        mock.add_file("a.txt", &root, b"a");
+        let laptop = mock.add_computer("Laptop");
+        let notes = mock.add_file("notes.txt", &laptop, b"b");
+
+        for include_computers in [false, true] {
+            let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+            provider.set_include_computers(include_computers);
+            provider.initialize_entries().await.unwrap();
+
+            let found = provider.find_first_child_by_name("Laptop", &root);
+            assert_eq!(found.is_some(), include_computers);
+            if !include_computers {
+                assert!(!provider.entries.contains_key(&notes));
+                continue;
+            }
+            assert_eq!(found.unwrap().attr.perm, 0o555);
+            assert_eq!(provider.entries[&notes].attr.perm, 0o444);
+            assert!(matches!(
+                provider.mkdir_inner(&laptop, "new").await,
+                Err(ProviderError::PermissionDenied(_))
+            ));
+            assert!(matches!(
+                provider.remove_inner(&laptop, "notes.txt", false).await,
+                Err(ProviderError::PermissionDenied(_))
+            ));
         }
-        Ok(buf)
     }
-    fn create_file_metadata_from_entry(entry: &FileData) -> FileMetadata {
-        FileMetadata {
-            attr: entry.attr.clone(),
-            name: entry
-                .changed_metadata
-                .name
-                .as_ref()
-                .unwrap_or(
-                    entry
-                        .metadata
-                        .name
-                        .as_ref()
-                        .unwrap_or(&"NO_NAME".to_string()),
-                )
-                .clone(),
-            id: DriveId::from(entry.metadata.id.as_ref().unwrap()),
+
+    #[tokio::test]
+    async fn upload_keeps_local_modified_time() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"content").await;
+
+        // touch -d @1000000000 file
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let mut request =
+            ProviderSetAttrRequest::new(id.clone(), None, None, None, None, None, None, tx);
+        request.mtime = Some(mtime);
+        provider.set_attr(request).await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            ProviderResponse::SetAttr(m) if m.attr.mtime == mtime
+        ));
+
+        let metadata = provider.create_upload_metadata(&id).unwrap();
+        assert_eq!(metadata.modified_time, Some(mtime.into()));
+        assert_eq!(metadata.mime_type.as_deref(), Some("text/plain"));
+    }
+
+    fn create_provider_with_large_folder(parent: &DriveId) -> (DriveFileProvider, TempDir) {
+        let (mut provider, dir) = create_provider();
+        for i in 0..10_000 {
+            provider.add_drive_entry_to_entries(DriveFileMetadata {
+                id: Some(format!("id{}", i)),
+                name: Some(format!("File{}.txt", i)),
+                parents: Some(vec![parent.to_string()]),
+                mime_type: Some("text/plain".to_string()),
+                ..Default::default()
+            });
         }
+        (provider, dir)
     }
-    //endregion
 
-    //region drive helpers
-    #[instrument]
-    async fn get_changes(&mut self) -> Result<Vec<Change>> {
-        if self.last_checked_for_changes + self.allowed_cache_time > SystemTime::now() {
-            debug!("not checking for changes since we already checked recently");
-            return Ok(vec![]);
+    #[test]
+    fn case_insensitive_lookup_in_a_large_folder() {
+        let parent = DriveId::from("parent");
+        let (provider, _dir) = create_provider_with_large_folder(&parent);
+
+        let found = provider.find_first_child_by_name("file9999.TXT", &parent);
+        assert_eq!(
+            found.and_then(|e| e.get_id()),
+            Some(DriveId::from("id9999"))
+        );
+        assert!(provider
+            .find_first_child_by_name("File10000.txt", &parent)
+            .is_none());
+    }
+
+    /// compares the lookups through the name index with going through all
+    /// children, like it was done before the index. 10k lookups in a folder with
+    /// 10k children have to take less than 100ms. Going through the children is
+    /// so slow that only every 100th name is looked up that way
+    ///
+    /// run with `cargo test --release -- --ignored --nocapture lookup_benchmark`
+    #[test]
+    #[ignore]
+    fn lookup_benchmark() {
+        let parent = DriveId::from("parent");
+        let (provider, _dir) = create_provider_with_large_folder(&parent);
+        let names: Vec<String> = (0..10_000).map(|i| format!("file{}.TXT", i)).collect();
+
+        let start = std::time::Instant::now();
+        for name in &names {
+            assert!(provider.find_first_child_by_name(name, &parent).is_some());
         }
-        debug!("checking for changes...");
-        let changes: Result<Vec<Change>> = self
-            .drive
-            .get_changes_since(&mut self.changes_start_token)
-            .await?
-            .into_iter()
-            .map(Change::try_from)
-            .collect();
+        let indexed = start.elapsed();
 
-        self.last_checked_for_changes = SystemTime::now();
-        debug!(
-            "checked for changes, found {} changes",
-            changes.as_ref().unwrap_or(&Vec::<Change>::new()).len()
+        let children = &provider.children[&parent];
+        let start = std::time::Instant::now();
+        for name in names.iter().step_by(100) {
+            let children = children
+                .iter()
+                .map(|id| (id, provider.entries[id].metadata.name.as_deref()));
+            assert!(find_child_by_name(children, name).is_some());
+        }
+        let scanned = start.elapsed() / (names.len() / 100) as u32;
+
+        println!(
+            "lookups in a folder with {} children: {:?} each with the index, {:?} each going through the children",
+            children.len(),
+            indexed / names.len() as u32,
+            scanned
         );
-        changes
+        assert!(indexed < Duration::from_millis(100));
     }
 
-    #[instrument]
-    async fn update_remote_metadata(&mut self, id: DriveId) -> Result<()> {
-        trace!("Uploading changed metadata");
-        let mut file_data = self.entries.get_mut(&id);
-        if file_data.is_none() {
-            return Err(anyhow!("Could not get entry with id: {}", id));
+    #[tokio::test]
+    async fn rename_never_replaces_the_target() {
+        let (mut provider, _dir) = create_provider();
+        let parent = provider.alt_root_id.clone();
+        for name in ["a", "b"] {
+            add_local_file(&mut provider, &DriveId::from(name), b"").await;
+            provider.add_parent_child_relation(parent.clone(), DriveId::from(name));
         }
-        let file_data = file_data.unwrap();
-        //extract changes from the file_data and replaces with new empty one
-        let mut metadata = DriveFileMetadata::default();
-        swap(&mut file_data.changed_metadata, &mut metadata);
-        Self::prepare_changed_metadata_for_upload(&id, &mut metadata);
+        provider.add_drive_entry_to_entries(DriveFileMetadata {
+            id: Some(parent.to_string()),
+            name: Some("root".to_string()),
+            mime_type: Some("application/vnd.google-apps.folder".to_string()),
+            ..Default::default()
+        });
+        let (a, b) = (&"a".to_string(), &"b".to_string());
+        for (flags, errno) in [
+            (libc::RENAME_NOREPLACE, libc::EEXIST),
+            (0, libc::EEXIST),
+            (libc::RENAME_WHITEOUT, libc::EINVAL),
+            (libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE, libc::EINVAL),
+        ] {
+            let e = provider
+                .rename_inner(&parent, a, &parent, b, flags)
+                .await
+                .unwrap_err();
+            assert_eq!(e.errno(), errno, "flags: {:#x}", flags);
+        }
+        assert_eq!(
+            provider
+                .find_first_child_by_name("b", &parent)
+                .and_then(|e| e.get_id()),
+            Some(DriveId::from("b"))
+        );
+    }
 
-        self.drive
-            .update_file_metadata_on_drive(metadata, &file_data.metadata)
-            .await?;
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rename_of_a_file_with_a_dirty_handle() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let id = mock.add_file("a.txt", &root, b"hello");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request =
+            ProviderWriteContentRequest::new(id.clone(), 0, fh, b"hello world".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
 
-        self.reset_local_metadata_to_remote_version(&id).await?;
+        let (a, b, c) = (
+            "a.txt".to_string(),
+            "b.txt".to_string(),
+            "c.txt".to_string(),
+        );
+        provider
+            .rename_inner(&root, &a, &root, &b, 0)
+            .await
+            .unwrap();
+        // the release uploads in the background, the next rename has to wait for it
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        provider
+            .release_file(ProviderReleaseFileRequest::new(id.clone(), fh, tx))
+            .await
+            .unwrap();
+        rx.recv().await.unwrap();
+        provider
+            .rename_inner(&root, &b, &root, &c, 0)
+            .await
+            .unwrap();
 
-        Ok(())
+        assert!(provider.running_requests.is_empty());
+        let metadata = mock.metadata(&id).unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("c.txt"));
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"hello world"[..]));
+        assert_eq!(provider.entries[&id].attr.size, 11);
     }
 
-    async fn reset_local_metadata_to_remote_version(&mut self, id: &DriveId) -> Result<()> {
-        let new_metadata = self.drive.get_metadata_for_file(id.clone()).await?;
-        let file_data = self.entries.get_mut(id);
-        if file_data.is_none() {
-            return Err(anyhow!("Could not get entry with id: {}", id));
-        }
-        let file_data = file_data.unwrap();
-        file_data.metadata = new_metadata;
-        file_data.changed_metadata = DriveFileMetadata::default();
-        Ok(())
+    #[tokio::test(flavor = "multi_thread")]
+    async fn running_uploads_hold_the_file_lock() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let id = mock.add_file("a.txt", &root, b"hello");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        let fh = open(&mut provider, &id, libc::O_RDWR).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request =
+            ProviderWriteContentRequest::new(id.clone(), 0, fh, b"hello world".to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
+
+        let held = mock.hold_uploads().await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        provider
+            .release_file(ProviderReleaseFileRequest::new(id.clone(), fh, tx))
+            .await
+            .unwrap();
+        rx.recv().await.unwrap();
+        // whatever changes the file takes its write lock, it has to wait for the upload
+        let lock = provider.file_locks.get(&id);
+        let waiting = tokio::time::timeout(Duration::from_millis(200), lock.clone().write_owned());
+        assert!(waiting.await.is_err(), "the file changed during its upload");
+
+        drop(held);
+        tokio::time::timeout(Duration::from_secs(5), lock.write_owned())
+            .await
+            .expect("the upload did not release the file");
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"hello world"[..]));
     }
 
-    /// starts a download of the specified file and puts it in the running_requests map
-    ///
-    /// - will return an Error if another request is already running for the same id, so all callers should make sure of that
-    async fn start_download_call(
-        &mut self,
-        request: &ProviderOpenFileRequest,
-        drive: GoogleDrive,
-        target_path: &PathBuf,
-    ) -> Result<()> {
-        let file_id = self.get_correct_id(request.file_id.clone());
-        let id = file_id.clone();
-        let entry = self.entries.get_mut(&id).context("could not find entry")?;
-        entry.is_local = true;
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rename_exchange_swaps_the_entries() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let folder = mock.add_folder("folder", &root);
+        let a = mock.add_file("a.txt", &root, b"a");
+        let b = mock.add_file("b.txt", &folder, b"b");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        let root = provider.get_correct_id(DriveId::root());
 
-        if let Some(_handle) = self.running_requests.get(&id) {
-            return send_error_response!(
-                request,
-                anyhow!("Id already has a request running"),
-                libc::EIO,
-            );
+        let (a_name, b_name) = (&"a.txt".to_string(), &"b.txt".to_string());
+        let result = provider
+            .rename_inner(&root, a_name, &folder, b_name, libc::RENAME_EXCHANGE)
+            .await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        let find = |provider: &DriveFileProvider, name: &str, parent: &DriveId| {
+            provider
+                .find_first_child_by_name(name, parent)
+                .and_then(|e| e.get_id())
+        };
+        assert_eq!(find(&provider, "b.txt", &folder), Some(a.clone()));
+        assert_eq!(find(&provider, "a.txt", &root), Some(b.clone()));
+        let a_metadata = mock.metadata(&a).unwrap();
+        assert_eq!(a_metadata.name.as_deref(), Some("b.txt"));
+        assert_eq!(a_metadata.parents, Some(vec![folder.to_string()]));
+        let b_metadata = mock.metadata(&b).unwrap();
+        assert_eq!(b_metadata.name.as_deref(), Some("a.txt"));
+        assert_eq!(b_metadata.parents, Some(vec![root.to_string()]));
+
+        // there is nothing to exchange with
+        let missing = &"missing".to_string();
+        let e = provider
+            .rename_inner(&root, a_name, &root, missing, libc::RENAME_EXCHANGE)
+            .await
+            .unwrap_err();
+        assert_eq!(e.errno(), libc::ENOENT);
+    }
+
+    #[tokio::test]
+    async fn name_index_follows_renames_and_moves() {
+        let (mut provider, _dir) = create_provider();
+        let parent = DriveId::from("parent");
+        let other_parent = DriveId::from("other_parent");
+        for id in ["a", "b"] {
+            provider.add_drive_entry_to_entries(DriveFileMetadata {
+                id: Some(id.to_string()),
+                name: Some("same".to_string()),
+                parents: Some(vec![parent.to_string()]),
+                mime_type: Some("text/plain".to_string()),
+                ..Default::default()
+            });
         }
-        let target_path = target_path.clone();
-        let handle: JoinHandle<Result<()>> = tokio::spawn(async move {
-            let _metadata: DriveFileMetadata = drive.download_file(file_id, &target_path).await?;
-            Ok(())
-        });
+        let find = |provider: &DriveFileProvider, name: &str, parent: &DriveId| {
+            provider
+                .find_first_child_by_name(name, parent)
+                .and_then(|e| e.get_id())
+        };
+        assert_eq!(find(&provider, "same", &parent), Some(DriveId::from("a")));
 
-        self.running_requests.insert(id, handle);
-        Ok(())
+        let change = Change {
+            id: DriveId::from("a"),
+            kind: ChangeType::File(DriveFileMetadata {
+                name: Some("renamed".to_string()),
+                ..Default::default()
+            }),
+            time: Default::default(),
+        };
+        provider.process_change(change).await.unwrap();
+        assert_eq!(
+            find(&provider, "renamed", &parent),
+            Some(DriveId::from("a"))
+        );
+        // the other entry with the same name takes over
+        assert_eq!(find(&provider, "same", &parent), Some(DriveId::from("b")));
+
+        provider.remove_parent_child_relation(parent.clone(), DriveId::from("b"));
+        provider.add_parent_child_relation(other_parent.clone(), DriveId::from("b"));
+        assert_eq!(find(&provider, "same", &parent), None);
+        assert_eq!(
+            find(&provider, "same", &other_parent),
+            Some(DriveId::from("b"))
+        );
     }
 
-    /// - will return an Error if another request is already running for the same id, so all callers should make sure of that
-    async fn start_upload_call(&mut self, id: DriveId, drive: GoogleDrive) -> Result<()> {
-        if self.running_requests.contains_key(&id) {
-            return Err(anyhow!("Id already has a request running"));
+    #[tokio::test]
+    async fn quota_is_served_from_the_cache() {
+        let (mut provider, _dir) = create_provider();
+        let quota = CachedQuota {
+            limit: Some(100),
+            usage: 30,
+            fetched_at: SystemTime::now(),
+        };
+        provider.quota = Some(quota);
+
+        // the provider can not reach google drive, so this has to come from the cache
+        assert_eq!(provider.get_quota().await.unwrap(), quota);
+    }
+
+    #[test]
+    fn moving_keeps_other_parents() {
+        let parents = vec!["a".to_string(), "b".to_string()];
+        let moved = move_to_parent(&parents, &DriveId::from("a"), &DriveId::from("c"));
+        assert_eq!(moved, vec!["b".to_string(), "c".to_string()]);
+
+        // moving it under a parent it already has
+        let moved = move_to_parent(&parents, &DriveId::from("a"), &DriveId::from("b"));
+        assert_eq!(moved, vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn resolve_path_follows_parents_and_moves() {
+        let (mut provider, _dir) = create_provider();
+        let root = provider.alt_root_id.to_string();
+        for (id, name, parent) in [
+            ("a", "A", root.as_str()),
+            ("b", "B", "a"),
+            ("file", "file.txt", "b"),
+            ("orphan", "orphan.txt", "missing"),
+        ] {
+            provider.add_drive_entry_to_entries(DriveFileMetadata {
+                id: Some(id.to_string()),
+                name: Some(name.to_string()),
+                parents: Some(vec![parent.to_string()]),
+                ..Default::default()
+            });
         }
+        let file = DriveId::from("file");
+        assert_eq!(
+            provider.resolve_path(&file),
+            Some(PathBuf::from("A/B/file.txt"))
+        );
+        assert_eq!(provider.resolve_path(&DriveId::from("orphan")), None);
 
-        let file_data = self
-            .entries
-            .get(&id)
-            .context("could not find data for id")?;
+        // moving a folder changes the cached path of everything inside it
+        provider.remove_parent_child_relation(DriveId::from("a"), DriveId::from("b"));
+        provider.add_parent_child_relation(provider.alt_root_id.clone(), DriveId::from("b"));
+        assert_eq!(
+            provider.resolve_path(&file),
+            Some(PathBuf::from("B/file.txt"))
+        );
 
-        let mut metadata = file_data.changed_metadata.clone();
-        Self::prepare_changed_metadata_for_upload(&id, &mut metadata);
-        metadata.mime_type = file_data.metadata.mime_type.clone();
+        // a cycle can not be resolved
+        provider.remove_parent_child_relation(provider.alt_root_id.clone(), DriveId::from("b"));
+        provider.add_parent_child_relation(file.clone(), DriveId::from("b"));
+        assert_eq!(provider.resolve_path(&file), None);
+    }
 
-        let target_path = self.construct_path(&id)?;
-        debug!(
-            "starting upload in the background for path: '{}' and metadata: {:?}",
-            target_path.display(),
-            metadata
+    #[tokio::test(flavor = "multi_thread")]
+    async fn moved_folders_take_their_children_along() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let folder = mock.add_folder("A", &root);
+        let nested = mock.add_folder("B", &folder);
+        let file = mock.add_file("file.txt", &nested, b"content");
+        let dest = mock.add_folder("Dest", &root);
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        assert_eq!(
+            provider.resolve_path(&file),
+            Some(PathBuf::from("A/B/file.txt"))
+        );
+
+        let (a, moved) = ("A".to_string(), "Moved".to_string());
+        provider
+            .rename_inner(&root, &a, &dest, &moved, 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            provider.resolve_path(&file),
+            Some(PathBuf::from("Dest/Moved/B/file.txt"))
+        );
+        assert!(provider.find_first_child_by_name("A", &root).is_none());
+        let found = provider
+            .find_first_child_by_name("Moved", &dest)
+            .and_then(|e| e.get_id());
+        assert_eq!(found.as_ref(), Some(&folder));
+        let found = provider
+            .find_first_child_by_name("file.txt", &nested)
+            .and_then(|e| e.get_id());
+        assert_eq!(found, Some(file));
+        assert_eq!(
+            mock.metadata(&folder).unwrap().parents,
+            Some(vec![dest.to_string()])
         );
-        let handle: JoinHandle<Result<()>> = tokio::spawn(async move {
-            //TODO1: only send the changed metadata over (+id), not all of it (currently only all data that could change and where changes should be written to the drive), since google drive only wants the changes
-            drive
-                .upload_file_content_from_path(metadata, &target_path)
-                .await?;
-            Ok(())
-        });
-        self.running_requests.insert(id, handle);
-        Ok(())
     }
 
-    fn prepare_changed_metadata_for_upload(id: &DriveId, mut metadata: &mut DriveFileMetadata) {
-        metadata.id = Some(id.clone().into());
-        remove_volatile_metadata(&mut metadata);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dot_names_are_remapped() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        mock.add_file("..", &root, b"a");
+        mock.add_file(".", &root, b"b");
+        mock.add_file("...", &root, b"c");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReadDirRequest::new(root.clone(), 0, 256, tx);
+        provider.read_dir(request).await.unwrap();
+        let ProviderResponse::ReadDir(response) = rx.recv().await.unwrap() else {
+            panic!("expected a read dir response");
+        };
+        let mut names: Vec<_> = response.entries.into_iter().map(|e| e.name).collect();
+        names.sort();
+        assert_eq!(names, ["...", ".._", "._"]);
+        assert!(provider.find_first_child_by_name("..", &root).is_none());
+        assert!(provider.find_first_child_by_name(".._", &root).is_some());
     }
 
-    /// Checks if a drive request for this ID is running and if there is, waits for it.
-    ///
-    /// After awaiting, it removes the request from the map
-    async fn wait_for_running_drive_request_if_exists(&mut self, file_id: &DriveId) -> Result<()> {
-        if let Some(handle) = self.running_requests.get_mut(&file_id) {
-            debug!("DriveFileProvider::open_file() waiting for download/upload to finish");
-            let handle_result = handle.await?;
-            if let Err(e) = handle_result {
-                error!("async request had an error: {:?}", e);
-            }
-            self.running_requests.remove(&file_id);
+    #[test]
+    fn relations_that_form_a_cycle_are_refused() {
+        let (mut provider, _dir) = create_provider();
+        // corrupt data where both folders are the parent of the other
+        for (id, parent) in [("a", "b"), ("b", "a")] {
+            provider.add_drive_entry_to_entries(DriveFileMetadata {
+                id: Some(id.to_string()),
+                name: Some(id.to_string()),
+                mime_type: Some("application/vnd.google-apps.folder".to_string()),
+                parents: Some(vec![parent.to_string()]),
+                ..Default::default()
+            });
         }
-        Ok(())
+        let (a, b) = (DriveId::from("a"), DriveId::from("b"));
+        assert!(provider.parents[&a].contains(&b));
+        assert!(provider.parents.get(&b).is_none_or(Vec::is_empty));
+        assert_eq!(provider.resolve_path(&a), None);
+        assert_eq!(provider.resolve_path(&b), None);
+
+        // the child can still be moved somewhere else
+        provider.add_parent_child_relation(provider.alt_root_id.clone(), b.clone());
+        assert_eq!(provider.resolve_path(&a), Some(PathBuf::from("b/a")));
     }
-    //endregion
 
-    fn create_fh(
-        &mut self,
-        flags: HandleFlags,
-        path: PathBuf,
-        create: bool,
-        mark_for_open: bool,
-    ) -> u64 {
-        let fh = self.next_fh;
-        self.next_fh += 1;
-        let file_handle = FileHandleData {
-            creating: create,
-            flags,
-            file: None,
-            path,
-            marked_for_open: mark_for_open,
-            has_content_changed: false,
+    #[tokio::test]
+    async fn path_filter_removes_entries() {
+        let (mut provider, _dir) = create_provider();
+        provider.set_path_filter(PathFilter::new(&["Work/**"], &["Work/tmp"]).unwrap());
+        let root = provider.alt_root_id.to_string();
+        let folder = "application/vnd.google-apps.folder";
+        for (id, name, parent, mime_type) in [
+            ("work", "Work", root.as_str(), folder),
+            ("report", "report.txt", "work", "text/plain"),
+            ("tmp", "tmp", "work", folder),
+            ("cache", "cache.bin", "tmp", "text/plain"),
+            ("music", "Music", root.as_str(), folder),
+            ("song", "song.mp3", "music", "text/plain"),
+        ] {
+            provider.add_drive_entry_to_entries(DriveFileMetadata {
+                id: Some(id.to_string()),
+                name: Some(name.to_string()),
+                parents: Some(vec![parent.to_string()]),
+                mime_type: Some(mime_type.to_string()),
+                ..Default::default()
+            });
+        }
+        assert_eq!(
+            provider.resolve_path(&DriveId::from("cache")),
+            Some(PathBuf::from("Work/tmp/cache.bin"))
+        );
+
+        provider.remove_filtered_entries();
+        let mut ids: Vec<String> = provider.entries.keys().map(|id| id.to_string()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["report", "work"]);
+        assert!(provider
+            .find_first_child_by_name("tmp", &DriveId::from("work"))
+            .is_none());
+
+        // moving the report into the excluded folder removes it
+        provider.add_drive_entry_to_entries(DriveFileMetadata {
+            id: Some("tmp".to_string()),
+            name: Some("tmp".to_string()),
+            parents: Some(vec!["work".to_string()]),
+            mime_type: Some(folder.to_string()),
+            ..Default::default()
+        });
+        let change = Change {
+            id: DriveId::from("report"),
+            kind: ChangeType::File(DriveFileMetadata {
+                parents: Some(vec!["tmp".to_string()]),
+                ..Default::default()
+            }),
+            time: Default::default(),
         };
-        self.file_handles.insert(fh, file_handle);
-        fh
+        provider.process_change(change).await.unwrap();
+        assert!(!provider.entries.contains_key(&DriveId::from("report")));
     }
-    /// constructs the path where the file is stored locally. This is not necessarily a
-    /// path with the correct file ending or folder structure, it could just be a unique id.
-    fn construct_path(&self, id: &DriveId) -> Result<PathBuf> {
-        let metadata = self.entries.get(id).context("No data found for id")?;
-        //TODO: check if every drive_id is actually a valid filepath/does
-        //      not contain characters that cannot be used in a path
-        if metadata.perma {
-            Ok(self.perma_dir.join(id.as_str()))
-        } else {
-            Ok(self.cache_dir.join(id.as_str()))
+
+    async fn lookup(
+        provider: &mut DriveFileProvider,
+        parent: &DriveId,
+        name: &str,
+    ) -> Option<FileMetadata> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderLookupRequest::new(parent.clone(), name.into(), tx);
+        provider.lookup(request).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::Lookup(found) => found,
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn revisions_can_be_read_from_the_revisions_folder() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let file = mock.add_file("notes.txt", &root, b"current");
+        let revision = mock.add_revision(&file, b"old");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+
+        assert!(lookup(&mut provider, &root, "notes.txt@revisions")
+            .await
+            .is_none());
+
+        provider.set_show_revisions(true);
+        let folder = lookup(&mut provider, &root, "notes.txt@revisions")
+            .await
+            .expect("the revisions folder exists");
+        assert_eq!(folder.attr.kind, FileType::Directory);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReadDirRequest::new(folder.id.clone(), 0, 256, tx);
+        provider.read_dir(request).await.unwrap();
+        let ProviderResponse::ReadDir(response) = rx.recv().await.unwrap() else {
+            panic!("expected a read dir response");
+        };
+        assert_eq!(response.entries.len(), 1);
+        let entry = &response.entries[0];
+        assert!(entry.name.ends_with(&format!("_{}.txt", revision)));
+        assert_eq!(entry.attr.perm, 0o444);
+
+        let fh = open(&mut provider, &entry.id, libc::O_RDONLY).await;
+        // the revision is downloaded in the background, it can be cancelled
+        assert!(provider.running_requests.contains_key(&entry.id));
+        assert_eq!(read(&mut provider, &entry.id, fh, 0, 100).await, b"old");
+        assert!(provider.check_writable(&entry.id).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reloaded_auth_uses_the_new_drive() {
+        let old = MockDrive::start().await.unwrap();
+        let new = MockDrive::start().await.unwrap();
+        // the same order of additions gives both mocks the same ids
+        let id = old.add_file("a.txt", &old.root_id(), b"old");
+        assert_eq!(new.add_file("a.txt", &new.root_id(), b"new"), id);
+        let (mut provider, _dir) = create_provider_with_drive(old.drive());
+        provider.initialize_entries().await.unwrap();
+
+        provider
+            .handle_state_command(ProviderCommand::ReloadAuth {
+                drive: Box::new(new.drive()),
+            })
+            .await;
+        let connections = old.connection_count();
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        assert_eq!(read(&mut provider, &id, fh, 0, 100).await, b"new");
+        assert_eq!(old.connection_count(), connections);
+    }
+
+    /// true if the flush uploaded the content
+    async fn write_and_flush(
+        provider: &mut DriveFileProvider,
+        id: &DriveId,
+        content: &[u8],
+    ) -> bool {
+        let fh = open(provider, id, libc::O_RDWR).await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderWriteContentRequest::new(id.clone(), 0, fh, content.to_vec(), tx);
+        provider.write_content(request).await.unwrap();
+        rx.recv().await.unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        provider
+            .flush(ProviderFlushRequest::new(id.clone(), fh, tx))
+            .await
+            .unwrap();
+        matches!(rx.recv().await, Some(ProviderResponse::Flush))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn uploads_that_failed_on_the_credentials_are_retried_after_reload_auth() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("a.txt", &mock.root_id(), b"old");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        assert_eq!(read(&mut provider, &id, fh, 0, 100).await, b"old");
+
+        mock.set_unauthorized(true);
+        assert!(!write_and_flush(&mut provider, &id, b"new").await);
+        assert_eq!(provider.auth_failed_uploads, vec![id.clone()]);
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"old"[..]));
+
+        // the user logged in again
+        mock.set_unauthorized(false);
+        provider
+            .handle_state_command(ProviderCommand::ReloadAuth {
+                drive: Box::new(mock.drive()),
+            })
+            .await;
+        provider
+            .wait_for_drive_request_if_exists(&id)
+            .await
+            .unwrap();
+        assert!(provider.auth_failed_uploads.is_empty());
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"new"[..]));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn uploads_that_failed_on_the_credentials_are_forgotten_once_obsolete() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let id = mock.add_file("a.txt", &root, b"old");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        assert!(write_and_flush(&mut provider, &id, b"new").await);
+
+        mock.set_unauthorized(true);
+        assert!(!write_and_flush(&mut provider, &id, b"newer").await);
+        assert_eq!(provider.auth_failed_uploads, vec![id.clone()]);
+        // the credentials work again without a reload, like after a token refresh
+        mock.set_unauthorized(false);
+        assert!(write_and_flush(&mut provider, &id, b"newest").await);
+        assert!(provider.auth_failed_uploads.is_empty());
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"newest"[..]));
+
+        mock.set_unauthorized(true);
+        assert!(!write_and_flush(&mut provider, &id, b"lost").await);
+        assert_eq!(provider.auth_failed_uploads, vec![id.clone()]);
+        mock.set_unauthorized(false);
+        let root = provider.get_correct_id(DriveId::root());
+        provider.remove_inner(&root, "a.txt", false).await.unwrap();
+        assert!(provider.auth_failed_uploads.is_empty());
+    }
+
+    #[tokio::test]
+    async fn files_without_mime_type_are_regular_files() {
+        let (mut provider, _dir) = create_provider();
+        let root = provider.alt_root_id.clone();
+        provider.add_drive_entry_to_entries(DriveFileMetadata {
+            id: Some("untyped".to_string()),
+            name: Some("untyped".to_string()),
+            parents: Some(vec![root.to_string()]),
+            ..Default::default()
+        });
+        let entry = provider
+            .find_first_child_by_name("untyped", &root)
+            .expect("the file was added");
+        assert_eq!(entry.attr.kind, FileType::RegularFile);
+        assert!(provider.is_visible(entry));
+    }
+
+    #[tokio::test]
+    async fn large_files_wait_for_their_upload_window() {
+        let (mut provider, _dir) = create_provider();
+        provider.set_upload_windows(UploadWindows::new(&[(1024, Duration::from_secs(60))]));
+        let small = DriveId::from("small");
+        let large = DriveId::from("large");
+        add_local_file(&mut provider, &small, &[1; 10]).await;
+        add_local_file(&mut provider, &large, &[1; 2048]).await;
+
+        provider.schedule_upload(&small).await.unwrap();
+        provider.schedule_upload(&large).await.unwrap();
+        assert!(provider.running_requests.contains_key(&small));
+        assert!(!provider.running_requests.contains_key(&large));
+        let upload_at = provider.scheduled_uploads[&large];
+        assert!(upload_at > tokio::time::Instant::now() + Duration::from_secs(50));
+
+        provider.start_scheduled_uploads(false).await;
+        assert!(!provider.running_requests.contains_key(&large));
+        provider.start_scheduled_uploads(true).await;
+        assert!(provider.running_requests.contains_key(&large));
+        assert!(provider.scheduled_uploads.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn conflicting_changes_keep_the_local_content_as_a_named_copy() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let id = mock.add_file("notes.txt", &root, b"old");
+        let drive = mock.drive();
+        let (mut provider, _dir) = create_provider_with_drive(drive.clone());
+        provider
+            .set_conflict_name_template(ConflictNameTemplate::new("{stem}.local{ext}").unwrap());
+        provider.set_upload_windows(UploadWindows::new(&[(0, Duration::from_secs(60))]));
+        provider.changes_start_token = drive.get_start_page_token().await.unwrap();
+        provider.initialize_entries().await.unwrap();
+
+        // the local change waits for its upload window while the remote one comes in
+        assert!(write_and_flush(&mut provider, &id, b"local").await);
+        mock.set_content(&id, b"remote");
+        provider.check_and_apply_changes().await;
+        provider.finish_uploads().await;
+
+        let copy = mock
+            .find("notes.local.txt", &root)
+            .expect("the copy was created");
+        assert_eq!(mock.content(&copy).as_deref(), Some(&b"local"[..]));
+        assert_eq!(mock.content(&id).as_deref(), Some(&b"remote"[..]));
+        assert!(!provider.has_pending_upload(&id));
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        assert_eq!(read(&mut provider, &id, fh, 0, 100).await, b"remote");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn opening_a_folder_fails_with_eisdir() {
+        let mock = MockDrive::start().await.unwrap();
+        let folder = mock.add_folder("folder", &mock.root_id());
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderOpenFileRequest::new(folder, libc::O_RDONLY, tx);
+        provider.open_file(request).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::Error(_, errno) => assert_eq!(errno, libc::EISDIR),
+            response => panic!("unexpected response: {:?}", response),
         }
     }
-    async fn initialize_entries(&mut self) -> Result<()> {
-        self.add_root_entry()
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn renamed_native_files_keep_their_extension() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let doc = mock.add_document("Report", &root);
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        let found = lookup(&mut provider, &root, "Report.gdoc").await.unwrap();
+        assert_eq!(found.id, doc);
+
+        let (old, new) = ("Report.gdoc".to_string(), "Summary.gdoc".to_string());
+        provider
+            .rename_inner(&root, &old, &root, &new, 0)
             .await
-            .expect("adding the root entry has to work, otherwise nothing else works");
-        let entries = self.drive.list_all_files().await?;
-        for entry in entries {
-            self.add_drive_entry_to_entries(entry);
-        }
-        // for (i, (id, data)) in self.entries.iter().enumerate() {
-        //     info!("entry {:3} id: {:>40} data: {:?}", i, id, data);
-        // }
-        Ok(())
+            .unwrap();
+        assert_eq!(
+            mock.metadata(&doc).unwrap().name.as_deref(),
+            Some("Summary")
+        );
+        assert!(lookup(&mut provider, &root, "Report.gdoc").await.is_none());
+        let found = lookup(&mut provider, &root, "Summary.gdoc").await.unwrap();
+        assert_eq!(found.id, doc);
     }
 
-    fn add_drive_entry_to_entries(&mut self, entry: DriveFileMetadata) -> bool {
-        let id = &entry.id;
-        if let Some(id) = id {
-            let id = DriveId::from(id);
-            let attr = self.create_file_attr_from_metadata(&entry);
-            if attr.is_err() {
-                warn!(
-                    "error while creating FileAttr from metadata: {:?} entry: {:?}",
-                    attr, entry
-                );
-                return true;
-            }
-            let attr = attr.unwrap();
-            self.add_child_parent_relations(&entry, &id);
-            let entry_data = FileData {
-                metadata: entry,
-                changed_metadata: Default::default(),
-                perma: false, //TODO: read the perma marker from somewhere (maybe only after all files have been checked?)
-                attr,
-                is_local: false,
-            };
-            self.entries.insert(id, entry_data);
-        }
-        false
+    #[tokio::test(flavor = "multi_thread")]
+    async fn moving_a_file_out_of_one_of_its_folders_keeps_the_other() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let first = mock.add_folder("first", &root);
+        let second = mock.add_folder("second", &root);
+        let target = mock.add_folder("target", &root);
+        let file = mock.add_file("file.txt", &first, b"content");
+        mock.add_parent(&file, &second);
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+
+        let name = "file.txt".to_string();
+        provider
+            .rename_inner(&first, &name, &target, &name, 0)
+            .await
+            .unwrap();
+        provider
+            .wait_for_drive_request_if_exists(&file)
+            .await
+            .unwrap();
+        let mut parents = mock.metadata(&file).unwrap().parents.unwrap();
+        parents.sort();
+        let mut expected = vec![second.to_string(), target.to_string()];
+        expected.sort();
+        assert_eq!(parents, expected);
+        assert!(lookup(&mut provider, &second, "file.txt").await.is_some());
+        assert!(lookup(&mut provider, &first, "file.txt").await.is_none());
     }
 
-    fn add_child_parent_relations(&mut self, entry: &DriveFileMetadata, id: &DriveId) {
-        if let Some(parents) = &entry.parents {
-            for parent in parents {
-                let parent_id = DriveId::from(parent);
-                self.add_parent_child_relation(parent_id, id.clone());
-            }
-        } else {
-            //file is at root level
-            self.add_parent_child_relation(self.get_correct_id(DriveId::root()), id.clone());
+    #[tokio::test(flavor = "multi_thread")]
+    async fn prefetched_folders_are_pinned() {
+        let mock = MockDrive::start().await.unwrap();
+        let root = mock.root_id();
+        let trip = mock.add_folder("Trip", &root);
+        let maps = mock.add_folder("Maps", &trip);
+        let files = [
+            mock.add_file("plan.txt", &trip, b"plan"),
+            mock.add_file("city.png", &maps, b"city"),
+            mock.add_file("area.png", &maps, b"area"),
+        ];
+        let skipped = mock.add_file("big.iso", &maps, b"skipped");
+        let elsewhere = mock.add_file("other.txt", &root, b"other");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.set_path_filter(PathFilter::new(&[], &["**/*.iso"]).unwrap());
+        provider.initialize_entries().await.unwrap();
+        let perma_dir = provider.perma_dir.clone();
+
+        provider.prefetch(Path::new("Trip")).await.unwrap();
+        while provider.prefetches.is_running() {
+            let (id, ok) = provider.prefetches.done_rx.recv().await.unwrap();
+            provider.finish_prefetch(id, ok).await;
         }
-    }
 
-    fn create_file_attr_from_metadata(&self, metadata: &DriveFileMetadata) -> Result<FileAttr> {
-        let kind = convert_mime_type_to_file_type(
-            metadata.mime_type.as_ref().unwrap_or(&"NONE".to_string()),
-        )?;
-        // let permissions= todo!("read default permissions from a file or read specific permissions for id from somewhere (if the permissions were set in a previous sessions and stuff like that should be carried over to the next session");
-        let permissions = match kind {
-            FileType::Directory => 0o755,
-            _ => 0o644,
-        };
-        let attributes = FileAttr {
-            ino: 0,
-            size: (*metadata.size.as_ref().unwrap_or(&0)) as u64,
-            blocks: 0,
-            atime: metadata
-                .viewed_by_me_time
-                .map(SystemTime::from)
-                .unwrap_or(UNIX_EPOCH),
-            mtime: metadata
-                .modified_time
-                .map(SystemTime::from)
-                .unwrap_or(UNIX_EPOCH),
-            ctime: SystemTime::now(),
-            crtime: metadata
-                .created_time
-                .map(SystemTime::from)
-                .unwrap_or(UNIX_EPOCH),
-            kind,
-            perm: permissions,
-            nlink: 1,
-            uid: 0,
-            gid: 0,
-            rdev: 0,
-            blksize: 4096,
-            flags: 0,
-        };
-        Ok(attributes)
+        let progress = provider.prefetches.progress.unwrap();
+        assert_eq!((progress.total, progress.done, progress.failed), (3, 3, 0));
+        for id in &files {
+            assert!(provider.entries[id].is_local && provider.entries[id].perma);
+            assert_eq!(
+                std::fs::read(perma_dir.join(id.as_str())).unwrap(),
+                mock.content(id).unwrap()
+            );
+        }
+        assert_eq!(load_pinned(&perma_dir), HashSet::from(files));
+        assert!(!provider.entries.contains_key(&skipped));
+        assert!(!provider.entries[&elsewhere].is_local);
+        assert!(provider.prefetch(Path::new("Trip/plan.txt")).await.is_err());
     }
-    async fn add_root_entry(&mut self) -> Result<()> {
-        let metadata = self
-            .drive
-            .get_metadata_for_file(self.get_correct_id(DriveId::root()))
-            .await?;
-        let attr = self.create_file_attr_from_metadata(&metadata)?;
-        let returned_id = metadata.id.as_ref().unwrap().clone();
-        let data = FileData {
-            metadata,
-            changed_metadata: Default::default(),
-            attr,
-            perma: false,
-            is_local: false,
-        };
 
-        let root_id = DriveId::from(returned_id);
-        self.alt_root_id = root_id.clone();
-        self.entries.insert(root_id, data);
-        Ok(())
-    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn no_cache_files_are_read_from_the_drive() {
+        let mock = MockDrive::start().await.unwrap();
+        let content: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let id = mock.add_file("movie.mkv", &mock.root_id(), &content);
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.set_no_cache(NoCachePaths::new(&["**/*.mkv"]).unwrap());
+        provider.set_read_ahead_size(2000);
+        provider.initialize_entries().await.unwrap();
 
-    /// changes alias ids like ```DriveId::root()``` into their actual IDs on the drive
-    fn get_correct_id(&self, id: DriveId) -> DriveId {
-        if id == DriveId::root() {
-            trace!("aliasing DriveId::root() to actual root: {}", id);
-            return self.alt_root_id.clone();
+        let fh = open(&mut provider, &id, libc::O_RDONLY).await;
+        let mut read_back = Vec::new();
+        for offset in (0..5000).step_by(1000) {
+            read_back.extend(read(&mut provider, &id, fh, offset, 1000).await);
         }
-        return id;
+        assert_eq!(read_back, content);
+        assert!(read(&mut provider, &id, fh, 5000, 1000).await.is_empty());
+        assert_eq!(
+            read(&mut provider, &id, fh, 4500, 100).await,
+            content[4500..4600]
+        );
+
+        assert!(!provider.entries[&id].is_local);
+        assert!(!provider.cache_dir.join(id.as_str()).exists());
+        // the saved entries are in the perma dir, which is the cache dir here
+        let cached = std::fs::read_dir(&provider.cache_dir).unwrap().filter(|e| {
+            !e.as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with('.')
+        });
+        assert_eq!(cached.count(), 0);
     }
 
-    async fn process_change(&mut self, change: Change) -> Result<()> {
-        let id = change.id;
-        let id = self.get_correct_id(id);
-        match change.kind {
-            ChangeType::Drive(drive) => {
-                error!("drive changes are not supported yet: {:?}", drive);
-                // todo!("drive changes are not supported yet: {:?}", drive);
-            }
-            ChangeType::File(file_change) => {
-                //TODO: check if local has changes that conflict (content)
-                //TODO: check if the content was changed (checksum) and schedule
-                // a download if it is a local/perm file or mark it for download on next open
-                trace!("file change: {:?}", file_change);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expired_changes_tokens_fall_back_to_a_full_listing() {
+        let mock = MockDrive::start().await.unwrap();
+        let folder = mock.add_folder("Documents", &mock.root_id());
+        let kept = mock.add_file("kept.txt", &folder, b"kept");
+        let deleted = mock.add_file("deleted.txt", &folder, b"deleted");
+        let trashed = mock.add_file("trashed.txt", &folder, b"trashed");
+        let shared = mock.add_shared_file("shared.txt", &folder, b"shared");
+        let drive = mock.drive();
+        let (mut provider, _dir) = create_provider_with_drive(drive.clone());
+        provider.changes_start_token = drive.get_start_page_token().await.unwrap();
+        provider.initialize_entries().await.unwrap();
+        provider.load_entry_if_unknown(&shared).await.unwrap();
 
-                self.process_remote_file_moved(&id, &file_change);
-                let entry = self.entries.get_mut(&id);
-                if let Some(entry) = entry {
-                    process_file_change(entry, file_change)?;
-                } else {
-                    let entry = self.drive.get_metadata_for_file(id).await?;
-                    self.add_drive_entry_to_entries(entry);
-                    // todo!("there was a file/dir added on the remote since this ID is unknown")
-                    //TODO1: check if this is working
-                }
-            }
-            ChangeType::Removed => {
-                todo!("remove local file/dir since it was deleted on the remote");
-            }
+        let added = mock.add_file("added.txt", &folder, b"added");
+        drive.delete_file(deleted.clone()).await.unwrap();
+        drive.trash_file(trashed.clone()).await.unwrap();
+        mock.expire_page_tokens();
+        provider.check_and_apply_changes().await;
+
+        assert!(provider.entries.contains_key(&kept));
+        assert!(provider.entries.contains_key(&added));
+        assert!(!provider.entries.contains_key(&deleted));
+        assert!(!provider.entries.contains_key(&trashed));
+        // it is not in the listing of the own files, but still there
+        assert!(provider.entries.contains_key(&shared));
+        let current_token = drive.get_start_page_token().await.unwrap();
+        assert_eq!(
+            provider.changes_start_token.start_page_token,
+            current_token.start_page_token
+        );
+
+        // the changes are followed from the new token on
+        let later = mock.add_file("later.txt", &folder, b"later");
+        provider.last_checked_for_changes = SystemTime::UNIX_EPOCH;
+        provider.check_and_apply_changes().await;
+        assert!(provider.entries.contains_key(&later));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unlisted_entries_are_checked_at_the_same_time() {
+        let mock = MockDrive::start().await.unwrap();
+        let drive = mock.drive();
+        let (mut provider, _dir) = create_provider_with_drive(drive.clone());
+        provider.changes_start_token = drive.get_start_page_token().await.unwrap();
+        provider.initialize_entries().await.unwrap();
+        let shared: Vec<DriveId> = (0..16)
+            .map(|i| mock.add_shared_file(&format!("{}.txt", i), &mock.root_id(), b""))
+            .collect();
+        for id in &shared {
+            provider.load_entry_if_unknown(id).await.unwrap();
         }
-        Ok(())
+
+        // one at a time the 16 checks alone would take 1.6s
+        mock.set_response_delay(Duration::from_millis(100));
+        mock.expire_page_tokens();
+        let started = std::time::Instant::now();
+        provider.check_and_apply_changes().await;
+        assert!(started.elapsed() < Duration::from_millis(1200));
+        assert!(shared.iter().all(|id| provider.entries.contains_key(id)));
     }
 
-    #[instrument(skip(self, file_change))]
-    fn process_remote_file_moved(&mut self, id: &DriveId, file_change: &DriveFileMetadata) {
-        if let Some(changed_parents) = &file_change.parents {
-            trace!("parent was changed! {:?}", id);
-            let entry = self.entries.get(&id);
-            if let Some(entry) = entry {
-                trace!(
-                    "changed_parents: {:?} before change: {:?}",
-                    changed_parents,
-                    entry.metadata.parents
-                );
-                if Some(changed_parents) != entry.metadata.parents.as_ref() {
-                    if let Some(existing_parents) = entry.metadata.parents.clone() {
-                        for e in existing_parents {
-                            let old_parent_id = self.get_correct_id(DriveId::from(&e));
-                            trace!("(1) converted id from {} to {}", e, old_parent_id);
-                            self.remove_parent_child_relation(old_parent_id, id.clone());
-                        }
-                    }
-                    trace!("done removing old parents");
-                    for new_parent in changed_parents {
-                        let new_parent_id = self.get_correct_id(DriveId::from(new_parent));
-                        trace!("(2) converted id from {} to {} ", new_parent, new_parent_id);
-                        self.add_parent_child_relation(new_parent_id, id.clone());
-                    }
-                    trace!("done adding new parents");
-                    let entry_m = self.entries.get_mut(id);
-                    if let Some(entry_m) = entry_m {
-                        entry_m.metadata.parents = file_change.parents.clone();
-                    }
-                    trace!("done modifying metadata");
-                } else {
-                    trace!(
-                        "before and after are equal: {:?} == {:?}",
-                        Some(changed_parents),
-                        entry.metadata.parents
-                    );
-                }
-            } else {
-                warn!(
-                    "A remote file was moved but is unknown locally. is this right?: {:?}",
-                    id
-                );
-            }
+    async fn get_xattr(provider: &mut DriveFileProvider, id: &DriveId, name: &str) -> Vec<u8> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderGetXattrRequest::new(id.clone(), name.to_string(), tx);
+        provider.get_xattr(request).await.unwrap();
+        match rx.recv().await.unwrap() {
+            ProviderResponse::GetXattr(value) => value,
+            response => panic!("unexpected response: {:?}", response),
         }
     }
-}
-#[instrument]
-fn process_file_change(entry: &mut FileData, change: DriveFileMetadata) -> Result<()> {
-    if let Some(size) = change.size {
-        entry.metadata.size = Some(size);
-        entry.attr.size = size as u64;
-        //TODO1: set the size of the cached file if necessary
+
+    async fn set_xattr(
+        provider: &mut DriveFileProvider,
+        id: &DriveId,
+        name: &str,
+        value: &[u8],
+    ) -> ProviderResponse {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request =
+            ProviderSetXattrRequest::new(id.clone(), name.to_string(), value.to_vec(), tx);
+        provider.set_xattr(request).await.unwrap();
+        rx.recv().await.unwrap()
     }
-    if let Some(name) = change.name {
-        entry.metadata.name = Some(name);
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_starred_flag_is_an_xattr() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("notes.txt", &mock.root_id(), b"notes");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        assert_eq!(get_xattr(&mut provider, &id, STARRED_XATTR).await, b"0");
+
+        let response = set_xattr(&mut provider, &id, STARRED_XATTR, b"1\n").await;
+        assert!(matches!(response, ProviderResponse::SetXattr));
+        assert_eq!(mock.metadata(&id).unwrap().starred, Some(true));
+        assert_eq!(get_xattr(&mut provider, &id, STARRED_XATTR).await, b"1");
+
+        let response = set_xattr(&mut provider, &id, STARRED_XATTR, b"0").await;
+        assert!(matches!(response, ProviderResponse::SetXattr));
+        assert_eq!(mock.metadata(&id).unwrap().starred, Some(false));
+        assert_eq!(get_xattr(&mut provider, &id, STARRED_XATTR).await, b"0");
+
+        let response = set_xattr(&mut provider, &id, STARRED_XATTR, b"yes").await;
+        assert!(matches!(response, ProviderResponse::Error(_, libc::EINVAL)));
+        let response = set_xattr(&mut provider, &id, "user.drive.unknown", b"1").await;
+        assert!(matches!(
+            response,
+            ProviderResponse::Error(_, libc::ENOTSUP)
+        ));
     }
-    if let Some(parents) = change.parents {
-        if Some(&parents) != entry.metadata.parents.as_ref() {
-            //TODO1: change the parent child relations
-            warn!(
-                "parents changed from {:?}: {:?}",
-                entry.metadata.parents,
-                Some(parents)
-            )
-        }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn files_are_pinned_with_an_xattr() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("map.png", &mock.root_id(), b"map");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.initialize_entries().await.unwrap();
+        assert_eq!(get_xattr(&mut provider, &id, PINNED_XATTR).await, b"0");
+
+        let response = set_xattr(&mut provider, &id, PINNED_XATTR, b"1\n").await;
+        assert!(matches!(response, ProviderResponse::SetXattr));
+        assert_eq!(get_xattr(&mut provider, &id, PINNED_XATTR).await, b"1");
+        // the answer does not wait for the download
+        assert!(provider.running_requests.contains_key(&id));
+        provider
+            .wait_for_drive_request_if_exists(&id)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(provider.perma_dir.join(id.as_str())).unwrap(),
+            b"map"
+        );
+        assert_eq!(
+            load_pinned(&provider.perma_dir),
+            HashSet::from([id.clone()])
+        );
+
+        let response = set_xattr(&mut provider, &id, PINNED_XATTR, b"0").await;
+        assert!(matches!(response, ProviderResponse::SetXattr));
+        assert_eq!(get_xattr(&mut provider, &id, PINNED_XATTR).await, b"0");
+        assert!(load_pinned(&provider.perma_dir).is_empty());
+        let folder = provider.alt_root_id.clone();
+        let response = set_xattr(&mut provider, &folder, PINNED_XATTR, b"1").await;
+        assert!(matches!(response, ProviderResponse::Error(_, libc::EISDIR)));
     }
-    if let Some(description) = change.description {
-        entry.metadata.description = Some(description);
+
+    #[test]
+    fn entries_report_the_configured_blksize() {
+        let (mut provider, _dir) = create_provider();
+        let metadata = DriveFileMetadata {
+            id: Some("video".to_string()),
+            name: Some("video.mp4".to_string()),
+            size: Some(1 << 30),
+            mime_type: Some("video/mp4".to_string()),
+            ..Default::default()
+        };
+        let attr = provider.create_file_attr_from_metadata(&metadata).unwrap();
+        assert_eq!(attr.blksize, DEFAULT_BLKSIZE);
+
+        provider.set_blksize(128 * 1024);
+        let attr = provider.create_file_attr_from_metadata(&metadata).unwrap();
+        assert_eq!(attr.blksize, 128 * 1024);
     }
-    if let Some(thumbnail_link) = change.thumbnail_link {
-        entry.metadata.thumbnail_link = Some(thumbnail_link);
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn verifying_the_cache_evicts_corrupt_files() {
+        let mock = MockDrive::start().await.unwrap();
+        let intact = mock.add_file("intact.txt", &mock.root_id(), b"intact content");
+        let corrupt = mock.add_file("corrupt.txt", &mock.root_id(), b"corrupt content");
+        let (mut provider, dir) = create_provider_with_drive(mock.drive());
+        // what a previous run that crashed while writing left behind
+        std::fs::write(dir.path().join(intact.as_str()), b"intact content").unwrap();
+        std::fs::write(dir.path().join(corrupt.as_str()), b"corr").unwrap();
+        provider.set_verify_cache(true);
+        provider.initialize_entries().await.unwrap();
+
+        assert!(provider.entries[&intact].is_local);
+        assert!(!provider.entries[&corrupt].is_local);
+        assert!(!dir.path().join(corrupt.as_str()).exists());
+        let verification = provider.verify_cache().await;
+        assert_eq!(verification, CacheVerification::default());
+
+        let fh = open(&mut provider, &corrupt, libc::O_RDONLY).await;
+        assert_eq!(
+            read(&mut provider, &corrupt, fh, 0, 100).await,
+            b"corrupt content"
+        );
     }
-    warn!("not all changes have been implemented");
-    //TODO2: implement all other needed changes!
-    // if let Some() = change.{
-    //      entry.metadata. = ;
-    // }
-    Ok(())
-}
 
-fn remove_volatile_metadata(metadata: &mut DriveFileMetadata) {
-    metadata.size = None;
-    metadata.created_time = None;
-    metadata.trashed_time = None;
-    metadata.trashed = None;
-    metadata.modified_by_me_time = None;
-    metadata.modified_time = None;
-    metadata.shared_with_me_time = None;
-    metadata.viewed_by_me_time = None;
-    metadata.explicitly_trashed = None;
-    metadata.md5_checksum = None;
-    // metadata.parents = None;
-    // parents have to be set differently: "The parents field is not directly writable in update requests. Use the addParents and removeParents parameters instead."
-    metadata.kind = None;
-}
+    #[tokio::test]
+    async fn read_dir_of_a_file_fails_with_enotdir() {
+        let (mut provider, _dir) = create_provider();
+        let id = DriveId::from("file");
+        add_local_file(&mut provider, &id, b"content").await;
 
-fn convert_mime_type_to_file_type(mime_type: &str) -> Result<FileType> {
-    Ok(match mime_type {
-        "application/vnd.google-apps.folder" => FileType::Directory,
-        "application/vnd.google-apps.document"
-        | "application/vnd.google-apps.spreadsheet"
-        | "application/vnd.google-apps.drawing"
-        | "application/vnd.google-apps.form"
-        | "application/vnd.google-apps.presentation"
-        | "application/vnd.google-apps.drive-sdk"
-        | "application/vnd.google-apps.script"
-        | "application/vnd.google-apps.*"
-        //TODO: add all relevant mime types to ignore or match only the start or something
-        => return Err(anyhow!("google app files are not supported (docs, sheets, etc)")),
-        _ => FileType::RegularFile,
-    })
-}
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let request = ProviderReadDirRequest::new(id, 0, 256, tx);
+        provider.read_dir(request).await.unwrap();
+        let response = rx.recv().await.unwrap();
+        assert!(matches!(
+            response,
+            ProviderResponse::Error(_, libc::ENOTDIR)
+        ));
+    }
 
-// TODOs:
-// TODO: actually upload the changes to google drive at release (start it in there, don't wait for it to finish)
-// TODO: implement the changes api again (maybe with periodic updates?)
-// TODO: decide what to do with a fsync
-// TODO: create a way to write to a file and read
-//      - read and write at least kind of work ('echo "hi" >> file' does work, opening editors like vim, nano or gui editors like kate dont, they hang up at write, open or just don't write something correct)
-//          probably truncate flags or something
-//           - when running 'echo "1231234" > file' first a setattr gets called, setting the size to 0, and then stuff gets written
-// TODO: conform to the flags passed with open like 'read-write' or 'readonly'
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stopping_starts_the_scheduled_uploads() {
+        let mock = MockDrive::start().await.unwrap();
+        let id = mock.add_file("file.txt", &mock.root_id(), b"old");
+        let (mut provider, _dir) = create_provider_with_drive(mock.drive());
+        provider.set_upload_windows(UploadWindows::new(&[(0, Duration::from_secs(60))]));
+        provider.load_entry_if_unknown(&id).await.unwrap();
+        add_local_file(&mut provider, &id, b"changed").await;
+        provider.schedule_upload(&id).await.unwrap();
+        assert!(provider.scheduled_uploads.contains_key(&id));
+
+        let (_request_tx, request_rx) = tokio::sync::mpsc::channel(1);
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(1);
+        let listener = tokio::spawn(async move {
+            provider.listen(request_rx, command_rx).await;
+        });
+        command_tx.send(ProviderCommand::Stop).await.unwrap();
+        listener.await.unwrap();
+        assert_eq!(mock.content(&id).unwrap(), b"changed");
+    }
+}
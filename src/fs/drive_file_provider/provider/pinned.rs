@@ -0,0 +1,51 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use tracing::warn;
+
+use crate::google_drive::DriveId;
+
+/// the file in the perma dir that lists the pinned files, one id per line
+const PINNED_FILE_NAME: &str = ".pinned";
+
+fn pinned_path(perma_dir: &Path) -> PathBuf {
+    perma_dir.join(PINNED_FILE_NAME)
+}
+
+/// the ids of the files that were pinned, empty if nothing was pinned yet
+pub(crate) fn load_pinned(perma_dir: &Path) -> HashSet<DriveId> {
+    match std::fs::read_to_string(pinned_path(perma_dir)) {
+        Ok(content) => content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(DriveId::from)
+            .collect(),
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("could not read the pinned files: {:?}", e);
+            }
+            HashSet::new()
+        }
+    }
+}
+
+pub(crate) fn save_pinned(perma_dir: &Path, pinned: &HashSet<DriveId>) -> io::Result<()> {
+    let mut ids: Vec<&str> = pinned.iter().map(DriveId::as_str).collect();
+    ids.sort_unstable();
+    let mut content = ids.join("\n");
+    content.push('\n');
+    std::fs::write(pinned_path(perma_dir), content)
+}
+
+/// moves a cache file between the cache and the perma dir, those don't have
+/// to be on the same filesystem
+pub(crate) fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from)
+}
@@ -0,0 +1,69 @@
+use std::collections::{HashSet, VecDeque};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::fs::drive_file_provider::PrefetchProgress;
+use crate::google_drive::DriveId;
+
+/// how many files of a prefetch get downloaded at the same time
+pub(crate) const MAX_CONCURRENT_PREFETCHES: usize = 4;
+
+/// the files of the prefetched folders that are not pinned yet
+pub(crate) struct Prefetches {
+    pub(crate) queue: VecDeque<DriveId>,
+    /// the files whose download is running
+    pub(crate) running: HashSet<DriveId>,
+    /// `None` until the first folder gets prefetched
+    pub(crate) progress: Option<PrefetchProgress>,
+    done_tx: UnboundedSender<(DriveId, bool)>,
+    /// the downloads that ended and if they succeeded
+    pub(crate) done_rx: UnboundedReceiver<(DriveId, bool)>,
+}
+
+impl Default for Prefetches {
+    fn default() -> Self {
+        let (done_tx, done_rx) = unbounded_channel();
+        Self {
+            queue: VecDeque::new(),
+            running: HashSet::new(),
+            progress: None,
+            done_tx,
+            done_rx,
+        }
+    }
+}
+
+impl Prefetches {
+    pub(crate) fn is_running(&self) -> bool {
+        !self.running.is_empty()
+    }
+    /// the guard for the download task of the file, see [PrefetchDone]
+    pub(crate) fn done_guard(&self, id: DriveId) -> PrefetchDone {
+        PrefetchDone {
+            id,
+            ok: false,
+            tx: self.done_tx.clone(),
+        }
+    }
+}
+
+/// reports the end of a prefetch download when it gets dropped, so a cancelled
+/// download is reported as failed instead of never
+pub(crate) struct PrefetchDone {
+    id: DriveId,
+    ok: bool,
+    tx: UnboundedSender<(DriveId, bool)>,
+}
+
+impl PrefetchDone {
+    pub(crate) fn succeeded(mut self) {
+        self.ok = true;
+    }
+}
+
+impl Drop for PrefetchDone {
+    fn drop(&mut self) {
+        // the provider is gone if nobody listens anymore
+        let _ = self.tx.send((self.id.clone(), self.ok));
+    }
+}
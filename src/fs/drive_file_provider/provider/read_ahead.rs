@@ -0,0 +1,101 @@
+/// how much gets read from the cache file at once by default when a handle is read sequentially
+pub(crate) const READ_AHEAD_SIZE: usize = 1 << 20;
+
+/// Data of a file handle that was read from the cache file ahead of time.
+///
+/// Sequential readers like `cat` or media players send many small reads, with
+/// this the file only has to be read once for every block of the read ahead size.
+#[derive(Debug, Default)]
+pub(crate) struct ReadAhead {
+    offset: u64,
+    data: Vec<u8>,
+    /// if the data goes up to the end of the file
+    reaches_eof: bool,
+    /// where the last read ended, a read starting there is sequential
+    next_offset: Option<u64>,
+}
+
+impl ReadAhead {
+    /// the buffered data for the range, `None` if not all of it is buffered
+    pub(crate) fn get(&self, offset: u64, size: usize) -> Option<&[u8]> {
+        let start = usize::try_from(offset.checked_sub(self.offset)?).ok()?;
+        if start > self.data.len() || (self.data.is_empty() && !self.reaches_eof) {
+            return None;
+        }
+        let end = start.saturating_add(size);
+        if end <= self.data.len() {
+            Some(&self.data[start..end])
+        } else if self.reaches_eof {
+            Some(&self.data[start..])
+        } else {
+            None
+        }
+    }
+    /// a read is sequential if it starts where the last one ended, or at the
+    /// start of the file if there was none
+    pub(crate) fn is_sequential(&self, offset: u64) -> bool {
+        self.next_offset.unwrap_or(0) == offset
+    }
+    pub(crate) fn fill(&mut self, offset: u64, data: Vec<u8>, reaches_eof: bool) {
+        self.offset = offset;
+        self.data = data;
+        self.reaches_eof = reaches_eof;
+    }
+    /// keeps what was read beyond the `size` bytes of the read for the next
+    /// reads, if `read_size` was more than that, and returns the data of the read
+    pub(crate) fn serve(
+        &mut self,
+        offset: u64,
+        size: usize,
+        data: Vec<u8>,
+        read_size: usize,
+    ) -> Vec<u8> {
+        let buf = if read_size > size {
+            let buf = data[..size.min(data.len())].to_vec();
+            let reaches_eof = data.len() < read_size;
+            self.fill(offset, data, reaches_eof);
+            buf
+        } else {
+            self.clear();
+            data
+        };
+        self.record_read(offset, buf.len());
+        buf
+    }
+    pub(crate) fn record_read(&mut self, offset: u64, size: usize) {
+        self.next_offset = Some(offset + size as u64);
+    }
+    /// has to be called when the file changes, so no outdated data is served
+    pub(crate) fn clear(&mut self) {
+        self.data = Vec::new();
+        self.reaches_eof = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_buffered_ranges() {
+        let mut read_ahead = ReadAhead::default();
+        assert!(read_ahead.is_sequential(0));
+        assert_eq!(read_ahead.get(0, 1), None);
+
+        read_ahead.fill(10, b"0123456789".to_vec(), false);
+        read_ahead.record_read(10, 2);
+        assert!(read_ahead.is_sequential(12));
+        assert!(!read_ahead.is_sequential(0));
+        assert_eq!(read_ahead.get(12, 3), Some(&b"234"[..]));
+        assert_eq!(read_ahead.get(18, 4), None);
+        assert_eq!(read_ahead.get(5, 2), None);
+
+        // at the end of the file the rest is all there is
+        read_ahead.fill(10, b"0123456789".to_vec(), true);
+        assert_eq!(read_ahead.get(18, 4), Some(&b"89"[..]));
+        assert_eq!(read_ahead.get(20, 4), Some(&b""[..]));
+
+        read_ahead.clear();
+        assert_eq!(read_ahead.get(12, 3), None);
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use google_drive3::api::Revision;
+
+use crate::google_drive::DriveId;
+
+/// the folder with the revisions of a file is named like the file with this at the end
+pub(crate) const REVISIONS_SUFFIX: &str = "@revisions";
+/// drive ids never contain an `@`, so the ids of the revision entries can't
+/// collide with the ids of files
+const REVISION_ID_SEPARATOR: &str = "@rev-";
+
+pub(crate) fn revisions_folder_id(file_id: &DriveId) -> DriveId {
+    DriveId::from(format!("{}{}", file_id, REVISIONS_SUFFIX))
+}
+
+/// the file whose revisions are in the folder, `None` if it is no revisions folder
+pub(crate) fn file_of_revisions_folder(id: &DriveId) -> Option<DriveId> {
+    id.as_str()
+        .strip_suffix(REVISIONS_SUFFIX)
+        .map(DriveId::from)
+}
+
+pub(crate) fn revision_entry_id(file_id: &DriveId, revision_id: &str) -> DriveId {
+    DriveId::from(format!(
+        "{}{}{}",
+        file_id, REVISION_ID_SEPARATOR, revision_id
+    ))
+}
+
+/// the file and the revision id of a revision entry, `None` if it is no revision
+pub(crate) fn parse_revision_entry_id(id: &DriveId) -> Option<(DriveId, String)> {
+    let (file_id, revision_id) = id.as_str().split_once(REVISION_ID_SEPARATOR)?;
+    Some((DriveId::from(file_id), revision_id.to_string()))
+}
+
+/// `<modified time>_<revision id>` with the extension of the file, so the
+/// revisions sort by their time and open with the same program as the file
+pub(crate) fn revision_name(revision: &Revision, revision_id: &str, file_name: &str) -> String {
+    let mut name = match revision.modified_time {
+        Some(modified_time) => format!(
+            "{}_{}",
+            modified_time.format("%Y-%m-%d_%H-%M-%S"),
+            revision_id
+        ),
+        None => revision_id.to_string(),
+    };
+    if let Some(extension) = Path::new(file_name).extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    name
+}
@@ -0,0 +1,109 @@
+use std::{fs::File, io, os::fd::AsRawFd};
+
+use super::block_map::BlockMap;
+
+/// where the next data (`SEEK_DATA`) or hole (`SEEK_HOLE`) at or after `offset`
+/// starts in a file of `size` bytes whose content is in the cache file `file`.
+/// `None` if there is no data after the offset, `lseek` fails with `ENXIO` then.
+///
+/// The blocks a partial cache file does not have yet are data, they only are
+/// not downloaded yet. Everywhere else the holes of the cache file are the
+/// holes of the file, the end of the file counts as one as well.
+pub(crate) fn seek_data_or_hole(
+    file: &File,
+    size: u64,
+    block_map: Option<&BlockMap>,
+    offset: u64,
+    whence: i32,
+) -> io::Result<Option<u64>> {
+    if offset >= size {
+        return Ok(None);
+    }
+    let missing_from = |offset: u64| {
+        block_map
+            .and_then(|map| map.missing_ranges(offset, u64::MAX).first().copied())
+            .map(|(start, len)| (start.max(offset), start + len))
+    };
+    if whence == libc::SEEK_DATA {
+        let data = seek(file, offset, libc::SEEK_DATA)?.unwrap_or(size);
+        let data = missing_from(offset).map_or(data, |(start, _)| data.min(start));
+        return Ok((data < size).then_some(data));
+    }
+    let mut offset = offset;
+    loop {
+        let hole = seek(file, offset, libc::SEEK_HOLE)?
+            .unwrap_or(size)
+            .min(size);
+        match missing_from(hole) {
+            // the hole is only a block that is not downloaded yet
+            Some((start, end)) if start == hole && end < size => offset = end,
+            Some((start, _)) if start == hole => return Ok(Some(size)),
+            _ => return Ok(Some(hole)),
+        }
+    }
+}
+
+/// `lseek` on the cache file, `None` for `ENXIO`
+fn seek(file: &File, offset: u64, whence: i32) -> io::Result<Option<u64>> {
+    // SAFETY: lseek only moves the offset of the descriptor, which `file` owns
+    let result = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) };
+    if result >= 0 {
+        return Ok(Some(result as u64));
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::ENXIO) => Ok(None),
+        _ => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::FileExt;
+
+    use super::super::block_map::BLOCK_SIZE;
+    use super::*;
+
+    #[test]
+    fn data_and_holes_follow_the_cache_file_and_the_missing_blocks() {
+        const B: u64 = BLOCK_SIZE;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sparse");
+        // data in block 1 and 4, the rest are holes
+        let file = File::create(&path).unwrap();
+        file.set_len(8 * B).unwrap();
+        file.write_all_at(&[1; BLOCK_SIZE as usize], B).unwrap();
+        file.write_all_at(&[1; BLOCK_SIZE as usize], 4 * B).unwrap();
+        file.sync_all().unwrap();
+        let file = File::open(&path).unwrap();
+        let data = |map: Option<&BlockMap>, offset| {
+            seek_data_or_hole(&file, 8 * B, map, offset, libc::SEEK_DATA).unwrap()
+        };
+        let hole = |map: Option<&BlockMap>, offset| {
+            seek_data_or_hole(&file, 8 * B, map, offset, libc::SEEK_HOLE).unwrap()
+        };
+
+        assert_eq!(data(None, 0), Some(B));
+        assert_eq!(data(None, B + 10), Some(B + 10));
+        assert_eq!(data(None, 2 * B), Some(4 * B));
+        assert_eq!(data(None, 5 * B), None);
+        assert_eq!(hole(None, 0), Some(0));
+        assert_eq!(hole(None, B), Some(2 * B));
+        assert_eq!(hole(None, 5 * B), Some(5 * B));
+        assert_eq!(data(None, 8 * B), None);
+        assert_eq!(hole(None, 8 * B), None);
+
+        // block 6 and 7 are not downloaded, their content is still data
+        let mut map = BlockMap::new(8 * B);
+        map.mark_present(0, 6 * B);
+        assert_eq!(data(Some(&map), 5 * B), Some(6 * B));
+        assert_eq!(hole(Some(&map), 5 * B), Some(5 * B));
+        assert_eq!(hole(Some(&map), 6 * B), Some(8 * B));
+        // block 2 is missing, so the hole after block 1 starts at block 3
+        let mut map = BlockMap::new(8 * B);
+        map.mark_present(0, 2 * B);
+        map.mark_present(3 * B, 5 * B);
+        assert_eq!(hole(Some(&map), B), Some(3 * B));
+        assert_eq!(data(Some(&map), 2 * B + 1), Some(2 * B + 1));
+    }
+}
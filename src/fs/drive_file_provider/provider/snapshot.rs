@@ -0,0 +1,245 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use google_drive3::api::StartPageToken;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::google_drive::DriveId;
+use crate::prelude::*;
+
+/// the folder in the perma dir with the entries of the last run
+const SNAPSHOT_DIR_NAME: &str = ".entries";
+/// the page token and the root, the snapshot is complete once it exists
+const STATE_FILE_NAME: &str = "state.json";
+/// the folder in the snapshot with one record per entry, named after its id
+const RECORDS_DIR_NAME: &str = "records";
+
+/// The entries the provider knew when it last ran, with the page token of the
+/// changes api from that time. Starting offline uses them instead of listing
+/// the drive, the changes since then get applied once the provider is online.
+///
+/// Every entry has its own record, so a change only rewrites the record of
+/// the entry it changed, see [save_record] and [remove_record].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Snapshot {
+    pub start_page_token: StartPageToken,
+    pub root: DriveFileMetadata,
+    pub entries: Vec<DriveFileMetadata>,
+}
+
+fn snapshot_dir(perma_dir: &Path) -> PathBuf {
+    perma_dir.join(SNAPSHOT_DIR_NAME)
+}
+
+fn record_path(perma_dir: &Path, id: &str) -> PathBuf {
+    snapshot_dir(perma_dir)
+        .join(RECORDS_DIR_NAME)
+        .join(format!("{}.json", id))
+}
+
+pub(crate) fn has_snapshot(perma_dir: &Path) -> bool {
+    snapshot_dir(perma_dir).join(STATE_FILE_NAME).is_file()
+}
+
+/// the snapshot of the last run, `None` if there is none or it can't be read
+pub(crate) fn load_snapshot(perma_dir: &Path) -> Option<Snapshot> {
+    match read_snapshot(&snapshot_dir(perma_dir)) {
+        Ok(snapshot) => Some(snapshot),
+        Err(e) => {
+            let not_found = e
+                .downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::NotFound);
+            if !not_found {
+                warn!("could not read the saved entries: {:?}", e);
+            }
+            None
+        }
+    }
+}
+
+fn read_snapshot(dir: &Path) -> Result<Snapshot> {
+    let mut state: Value = serde_json::from_slice(&std::fs::read(dir.join(STATE_FILE_NAME))?)?;
+    let mut entries = Vec::new();
+    for record in std::fs::read_dir(dir.join(RECORDS_DIR_NAME))? {
+        let path = record?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            let entry = serde_json::from_slice(&std::fs::read(&path)?)
+                .with_context(|| format!("the record {} is corrupt", path.display()))?;
+            entries.push(entry);
+        }
+    }
+    Ok(Snapshot {
+        start_page_token: serde_json::from_value(state["startPageToken"].take())?,
+        root: serde_json::from_value(state["root"].take())?,
+        entries,
+    })
+}
+
+/// replaces the whole snapshot. It is written next to the old one first, a
+/// crash while writing would leave nothing to start offline with otherwise
+pub(crate) fn save_snapshot(perma_dir: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    let dir = snapshot_dir(perma_dir);
+    let temp_dir = dir.with_extension("tmp");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir)?;
+    }
+    std::fs::create_dir_all(temp_dir.join(RECORDS_DIR_NAME))?;
+    for entry in &snapshot.entries {
+        if let Some(id) = &entry.id {
+            let path = temp_dir.join(RECORDS_DIR_NAME).join(format!("{}.json", id));
+            std::fs::write(path, json!(entry).to_string())?;
+        }
+    }
+    write_state_to(&temp_dir, &snapshot.start_page_token, &snapshot.root)?;
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::rename(temp_dir, dir)
+}
+
+/// updates the page token and the root of an existing snapshot
+pub(crate) fn save_state(
+    perma_dir: &Path,
+    start_page_token: &StartPageToken,
+    root: &DriveFileMetadata,
+) -> io::Result<()> {
+    write_state_to(&snapshot_dir(perma_dir), start_page_token, root)
+}
+
+fn write_state_to(
+    dir: &Path,
+    start_page_token: &StartPageToken,
+    root: &DriveFileMetadata,
+) -> io::Result<()> {
+    let state = json!({
+        "startPageToken": start_page_token,
+        "root": root,
+    });
+    write_atomically(&dir.join(STATE_FILE_NAME), state.to_string().as_bytes())
+}
+
+/// adds or replaces the record of the entry in an existing snapshot
+pub(crate) fn save_record(perma_dir: &Path, entry: &DriveFileMetadata) -> io::Result<()> {
+    let Some(id) = &entry.id else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the entry has no id",
+        ));
+    };
+    write_atomically(
+        &record_path(perma_dir, id),
+        json!(entry).to_string().as_bytes(),
+    )
+}
+
+/// removes the record of the entry, if there is one
+pub(crate) fn remove_record(perma_dir: &Path, id: &DriveId) -> io::Result<()> {
+    match std::fs::remove_file(record_path(perma_dir, id.as_str())) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, content)?;
+    std::fs::rename(temp_path, path)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// the content of every record, by the name of its file
+    pub(crate) fn records(perma_dir: &Path) -> HashMap<String, Vec<u8>> {
+        std::fs::read_dir(snapshot_dir(perma_dir).join(RECORDS_DIR_NAME))
+            .unwrap()
+            .map(|record| {
+                let record = record.unwrap();
+                let name = record.file_name().to_string_lossy().to_string();
+                (name, std::fs::read(record.path()).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn snapshots_survive_a_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!has_snapshot(dir.path()));
+        assert!(load_snapshot(dir.path()).is_none());
+
+        let file = |id: &str| DriveFileMetadata {
+            id: Some(id.to_string()),
+            name: Some(format!("{}.txt", id)),
+            ..Default::default()
+        };
+        let snapshot = Snapshot {
+            start_page_token: StartPageToken {
+                start_page_token: Some("42".to_string()),
+                ..Default::default()
+            },
+            root: file("root"),
+            entries: vec![file("a"), file("b")],
+        };
+        save_snapshot(dir.path(), &snapshot).unwrap();
+        assert!(has_snapshot(dir.path()));
+        let loaded = load_snapshot(dir.path()).unwrap();
+        assert_eq!(
+            loaded.start_page_token.start_page_token.as_deref(),
+            Some("42")
+        );
+        assert_eq!(loaded.root.id.as_deref(), Some("root"));
+        let mut names: Vec<_> = loaded.entries.iter().map(|e| e.name.clone()).collect();
+        names.sort();
+        assert_eq!(names, vec![Some("a.txt".into()), Some("b.txt".into())]);
+
+        std::fs::write(record_path(dir.path(), "a"), "{").unwrap();
+        assert!(load_snapshot(dir.path()).is_none());
+    }
+
+    #[test]
+    fn records_are_saved_and_removed_one_by_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |id: &str, name: &str| DriveFileMetadata {
+            id: Some(id.to_string()),
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+        let snapshot = Snapshot {
+            root: file("root", "root"),
+            entries: vec![file("a", "a.txt"), file("b", "b.txt")],
+            ..Default::default()
+        };
+        save_snapshot(dir.path(), &snapshot).unwrap();
+        let before = records(dir.path());
+
+        save_record(dir.path(), &file("a", "renamed.txt")).unwrap();
+        remove_record(dir.path(), &DriveId::from("b")).unwrap();
+        remove_record(dir.path(), &DriveId::from("missing")).unwrap();
+        let after = records(dir.path());
+        assert_eq!(after.len(), 1);
+        assert_ne!(after["a.json"], before["a.json"]);
+
+        let token = StartPageToken {
+            start_page_token: Some("7".to_string()),
+            ..Default::default()
+        };
+        save_state(dir.path(), &token, &snapshot.root).unwrap();
+        let loaded = load_snapshot(dir.path()).unwrap();
+        assert_eq!(
+            loaded.start_page_token.start_page_token.as_deref(),
+            Some("7")
+        );
+        assert_eq!(loaded.entries.len(), 1);
+        assert_eq!(loaded.entries[0].name.as_deref(), Some("renamed.txt"));
+    }
+}
@@ -0,0 +1,252 @@
+use std::io::Read;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, HeaderMap, Request, Response, Server, StatusCode};
+use tokio::sync::{mpsc::Sender, oneshot, watch};
+use tokio::time::sleep;
+use tracing::{debug, info, instrument, warn};
+
+use crate::fs::drive_file_provider::ProviderCommand;
+use crate::google_drive::{GoogleDrive, WatchChannel};
+use crate::prelude::*;
+
+/// how long a channel is requested for, google drive allows a week at most
+pub const DEFAULT_CHANNEL_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+/// a channel gets renewed this long before it expires, so no notification gets lost
+const RENEW_MARGIN: Duration = Duration::from_secs(5 * 60);
+/// how long to wait before trying to register a channel again after it failed
+const RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+/// renewing more often than this would only waste quota
+const MIN_RENEW_DELAY: Duration = Duration::from_secs(60);
+
+/// Lets google drive notify the provider about changes, so they show up
+/// without waiting for the next poll.
+///
+/// Google drive sends the notifications to a public https url, which has to
+/// forward them to `listen_address` (for example with a reverse proxy).
+/// While no channel is registered the changes are only polled, like without push.
+#[derive(Debug, Clone)]
+pub struct PushSettings {
+    pub callback_url: String,
+    pub listen_address: SocketAddr,
+    /// how long each channel is requested for, it gets renewed before it expires
+    pub channel_lifetime: Duration,
+}
+
+impl PushSettings {
+    pub fn new(callback_url: impl Into<String>, listen_address: SocketAddr) -> Self {
+        Self {
+            callback_url: callback_url.into(),
+            listen_address,
+            channel_lifetime: DEFAULT_CHANNEL_LIFETIME,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Notification {
+    /// the first message of a new channel, nothing changed
+    Sync,
+    Change,
+    /// not for the current channel or with the wrong token
+    Unknown,
+}
+
+/// receives the notifications and keeps a channel registered until `shutdown`
+/// fires, then stops the channel
+#[instrument(skip(drive, commands, shutdown))]
+pub(crate) async fn run_push_watcher(
+    drive: GoogleDrive,
+    settings: PushSettings,
+    commands: Sender<ProviderCommand>,
+    mut shutdown: oneshot::Receiver<()>,
+) {
+    let (current_channel_tx, current_channel_rx) = watch::channel(None);
+    let server = match start_receiver(settings.listen_address, current_channel_rx, commands) {
+        Ok(server) => tokio::spawn(server),
+        Err(e) => {
+            warn!(
+                "could not start the push notification receiver, only polling for changes: {:?}",
+                e
+            );
+            return;
+        }
+    };
+
+    let mut channel: Option<WatchChannel> = None;
+    loop {
+        let wait = match register_channel(&drive, &settings).await {
+            Ok(new_channel) => {
+                info!("registered push channel {}", new_channel.id);
+                let wait = time_until_renewal(
+                    new_channel.expiration,
+                    settings.channel_lifetime,
+                    SystemTime::now(),
+                );
+                current_channel_tx.send_replace(Some(new_channel.clone()));
+                if let Some(old_channel) = channel.replace(new_channel) {
+                    stop_channel(&drive, &old_channel).await;
+                }
+                wait
+            }
+            Err(e) => {
+                warn!(
+                    "could not register a push channel, only polling for changes until the next try: {:?}",
+                    e
+                );
+                RETRY_DELAY
+            }
+        };
+        tokio::select! {
+            _ = sleep(wait) => {},
+            _ = &mut shutdown => break,
+        }
+    }
+    server.abort();
+    if let Some(channel) = channel {
+        stop_channel(&drive, &channel).await;
+    }
+    debug!("push watcher finished");
+}
+
+async fn register_channel(drive: &GoogleDrive, settings: &PushSettings) -> Result<WatchChannel> {
+    let id = format!("drive_syncer-{}", random_hex()?);
+    let token = random_hex()?;
+    let expiration = SystemTime::now() + settings.channel_lifetime;
+    drive
+        .watch_changes(&settings.callback_url, id, token, expiration)
+        .await
+}
+
+async fn stop_channel(drive: &GoogleDrive, channel: &WatchChannel) {
+    if let Err(e) = drive.stop_watching(channel).await {
+        // it stops sending notifications when it expires anyway
+        warn!("could not stop push channel {}: {:?}", channel.id, e);
+    }
+}
+
+fn start_receiver(
+    address: SocketAddr,
+    current_channel: watch::Receiver<Option<WatchChannel>>,
+    commands: Sender<ProviderCommand>,
+) -> Result<impl std::future::Future<Output = hyper::Result<()>>> {
+    let make_service = make_service_fn(move |_| {
+        let current_channel = current_channel.clone();
+        let commands = commands.clone();
+        async move {
+            Ok::<_, std::convert::Infallible>(service_fn(move |request: Request<Body>| {
+                let notification =
+                    check_notification(request.headers(), current_channel.borrow().as_ref());
+                let commands = commands.clone();
+                async move {
+                    debug!("got push notification: {:?}", notification);
+                    let status = match notification {
+                        Notification::Sync => StatusCode::OK,
+                        Notification::Change => {
+                            // if the queue is full a check is already pending
+                            let _ = commands.try_send(ProviderCommand::CheckForChanges);
+                            StatusCode::OK
+                        }
+                        Notification::Unknown => StatusCode::FORBIDDEN,
+                    };
+                    let mut response = Response::new(Body::empty());
+                    *response.status_mut() = status;
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }))
+        }
+    });
+    Ok(Server::try_bind(&address)?.serve(make_service))
+}
+
+fn check_notification(headers: &HeaderMap, channel: Option<&WatchChannel>) -> Notification {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let Some(channel) = channel else {
+        return Notification::Unknown;
+    };
+    if header("x-goog-channel-id") != Some(channel.id.as_str())
+        || header("x-goog-channel-token") != Some(channel.token.as_str())
+    {
+        return Notification::Unknown;
+    }
+    match header("x-goog-resource-state") {
+        Some("sync") => Notification::Sync,
+        _ => Notification::Change,
+    }
+}
+
+/// how long to wait before the channel gets renewed, `requested_lifetime`
+/// is used if google drive did not say when it expires
+fn time_until_renewal(
+    expiration: Option<SystemTime>,
+    requested_lifetime: Duration,
+    now: SystemTime,
+) -> Duration {
+    let lifetime = expiration
+        .map(|expiration| expiration.duration_since(now).unwrap_or(Duration::ZERO))
+        .unwrap_or(requested_lifetime);
+    lifetime.saturating_sub(RENEW_MARGIN).max(MIN_RENEW_DELAY)
+}
+
+/// the channel token has to be unguessable, otherwise anyone could trigger polls
+fn random_hex() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_notifications_of_the_current_channel_are_accepted() {
+        let channel = WatchChannel {
+            id: "channel".to_string(),
+            token: "token".to_string(),
+            resource_id: None,
+            expiration: None,
+        };
+        let headers = |id: &str, token: &str, state: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-goog-channel-id", id.parse().unwrap());
+            headers.insert("x-goog-channel-token", token.parse().unwrap());
+            headers.insert("x-goog-resource-state", state.parse().unwrap());
+            headers
+        };
+
+        let change = headers("channel", "token", "change");
+        assert_eq!(
+            check_notification(&change, Some(&channel)),
+            Notification::Change
+        );
+        assert_eq!(check_notification(&change, None), Notification::Unknown);
+        assert_eq!(
+            check_notification(&headers("channel", "token", "sync"), Some(&channel)),
+            Notification::Sync
+        );
+        assert_eq!(
+            check_notification(&headers("channel", "forged", "change"), Some(&channel)),
+            Notification::Unknown
+        );
+        assert_eq!(
+            check_notification(&headers("old", "token", "change"), Some(&channel)),
+            Notification::Unknown
+        );
+    }
+
+    #[test]
+    fn channels_are_renewed_before_they_expire() {
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(60 * 60);
+        assert_eq!(
+            time_until_renewal(Some(now + hour), DEFAULT_CHANNEL_LIFETIME, now),
+            hour - RENEW_MARGIN
+        );
+        assert_eq!(time_until_renewal(None, hour, now), hour - RENEW_MARGIN);
+        // already expired or about to
+        assert_eq!(time_until_renewal(Some(now), hour, now), MIN_RENEW_DELAY);
+    }
+}
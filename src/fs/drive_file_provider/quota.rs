@@ -0,0 +1,67 @@
+use std::time::{Duration, SystemTime};
+
+use google_drive3::api::AboutStorageQuota;
+
+use crate::common::is_cache_time_over;
+
+/// how long the storage quota is cached if nothing else is configured
+pub const DEFAULT_QUOTA_CACHE_TIME: Duration = Duration::from_secs(5 * 60);
+
+/// the storage quota of the drive at the time it was fetched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachedQuota {
+    /// the storage limit in bytes, `None` if the storage is unlimited
+    pub limit: Option<u64>,
+    /// the used storage in bytes, across all google services
+    pub usage: u64,
+    pub fetched_at: SystemTime,
+}
+
+impl CachedQuota {
+    pub(crate) fn from_storage_quota(quota: &AboutStorageQuota, fetched_at: SystemTime) -> Self {
+        Self {
+            limit: quota.limit.map(|limit| limit.max(0) as u64),
+            usage: quota.usage.unwrap_or(0).max(0) as u64,
+            fetched_at,
+        }
+    }
+    /// how many bytes can still be stored, `None` if the storage is unlimited
+    pub fn available(&self) -> Option<u64> {
+        self.limit.map(|limit| limit.saturating_sub(self.usage))
+    }
+    /// how long ago this was fetched
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed().unwrap_or(Duration::ZERO)
+    }
+    pub(crate) fn is_outdated(&self, cache_time: Duration) -> bool {
+        is_cache_time_over(self.fetched_at, cache_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_from_drive_response() {
+        let quota = AboutStorageQuota {
+            limit: Some(100),
+            usage: Some(30),
+            ..Default::default()
+        };
+        let quota = CachedQuota::from_storage_quota(&quota, SystemTime::now());
+        assert_eq!(quota.available(), Some(70));
+        assert!(!quota.is_outdated(Duration::from_secs(60)));
+
+        let unlimited = AboutStorageQuota {
+            usage: Some(30),
+            ..Default::default()
+        };
+        let unlimited = CachedQuota::from_storage_quota(
+            &unlimited,
+            SystemTime::now() - Duration::from_secs(120),
+        );
+        assert_eq!(unlimited.available(), None);
+        assert!(unlimited.is_outdated(Duration::from_secs(60)));
+    }
+}
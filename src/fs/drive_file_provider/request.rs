@@ -1,6 +1,7 @@
 use std::ffi::OsString;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Error;
 use fuser::{FileAttr, Filesystem};
@@ -8,21 +9,38 @@ use libc::c_int;
 use tokio::sync::mpsc::Sender;
 
 use crate::fs::drive2::HandleFlags;
-use crate::fs::drive_file_provider::FileHandleData;
-use crate::google_drive::DriveId;
+use crate::fs::drive_file_provider::{
+    CachedQuota, FileHandleData, HealthStatus, OpenCacheMode, PrefetchProgress, ProviderStats,
+};
+use crate::google_drive::{DriveId, UploadProgress};
 use crate::prelude::*;
 
 #[derive(Debug)]
 pub enum ProviderResponse {
-    OpenFile(u64, HandleFlags),
+    /// the cache mode is never [OpenCacheMode::Auto]
+    OpenFile(u64, HandleFlags, OpenCacheMode),
+    Flush,
     ReleaseFile,
     SetAttr(FileMetadata),
     Metadata(FileMetadata),
     Lookup(Option<FileMetadata>),
     ReadContent(Vec<u8>),
+    /// where the data or the hole that was asked for starts
+    Lseek(u64),
+    GetXattr(Vec<u8>),
+    SetXattr,
+    /// the names of the extended attributes of the entry
+    ListXattr(Vec<String>),
     ReadDir(ProviderReadDirResponse),
     Rename,
+    /// the id of the entry that was removed
+    Remove(DriveId),
+    /// the folder that was created, with the id google drive gave it
+    Mkdir(FileMetadata),
     WriteSize(u32),
+    Status(ProviderStatus),
+    /// the real id of the root folder, which `root` is an alias for
+    RootId(DriveId),
     // Ok,
     Error(Error, c_int),
     Unknown,
@@ -32,19 +50,90 @@ pub enum ProviderResponse {
 pub enum ProviderRequest {
     OpenFile(ProviderOpenFileRequest),
     Lookup(ProviderLookupRequest),
+    Flush(ProviderFlushRequest),
     ReleaseFile(ProviderReleaseFileRequest),
     Metadata(ProviderMetadataRequest),
-    SetAttr(ProviderSetAttrRequest),
+    /// boxed, since this is by far the biggest request
+    SetAttr(Box<ProviderSetAttrRequest>),
     ReadContent(ProviderReadContentRequest),
+    Lseek(ProviderLseekRequest),
+    GetXattr(ProviderGetXattrRequest),
+    SetXattr(ProviderSetXattrRequest),
+    ListXattr(ProviderListXattrRequest),
     ReadDir(ProviderReadDirRequest),
     Rename(ProviderRenameRequest),
+    Remove(ProviderRemoveRequest),
+    Mkdir(ProviderMkdirRequest),
     WriteContent(ProviderWriteContentRequest),
+    Status(ProviderStatusRequest),
+    RootId(ProviderRootIdRequest),
     Unknown,
 }
-pub trait ProviderRequestStruct {
-    fn get_file_id(&self) -> &DriveId;
+impl ProviderRequest {
+    /// the request as something that can be answered, `None` for
+    /// [ProviderRequest::Unknown]
+    pub fn responder(&self) -> Option<&dyn ProviderResponder> {
+        match self {
+            ProviderRequest::OpenFile(r) => Some(r),
+            ProviderRequest::Lookup(r) => Some(r),
+            ProviderRequest::Flush(r) => Some(r),
+            ProviderRequest::ReleaseFile(r) => Some(r),
+            ProviderRequest::Metadata(r) => Some(r),
+            ProviderRequest::SetAttr(r) => Some(r.as_ref()),
+            ProviderRequest::ReadContent(r) => Some(r),
+            ProviderRequest::Lseek(r) => Some(r),
+            ProviderRequest::GetXattr(r) => Some(r),
+            ProviderRequest::SetXattr(r) => Some(r),
+            ProviderRequest::ListXattr(r) => Some(r),
+            ProviderRequest::ReadDir(r) => Some(r),
+            ProviderRequest::Rename(r) => Some(r),
+            ProviderRequest::Remove(r) => Some(r),
+            ProviderRequest::Mkdir(r) => Some(r),
+            ProviderRequest::WriteContent(r) => Some(r),
+            ProviderRequest::Status(r) => Some(r),
+            ProviderRequest::RootId(r) => Some(r),
+            ProviderRequest::Unknown => None,
+        }
+    }
+}
+/// a request that gets answered through its response sender
+pub trait ProviderResponder {
     fn get_response_sender(&self) -> &Sender<ProviderResponse>;
 }
+pub trait ProviderRequestStruct: ProviderResponder {
+    fn get_file_id(&self) -> &DriveId;
+}
+macro_rules! impl_provider_responder {
+    ($($request:ty),* $(,)?) => {
+        $(
+            impl ProviderResponder for $request {
+                fn get_response_sender(&self) -> &Sender<ProviderResponse> {
+                    &self.response_sender
+                }
+            }
+        )*
+    };
+}
+impl_provider_responder!(
+    ProviderMetadataRequest,
+    ProviderSetAttrRequest,
+    ProviderOpenFileRequest,
+    ProviderLookupRequest,
+    ProviderReleaseFileRequest,
+    ProviderFlushRequest,
+    ProviderReadContentRequest,
+    ProviderLseekRequest,
+    ProviderGetXattrRequest,
+    ProviderSetXattrRequest,
+    ProviderListXattrRequest,
+    ProviderReadDirRequest,
+    ProviderWriteContentRequest,
+    ProviderRenameRequest,
+    ProviderRemoveRequest,
+    ProviderMkdirRequest,
+    ProviderStatusRequest,
+    ProviderRootIdRequest,
+);
 //region ProviderRequest structs
 #[derive(Debug)]
 pub struct ProviderMetadataRequest {
@@ -72,6 +161,9 @@ pub struct ProviderSetAttrRequest {
 
     pub flags: Option<u32>,
     pub fh: Option<u64>,
+    /// set separately, since most callers don't touch the times
+    pub atime: Option<SystemTime>,
+    pub mtime: Option<SystemTime>,
     pub response_sender: Sender<ProviderResponse>,
 }
 
@@ -94,6 +186,8 @@ impl ProviderSetAttrRequest {
             size,
             flags,
             fh,
+            atime: None,
+            mtime: None,
             response_sender,
         }
     }
@@ -103,10 +197,6 @@ impl ProviderRequestStruct for ProviderMetadataRequest {
     fn get_file_id(&self) -> &DriveId {
         &self.file_id
     }
-
-    fn get_response_sender(&self) -> &Sender<ProviderResponse> {
-        &self.response_sender
-    }
 }
 
 #[derive(Debug)]
@@ -179,6 +269,23 @@ impl ProviderReleaseFileRequest {
     }
 }
 
+#[derive(Debug)]
+pub struct ProviderFlushRequest {
+    pub file_id: DriveId,
+    pub fh: u64,
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderFlushRequest {
+    pub fn new(id: DriveId, fh: u64, response_sender: Sender<ProviderResponse>) -> Self {
+        Self {
+            file_id: id,
+            fh,
+            response_sender,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ProviderReadContentRequest {
     pub file_id: DriveId,
@@ -191,10 +298,6 @@ impl ProviderRequestStruct for ProviderReadContentRequest {
     fn get_file_id(&self) -> &DriveId {
         &self.file_id
     }
-
-    fn get_response_sender(&self) -> &Sender<ProviderResponse> {
-        &self.response_sender
-    }
 }
 
 impl ProviderReadContentRequest {
@@ -215,10 +318,103 @@ impl ProviderReadContentRequest {
     }
 }
 
+/// only `SEEK_DATA` and `SEEK_HOLE` get to the provider, the kernel answers the
+/// other seeks itself
+#[derive(Debug)]
+pub struct ProviderLseekRequest {
+    pub file_id: DriveId,
+    pub fh: u64,
+    pub offset: u64,
+    pub whence: i32,
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderLseekRequest {
+    pub(crate) fn new(
+        id: impl Into<DriveId>,
+        fh: u64,
+        offset: u64,
+        whence: i32,
+        response_sender: Sender<ProviderResponse>,
+    ) -> Self {
+        Self {
+            file_id: id.into(),
+            fh,
+            offset,
+            whence,
+            response_sender,
+        }
+    }
+}
+
+/// only the attributes that start with [DRIVE_XATTR_PREFIX](crate::fs::drive_file_provider::DRIVE_XATTR_PREFIX)
+/// get to the provider
+#[derive(Debug)]
+pub struct ProviderGetXattrRequest {
+    pub file_id: DriveId,
+    pub name: String,
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderGetXattrRequest {
+    pub(crate) fn new(
+        id: impl Into<DriveId>,
+        name: String,
+        response_sender: Sender<ProviderResponse>,
+    ) -> Self {
+        Self {
+            file_id: id.into(),
+            name,
+            response_sender,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProviderSetXattrRequest {
+    pub file_id: DriveId,
+    pub name: String,
+    pub value: Vec<u8>,
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderSetXattrRequest {
+    pub(crate) fn new(
+        id: impl Into<DriveId>,
+        name: String,
+        value: Vec<u8>,
+        response_sender: Sender<ProviderResponse>,
+    ) -> Self {
+        Self {
+            file_id: id.into(),
+            name,
+            value,
+            response_sender,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProviderListXattrRequest {
+    pub file_id: DriveId,
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderListXattrRequest {
+    pub(crate) fn new(id: impl Into<DriveId>, response_sender: Sender<ProviderResponse>) -> Self {
+        Self {
+            file_id: id.into(),
+            response_sender,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ProviderReadDirRequest {
     pub file_id: DriveId,
     pub offset: u64,
+    /// how many children are returned at most
+    pub limit: usize,
     pub response_sender: Sender<ProviderResponse>,
 }
 
@@ -226,20 +422,18 @@ impl ProviderRequestStruct for ProviderReadDirRequest {
     fn get_file_id(&self) -> &DriveId {
         &self.file_id
     }
-
-    fn get_response_sender(&self) -> &Sender<ProviderResponse> {
-        &self.response_sender
-    }
 }
 impl ProviderReadDirRequest {
     pub(crate) fn new(
         id: impl Into<DriveId>,
         offset: u64,
+        limit: usize,
         response_sender: Sender<ProviderResponse>,
     ) -> Self {
         Self {
             file_id: id.into(),
             offset,
+            limit,
             response_sender,
         }
     }
@@ -258,10 +452,6 @@ impl ProviderRequestStruct for ProviderWriteContentRequest {
     fn get_file_id(&self) -> &DriveId {
         &self.file_id
     }
-
-    fn get_response_sender(&self) -> &Sender<ProviderResponse> {
-        &self.response_sender
-    }
 }
 impl ProviderWriteContentRequest {
     pub(crate) fn new(
@@ -287,6 +477,8 @@ pub struct ProviderRenameRequest {
     pub original_parent: DriveId,
     pub new_name: OsString,
     pub new_parent: DriveId,
+    /// the flags of `renameat2`, like `RENAME_NOREPLACE`
+    pub flags: u32,
 
     pub response_sender: Sender<ProviderResponse>,
 }
@@ -297,6 +489,7 @@ impl ProviderRenameRequest {
         original_parent: DriveId,
         new_name: OsString,
         new_parent: DriveId,
+        flags: u32,
         response_sender: Sender<ProviderResponse>,
     ) -> Self {
         Self {
@@ -304,16 +497,126 @@ impl ProviderRenameRequest {
             original_parent,
             new_name,
             new_parent,
+            flags,
+            response_sender,
+        }
+    }
+}
+
+/// removes a file (`unlink`) or an empty folder (`rmdir`)
+#[derive(Debug)]
+pub struct ProviderRemoveRequest {
+    pub parent: DriveId,
+    pub name: OsString,
+    pub is_dir: bool,
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderRemoveRequest {
+    pub(crate) fn new(
+        parent: DriveId,
+        name: OsString,
+        is_dir: bool,
+        response_sender: Sender<ProviderResponse>,
+    ) -> Self {
+        Self {
+            parent,
+            name,
+            is_dir,
+            response_sender,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProviderMkdirRequest {
+    pub parent: DriveId,
+    pub name: OsString,
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderMkdirRequest {
+    pub(crate) fn new(
+        parent: DriveId,
+        name: OsString,
+        response_sender: Sender<ProviderResponse>,
+    ) -> Self {
+        Self {
+            parent,
+            name,
             response_sender,
         }
     }
 }
 
+#[derive(Debug)]
+pub struct ProviderStatusRequest {
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderStatusRequest {
+    pub(crate) fn new(response_sender: Sender<ProviderResponse>) -> Self {
+        Self { response_sender }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProviderRootIdRequest {
+    pub response_sender: Sender<ProviderResponse>,
+}
+
+impl ProviderRootIdRequest {
+    pub(crate) fn new(response_sender: Sender<ProviderResponse>) -> Self {
+        Self { response_sender }
+    }
+}
+
 // endregion
 //region ProviderResponse structs
 
+#[derive(Debug, Clone)]
+pub struct ProviderStatus {
+    /// the progress of every upload that is currently running
+    pub uploads: Vec<(DriveId, UploadProgress)>,
+    pub health: HealthStatus,
+    /// the storage quota, `None` if it could not be fetched yet
+    pub quota: Option<CachedQuota>,
+    pub stats: ProviderStats,
+    /// `None` if no folder was prefetched
+    pub prefetch: Option<PrefetchProgress>,
+}
+
+impl Display for ProviderStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "healthy: {}", self.health.is_healthy())?;
+        match self.health.cache_age() {
+            Some(age) => writeln!(f, "synced: {}s ago", age.as_secs())?,
+            None => writeln!(f, "synced: never")?,
+        }
+        match &self.quota {
+            Some(CachedQuota {
+                limit: Some(limit),
+                usage,
+                ..
+            }) => writeln!(f, "quota: {} of {} bytes used", usage, limit)?,
+            Some(quota) => writeln!(f, "quota: {} bytes used, unlimited", quota.usage)?,
+            None => writeln!(f, "quota: unknown")?,
+        }
+        writeln!(f, "stats: {}", self.stats)?;
+        if let Some(prefetch) = &self.prefetch {
+            writeln!(f, "prefetch: {}", prefetch)?;
+        }
+        for (id, progress) in &self.uploads {
+            writeln!(f, "upload {}: {}%", id, progress.percent())?;
+        }
+        Ok(())
+    }
+}
+
 pub struct ProviderReadDirResponse {
     pub entries: Vec<FileMetadata>,
+    /// the parent of the folder, `None` for the root
+    pub parent_id: Option<DriveId>,
 }
 impl Debug for ProviderReadDirResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -328,4 +631,6 @@ pub struct FileMetadata {
     // pub local_path: Option<PathBuf>,
     pub attr: FileAttr,
     // md5_checksum: Option<String>,
+    /// how long the kernel may cache the attributes, `None` for the default
+    pub ttl: Option<Duration>,
 }
@@ -0,0 +1,60 @@
+use std::fmt::{Display, Formatter};
+
+/// how many entries, relations and handles the provider currently holds, and
+/// how much content it moved.
+///
+/// Watching these over time shows handle leaks or runaway growth.
+/// The [Display] format is `key=value` pairs separated by spaces and only
+/// ever gets new keys appended, so scripts can rely on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProviderStats {
+    pub entries: usize,
+    pub children: usize,
+    pub parents: usize,
+    pub file_handles: usize,
+    pub running_requests: usize,
+    /// the content downloaded since the program started
+    pub downloaded_bytes: u64,
+    /// the content uploaded since the program started
+    pub uploaded_bytes: u64,
+}
+
+impl Display for ProviderStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "entries={} children={} parents={} file_handles={} running_requests={} \
+             downloaded_bytes={} uploaded_bytes={}",
+            self.entries,
+            self.children,
+            self.parents,
+            self.file_handles,
+            self.running_requests,
+            self.downloaded_bytes,
+            self.uploaded_bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_format_is_stable() {
+        let stats = ProviderStats {
+            entries: 5,
+            children: 2,
+            parents: 4,
+            file_handles: 1,
+            running_requests: 0,
+            downloaded_bytes: 2048,
+            uploaded_bytes: 0,
+        };
+        assert_eq!(
+            stats.to_string(),
+            "entries=5 children=2 parents=4 file_handles=1 running_requests=0 \
+             downloaded_bytes=2048 uploaded_bytes=0"
+        );
+    }
+}
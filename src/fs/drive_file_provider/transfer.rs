@@ -0,0 +1,17 @@
+use crate::google_drive::{DriveId, TransferredBytes};
+
+/// which way the content of a file is moving
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    Download,
+    Upload,
+}
+
+/// a download or upload that is currently running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferInfo {
+    pub file_id: DriveId,
+    pub direction: TransferDirection,
+    /// what was moved of the file since the program started, in both directions
+    pub transferred: TransferredBytes,
+}
@@ -0,0 +1,31 @@
+//! The extended attributes that show the metadata of the files on google drive,
+//! like `user.drive.starred`.
+
+/// every attribute of the mount starts with this, the others can't be stored
+pub const DRIVE_XATTR_PREFIX: &str = "user.drive.";
+/// if the file is starred on google drive, `1` or `0`
+pub const STARRED_XATTR: &str = "user.drive.starred";
+/// if the file is pinned, so it stays available offline. Setting it to `1`
+/// pins the file and downloads it in the background, `0` unpins it
+pub const PINNED_XATTR: &str = "user.drive.pinned";
+/// the status of the mount, like its health, the quota and the running
+/// uploads. Only the root of the mount has it and it can't be set
+pub const STATUS_XATTR: &str = "user.drive.status";
+/// the attributes that every entry has
+pub const DRIVE_XATTRS: &[&str] = &[STARRED_XATTR, PINNED_XATTR];
+
+pub(crate) fn flag_value(flag: bool) -> Vec<u8> {
+    match flag {
+        true => b"1".to_vec(),
+        false => b"0".to_vec(),
+    }
+}
+
+/// `1` or `0`, a trailing newline (like from `echo`) is ignored
+pub(crate) fn parse_flag(value: &[u8]) -> Option<bool> {
+    match value.strip_suffix(b"\n").unwrap_or(value) {
+        b"1" => Some(true),
+        b"0" => Some(false),
+        _ => None,
+    }
+}
@@ -1,6 +1,9 @@
 pub use inode::*;
 
 mod inode;
+mod mount_generation;
+
+pub(crate) use mount_generation::next_mount_generation;
 
 pub mod drive;
 pub mod drive2;
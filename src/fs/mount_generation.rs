@@ -0,0 +1,45 @@
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::{debug, warn};
+
+/// the file in the cache dir that holds the generation of the last mount
+const MOUNT_GENERATION_FILE_NAME: &str = ".mount_generation";
+
+/// Counts the mounts that used the cache dir and returns the count of this one.
+///
+/// The inos get handed out again after a remount, the generation lets the
+/// kernel tell them apart from the ones it still knows from before. If the
+/// count can't be kept, the seconds since the epoch are used instead, they
+/// grow too (unless the clock goes back).
+pub(crate) fn next_mount_generation(cache_dir: &Path) -> u64 {
+    let path = cache_dir.join(MOUNT_GENERATION_FILE_NAME);
+    let last = match std::fs::read_to_string(&path) {
+        Ok(content) => content.trim().parse::<u64>().ok(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Some(0),
+        Err(e) => {
+            warn!("could not read the mount generation: {:?}", e);
+            None
+        }
+    };
+    let generation = match last {
+        Some(last) => last + 1,
+        None => seconds_since_epoch(),
+    };
+    let written = std::fs::create_dir_all(cache_dir)
+        .and_then(|_| std::fs::write(&path, format!("{}\n", generation)));
+    if let Err(e) = written {
+        warn!("could not save the mount generation: {:?}", e);
+        return generation.max(seconds_since_epoch());
+    }
+    debug!("this is mount generation {}", generation);
+    generation
+}
+
+fn seconds_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(1)
+}
@@ -0,0 +1,202 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use google_drive3::client;
+use hyper::StatusCode;
+use libc::c_int;
+
+/// the errno for each error reason google drive reports, see
+/// <https://developers.google.com/drive/api/guides/handle-errors>
+const REASON_ERRNOS: &[(&str, c_int)] = &[
+    ("notFound", libc::ENOENT),
+    ("storageQuotaExceeded", libc::EDQUOT),
+    ("teamDriveFileLimitExceeded", libc::EDQUOT),
+    ("userRateLimitExceeded", libc::EAGAIN),
+    ("rateLimitExceeded", libc::EAGAIN),
+    ("sharingRateLimitExceeded", libc::EAGAIN),
+    ("dailyLimitExceeded", libc::EAGAIN),
+    ("backendError", libc::EAGAIN),
+    ("insufficientFilePermissions", libc::EACCES),
+    ("appNotAuthorizedToFile", libc::EACCES),
+    ("cannotDownloadAbusiveFile", libc::EACCES),
+    ("fileNotDownloadable", libc::EACCES),
+    ("domainPolicy", libc::EACCES),
+    ("badRequest", libc::EINVAL),
+    ("invalid", libc::EINVAL),
+];
+
+/// What google drive answered to a failed request.
+///
+/// Gets attached as context to every error of a drive call, so it shows up in
+/// the logs and the provider can report a fitting errno.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: Option<StatusCode>,
+    /// the reason of the first error google drive listed, like `notFound`
+    pub reason: Option<String>,
+    /// the parameter or header the first error is about, like `pageToken`
+    pub location: Option<String>,
+    pub message: Option<String>,
+}
+
+impl ApiError {
+    /// `None` if the error did not come from google drive (for example a connection error)
+    pub fn from_client_error(e: &client::Error) -> Option<Self> {
+        match e {
+            client::Error::BadRequest(value) => {
+                let error = &value["error"];
+                let status = error["code"]
+                    .as_u64()
+                    .and_then(|code| StatusCode::from_u16(code.try_into().ok()?).ok());
+                let reason = error["errors"][0]["reason"].as_str().map(str::to_string);
+                let location = error["errors"][0]["location"].as_str().map(str::to_string);
+                let message = error["message"].as_str().map(str::to_string);
+                Some(Self {
+                    status,
+                    reason,
+                    location,
+                    message,
+                })
+            }
+            client::Error::Failure(response) => Some(Self {
+                status: Some(response.status()),
+                reason: None,
+                location: None,
+                message: None,
+            }),
+            _ => None,
+        }
+    }
+    /// the api error somewhere in the chain of `e`
+    pub fn find(e: &anyhow::Error) -> Option<&ApiError> {
+        e.downcast_ref::<ApiError>()
+    }
+    pub fn errno(&self) -> Option<c_int> {
+        let by_reason = self.reason.as_deref().and_then(|reason| {
+            REASON_ERRNOS
+                .iter()
+                .find(|(known, _)| *known == reason)
+                .map(|(_, errno)| *errno)
+        });
+        by_reason.or_else(|| match self.status? {
+            StatusCode::BAD_REQUEST => Some(libc::EINVAL),
+            StatusCode::FORBIDDEN => Some(libc::EACCES),
+            StatusCode::NOT_FOUND => Some(libc::ENOENT),
+            StatusCode::TOO_MANY_REQUESTS => Some(libc::EAGAIN),
+            _ => None,
+        })
+    }
+    /// if the storage of the account is full
+    pub fn is_quota_exceeded(&self) -> bool {
+        self.reason.as_deref() == Some("storageQuotaExceeded")
+    }
+    /// if the value of the parameter `location` was not accepted, like a page
+    /// token of the changes that is too old
+    pub fn is_invalid_value(&self, location: &str) -> bool {
+        self.status == Some(StatusCode::BAD_REQUEST)
+            && self.reason.as_deref() == Some("invalid")
+            && self.location.as_deref() == Some(location)
+    }
+    /// if the same request might work when it is sent again later
+    pub fn is_retryable(&self) -> bool {
+        self.errno() == Some(libc::EAGAIN) || self.status.is_some_and(|s| s.is_server_error())
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "google drive answered")?;
+        if let Some(status) = self.status {
+            write!(f, " with {}", status)?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// google drive did not answer within the request timeout, sending the request
+/// again might work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestTimeoutError(pub Duration);
+
+impl Display for RequestTimeoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "google drive did not answer within {:?}", self.0)
+    }
+}
+
+impl std::error::Error for RequestTimeoutError {}
+
+/// adds the [ApiError] as context, if the error came from google drive and does not have it yet
+pub(crate) fn with_api_error(e: anyhow::Error) -> anyhow::Error {
+    if ApiError::find(&e).is_some() {
+        return e;
+    }
+    let api_error = e
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<client::Error>())
+        .find_map(ApiError::from_client_error);
+    match api_error {
+        Some(api_error) => e.context(api_error),
+        None => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reason_and_status_are_extracted() {
+        let e = client::Error::BadRequest(json!({
+            "error": {
+                "code": 403,
+                "message": "The user's Drive storage quota has been exceeded.",
+                "errors": [{ "reason": "storageQuotaExceeded" }]
+            }
+        }));
+        let e = with_api_error(anyhow::Error::from(e).context("could not upload"));
+        let api_error = ApiError::find(&e).unwrap();
+        assert_eq!(api_error.status, Some(StatusCode::FORBIDDEN));
+        assert_eq!(api_error.reason.as_deref(), Some("storageQuotaExceeded"));
+        assert_eq!(api_error.errno(), Some(libc::EDQUOT));
+        assert!(!api_error.is_retryable());
+        assert_eq!(
+            api_error.to_string(),
+            "google drive answered with 403 Forbidden (storageQuotaExceeded): \
+             The user's Drive storage quota has been exceeded."
+        );
+
+        // without a known reason the status decides
+        let api_error = ApiError {
+            status: Some(StatusCode::TOO_MANY_REQUESTS),
+            reason: Some("somethingNew".to_string()),
+            location: None,
+            message: None,
+        };
+        assert_eq!(api_error.errno(), Some(libc::EAGAIN));
+        assert!(api_error.is_retryable());
+
+        let e = client::Error::BadRequest(json!({
+            "error": {
+                "code": 400,
+                "message": "Invalid Value",
+                "errors": [{ "reason": "invalid", "location": "pageToken" }]
+            }
+        }));
+        let api_error = ApiError::from_client_error(&e).unwrap();
+        assert!(api_error.is_invalid_value("pageToken"));
+        assert!(!api_error.is_invalid_value("fileId"));
+
+        assert!(ApiError::find(&with_api_error(anyhow::anyhow!("io"))).is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use google_drive3::{client, oauth2};
+use hyper::StatusCode;
+
+use crate::prelude::*;
+
+/// gets a new access token, even if the current one did not expire yet
+pub(crate) type TokenRefresher = Arc<dyn Fn() -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// google drive does not accept the credentials anymore, and they could not be refreshed
+#[derive(Debug)]
+pub struct NotAuthenticatedError;
+
+impl Display for NotAuthenticatedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "google drive does not accept the credentials anymore, \
+             delete the tokens file and restart to log in again"
+        )
+    }
+}
+
+impl std::error::Error for NotAuthenticatedError {}
+
+/// how to create the client secret for this app
+const SETUP_URL: &str =
+    "https://developers.google.com/workspace/guides/create-credentials#desktop-app";
+
+/// the credentials for google drive could not be loaded, each with what to do about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialsError {
+    /// there is no client secret at the path
+    MissingClientSecret(PathBuf),
+    /// the file at the path is not the json of an OAuth client
+    InvalidClientSecret(PathBuf),
+    /// the login was cancelled or google did not accept it, the path is where
+    /// the tokens would have been stored
+    ConsentFailed(PathBuf),
+}
+
+impl Display for CredentialsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CredentialsError::MissingClientSecret(path) => write!(
+                f,
+                "there is no client secret at {}, create an OAuth client for a \
+                 desktop app and save its json there (see {})",
+                path.display(),
+                SETUP_URL
+            ),
+            CredentialsError::InvalidClientSecret(path) => write!(
+                f,
+                "the client secret at {} is not valid, download the json of an \
+                 OAuth client for a desktop app again (see {})",
+                path.display(),
+                SETUP_URL
+            ),
+            CredentialsError::ConsentFailed(path) => write!(
+                f,
+                "the login to google drive did not succeed, restart to try again. \
+                 The tokens would be stored at {}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CredentialsError {}
+
+/// reads the oauth client of the app, the error says what to do if it is
+/// missing or not valid
+pub(crate) async fn read_client_secret(path: &Path) -> Result<oauth2::ApplicationSecret> {
+    oauth2::read_application_secret(path).await.map_err(|e| {
+        let path = path.to_path_buf();
+        match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                anyhow!(e).context(CredentialsError::MissingClientSecret(path))
+            }
+            std::io::ErrorKind::InvalidData => {
+                anyhow!(e).context(CredentialsError::InvalidClientSecret(path))
+            }
+            _ => anyhow!(e).context(format!(
+                "could not read the client secret at {}",
+                path.display()
+            )),
+        }
+    })
+}
+
+/// checks if google drive rejected the request because of the credentials
+pub(crate) fn is_unauthorized(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<client::Error>())
+        .any(|e| match e {
+            client::Error::Failure(response) => response.status() == StatusCode::UNAUTHORIZED,
+            client::Error::BadRequest(value) => value["error"]["code"] == 401,
+            client::Error::MissingToken(_) => true,
+            _ => false,
+        })
+}
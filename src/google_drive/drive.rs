@@ -1,33 +1,224 @@
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Debug, Display};
+use std::future::Future;
 use std::io::Write;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context};
-use google_drive3::api::{Change, File, Scope, StartPageToken};
+use google_drive3::api::{
+    AboutStorageQuota, Change, Channel, Drive, File, Revision, Scope, StartPageToken,
+};
 use google_drive3::hyper::client::HttpConnector;
 use google_drive3::hyper::{Body, Response};
 use google_drive3::hyper_rustls::HttpsConnector;
-use google_drive3::DriveHub;
+use google_drive3::{client, DriveHub};
 use google_drive3::{hyper_rustls, oauth2};
 use hyper::Client;
 use tokio::fs;
 use tracing::{debug, error, instrument, trace, warn};
 
-use crate::google_drive::{helpers, DriveId};
+use crate::async_helper::run_async_blocking;
+use crate::google_drive::api_error::{with_api_error, RequestTimeoutError};
+use crate::google_drive::auth::{
+    is_unauthorized, read_client_secret, CredentialsError, NotAuthenticatedError, TokenRefresher,
+};
+use crate::google_drive::transferred::UploadCountingReader;
+use crate::google_drive::{
+    helpers, DriveId, ProgressCallback, ProgressReader, TransferCounter, TransferredBytes,
+    FOLDER_MIME_TYPE,
+};
 use crate::prelude::*;
 
-const FIELDS_FILE: &str = "id, name, size, mimeType, kind, md5Checksum, parents, trashed, createdTime, modifiedTime, viewedByMeTime";
+/// the smallest chunk size the resumable upload allows
+pub const MIN_UPLOAD_CHUNK_SIZE: u64 = 1 << 18;
+/// every chunk is kept in memory while it is uploaded, so it should not get too big
+pub const MAX_UPLOAD_CHUNK_SIZE: u64 = 1 << 28;
+/// the chunk size the google api uses by default (8 MiB)
+pub const DEFAULT_UPLOAD_CHUNK_SIZE: u64 = 1 << 23;
+/// how long a call may wait for google drive to answer, see [GoogleDrive::set_request_timeout]
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// how long opening a connection to google drive may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEFAULT_FILES_URL: &str = "https://www.googleapis.com/drive/v3/files/";
+const FIELDS_FILE: &str = "id, name, size, mimeType, kind, md5Checksum, parents, trashed, createdTime, modifiedTime, modifiedByMeTime, viewedByMeTime, webViewLink, ownedByMe, starred";
+const FIELDS_REVISION: &str = "id, mimeType, modifiedTime, size, md5Checksum";
+
+pub(crate) type HttpClient = Client<HttpsConnector<HttpConnector>>;
+
+/// the client of every authenticated drive, so they share its connection pool.
+///
+/// The mounts of different accounts talk to the same hosts, this way they
+/// reuse the connections of each other instead of each opening their own.
+static SHARED_HTTP_CLIENT: OnceLock<HttpClient> = OnceLock::new();
 
 #[derive(Clone)]
 pub struct GoogleDrive {
     hub: DriveHub<HttpsConnector<HttpConnector>>,
+    /// `None` if there is nothing to refresh
+    token_refresher: Option<TokenRefresher>,
+    upload_chunk_size: u64,
+    request_timeout: Duration,
+    transferred: TransferCounter,
+    /// the hub can't send range requests, those go to this url directly
+    files_url: String,
+}
+
+impl GoogleDrive {
+    /// sets the size of the chunks resumable uploads are split into.
+    ///
+    /// Larger chunks need fewer round trips, which helps on links with a high latency.
+    /// Smaller chunks mean less has to be sent again when a chunk fails on a flaky link.
+    ///
+    /// The size is rounded down to a power of two (required by the api) and clamped
+    /// between [MIN_UPLOAD_CHUNK_SIZE] (256 KiB) and [MAX_UPLOAD_CHUNK_SIZE] (256 MiB).
+    pub fn set_upload_chunk_size(&mut self, upload_chunk_size: u64) {
+        self.upload_chunk_size = normalize_upload_chunk_size(upload_chunk_size);
+    }
+    pub fn upload_chunk_size(&self) -> u64 {
+        self.upload_chunk_size
+    }
+    /// sets how long a call may wait for google drive before it fails with a
+    /// [RequestTimeoutError].
+    ///
+    /// Downloads and uploads of content are not limited, how long they take
+    /// depends on the size of the file.
+    pub fn set_request_timeout(&mut self, request_timeout: Duration) {
+        self.request_timeout = request_timeout;
+    }
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+    /// how much content this drive (and its clones) moved since the program started
+    pub fn transferred_bytes(&self) -> TransferredBytes {
+        self.transferred.total()
+    }
+    /// the counter behind [GoogleDrive::transferred_bytes], it also has the bytes of each file
+    pub fn transfer_counter(&self) -> TransferCounter {
+        self.transferred.clone()
+    }
+    /// counts the transfers of this drive with the counter of another one
+    pub(crate) fn set_transfer_counter(&mut self, transferred: TransferCounter) {
+        self.transferred = transferred;
+    }
+}
+
+fn normalize_upload_chunk_size(upload_chunk_size: u64) -> u64 {
+    let size = upload_chunk_size.clamp(MIN_UPLOAD_CHUNK_SIZE, MAX_UPLOAD_CHUNK_SIZE);
+    1 << (u64::BITS - 1 - size.leading_zeros())
+}
+
+/// tells the resumable upload which chunk size to use
+struct UploadDelegate {
+    chunk_size: u64,
+}
+
+impl client::Delegate for UploadDelegate {
+    fn chunk_size(&mut self) -> u64 {
+        self.chunk_size
+    }
 }
 
 impl GoogleDrive {
+    /// runs the call and if google drive did not accept the credentials, refreshes
+    /// the token and runs it once more.
+    ///
+    /// If the token can't be refreshed (for example because it was revoked) the
+    /// refresh falls back to the login flow. If that fails too, the error contains a
+    /// [NotAuthenticatedError], so the user can be told to log in again.
+    async fn with_reauth<T, F, Fut>(&self, call: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let timeout = self.request_timeout;
+        self.with_reauth_within(Some(timeout), call).await
+    }
+    /// like [GoogleDrive::with_reauth], each try fails with a [RequestTimeoutError]
+    /// if it takes longer than `timeout`, `None` lets it take as long as it needs
+    async fn with_reauth_within<T, F, Fut>(&self, timeout: Option<Duration>, call: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let call = || with_timeout(timeout, call());
+        let e = match call().await {
+            Err(e) if is_unauthorized(&e) => with_api_error(e),
+            result => return result.map_err(with_api_error),
+        };
+        warn!(
+            "the credentials were not accepted, refreshing the token: {:?}",
+            e
+        );
+        if let Some(token_refresher) = &self.token_refresher {
+            if let Err(refresh_error) = token_refresher().await {
+                error!("{} ({:?})", NotAuthenticatedError, refresh_error);
+                return Err(e.context(NotAuthenticatedError));
+            }
+        }
+        match call().await {
+            Err(e) if is_unauthorized(&e) => {
+                error!("{}", NotAuthenticatedError);
+                Err(with_api_error(e).context(NotAuthenticatedError))
+            }
+            result => result.map_err(with_api_error),
+        }
+    }
+    /// waits for one page of a listing. A listing can have any number of pages,
+    /// so they are run with [GoogleDrive::with_reauth_within] without a timeout
+    /// and only each page fails with a [RequestTimeoutError] if it takes too long
+    async fn with_page_timeout<T, E>(
+        &self,
+        page: impl Future<Output = std::result::Result<T, E>>,
+    ) -> Result<T>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        with_timeout(Some(self.request_timeout), async { Ok(page.await?) }).await
+    }
+}
+
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    call: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return call.await;
+    };
+    match tokio::time::timeout(timeout, call).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!("google drive did not answer within {:?}", timeout);
+            Err(anyhow!(RequestTimeoutError(timeout)))
+        }
+    }
+}
+
+/// which files [GoogleDrive::list_all_files] returns besides the ones in My Drive
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListOptions {
+    /// the files in the trash
+    pub include_trashed: bool,
+    /// the files of the computers that are backed up to drive
+    pub include_computers: bool,
+}
+
+impl GoogleDrive {
+    /// lists all files this user owns, see [ListOptions] for which ones
     #[instrument]
-    pub(crate) async fn list_all_files(&self) -> Result<Vec<File>> {
+    pub(crate) async fn list_all_files(&self, options: ListOptions) -> Result<Vec<File>> {
+        self.with_reauth_within(None, || self.list_all_files_once(options))
+            .await
+    }
+    async fn list_all_files_once(&self, options: ListOptions) -> Result<Vec<File>> {
+        let query = if options.include_trashed {
+            "'me' in owners"
+        } else {
+            "trashed = false and 'me' in owners" //gets only own files and files not in the trash bin
+        };
         let mut files = Vec::new();
         let mut page_token: Option<String> = None;
         loop {
@@ -36,12 +227,16 @@ impl GoogleDrive {
                 .hub
                 .files()
                 .list()
-                .q("trashed = false and 'me' in owners") //gets only own files and files not in the trash bin
+                .q(query)
                 .param("fields", &format!("nextPageToken, files({})", FIELDS_FILE));
+            if options.include_computers {
+                // the backups of computers are only in the corpus of everything the user owns
+                request = request.corpora("user").spaces("drive");
+            }
             if let Some(page_token) = page_token {
                 request = request.page_token(&page_token);
             }
-            let (_response, result) = request.doit().await?;
+            let (_response, result) = self.with_page_timeout(request.doit()).await?;
             let result_files = result.files.ok_or(anyhow!("no file list returned"))?;
             debug!("list_files: response: {:?}", result_files.len());
             files.extend(result_files);
@@ -54,12 +249,67 @@ impl GoogleDrive {
     }
 }
 
+impl GoogleDrive {
+    /// the id of the shared drive with this name, it is an error if there is
+    /// none or more than one with it
+    #[instrument]
+    pub async fn find_shared_drive_by_name(&self, name: &str) -> Result<DriveId> {
+        let drives = self
+            .with_reauth_within(None, || self.list_shared_drives_once())
+            .await?;
+        let mut matching = drives.iter().filter(|d| d.name.as_deref() == Some(name));
+        let problem = match (matching.next(), matching.next()) {
+            (Some(drive), None) => {
+                return drive
+                    .id
+                    .clone()
+                    .map(DriveId::from)
+                    .context("the shared drive has no id");
+            }
+            (None, _) => "there is no shared drive",
+            (Some(_), Some(_)) => "there is more than one shared drive",
+        };
+        let available: Vec<&str> = drives.iter().filter_map(|d| d.name.as_deref()).collect();
+        Err(anyhow!(
+            "{} named '{}', the available shared drives are: {}",
+            problem,
+            name,
+            available.join(", ")
+        ))
+    }
+    async fn list_shared_drives_once(&self) -> Result<Vec<Drive>> {
+        let mut drives = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self
+                .hub
+                .drives()
+                .list()
+                .page_size(100)
+                .param("fields", "nextPageToken, drives(id, name)");
+            if let Some(page_token) = &page_token {
+                request = request.page_token(page_token);
+            }
+            let (_response, result) = self.with_page_timeout(request.doit()).await?;
+            drives.extend(result.drives.unwrap_or_default());
+            page_token = result.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+        Ok(drives)
+    }
+}
+
 impl GoogleDrive {
     #[instrument]
     pub(crate) async fn get_start_page_token(&self) -> Result<StartPageToken> {
-        let (_response, start_page_token) =
-            self.hub.changes().get_start_page_token().doit().await?;
-        Ok(start_page_token)
+        self.with_reauth(|| async {
+            let (_response, start_page_token) =
+                self.hub.changes().get_start_page_token().doit().await?;
+            Ok(start_page_token)
+        })
+        .await
     }
 }
 
@@ -68,6 +318,20 @@ impl GoogleDrive {
     pub(crate) async fn get_changes_since(
         &self,
         start_page_token: &mut StartPageToken,
+    ) -> Result<Vec<Change>> {
+        let (changes, new_start_page_token) = self
+            .with_reauth_within(None, || async {
+                let mut start_page_token = start_page_token.clone();
+                let changes = self.get_changes_since_once(&mut start_page_token).await?;
+                Ok((changes, start_page_token))
+            })
+            .await?;
+        *start_page_token = new_start_page_token;
+        Ok(changes)
+    }
+    async fn get_changes_since_once(
+        &self,
+        start_page_token: &mut StartPageToken,
     ) -> Result<Vec<Change>> {
         let mut changes = vec![];
         let mut page_token: Option<String> = None;
@@ -97,12 +361,15 @@ impl GoogleDrive {
             if let Some(page_token) = &page_token {
                 request = request.page_token(page_token);
             }
-            let response = request.doit().await.context("could not get changes");
-            if let Err(e) = &response {
-                error!("error getting changes: {:?}", e);
-                return Err(anyhow!("error getting changes: {:?}", e));
-            }
-            let (_response, change_list) = response?;
+            let (_response, change_list) = match self.with_page_timeout(request.doit()).await {
+                Ok(response) => response,
+                Err(e) => {
+                    // keeps the drive error, so the caller can tell why it failed
+                    let e = with_api_error(e).context("could not get changes");
+                    error!("error getting changes: {:?}", e);
+                    return Err(e);
+                }
+            };
             if let Some(change_list) = change_list.changes {
                 changes.extend(change_list);
             }
@@ -112,35 +379,217 @@ impl GoogleDrive {
                 start_page_token.start_page_token = Some(new_start_page_token);
                 break;
             } else {
-                error!("no next_page_token or new_start_page_token");
-                break;
+                // the start page token is kept, so the next poll asks for the same changes again
+                let e =
+                    anyhow!("the changes api returned no next_page_token or new_start_page_token");
+                error!("{}", e);
+                return Err(e);
             }
         }
         Ok(changes)
     }
 }
 
+impl GoogleDrive {
+    #[instrument]
+    pub(crate) async fn get_storage_quota(&self) -> Result<AboutStorageQuota> {
+        self.with_reauth(|| async {
+            let (_response, about) = self
+                .hub
+                .about()
+                .get()
+                .param("fields", "storageQuota")
+                .doit()
+                .await?;
+            about
+                .storage_quota
+                .ok_or(anyhow!("no storage quota returned"))
+        })
+        .await
+    }
+}
+
+/// a web hook channel google drive sends change notifications to
+#[derive(Debug, Clone)]
+pub struct WatchChannel {
+    pub id: String,
+    /// sent back with every notification, so they can be told apart from forged ones
+    pub token: String,
+    /// the id google drive gave the watched resource, needed to stop the channel
+    pub resource_id: Option<String>,
+    /// when google drive stops sending notifications to this channel
+    pub expiration: Option<SystemTime>,
+}
+
+impl GoogleDrive {
+    /// registers a web hook at `callback_url` that gets notified about every change on the drive
+    #[instrument(skip(token))]
+    pub(crate) async fn watch_changes(
+        &self,
+        callback_url: &str,
+        id: String,
+        token: String,
+        expiration: SystemTime,
+    ) -> Result<WatchChannel> {
+        let page_token = self
+            .get_start_page_token()
+            .await?
+            .start_page_token
+            .context("no start page token returned")?;
+        let request = Channel {
+            id: Some(id.clone()),
+            type_: Some("web_hook".to_string()),
+            address: Some(callback_url.to_string()),
+            token: Some(token.clone()),
+            expiration: Some(expiration.duration_since(UNIX_EPOCH)?.as_millis() as i64),
+            ..Default::default()
+        };
+        let channel = self
+            .with_reauth(|| async {
+                let (_response, channel) = self
+                    .hub
+                    .changes()
+                    .watch(request.clone(), &page_token)
+                    .doit()
+                    .await?;
+                Ok(channel)
+            })
+            .await?;
+        Ok(WatchChannel {
+            id,
+            token,
+            resource_id: channel.resource_id,
+            expiration: channel
+                .expiration
+                .map(|millis| UNIX_EPOCH + Duration::from_millis(millis as u64)),
+        })
+    }
+    /// tells google drive to stop sending notifications to the channel
+    #[instrument]
+    pub(crate) async fn stop_watching(&self, channel: &WatchChannel) -> Result<()> {
+        let request = Channel {
+            id: Some(channel.id.clone()),
+            resource_id: channel.resource_id.clone(),
+            ..Default::default()
+        };
+        self.with_reauth(|| async {
+            self.hub.channels().stop(request.clone()).doit().await?;
+            Ok(())
+        })
+        .await
+    }
+}
+
 impl GoogleDrive {
     #[instrument]
     pub(crate) async fn get_metadata_for_file(&self, drive_id: DriveId) -> Result<File> {
         let drive_id = drive_id.to_string();
-        let (_response, file) = self
-            .hub
-            .files()
-            .get(&drive_id)
-            .param("fields", &FIELDS_FILE)
-            .doit()
-            .await?;
+        self.with_reauth(|| async {
+            let (_response, file) = self
+                .hub
+                .files()
+                .get(&drive_id)
+                .param("fields", &FIELDS_FILE)
+                .doit()
+                .await?;
+            Ok(file)
+        })
+        .await
+    }
+}
 
-        Ok(file)
+impl GoogleDrive {
+    /// creates an empty folder, the returned file has the id google drive gave it
+    #[instrument]
+    pub async fn create_folder(&self, name: &str, parent: DriveId) -> Result<File> {
+        self.with_reauth(|| async {
+            let folder = File {
+                name: Some(name.to_string()),
+                mime_type: Some(FOLDER_MIME_TYPE.to_string()),
+                parents: Some(vec![parent.to_string()]),
+                ..Default::default()
+            };
+            // folders have no content, so only the metadata part of the upload matters
+            let (_response, file) = self
+                .hub
+                .files()
+                .create(folder)
+                .param("fields", FIELDS_FILE)
+                .upload(std::io::Cursor::new(Vec::new()), FOLDER_MIME_TYPE.parse()?)
+                .await?;
+            Ok(file)
+        })
+        .await
+    }
+    /// creates an empty file, its content gets uploaded like the one of any other file
+    #[instrument]
+    pub async fn create_file(
+        &self,
+        name: &str,
+        parent: DriveId,
+        mime_type: Option<&str>,
+    ) -> Result<File> {
+        let mime_type = mime_type.unwrap_or("application/octet-stream");
+        self.with_reauth(|| async {
+            let file = File {
+                name: Some(name.to_string()),
+                mime_type: Some(mime_type.to_string()),
+                parents: Some(vec![parent.to_string()]),
+                ..Default::default()
+            };
+            let (_response, file) = self
+                .hub
+                .files()
+                .create(file)
+                .param("fields", FIELDS_FILE)
+                .upload(std::io::Cursor::new(Vec::new()), mime_type.parse()?)
+                .await?;
+            Ok(file)
+        })
+        .await
+    }
+    /// moves the file or folder (with everything in it) to the trash
+    #[instrument]
+    pub async fn trash_file(&self, drive_id: DriveId) -> Result<()> {
+        let drive_id = drive_id.to_string();
+        self.with_reauth(|| async {
+            let file = File {
+                trashed: Some(true),
+                ..Default::default()
+            };
+            self.hub
+                .files()
+                .update(file, &drive_id)
+                .doit_without_upload()
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+    /// deletes the file or folder (with everything in it) without moving it to the trash
+    #[instrument]
+    pub async fn delete_file(&self, drive_id: DriveId) -> Result<()> {
+        let drive_id = drive_id.to_string();
+        self.with_reauth(|| async {
+            self.hub.files().delete(&drive_id).doit().await?;
+            Ok(())
+        })
+        .await
     }
 }
 
 impl GoogleDrive {
-    #[instrument(skip(file), fields(file_name = file.name, file_id = file.drive_id))]
-    pub async fn upload_file_content_from_path(&self, file: File, path: &Path) -> Result<()> {
-        update_file_content_on_drive_from_path(&self, file, path).await?;
-        Ok(())
+    #[instrument(skip(file, progress), fields(file_name = file.name, file_id = file.drive_id))]
+    pub async fn upload_file_content_from_path(
+        &self,
+        file: File,
+        path: &Path,
+        progress: Option<ProgressCallback>,
+    ) -> Result<()> {
+        self.with_reauth_within(None, || {
+            update_file_content_on_drive_from_path(self, file.clone(), path, progress.clone())
+        })
+        .await
     }
 }
 
@@ -151,8 +600,10 @@ impl GoogleDrive {
         changed_data: File,
         original_file: &File,
     ) -> Result<()> {
-        update_file_metadata_on_drive(&self, changed_data, original_file).await?;
-        Ok(())
+        self.with_reauth(|| {
+            update_file_metadata_on_drive(self, changed_data.clone(), original_file)
+        })
+        .await
     }
 }
 
@@ -165,7 +616,11 @@ impl GoogleDrive {
             target_file.display()
         );
 
-        let file = download_file_by_id(&self, file_id, target_file.as_path()).await;
+        let file = self
+            .with_reauth_within(None, || {
+                download_file_by_id(self, file_id.clone(), target_file.as_path())
+            })
+            .await;
         debug!("download_file: completed");
         let file = file?;
 
@@ -173,6 +628,73 @@ impl GoogleDrive {
 
         Ok(file)
     }
+    /// downloads `length` bytes of the content, starting at `offset`.
+    ///
+    /// Less than `length` bytes are returned if the file ends before that. The
+    /// range can be the whole file, so like [GoogleDrive::download_file] it is
+    /// not limited by the request timeout.
+    #[instrument]
+    pub async fn download_range(
+        &self,
+        file_id: DriveId,
+        offset: u64,
+        length: u64,
+    ) -> Result<Vec<u8>> {
+        self.with_reauth_within(None, || {
+            download_range_by_id(self, &file_id, offset, length)
+        })
+        .await
+    }
+    /// the stored revisions of the content, the oldest first. Google docs
+    /// have revisions too, but their content can only be exported
+    #[instrument]
+    pub async fn list_revisions(&self, file_id: DriveId) -> Result<Vec<Revision>> {
+        self.with_reauth_within(None, || self.list_revisions_once(&file_id))
+            .await
+    }
+    async fn list_revisions_once(&self, file_id: &DriveId) -> Result<Vec<Revision>> {
+        let mut revisions = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self
+                .hub
+                .revisions()
+                .list(file_id.as_str())
+                .add_scope(Scope::Readonly)
+                .param(
+                    "fields",
+                    &format!("nextPageToken, revisions({})", FIELDS_REVISION),
+                );
+            if let Some(page_token) = page_token {
+                request = request.page_token(&page_token);
+            }
+            let (_response, result) = self.with_page_timeout(request.doit()).await?;
+            revisions.extend(result.revisions.unwrap_or_default());
+            page_token = result.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+        debug!(
+            "list_revisions: {} revisions of {}",
+            revisions.len(),
+            file_id
+        );
+        Ok(revisions)
+    }
+    /// downloads the content of the revision of the file to the target
+    #[instrument]
+    pub async fn download_revision(
+        &self,
+        file_id: DriveId,
+        revision_id: &str,
+        target_file: &Path,
+    ) -> Result<()> {
+        self.with_reauth_within(None, || {
+            download_revision_by_id(self, &file_id, revision_id, target_file)
+        })
+        .await
+    }
 }
 
 impl GoogleDrive {
@@ -239,27 +761,136 @@ impl GoogleDrive {
 impl GoogleDrive {
     #[instrument]
     pub(crate) async fn new() -> Result<Self> {
-        let auth = oauth2::read_application_secret("auth/client_secret.json").await?;
+        Self::from_auth_files("auth/client_secret.json", "auth/tokens.json").await
+    }
+    /// creates a drive for the account the tokens belong to.
+    ///
+    /// Every account needs its own tokens file, the client secret can be shared.
+    #[instrument]
+    pub async fn from_auth_files(
+        client_secret_path: impl AsRef<Path> + Debug,
+        tokens_path: impl AsRef<Path> + Debug,
+    ) -> Result<Self> {
+        Self::load_auth_files(client_secret_path, tokens_path, true).await
+    }
+    /// like [GoogleDrive::from_auth_files], but without logging in right away.
+    /// Without the network the token can't be refreshed, this gets done with
+    /// the first request to the drive instead
+    #[instrument]
+    pub async fn from_auth_files_without_login(
+        client_secret_path: impl AsRef<Path> + Debug,
+        tokens_path: impl AsRef<Path> + Debug,
+    ) -> Result<Self> {
+        Self::load_auth_files(client_secret_path, tokens_path, false).await
+    }
+    async fn load_auth_files(
+        client_secret_path: impl AsRef<Path> + Debug,
+        tokens_path: impl AsRef<Path> + Debug,
+        login: bool,
+    ) -> Result<Self> {
+        let auth = read_client_secret(client_secret_path.as_ref()).await?;
 
         let auth = oauth2::InstalledFlowAuthenticator::builder(
             auth,
             oauth2::InstalledFlowReturnMethod::HTTPRedirect,
         )
-        .persist_tokens_to_disk("auth/tokens.json")
+        .persist_tokens_to_disk(tokens_path.as_ref().to_path_buf())
         .build()
-        .await?;
-        let http_client = Client::builder().build(
+        .await
+        .with_context(|| {
+            format!(
+                "could not load the tokens from {}",
+                tokens_path.as_ref().display()
+            )
+        })?;
+        // logs in right away if there are no tokens yet, so a failed login
+        // shows up here instead of at the first request
+        if login {
+            auth.token(&[Scope::Full.as_ref()])
+                .await
+                .map_err(|e| match e {
+                    oauth2::Error::AuthError(_) => anyhow!(e).context(
+                        CredentialsError::ConsentFailed(tokens_path.as_ref().to_path_buf()),
+                    ),
+                    e => anyhow!(e).context("could not get a token for google drive"),
+                })?;
+        }
+        let refresh_auth = auth.clone();
+        let token_refresher: TokenRefresher = Arc::new(move || {
+            let auth = refresh_auth.clone();
+            Box::pin(async move {
+                auth.force_refreshed_token(&[Scope::Full.as_ref()]).await?;
+                Ok(())
+            })
+        });
+        let hub = DriveHub::new(Self::shared_http_client(), auth);
+
+        let drive = GoogleDrive {
+            hub,
+            token_refresher: Some(token_refresher),
+            upload_chunk_size: DEFAULT_UPLOAD_CHUNK_SIZE,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            transferred: TransferCounter::default(),
+            files_url: DEFAULT_FILES_URL.to_string(),
+        };
+        Ok(drive)
+    }
+    /// creates a drive that is not authenticated, every call to the api will fail.
+    ///
+    /// This is only useful for tests that never reach the api.
+    #[cfg(test)]
+    pub(crate) fn new_unauthenticated() -> Self {
+        let hub = DriveHub::new(Self::create_http_client(), google_drive3::client::NoToken);
+        GoogleDrive {
+            hub,
+            token_refresher: None,
+            upload_chunk_size: DEFAULT_UPLOAD_CHUNK_SIZE,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            transferred: TransferCounter::default(),
+            files_url: DEFAULT_FILES_URL.to_string(),
+        }
+    }
+    /// creates a drive that sends every request to `api_url` instead of google,
+    /// like a [MockDrive](crate::google_drive::mock::MockDrive).
+    #[cfg(any(test, feature = "fuse-tests"))]
+    pub fn with_api_url(api_url: &str) -> Self {
+        Self::with_api_url_and_client(api_url, Self::create_http_client())
+    }
+    /// like [GoogleDrive::with_api_url], drives with the same client share its connections
+    #[cfg(any(test, feature = "fuse-tests"))]
+    pub(crate) fn with_api_url_and_client(api_url: &str, client: HttpClient) -> Self {
+        // resumable uploads refuse to start without a token
+        let mut hub = DriveHub::new(client, "mock-token".to_string());
+        hub.root_url(api_url.to_string());
+        hub.base_url(format!("{}drive/v3/", api_url));
+        GoogleDrive {
+            hub,
+            token_refresher: None,
+            upload_chunk_size: DEFAULT_UPLOAD_CHUNK_SIZE,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            transferred: TransferCounter::default(),
+            files_url: format!("{}drive/v3/files/", api_url),
+        }
+    }
+    /// the connections of a client are tied to the runtime they were opened
+    /// on, so the tests, which all have their own runtime, don't use this
+    fn shared_http_client() -> HttpClient {
+        SHARED_HTTP_CLIENT
+            .get_or_init(Self::create_http_client)
+            .clone()
+    }
+    pub(crate) fn create_http_client() -> HttpClient {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(Some(CONNECT_TIMEOUT));
+        Client::builder().build(
             hyper_rustls::HttpsConnectorBuilder::new()
                 .with_native_roots()
                 .https_or_http()
                 .enable_http1()
                 .enable_http2()
-                .build(),
-        );
-        let hub = DriveHub::new(http_client, auth);
-
-        let drive = GoogleDrive { hub };
-        Ok(drive)
+                .wrap_connector(http),
+        )
     }
     #[instrument]
     pub async fn list_files(&self, folder_id: DriveId) -> Result<Vec<File>> {
@@ -275,19 +906,24 @@ impl GoogleDrive {
         if folder_id.contains('\'') {
             return Err(anyhow!("folder_id contains invalid character"));
         }
+        self.with_reauth_within(None, || self.list_files_once(&folder_id))
+            .await
+    }
+    async fn list_files_once(&self, folder_id: &str) -> Result<Vec<File>> {
         let mut files = Vec::new();
-        let mut page_token = None;
+        let mut page_token: Option<String> = None;
         loop {
             debug!("list_files: page_token: {:?}", page_token);
-            let (_response, result) = self
+            let mut request = self
                 .hub
                 .files()
                 .list()
                 .param("fields", &format!("nextPageToken, files({})", FIELDS_FILE))
-                // .page_token(page_token.as_ref().map(String::as_str))
-                .q(format!("'{}' in parents", folder_id).as_str())
-                .doit()
-                .await?;
+                .q(format!("'{}' in parents and trashed = false", folder_id).as_str());
+            if let Some(page_token) = page_token {
+                request = request.page_token(&page_token);
+            }
+            let (_response, result) = self.with_page_timeout(request.doit()).await?;
             let result_files = result.files.ok_or(anyhow!("no file list returned"))?;
             debug!("list_files: response: {:?}", result_files.len());
             files.extend(result_files);
@@ -354,7 +990,11 @@ async fn download_file_by_id(
 
     debug!("download_file_by_id(): response: {:?}", response);
     debug!("download_file_by_id(): content: {:?}", content);
-    write_body_to_file(response, target_path).await?;
+    let drive_id = DriveId::from(&id);
+    write_body_to_file(response, target_path, |bytes| {
+        hub.transferred.add_downloaded(&drive_id, bytes)
+    })
+    .await?;
     let (_, file) = hub
         .hub
         .files()
@@ -368,25 +1008,124 @@ async fn download_file_by_id(
     Ok(file)
 }
 
-async fn write_body_to_file(response: Response<Body>, target_path: &Path) -> Result<()> {
-    use futures::StreamExt;
-    debug!("write_body_to_file(): target_path: {:?}", target_path);
+async fn download_revision_by_id(
+    hub: &GoogleDrive,
+    file_id: &DriveId,
+    revision_id: &str,
+    target_path: &Path,
+) -> Result<()> {
+    let (response, _) = hub
+        .hub
+        .revisions()
+        .get(file_id.as_str(), revision_id)
+        .add_scope(Scope::Readonly)
+        .acknowledge_abuse(true)
+        .param("alt", "media")
+        .doit()
+        .await?;
+    write_body_to_file(response, target_path, |bytes| {
+        hub.transferred.add_downloaded(file_id, bytes)
+    })
+    .await
+}
+
+async fn download_range_by_id(
+    hub: &GoogleDrive,
+    id: &DriveId,
+    offset: u64,
+    length: u64,
+) -> Result<Vec<u8>> {
+    if length == 0 {
+        return Ok(Vec::new());
+    }
+    let token = hub
+        .hub
+        .auth
+        .get_token(&[Scope::Readonly.as_ref()])
+        .await
+        .map_err(client::Error::MissingToken)?;
+    let url = format!("{}{}?alt=media&acknowledgeAbuse=true", hub.files_url, id);
+    let mut request = hyper::Request::get(url).header(
+        hyper::header::RANGE,
+        format!("bytes={}-{}", offset, offset + length - 1),
+    );
+    if let Some(token) = token {
+        request = request.header(hyper::header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let response = hub.hub.client.request(request.body(Body::empty())?).await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(client::Error::Failure(response).into());
+    }
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    hub.transferred.add_downloaded(id, body.len() as u64);
+    debug!(
+        "download_range_by_id(): got {} bytes ({})",
+        body.len(),
+        status
+    );
+    if status == hyper::StatusCode::PARTIAL_CONTENT {
+        return Ok(body.to_vec());
+    }
+    // the whole content, if the range was ignored
+    let start = (offset as usize).min(body.len());
+    let end = (offset + length).min(body.len() as u64) as usize;
+    Ok(body[start..end].to_vec())
+}
 
-    let mut file = std::fs::File::create(target_path)?;
+/// writes the body to a temporary file next to the target and renames it to the
+/// target once it is complete, so anything that writes to the target while the
+/// download is running can not get mixed into the downloaded content
+/// `on_chunk` gets the size of every chunk that arrived
+async fn write_body_to_file(
+    response: Response<Body>,
+    target_path: &Path,
+    on_chunk: impl FnMut(u64),
+) -> Result<()> {
+    debug!("write_body_to_file(): target_path: {:?}", target_path);
+    let download_path = get_download_path(target_path);
+    let result = write_stream_to_file(response.into_body(), &download_path, on_chunk).await;
+    if let Err(e) = result {
+        if let Err(remove_error) = std::fs::remove_file(&download_path) {
+            warn!(
+                "could not remove the incomplete download at {}: {}",
+                download_path.display(),
+                remove_error
+            );
+        }
+        return Err(e);
+    }
+    std::fs::rename(&download_path, target_path)?;
+    debug!("write_body_to_file(): done");
+    Ok(())
+}
 
-    let mut stream = response.into_body();
-    let _buffer = bytes::BytesMut::new();
+async fn write_stream_to_file(
+    mut stream: Body,
+    path: &Path,
+    mut on_chunk: impl FnMut(u64),
+) -> Result<()> {
+    use futures::StreamExt;
+    let mut file = std::fs::File::create(path)?;
     let mut counter = 0;
     while let Some(chunk) = stream.next().await {
         let chunk = chunk?;
+        on_chunk(chunk.len() as u64);
         trace!("write_body_to_file(): chunk counter: {}", counter);
         file.write_all(&chunk)?;
         counter += 1;
     }
-    debug!("write_body_to_file(): done");
+    file.sync_all()?;
     Ok(())
 }
 
+/// the path a download is written to before it is complete
+fn get_download_path(target_path: &Path) -> PathBuf {
+    let mut name = target_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".download");
+    target_path.with_file_name(name)
+}
+
 async fn get_file_header_by_id(hub: &GoogleDrive, id: &str) -> Result<File> {
     debug!("get_file_header_by_id(): id: {:?}", id);
     let (_response, content) = hub.hub.files().get(id).doit().await?;
@@ -461,12 +1200,20 @@ pub async fn create_file_on_drive(
     content: fs::File,
 ) -> Result<File> {
     let stream = content.into_std().await;
-    let (response, file) = drive
-        .hub
-        .files()
-        .create(file)
-        .upload_resumable(stream, mime_type)
-        .await?;
+    let drive = drive.clone();
+    let (response, file) = run_on_blocking_thread(move || async move {
+        let mut delegate = UploadDelegate {
+            chunk_size: drive.upload_chunk_size,
+        };
+        Ok(drive
+            .hub
+            .files()
+            .create(file)
+            .delegate(&mut delegate)
+            .upload_resumable(stream, mime_type)
+            .await?)
+    })
+    .await?;
     debug!("create_file(): response: {:?}", response);
     debug!("create_file(): file: {:?}", file);
     Ok(file)
@@ -503,15 +1250,14 @@ pub async fn update_file_metadata_on_drive(
     let mut call = drive.hub.files().update(changed_data, id.as_str());
 
     if has_parent_change {
-        //remove old parents
-        if let Some(existing_parents) = original_file.parents.as_ref() {
-            for x in existing_parents.iter() {
-                call = call.remove_parents(x);
-            }
+        //only touch the parents that actually changed, so other parents of the file stay
+        let existing_parents = original_file.parents.clone().unwrap_or_default();
+        let (added_parents, removed_parents) = get_parent_changes(&existing_parents, &parents);
+        for x in removed_parents {
+            call = call.remove_parents(x);
         }
-        //add new parents
-        for new_parent in parents {
-            call = call.add_parents(new_parent.as_str());
+        for new_parent in added_parents {
+            call = call.add_parents(new_parent);
         }
     }
 
@@ -536,11 +1282,44 @@ pub async fn update_file_metadata_on_drive(
     Ok(())
 }
 
-#[instrument(skip(file), fields(drive_id = file.drive_id))]
+/// runs the future created by `f` on a blocking thread and waits for it.
+///
+/// The resumable uploads read their content with blocking reads from a
+/// [std::fs::File], which would block the runtime thread they run on and with
+/// that every other task on that thread.
+async fn run_on_blocking_thread<F, T>(f: impl FnOnce() -> F + Send + 'static) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || run_async_blocking(f())).await?
+}
+
+/// returns the parents that have to be added and the ones that have to be removed
+/// to get from the existing to the changed parents
+fn get_parent_changes<'a>(
+    existing_parents: &'a [String],
+    changed_parents: &'a [String],
+) -> (Vec<&'a str>, Vec<&'a str>) {
+    let added = changed_parents
+        .iter()
+        .filter(|p| !existing_parents.contains(p))
+        .map(String::as_str)
+        .collect();
+    let removed = existing_parents
+        .iter()
+        .filter(|p| !changed_parents.contains(p))
+        .map(String::as_str)
+        .collect();
+    (added, removed)
+}
+
+#[instrument(skip(file, progress), fields(drive_id = file.drive_id))]
 pub async fn update_file_content_on_drive_from_path(
     drive: &GoogleDrive,
     file: File,
     source_path: &Path,
+    progress: Option<ProgressCallback>,
 ) -> Result<()> {
     debug!(
         "update_file_content_on_drive_from_path(): source_path: {:?}",
@@ -555,16 +1334,18 @@ pub async fn update_file_content_on_drive_from_path(
     //     debug!("update_file_content_on_drive_from_path(): content: {:?}", s);
     // }
     let content = fs::File::open(source_path).await?;
-    update_file_content_on_drive(drive, file, content).await?;
+    update_file_content_on_drive(drive, file, content, progress).await?;
     Ok(())
 }
 
-#[instrument(skip(file, content))]
+#[instrument(skip(file, content, progress))]
 async fn update_file_content_on_drive(
     drive: &GoogleDrive,
     mut file: File,
     content: fs::File,
+    progress: Option<ProgressCallback>,
 ) -> Result<()> {
+    let total = content.metadata().await?.len();
     let stream = content.into_std().await;
     let mime_type = helpers::get_mime_from_file_metadata(&file)?;
     let id = file
@@ -575,14 +1356,367 @@ async fn update_file_content_on_drive(
     file.id = None;
     file.mime_type = None;
     debug!("starting upload");
-    let (response, file) = drive
-        .hub
-        .files()
-        .update(file, &id)
-        .upload_resumable(stream, mime_type)
-        .await?;
+    let drive = drive.clone();
+    let stream = UploadCountingReader::new(stream, DriveId::from(&id), drive.transfer_counter());
+    let (response, file) = run_on_blocking_thread(move || async move {
+        let mut delegate = UploadDelegate {
+            chunk_size: drive.upload_chunk_size,
+        };
+        let call = drive.hub.files().update(file, &id).delegate(&mut delegate);
+        Ok(match progress {
+            Some(progress) => {
+                let stream = ProgressReader::new(stream, total, progress);
+                call.upload_resumable(stream, mime_type).await?
+            }
+            None => call.upload_resumable(stream, mime_type).await?,
+        })
+    })
+    .await?;
     debug!("upload done!");
     debug!("update_file_on_drive(): response: {:?}", response);
     debug!("update_file_on_drive(): file: {:?}", file);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shared_drives_are_found_by_name() {
+        let mock = crate::google_drive::mock::MockDrive::start().await.unwrap();
+        let marketing = mock.add_shared_drive("Marketing");
+        mock.add_shared_drive("Sales");
+        mock.add_shared_drive("Sales");
+        let drive = mock.drive();
+
+        let found = drive.find_shared_drive_by_name("Marketing").await.unwrap();
+        assert_eq!(found, marketing);
+        let e = drive.find_shared_drive_by_name("Sales").await.unwrap_err();
+        assert!(e.to_string().contains("more than one"), "{}", e);
+        let e = drive.find_shared_drive_by_name("Legal").await.unwrap_err();
+        assert!(e.to_string().contains("Marketing, Sales, Sales"), "{}", e);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn downloads_are_counted() {
+        let mock = crate::google_drive::mock::MockDrive::start().await.unwrap();
+        let content = vec![7u8; 5000];
+        let id = mock.add_file("file.bin", &mock.root_id(), &content);
+        let drive = mock.drive();
+        let dir = tempfile::tempdir().unwrap();
+
+        drive
+            .download_file(id.clone(), &dir.path().join("file.bin"))
+            .await
+            .unwrap();
+        let expected = TransferredBytes {
+            downloaded: 5000,
+            uploaded: 0,
+        };
+        assert_eq!(drive.transferred_bytes(), expected);
+        assert_eq!(drive.transfer_counter().of_file(&id), expected);
+
+        // the clones share the counter
+        drive.clone().download_range(id, 0, 100).await.unwrap();
+        assert_eq!(drive.transferred_bytes().downloaded, 5100);
+    }
+
+    #[tokio::test]
+    async fn calls_fail_when_google_drive_does_not_answer() {
+        // accepts the connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}/", listener.local_addr().unwrap());
+        let _server = tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+        let mut drive = GoogleDrive::with_api_url(&api_url);
+        drive.set_request_timeout(Duration::from_millis(200));
+
+        let started = std::time::Instant::now();
+        let e = drive
+            .get_metadata_for_file(DriveId::from("file"))
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            e.downcast_ref::<RequestTimeoutError>(),
+            Some(&RequestTimeoutError(Duration::from_millis(200)))
+        );
+    }
+
+    #[tokio::test]
+    async fn long_listings_only_time_out_on_a_slow_page() {
+        let mock = crate::google_drive::mock::MockDrive::start().await.unwrap();
+        for i in 0..8 {
+            mock.add_file(&format!("{}.txt", i), &mock.root_id(), b"");
+        }
+        mock.set_page_size(1);
+        mock.set_response_delay(Duration::from_millis(50));
+        let mut drive = mock.drive();
+        drive.set_request_timeout(Duration::from_millis(200));
+
+        // the 8 pages take longer than the timeout together, but each is fast enough
+        let started = std::time::Instant::now();
+        let files = drive.list_all_files(ListOptions::default()).await.unwrap();
+        assert!(started.elapsed() > drive.request_timeout());
+        assert_eq!(files.len(), 8);
+
+        mock.set_response_delay(Duration::from_millis(500));
+        let e = drive
+            .list_all_files(ListOptions::default())
+            .await
+            .unwrap_err();
+        assert!(e.downcast_ref::<RequestTimeoutError>().is_some());
+    }
+
+    #[tokio::test]
+    async fn unusable_client_secret_names_the_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let tokens_path = dir.path().join("tokens.json");
+        let missing = dir.path().join("client_secret.json");
+        let e = GoogleDrive::from_auth_files(&missing, &tokens_path)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<CredentialsError>(),
+            Some(&CredentialsError::MissingClientSecret(missing.clone()))
+        );
+        assert!(e.to_string().contains(&missing.display().to_string()));
+
+        std::fs::write(&missing, "{ not json").unwrap();
+        let e = GoogleDrive::from_auth_files(&missing, &tokens_path)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<CredentialsError>(),
+            Some(&CredentialsError::InvalidClientSecret(missing))
+        );
+    }
+
+    /// the upload reads its content on a blocking thread, so a slow read must
+    /// not stop the other tasks on the runtime
+    #[tokio::test(flavor = "current_thread")]
+    async fn slow_upload_reads_keep_the_runtime_responsive() {
+        let mock = crate::google_drive::mock::MockDrive::start().await.unwrap();
+        let id = mock.add_file("large.bin", &mock.root_id(), b"");
+        let mut content = tempfile::NamedTempFile::new().unwrap();
+        content.write_all(&vec![1u8; 1024 * 1024]).unwrap();
+
+        let ticks = Arc::new(AtomicU64::new(0));
+        let ticker = tokio::spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        });
+        // the progress is reported from inside the reads, stalling it stalls them
+        let ticks_while_reading = Arc::new(AtomicU64::new(0));
+        let progress: ProgressCallback = Arc::new({
+            let (ticks, ticks_while_reading) = (ticks.clone(), ticks_while_reading.clone());
+            move |_, _| {
+                let before = ticks.load(Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                let during = ticks.load(Ordering::SeqCst) - before;
+                ticks_while_reading.fetch_add(during, Ordering::SeqCst);
+            }
+        });
+        let metadata = File {
+            id: Some(id.to_string()),
+            mime_type: Some("application/octet-stream".to_string()),
+            ..Default::default()
+        };
+        mock.drive()
+            .upload_file_content_from_path(metadata, content.path(), Some(progress))
+            .await
+            .unwrap();
+        ticker.abort();
+
+        assert_eq!(mock.content(&id).map(|c| c.len()), Some(1024 * 1024));
+        assert!(ticks_while_reading.load(Ordering::SeqCst) > 0);
+    }
+
+    /// a drive api that does not accept the first requests and answers every other one with `body`
+    async fn start_mock_drive(
+        unauthorized_responses: usize,
+        body: &'static str,
+    ) -> (GoogleDrive, Arc<AtomicU64>) {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Server, StatusCode};
+
+        let requests = Arc::new(AtomicU64::new(0));
+        let server_requests = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let requests = server_requests.clone();
+            async move {
+                Ok::<_, std::convert::Infallible>(service_fn(move |_request| {
+                    let request = requests.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        let response = if request < unauthorized_responses as u64 {
+                            Response::builder()
+                                .status(StatusCode::UNAUTHORIZED)
+                                .body(Body::from(
+                                    r#"{"error":{"code":401,"message":"Invalid Credentials"}}"#,
+                                ))
+                        } else {
+                            Response::builder().body(Body::from(body))
+                        };
+                        Ok::<_, std::convert::Infallible>(response.unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        let mut drive = GoogleDrive::new_unauthenticated();
+        drive.hub.base_url(format!("http://{}/", address));
+        (drive, requests)
+    }
+
+    const START_PAGE_TOKEN: &str = r#"{"startPageToken":"42"}"#;
+
+    #[tokio::test]
+    async fn recovers_from_unauthorized() {
+        let (drive, requests) = start_mock_drive(1, START_PAGE_TOKEN).await;
+        let token = drive.get_start_page_token().await.unwrap();
+        assert_eq!(token.start_page_token.as_deref(), Some("42"));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_not_authenticated_when_it_stays_unauthorized() {
+        let (drive, requests) = start_mock_drive(usize::MAX, START_PAGE_TOKEN).await;
+        let e = drive.get_start_page_token().await.unwrap_err();
+        assert!(e.downcast_ref::<NotAuthenticatedError>().is_some());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn changes_without_any_token_are_an_error() {
+        let (drive, _) = start_mock_drive(0, r#"{"changes":[]}"#).await;
+        let mut token = StartPageToken {
+            start_page_token: Some("7".to_string()),
+            ..Default::default()
+        };
+        assert!(drive.get_changes_since(&mut token).await.is_err());
+        assert_eq!(token.start_page_token.as_deref(), Some("7"));
+    }
+
+    #[tokio::test]
+    async fn drives_with_the_same_client_reuse_its_connections() {
+        let mock = crate::google_drive::mock::MockDrive::start().await.unwrap();
+        let drives = [mock.drive(), mock.drive(), mock.drive()];
+        for _ in 0..3 {
+            for drive in &drives {
+                drive.get_start_page_token().await.unwrap();
+            }
+        }
+        assert_eq!(mock.connection_count(), 1);
+    }
+
+    /// the drives take turns with bursts of concurrent requests, like the
+    /// mounts of different accounts do. Returns how many connections they opened
+    async fn connections_for_bursts(client: impl Fn() -> HttpClient) -> usize {
+        const DRIVES: usize = 3;
+        const BURSTS: usize = 3;
+        const CONCURRENT_REQUESTS: usize = 4;
+        let mock = crate::google_drive::mock::MockDrive::start().await.unwrap();
+        let drives: Vec<_> = (0..DRIVES)
+            .map(|_| mock.drive_with_client(client()))
+            .collect();
+        for _ in 0..BURSTS {
+            for drive in &drives {
+                let burst = (0..CONCURRENT_REQUESTS).map(|_| drive.get_start_page_token());
+                for token in futures::future::join_all(burst).await {
+                    token.unwrap();
+                }
+            }
+        }
+        mock.connection_count()
+    }
+
+    /// the only test that uses the shared client, its connections belong to
+    /// the runtime of the test that opened them
+    #[tokio::test]
+    async fn shared_http_client_opens_fewer_connections_under_load() {
+        let with_own_clients = connections_for_bursts(GoogleDrive::create_http_client).await;
+        let with_shared_client = connections_for_bursts(GoogleDrive::shared_http_client).await;
+        eprintln!(
+            "connections for the same load: {} with a client per drive, {} with the shared one",
+            with_own_clients, with_shared_client
+        );
+        // every drive needs at least one connection of its own without sharing
+        assert!(with_own_clients >= 3, "{}", with_own_clients);
+        assert!(
+            with_shared_client < with_own_clients,
+            "{} < {}",
+            with_shared_client,
+            with_own_clients
+        );
+    }
+
+    /// a write that arrives while the download is still streaming must not end
+    /// up in the middle of the downloaded content
+    #[tokio::test]
+    async fn write_during_download_does_not_corrupt_it() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target_path = dir.path().join("file");
+        std::fs::write(&target_path, b"old content").unwrap();
+
+        let (mut body_sender, body) = Body::channel();
+        let download_target = target_path.clone();
+        let download = tokio::spawn(async move {
+            write_body_to_file(Response::new(body), &download_target, |_| {}).await
+        });
+        body_sender.send_data("downloaded ".into()).await.unwrap();
+        // give the download the chance to write the first chunk
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut local = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&target_path)
+            .unwrap();
+        local.write_all(b"LOCAL").unwrap();
+        assert!(get_download_path(&target_path).exists());
+
+        body_sender.send_data("content".into()).await.unwrap();
+        drop(body_sender);
+        download.await.unwrap().unwrap();
+
+        assert_eq!(std::fs::read(&target_path).unwrap(), b"downloaded content");
+        assert!(!get_download_path(&target_path).exists());
+    }
+
+    #[test]
+    fn upload_chunk_size_is_a_power_of_two_in_bounds() {
+        assert_eq!(normalize_upload_chunk_size(0), MIN_UPLOAD_CHUNK_SIZE);
+        assert_eq!(normalize_upload_chunk_size(3 << 20), 2 << 20);
+        assert_eq!(normalize_upload_chunk_size(16 << 20), 16 << 20);
+        assert_eq!(normalize_upload_chunk_size(u64::MAX), MAX_UPLOAD_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn parent_changes_keep_untouched_parents() {
+        let existing = vec!["a".to_string(), "b".to_string()];
+        let changed = vec!["c".to_string(), "b".to_string()];
+        let (added, removed) = get_parent_changes(&existing, &changed);
+        assert_eq!(added, vec!["c"]);
+        assert_eq!(removed, vec!["a"]);
+
+        let (added, removed) = get_parent_changes(&existing, &existing);
+        assert!(added.is_empty());
+        assert!(removed.is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fuser::FileAttr;
+use google_drive3::api::File;
+
+/// The POSIX times of a file on google drive.
+///
+/// Drive does not know access or status change times, so they are derived:
+///
+/// - `mtime`: `modifiedTime`, the last change of the content by anyone
+/// - `atime`: the later of `viewedByMeTime` and `modifiedByMeTime`, since
+///   writing a file accesses it as well. `mtime` if this user never opened it
+/// - `ctime`: same as `mtime`, drive does not track metadata changes on their own
+/// - `crtime`: `createdTime`
+///
+/// Times drive did not report are the unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileTimes {
+    pub atime: SystemTime,
+    pub mtime: SystemTime,
+    pub ctime: SystemTime,
+    pub crtime: SystemTime,
+}
+
+impl FileTimes {
+    pub fn from_metadata(metadata: &File) -> Self {
+        let mtime = metadata
+            .modified_time
+            .map(SystemTime::from)
+            .unwrap_or(UNIX_EPOCH);
+        let atime = [metadata.viewed_by_me_time, metadata.modified_by_me_time]
+            .into_iter()
+            .flatten()
+            .max()
+            .map(SystemTime::from)
+            .unwrap_or(mtime);
+        let crtime = metadata
+            .created_time
+            .map(SystemTime::from)
+            .unwrap_or(UNIX_EPOCH);
+        Self {
+            atime,
+            mtime,
+            ctime: mtime,
+            crtime,
+        }
+    }
+    pub fn apply(&self, attr: &mut FileAttr) {
+        attr.atime = self.atime;
+        attr.mtime = self.mtime;
+        attr.ctime = self.ctime;
+        attr.crtime = self.crtime;
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::anyhow;
+use google_drive3::api::File;
+
+use crate::google_drive::DriveId;
+use crate::prelude::*;
+
+pub(crate) const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+const NATIVE_MIME_TYPE_PREFIX: &str = "application/vnd.google-apps.";
+
+/// Typed access to the metadata google drive sent for a file.
+///
+/// Every field of the api is optional, this keeps the unwrapping (and what a
+/// missing field means) in one place instead of at every use.
+#[derive(Debug, Clone, Copy)]
+pub struct DriveMetadata<'a>(&'a File);
+
+impl<'a> DriveMetadata<'a> {
+    pub fn new(file: &'a File) -> Self {
+        Self(file)
+    }
+    pub fn file(&self) -> &'a File {
+        self.0
+    }
+    /// fails if the metadata has no (or an empty) id
+    pub fn id(&self) -> Result<DriveId> {
+        match self.0.id.as_deref() {
+            Some(id) if !id.is_empty() => Ok(DriveId::from(id)),
+            _ => Err(anyhow!("the metadata has no id")),
+        }
+    }
+    /// fails if the metadata has no (or an empty) name
+    pub fn name(&self) -> Result<&'a str> {
+        match self.0.name.as_deref() {
+            Some(name) if !name.is_empty() => Ok(name),
+            _ => Err(anyhow!("the metadata has no name")),
+        }
+    }
+    /// folders and google docs have no size, they (and invalid sizes) are 0
+    pub fn size(&self) -> u64 {
+        self.0
+            .size
+            .and_then(|size| u64::try_from(size).ok())
+            .unwrap_or(0)
+    }
+    /// empty if the mime type is not known, those files are shown as regular files
+    pub fn mime(&self) -> &'a str {
+        self.0.mime_type.as_deref().unwrap_or_default()
+    }
+    pub fn is_folder(&self) -> bool {
+        self.mime() == FOLDER_MIME_TYPE
+    }
+    /// if the file only exists in the google apps (docs, sheets etc.), folders
+    /// have a google apps mime type too but are not native files
+    pub fn is_native(&self) -> bool {
+        self.mime().starts_with(NATIVE_MIME_TYPE_PREFIX) && !self.is_folder()
+    }
+    pub fn is_trashed(&self) -> bool {
+        self.0.trashed == Some(true)
+    }
+}
+
+impl<'a> From<&'a File> for DriveMetadata<'a> {
+    fn from(file: &'a File) -> Self {
+        Self::new(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors_handle_partial_metadata() {
+        let empty = File::default();
+        let metadata = DriveMetadata::new(&empty);
+        assert!(metadata.id().is_err());
+        assert!(metadata.name().is_err());
+        assert_eq!(metadata.size(), 0);
+        assert_eq!(metadata.mime(), "");
+        assert!(!metadata.is_folder());
+        assert!(!metadata.is_native());
+        assert!(!metadata.is_trashed());
+
+        let file = File {
+            id: Some("id".to_string()),
+            name: Some(String::new()),
+            size: Some(-5),
+            mime_type: Some("application/vnd.google-apps.document".to_string()),
+            trashed: Some(true),
+            ..Default::default()
+        };
+        let metadata = DriveMetadata::new(&file);
+        assert_eq!(metadata.id().unwrap(), DriveId::from("id"));
+        assert!(metadata.name().is_err());
+        assert_eq!(metadata.size(), 0);
+        assert!(metadata.is_native());
+        assert!(metadata.is_trashed());
+
+        let folder = File {
+            size: Some(42),
+            mime_type: Some(FOLDER_MIME_TYPE.to_string()),
+            ..Default::default()
+        };
+        let metadata = DriveMetadata::new(&folder);
+        assert_eq!(metadata.size(), 42);
+        assert!(metadata.is_folder());
+        assert!(!metadata.is_native());
+    }
+}
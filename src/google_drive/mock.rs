@@ -0,0 +1,933 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use google_drive3::api::{
+    About, AboutStorageQuota, Change, ChangeList, Drive, DriveList, File, FileList, Revision,
+    RevisionList, StartPageToken,
+};
+use google_drive3::chrono::Utc;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode, Uri};
+use md5::{Digest, Md5};
+use tracing::{debug, warn};
+
+use crate::google_drive::{DriveId, GoogleDrive, HttpClient};
+use crate::prelude::*;
+
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+const NATIVE_MIME_TYPE_PREFIX: &str = "application/vnd.google-apps.";
+const MOCK_STORAGE_LIMIT: i64 = 1 << 30;
+
+/// An in-memory stand-in for the parts of the google drive api this crate uses.
+///
+/// It serves the api over http on localhost, so everything above the hub
+/// (the provider, the filesystem, a whole [Mount](crate::mount::Mount)) runs
+/// like it would against the real drive, just without an account.
+///
+/// Every change (through the api or the `add_*` functions) shows up in the
+/// changes api, like changes made by other clients would.
+#[derive(Debug, Clone)]
+pub struct MockDrive {
+    state: Arc<Mutex<MockState>>,
+    api_url: String,
+    /// shared by all drives of this mock, like the drives of a real mount share theirs
+    client: HttpClient,
+    /// the chunks of resumable uploads wait for this, see [MockDrive::hold_uploads]
+    upload_gate: Arc<tokio::sync::RwLock<()>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    files: HashMap<String, MockFile>,
+    root_id: String,
+    /// the ids of changed files, the page tokens are indices into this
+    changes: Vec<String>,
+    /// the page tokens below this are rejected, like expired ones
+    oldest_valid_page_token: usize,
+    uploads: HashMap<String, MockUpload>,
+    /// how many uploads were completed
+    completed_uploads: usize,
+    /// how many connections were accepted
+    connections: usize,
+    /// uploads fail with `storageQuotaExceeded` and the quota is used up
+    storage_full: bool,
+    /// every request fails with `401`, see [MockDrive::set_unauthorized]
+    unauthorized: bool,
+    /// how many files a page of the file list has at most, all of them if `None`
+    page_size: Option<usize>,
+    /// how long every request takes to be answered
+    response_delay: std::time::Duration,
+    /// the file id, offset and length of every range download
+    range_downloads: Vec<(String, u64, u64)>,
+    shared_drives: Vec<Drive>,
+    /// the older contents of the files, by file id
+    revisions: HashMap<String, Vec<MockRevision>>,
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct MockFile {
+    metadata: File,
+    content: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct MockRevision {
+    metadata: Revision,
+    content: Vec<u8>,
+}
+
+/// a resumable upload that has not received all of its content yet
+#[derive(Debug)]
+struct MockUpload {
+    file_id: String,
+    content: Vec<u8>,
+}
+
+impl MockDrive {
+    /// starts serving an empty drive on a free port of localhost
+    pub async fn start() -> Result<Self> {
+        let mut state = MockState::default();
+        let root_id = state.insert_file("My Drive", FOLDER_MIME_TYPE, None, Vec::new());
+        state.root_id = root_id;
+        let state = Arc::new(Mutex::new(state));
+
+        let upload_gate = Arc::new(tokio::sync::RwLock::new(()));
+        let service_state = state.clone();
+        let service_upload_gate = upload_gate.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            let upload_gate = service_upload_gate.clone();
+            if let Ok(mut state) = state.lock() {
+                state.connections += 1;
+            }
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let state = state.clone();
+                    let upload_gate = upload_gate.clone();
+                    async move {
+                        let response = handle_request(&state, &upload_gate, request).await;
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))?.serve(make_service);
+        let api_url = format!("http://{}/", server.local_addr());
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                warn!("mock drive stopped: {:?}", e);
+            }
+        });
+        debug!("mock drive listening on {}", api_url);
+        Ok(Self {
+            state,
+            api_url,
+            client: GoogleDrive::create_http_client(),
+            upload_gate,
+        })
+    }
+    /// a drive that talks to this mock
+    pub fn drive(&self) -> GoogleDrive {
+        self.drive_with_client(self.client.clone())
+    }
+    /// a drive that talks to this mock through `client`
+    pub(crate) fn drive_with_client(&self, client: HttpClient) -> GoogleDrive {
+        GoogleDrive::with_api_url_and_client(&self.api_url, client)
+    }
+    pub fn root_id(&self) -> DriveId {
+        DriveId::from(self.lock().root_id.clone())
+    }
+    pub fn add_folder(&self, name: &str, parent: &DriveId) -> DriveId {
+        let id =
+            self.lock()
+                .insert_file(name, FOLDER_MIME_TYPE, Some(parent.to_string()), Vec::new());
+        DriveId::from(id)
+    }
+    /// the folder of a backed up computer, it has no parent like the root
+    pub fn add_computer(&self, name: &str) -> DriveId {
+        let id = self
+            .lock()
+            .insert_file(name, FOLDER_MIME_TYPE, None, Vec::new());
+        DriveId::from(id)
+    }
+    /// a shared drive the user is a member of, it only shows up in the drives api
+    pub fn add_shared_drive(&self, name: &str) -> DriveId {
+        let mut state = self.lock();
+        state.next_id += 1;
+        let id = format!("mock-drive-{}", state.next_id);
+        state.shared_drives.push(Drive {
+            id: Some(id.clone()),
+            name: Some(name.to_string()),
+            kind: Some("drive#drive".to_string()),
+            ..Default::default()
+        });
+        DriveId::from(id)
+    }
+    pub fn add_file(&self, name: &str, parent: &DriveId, content: &[u8]) -> DriveId {
+        let id = self.lock().insert_file(
+            name,
+            "text/plain",
+            Some(parent.to_string()),
+            content.to_vec(),
+        );
+        DriveId::from(id)
+    }
+    /// puts the file or folder into one more folder, like adding it to a
+    /// folder in the web ui does
+    pub fn add_parent(&self, id: &DriveId, parent: &DriveId) {
+        let mut state = self.lock();
+        if let Some(file) = state.files.get_mut(id.as_str()) {
+            file.metadata
+                .parents
+                .get_or_insert_with(Vec::new)
+                .push(parent.to_string());
+        }
+        state.changes.push(id.to_string());
+    }
+    /// a file someone else owns and shared with the user, listings of the
+    /// files the user owns leave it out
+    pub fn add_shared_file(&self, name: &str, parent: &DriveId, content: &[u8]) -> DriveId {
+        let id = self.add_file(name, parent, content);
+        if let Some(file) = self.lock().files.get_mut(id.as_str()) {
+            file.metadata.owned_by_me = Some(false);
+        }
+        id
+    }
+    /// a google doc, it has no content that could be downloaded
+    pub fn add_document(&self, name: &str, parent: &DriveId) -> DriveId {
+        let id = self.lock().insert_file(
+            name,
+            "application/vnd.google-apps.document",
+            Some(parent.to_string()),
+            Vec::new(),
+        );
+        DriveId::from(id)
+    }
+    /// the id of the child of `parent` with the name, trashed ones included
+    pub fn find(&self, name: &str, parent: &DriveId) -> Option<DriveId> {
+        let state = self.lock();
+        state
+            .files
+            .values()
+            .map(|file| &file.metadata)
+            .find(|file| {
+                file.name.as_deref() == Some(name)
+                    && file.parents.iter().flatten().any(|p| p == parent.as_str())
+            })
+            .and_then(|file| file.id.as_deref())
+            .map(DriveId::from)
+    }
+    pub fn content(&self, id: &DriveId) -> Option<Vec<u8>> {
+        let state = self.lock();
+        state
+            .files
+            .get(id.as_str())
+            .map(|file| file.content.clone())
+    }
+    /// adds a revision with the content to the file and returns its id,
+    /// the content of the file itself does not change
+    pub fn add_revision(&self, id: &DriveId, content: &[u8]) -> String {
+        let mut state = self.lock();
+        state.next_id += 1;
+        let revision_id = format!("mock-revision-{}", state.next_id);
+        let metadata = Revision {
+            id: Some(revision_id.clone()),
+            modified_time: Some(Utc::now()),
+            size: Some(content.len() as i64),
+            md5_checksum: Some(format!("{:x}", Md5::digest(content))),
+            ..Default::default()
+        };
+        state
+            .revisions
+            .entry(id.to_string())
+            .or_default()
+            .push(MockRevision {
+                metadata,
+                content: content.to_vec(),
+            });
+        revision_id
+    }
+    pub fn metadata(&self, id: &DriveId) -> Option<File> {
+        let state = self.lock();
+        state
+            .files
+            .get(id.as_str())
+            .map(|file| file.metadata.clone())
+    }
+    /// replaces the content of the file, like an upload of another client would
+    pub fn set_content(&self, id: &DriveId, content: &[u8]) {
+        let mut state = self.lock();
+        if let Some(file) = state.files.get_mut(id.as_str()) {
+            file.content = content.to_vec();
+            file.metadata.modified_time = Some(Utc::now());
+        }
+        state.set_content_metadata(id.as_str());
+        state.changes.push(id.to_string());
+    }
+    /// how many uploads of file content were completed
+    pub fn upload_count(&self) -> usize {
+        self.lock().completed_uploads
+    }
+    /// makes the storage full, like it would be if the account ran out of space
+    pub fn set_storage_full(&self, storage_full: bool) {
+        self.lock().storage_full = storage_full;
+    }
+    /// answers every request with `401`, like google drive does once the
+    /// credentials were revoked
+    pub fn set_unauthorized(&self, unauthorized: bool) {
+        self.lock().unauthorized = unauthorized;
+    }
+    /// splits the file list into pages of at most `page_size` files
+    pub fn set_page_size(&self, page_size: usize) {
+        self.lock().page_size = Some(page_size);
+    }
+    /// answers every request only after `delay`, like a slow connection would
+    pub fn set_response_delay(&self, delay: std::time::Duration) {
+        self.lock().response_delay = delay;
+    }
+    /// rejects the page tokens of the changes handed out so far, like google
+    /// drive does when they are too old
+    pub fn expire_page_tokens(&self) {
+        let mut state = self.lock();
+        state.oldest_valid_page_token = state.changes.len();
+    }
+    /// the offset and length of every range that was downloaded of the file
+    pub fn downloaded_ranges(&self, id: &DriveId) -> Vec<(u64, u64)> {
+        self.lock()
+            .range_downloads
+            .iter()
+            .filter(|(file_id, _, _)| file_id == id.as_str())
+            .map(|(_, offset, length)| (*offset, *length))
+            .collect()
+    }
+    /// the content of uploads is not taken until the returned guard is dropped,
+    /// like it would be with a slow connection
+    pub async fn hold_uploads(&self) -> tokio::sync::OwnedRwLockWriteGuard<()> {
+        self.upload_gate.clone().write_owned().await
+    }
+    /// how many connections the drives of this mock opened
+    pub fn connection_count(&self) -> usize {
+        self.lock().connections
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().expect("the mock drive state is poisoned")
+    }
+}
+
+impl MockState {
+    fn insert_file(
+        &mut self,
+        name: &str,
+        mime_type: &str,
+        parent: Option<String>,
+        content: Vec<u8>,
+    ) -> String {
+        self.next_id += 1;
+        let id = format!("mock-id-{}", self.next_id);
+        let now = Utc::now();
+        let metadata = File {
+            id: Some(id.clone()),
+            name: Some(name.to_string()),
+            mime_type: Some(mime_type.to_string()),
+            kind: Some("drive#file".to_string()),
+            parents: parent.map(|parent| vec![parent]),
+            trashed: Some(false),
+            created_time: Some(now),
+            modified_time: Some(now),
+            viewed_by_me_time: Some(now),
+            ..Default::default()
+        };
+        self.files
+            .insert(id.clone(), MockFile { metadata, content });
+        self.set_content_metadata(&id);
+        self.changes.push(id.clone());
+        id
+    }
+    fn resolve_id(&self, id: &str) -> String {
+        if id == "root" {
+            self.root_id.clone()
+        } else {
+            id.to_string()
+        }
+    }
+    fn set_content_metadata(&mut self, id: &str) {
+        if let Some(file) = self.files.get_mut(id) {
+            // folders and native files have no size or checksum
+            let is_native = file
+                .metadata
+                .mime_type
+                .as_deref()
+                .is_some_and(|mime| mime.starts_with(NATIVE_MIME_TYPE_PREFIX));
+            if !is_native {
+                file.metadata.size = Some(file.content.len() as i64);
+                file.metadata.md5_checksum = Some(format!("{:x}", Md5::digest(&file.content)));
+            }
+        }
+    }
+    /// applies the changed fields of `changes` and the parent changes from the query
+    fn update_metadata(
+        &mut self,
+        id: &str,
+        changes: File,
+        params: &HashMap<String, String>,
+    ) -> Option<File> {
+        let file = &mut self.files.get_mut(id)?.metadata;
+        if changes.name.is_some() {
+            file.name = changes.name;
+        }
+        if changes.modified_time.is_some() {
+            file.modified_time = changes.modified_time;
+        }
+        if changes.viewed_by_me_time.is_some() {
+            file.viewed_by_me_time = changes.viewed_by_me_time;
+        }
+        if changes.trashed.is_some() {
+            file.trashed = changes.trashed;
+        }
+        if changes.starred.is_some() {
+            file.starred = changes.starred;
+        }
+        let mut parents = file.parents.take().unwrap_or_default();
+        if let Some(removed) = params.get("removeParents") {
+            parents.retain(|parent| !removed.split(',').any(|removed| removed == parent));
+        }
+        if let Some(added) = params.get("addParents") {
+            parents.extend(added.split(',').map(str::to_string));
+        }
+        file.parents = Some(parents);
+        let file = file.clone();
+        self.changes.push(id.to_string());
+        Some(file)
+    }
+    fn list_files(&self, query: Option<&str>) -> Vec<File> {
+        // only the `name = '<name>'`, `'<id>' in parents`, `trashed = false` and
+        // `'me' in owners` parts of the query are understood
+        let include_trashed = !query.is_some_and(|query| query.contains("trashed = false"));
+        let only_owned = query.is_some_and(|query| query.contains("'me' in owners"));
+        let name = query.and_then(|query| {
+            let (_, name) = query.split_once("name = '")?;
+            Some(name.split_once('\'')?.0)
+        });
+        let parent = query.and_then(|query| {
+            let (parent, _) = query.split_once("' in parents")?;
+            Some(self.resolve_id(parent.rsplit('\'').next()?))
+        });
+        let mut files: Vec<File> = self
+            .files
+            .values()
+            .map(|file| &file.metadata)
+            .filter(|file| file.id.as_ref() != Some(&self.root_id))
+            .filter(|file| include_trashed || file.trashed != Some(true))
+            .filter(|file| !only_owned || file.owned_by_me != Some(false))
+            .filter(|file| match &parent {
+                Some(parent) => file.parents.iter().flatten().any(|p| p == parent),
+                None => true,
+            })
+            .filter(|file| name.is_none() || file.name.as_deref() == name)
+            .cloned()
+            .collect();
+        files.sort_by(|a, b| a.id.cmp(&b.id));
+        files
+    }
+    fn changes_since(&self, page_token: usize) -> Vec<Change> {
+        let mut ids: Vec<&String> = self.changes.iter().skip(page_token).collect();
+        ids.dedup();
+        ids.into_iter()
+            .map(|id| {
+                // deleted files are reported as removed, without the file
+                let file = self.files.get(id).map(|file| file.metadata.clone());
+                Change {
+                    change_type: Some("file".to_string()),
+                    file_id: Some(id.clone()),
+                    removed: Some(file.is_none()),
+                    file,
+                    time: Some(Utc::now()),
+                    kind: Some("drive#change".to_string()),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+async fn handle_request(
+    state: &Mutex<MockState>,
+    upload_gate: &tokio::sync::RwLock<()>,
+    request: Request<Body>,
+) -> Response<Body> {
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let params = query_params(&uri);
+    let content_range = request
+        .headers()
+        .get("Content-Range")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let range = request
+        .headers()
+        .get("Range")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_range);
+    let host = request
+        .headers()
+        .get("host")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("{:?}", e)),
+    };
+    debug!("mock drive: {} {}", method, uri);
+    if uri.path().starts_with("/upload-session/") {
+        drop(upload_gate.read().await);
+    }
+    let response_delay = state
+        .lock()
+        .expect("the mock drive state is poisoned")
+        .response_delay;
+    if !response_delay.is_zero() {
+        tokio::time::sleep(response_delay).await;
+    }
+
+    let mut state = state.lock().expect("the mock drive state is poisoned");
+    if state.unauthorized {
+        return error_response(StatusCode::UNAUTHORIZED, "Invalid Credentials");
+    }
+    let path = uri.path();
+    if let Some(session) = path.strip_prefix("/upload-session/") {
+        if method != Method::POST {
+            return error_response(StatusCode::METHOD_NOT_ALLOWED, path);
+        }
+        return upload_chunk(&mut state, session, content_range.as_deref(), &body);
+    }
+    if let Some(id) = path.strip_prefix("/resumable/upload/drive/v3/files/") {
+        if method != Method::PATCH {
+            return error_response(StatusCode::METHOD_NOT_ALLOWED, path);
+        }
+        if state.storage_full {
+            return quota_exceeded_response();
+        }
+        let id = state.resolve_id(id);
+        let changes = match parse_file(&body) {
+            Ok(changes) => changes,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("{:?}", e)),
+        };
+        if state.update_metadata(&id, changes, &params).is_none() {
+            return error_response(StatusCode::NOT_FOUND, &id);
+        }
+        state.next_id += 1;
+        let session = format!("upload-{}", state.next_id);
+        state.uploads.insert(
+            session.clone(),
+            MockUpload {
+                file_id: id,
+                content: Vec::new(),
+            },
+        );
+        let mut response = Response::new(Body::empty());
+        response.headers_mut().insert(
+            "Location",
+            format!("http://{}/upload-session/{}", host, session)
+                .parse()
+                .expect("the upload url is a valid header"),
+        );
+        return response;
+    }
+    if path == "/upload/drive/v3/files" {
+        if method != Method::POST {
+            return error_response(StatusCode::METHOD_NOT_ALLOWED, path);
+        }
+        return create_file(&mut state, &body);
+    }
+    let Some(api_path) = path.strip_prefix("/drive/v3/") else {
+        return error_response(StatusCode::NOT_FOUND, path);
+    };
+    match (method, api_path) {
+        (Method::GET, "about") => json_response(serde_json::to_value(&About {
+            storage_quota: Some(AboutStorageQuota {
+                limit: Some(MOCK_STORAGE_LIMIT),
+                usage: Some(if state.storage_full {
+                    MOCK_STORAGE_LIMIT
+                } else {
+                    state
+                        .files
+                        .values()
+                        .map(|file| file.content.len() as i64)
+                        .sum()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })),
+        (Method::GET, "changes/startPageToken") => {
+            json_response(serde_json::to_value(&StartPageToken {
+                start_page_token: Some(state.changes.len().to_string()),
+                kind: Some("drive#startPageToken".to_string()),
+            }))
+        }
+        (Method::GET, "changes") => {
+            let page_token = params
+                .get("pageToken")
+                .and_then(|token| token.parse().ok())
+                .unwrap_or(state.changes.len());
+            if page_token < state.oldest_valid_page_token {
+                return invalid_page_token_response();
+            }
+            json_response(serde_json::to_value(&ChangeList {
+                changes: Some(state.changes_since(page_token)),
+                new_start_page_token: Some(state.changes.len().to_string()),
+                ..Default::default()
+            }))
+        }
+        (Method::GET, "drives") => json_response(serde_json::to_value(&DriveList {
+            drives: Some(state.shared_drives.clone()),
+            ..Default::default()
+        })),
+        (Method::GET, "files") => {
+            let mut files = state.list_files(params.get("q").map(String::as_str));
+            // the page tokens are the index of the first file of the page
+            let start = params
+                .get("pageToken")
+                .and_then(|token| token.parse().ok())
+                .unwrap_or(0)
+                .min(files.len());
+            let end = state.page_size.map_or(files.len(), |page_size| {
+                (start + page_size).min(files.len())
+            });
+            let next_page_token = (end < files.len()).then(|| end.to_string());
+            json_response(serde_json::to_value(&FileList {
+                files: Some(files.drain(start..end).collect()),
+                next_page_token,
+                ..Default::default()
+            }))
+        }
+        (Method::GET, api_path) if api_path.contains("/revisions") => {
+            let (id, revision_id) = api_path["files/".len()..]
+                .split_once("/revisions")
+                .expect("the path contains the revisions");
+            let revisions = state.revisions.get(id).map(Vec::as_slice).unwrap_or(&[]);
+            let Some(revision_id) = revision_id.strip_prefix('/') else {
+                return json_response(serde_json::to_value(&RevisionList {
+                    revisions: Some(revisions.iter().map(|r| r.metadata.clone()).collect()),
+                    ..Default::default()
+                }));
+            };
+            match revisions
+                .iter()
+                .find(|r| r.metadata.id.as_deref() == Some(revision_id))
+            {
+                Some(revision) if params.get("alt").map(String::as_str) == Some("media") => {
+                    Response::new(Body::from(revision.content.clone()))
+                }
+                Some(revision) => json_response(serde_json::to_value(&revision.metadata)),
+                None => error_response(StatusCode::NOT_FOUND, revision_id),
+            }
+        }
+        (method, api_path) if api_path.starts_with("files/") => {
+            let id = state.resolve_id(&api_path["files/".len()..]);
+            if method == Method::PATCH {
+                let changes = match parse_file(&body) {
+                    Ok(changes) => changes,
+                    Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("{:?}", e)),
+                };
+                return match state.update_metadata(&id, changes, &params) {
+                    Some(file) => json_response(serde_json::to_value(&file)),
+                    None => error_response(StatusCode::NOT_FOUND, &id),
+                };
+            }
+            if method == Method::DELETE {
+                if state.files.remove(&id).is_none() {
+                    return error_response(StatusCode::NOT_FOUND, &id);
+                }
+                state.changes.push(id);
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::NO_CONTENT;
+                return response;
+            }
+            if method != Method::GET {
+                return error_response(StatusCode::METHOD_NOT_ALLOWED, path);
+            }
+            match state.files.get(&id) {
+                Some(file) if params.get("alt").map(String::as_str) == Some("media") => {
+                    let Some((first, last)) = range else {
+                        return Response::new(Body::from(file.content.clone()));
+                    };
+                    let end = (last + 1).min(file.content.len() as u64);
+                    let content =
+                        file.content[(first as usize).min(end as usize)..end as usize].to_vec();
+                    state.range_downloads.push((id, first, last + 1 - first));
+                    let mut response = Response::new(Body::from(content));
+                    *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+                    response
+                }
+                Some(file) => json_response(serde_json::to_value(&file.metadata)),
+                None => error_response(StatusCode::NOT_FOUND, &id),
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, path),
+    }
+}
+
+/// creates a file from a multipart upload, only the metadata part is used
+fn create_file(state: &mut MockState, body: &[u8]) -> Response<Body> {
+    // the metadata is the first part, a json object
+    let metadata = body.iter().position(|b| *b == b'{').and_then(|start| {
+        serde_json::Deserializer::from_slice(&body[start..])
+            .into_iter::<File>()
+            .next()
+    });
+    let metadata = match metadata {
+        Some(Ok(metadata)) => metadata,
+        _ => return error_response(StatusCode::BAD_REQUEST, "no metadata in the upload"),
+    };
+    let parent = metadata
+        .parents
+        .and_then(|parents| parents.into_iter().next())
+        .map(|parent| state.resolve_id(&parent));
+    let id = state.insert_file(
+        metadata.name.as_deref().unwrap_or_default(),
+        metadata
+            .mime_type
+            .as_deref()
+            .unwrap_or("application/octet-stream"),
+        parent,
+        Vec::new(),
+    );
+    json_response(serde_json::to_value(&state.files[&id].metadata))
+}
+
+/// takes one chunk of a resumable upload, the content gets replaced once the last one arrived
+fn upload_chunk(
+    state: &mut MockState,
+    session: &str,
+    content_range: Option<&str>,
+    body: &[u8],
+) -> Response<Body> {
+    let Some((first, total)) = content_range.and_then(parse_content_range) else {
+        return error_response(StatusCode::BAD_REQUEST, "invalid Content-Range");
+    };
+    let Some(upload) = state.uploads.get_mut(session) else {
+        return error_response(StatusCode::NOT_FOUND, session);
+    };
+    if first != upload.content.len() as u64 {
+        return error_response(StatusCode::BAD_REQUEST, "chunks have to be sent in order");
+    }
+    upload.content.extend_from_slice(body);
+    let received = upload.content.len() as u64;
+    if received < total {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::PERMANENT_REDIRECT;
+        response.headers_mut().insert(
+            "Range",
+            format!("bytes 0-{}", received - 1)
+                .parse()
+                .expect("the range is a valid header"),
+        );
+        return response;
+    }
+
+    let upload = state.uploads.remove(session).expect("the upload exists");
+    let Some(file) = state.files.get_mut(&upload.file_id) else {
+        return error_response(StatusCode::NOT_FOUND, &upload.file_id);
+    };
+    file.content = upload.content;
+    state.completed_uploads += 1;
+    state.set_content_metadata(&upload.file_id);
+    state.changes.push(upload.file_id.clone());
+    json_response(serde_json::to_value(&state.files[&upload.file_id].metadata))
+}
+
+/// the first and the last byte of a `bytes=<first>-<last>` range
+fn parse_range(range: &str) -> Option<(u64, u64)> {
+    let (first, last) = range.strip_prefix("bytes=")?.split_once('-')?;
+    Some((first.parse().ok()?, last.parse().ok()?))
+}
+
+/// the first byte and the total size of a `bytes <first>-<last>/<total>` range
+fn parse_content_range(content_range: &str) -> Option<(u64, u64)> {
+    let (range, total) = content_range.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, _last) = range.split_once('-')?;
+    Some((first.parse().ok()?, total.parse().ok()?))
+}
+
+fn parse_file(body: &[u8]) -> Result<File> {
+    if body.is_empty() {
+        return Ok(File::default());
+    }
+    Ok(serde_json::from_slice(body)?)
+}
+
+fn query_params(uri: &Uri) -> HashMap<String, String> {
+    uri.query()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((percent_decode(key).ok()?, percent_decode(value).ok()?))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut chars = value.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [
+                    chars.next().ok_or(anyhow!("incomplete escape"))?,
+                    chars.next().ok_or(anyhow!("incomplete escape"))?,
+                ];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex)?, 16)?);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn json_response(value: serde_json::Result<serde_json::Value>) -> Response<Body> {
+    match value {
+        Ok(json) => {
+            let mut response = Response::new(Body::from(json.to_string()));
+            response.headers_mut().insert(
+                "Content-Type",
+                "application/json".parse().expect("a valid header"),
+            );
+            response
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:?}", e)),
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    warn!("mock drive: {}: {}", status, message);
+    let body = serde_json::json!({
+        "error": { "code": status.as_u16(), "message": message }
+    });
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+/// what google drive answers to an upload when the storage is full
+fn quota_exceeded_response() -> Response<Body> {
+    let message = "The user's Drive storage quota has been exceeded.";
+    warn!("mock drive: {}", message);
+    let body = serde_json::json!({
+        "error": {
+            "code": 403,
+            "message": message,
+            "errors": [{ "reason": "storageQuotaExceeded", "message": message }],
+        }
+    });
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = StatusCode::FORBIDDEN;
+    response
+}
+
+/// what google drive answers to a page token of the changes it does not accept
+fn invalid_page_token_response() -> Response<Body> {
+    let message = "Invalid Value";
+    warn!("mock drive: {}", message);
+    let body = serde_json::json!({
+        "error": {
+            "code": 400,
+            "message": message,
+            "errors": [{
+                "reason": "invalid",
+                "message": message,
+                "location": "pageToken",
+                "locationType": "parameter",
+            }],
+        }
+    });
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = StatusCode::BAD_REQUEST;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::google_drive::ListOptions;
+
+    use super::*;
+
+    #[test]
+    fn query_params_are_decoded() {
+        let uri: Uri = "/drive/v3/files?q=%27abc%27+in+parents&alt=json"
+            .parse()
+            .unwrap();
+        let params = query_params(&uri);
+        assert_eq!(params["q"], "'abc' in parents");
+        assert_eq!(params["alt"], "json");
+        assert_eq!(parse_content_range("bytes 5-9/20"), Some((5, 20)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn drive_round_trip() {
+        let mock = MockDrive::start().await.unwrap();
+        let folder = mock.add_folder("folder", &mock.root_id());
+        let file_id = mock.add_file("a.txt", &folder, b"hello");
+        let drive = mock.drive();
+
+        let start_page_token = drive.get_start_page_token().await.unwrap();
+        let root = drive.get_metadata_for_file(DriveId::root()).await.unwrap();
+        assert_eq!(root.id, Some(mock.root_id().to_string()));
+        assert_eq!(
+            drive
+                .list_all_files(ListOptions::default())
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(drive.list_files(folder.clone()).await.unwrap().len(), 1);
+
+        let dir = tempfile::tempdir().unwrap();
+        let download_path = dir.path().join("a.txt");
+        let file = drive
+            .download_file(file_id.clone(), &download_path)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&download_path).unwrap(), b"hello");
+        assert_eq!(file.size, Some(5));
+
+        std::fs::write(&download_path, b"hello world").unwrap();
+        drive
+            .upload_file_content_from_path(file.clone(), &download_path, None)
+            .await
+            .unwrap();
+        assert_eq!(mock.content(&file_id).unwrap(), b"hello world");
+        assert_eq!(mock.metadata(&file_id).unwrap().size, Some(11));
+
+        let changed = File {
+            id: file.id.clone(),
+            name: Some("b.txt".to_string()),
+            parents: Some(vec![mock.root_id().to_string()]),
+            ..Default::default()
+        };
+        drive
+            .update_file_metadata_on_drive(changed, &file)
+            .await
+            .unwrap();
+        let metadata = mock.metadata(&file_id).unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("b.txt"));
+        assert_eq!(metadata.parents, Some(vec![mock.root_id().to_string()]));
+
+        let mut token = start_page_token;
+        let changes = drive.get_changes_since(&mut token).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(drive
+            .get_changes_since(&mut token)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
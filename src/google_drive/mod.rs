@@ -1,9 +1,32 @@
+pub use api_error::{ApiError, RequestTimeoutError};
+pub(crate) use auth::read_client_secret;
+pub use auth::{CredentialsError, NotAuthenticatedError};
 pub use drive::*;
 pub use drive_id::*;
+pub use file_times::FileTimes;
 pub use helpers::*;
+pub use metadata::DriveMetadata;
+pub(crate) use metadata::FOLDER_MIME_TYPE;
+pub use progress::*;
+pub use transferred::{TransferCounter, TransferredBytes};
+
+mod api_error;
+
+mod auth;
 
 mod helpers;
 
+mod metadata;
+
 mod drive;
 
 mod drive_id;
+
+mod file_times;
+
+mod progress;
+
+mod transferred;
+
+#[cfg(any(test, feature = "fuse-tests"))]
+pub mod mock;
@@ -0,0 +1,113 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// gets called with `(bytes_sent, total_bytes)` whenever an upload made progress
+pub type ProgressCallback = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadProgress {
+    pub bytes_sent: u64,
+    pub total: u64,
+}
+
+impl UploadProgress {
+    pub fn new(total: u64) -> Self {
+        Self {
+            bytes_sent: 0,
+            total,
+        }
+    }
+    /// how far the upload is, from 0 to 100
+    pub fn percent(&self) -> u8 {
+        if self.total == 0 {
+            return 100;
+        }
+        (self.bytes_sent.min(self.total) * 100 / self.total) as u8
+    }
+    /// updates the sent bytes and returns the 10% boundary that was crossed
+    /// with this update, if any
+    pub fn update(&mut self, bytes_sent: u64) -> Option<u8> {
+        let old_decile = self.percent() / 10;
+        self.bytes_sent = bytes_sent;
+        let new_decile = self.percent() / 10;
+        if new_decile > old_decile {
+            Some(new_decile * 10)
+        } else {
+            None
+        }
+    }
+}
+
+/// wraps a reader and reports the current position after every read.
+///
+/// The resumable upload seeks back to the start of a chunk when it has to
+/// retry, so the position is the amount of bytes that has been sent so far.
+pub(crate) struct ProgressReader<R> {
+    inner: R,
+    total: u64,
+    callback: ProgressCallback,
+}
+
+impl<R: Read + Seek> ProgressReader<R> {
+    pub(crate) fn new(inner: R, total: u64, callback: ProgressCallback) -> Self {
+        Self {
+            inner,
+            total,
+            callback,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            let position = self.inner.stream_position()?;
+            (self.callback)(position, self.total);
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn update_reports_crossed_boundaries() {
+        let mut progress = UploadProgress::new(1000);
+        assert_eq!(progress.update(50), None);
+        assert_eq!(progress.update(100), Some(10));
+        assert_eq!(progress.update(150), None);
+        assert_eq!(progress.update(390), Some(30));
+        assert_eq!(progress.update(1000), Some(100));
+        assert_eq!(progress.percent(), 100);
+    }
+
+    #[test]
+    fn reader_reports_position() {
+        let positions = Arc::new(Mutex::new(vec![]));
+        let positions_clone = positions.clone();
+        let callback: ProgressCallback = Arc::new(move |sent, total| {
+            positions_clone.lock().unwrap().push((sent, total));
+        });
+        let mut reader = ProgressReader::new(Cursor::new(vec![0u8; 10]), 10, callback);
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        reader.seek(SeekFrom::Start(4)).unwrap();
+        reader.read_to_end(&mut vec![]).unwrap();
+
+        let positions = positions.lock().unwrap();
+        assert_eq!(*positions, vec![(4, 10), (8, 10), (10, 10)]);
+    }
+}
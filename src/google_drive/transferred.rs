@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::google_drive::DriveId;
+
+/// how many bytes of content were moved in each direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferredBytes {
+    pub downloaded: u64,
+    pub uploaded: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    total: TransferredBytes,
+    files: HashMap<DriveId, TransferredBytes>,
+}
+
+/// Counts the content a drive moved since the program started.
+///
+/// The clones of a drive share the counter, so the downloads on other tasks
+/// are counted too. Bytes that had to be sent again after a failure count
+/// twice, they were moved twice.
+#[derive(Debug, Clone, Default)]
+pub struct TransferCounter {
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl TransferCounter {
+    fn lock(&self) -> MutexGuard<'_, Counts> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    pub(crate) fn add_downloaded(&self, id: &DriveId, bytes: u64) {
+        let mut counts = self.lock();
+        counts.total.downloaded += bytes;
+        counts.files.entry(id.clone()).or_default().downloaded += bytes;
+    }
+    pub(crate) fn add_uploaded(&self, id: &DriveId, bytes: u64) {
+        let mut counts = self.lock();
+        counts.total.uploaded += bytes;
+        counts.files.entry(id.clone()).or_default().uploaded += bytes;
+    }
+    pub fn total(&self) -> TransferredBytes {
+        self.lock().total
+    }
+    pub fn of_file(&self, id: &DriveId) -> TransferredBytes {
+        self.lock().files.get(id).copied().unwrap_or_default()
+    }
+}
+
+/// counts everything that is read from the inner reader as uploaded
+pub(crate) struct UploadCountingReader<R> {
+    inner: R,
+    id: DriveId,
+    counter: TransferCounter,
+}
+
+impl<R: Read + Seek> UploadCountingReader<R> {
+    pub(crate) fn new(inner: R, id: DriveId, counter: TransferCounter) -> Self {
+        Self { inner, id, counter }
+    }
+}
+
+impl<R: Read + Seek> Read for UploadCountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.counter.add_uploaded(&self.id, read as u64);
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for UploadCountingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
@@ -7,169 +7,200 @@ use std::{
 };
 use tempfile::TempDir;
 use tokio::{
-    select,
-    sync::mpsc::{channel, Receiver, Sender},
+    sync::mpsc::{channel, Sender},
     task::JoinHandle,
 };
-use tracing::{debug, error, info};
+use tracing::{debug, info};
 
 use prelude::*;
 
 use crate::{
     config::common_file_filter::CommonFileFilter,
+    config::conflict_name::ConflictNameTemplate,
+    config::{path_filter::PathFilter, ttl_overrides::TtlOverrides},
     fs::drive::{DriveFileUploader, DriveFilesystem, FileUploaderCommand, SyncSettings},
-    fs::drive_file_provider::{ProviderCommand, ProviderRequest},
-    fs::{drive2, drive_file_provider},
-    google_drive::GoogleDrive,
+    fs::drive_file_provider::{DeletionPolicy, OpenCacheMode, PushSettings},
+    google_drive::{DriveId, GoogleDrive},
+    mount::{run_mounts, Mount},
 };
 
 pub mod async_helper;
 pub mod common;
 pub mod config;
+pub mod download;
 pub mod fs;
 pub mod google_drive;
+pub mod log_level;
 mod macros;
+pub mod mount;
+pub mod preflight;
 pub mod prelude;
 
-//region drive2 full example
-pub async fn sample_drive2() -> Result<()> {
-    let mountpoint = Path::new("/tmp/fuse/3");
-    let perma_dir = Path::new("/tmp/fuse/2");
-    let cache_dir = get_cache_dir()?;
-
-    let (provider_command_tx, provider_command_rx) = channel(1);
-    let (provider_request_tx, provider_request_rx) = channel(1);
+//region run
+/// where [RunConfig::new] and the download look for the oauth client secret
+pub const DEFAULT_CLIENT_SECRET_PATH: &str = "auth/client_secret.json";
+/// where [RunConfig::new] and the download keep the tokens of the account
+pub const DEFAULT_TOKENS_PATH: &str = "auth/tokens.json";
 
-    let (filesystem_handle, unmount_callable) =
-        filesystem_thread_starter(provider_request_tx, mountpoint).await?;
-    let provider_handle = provider_thread_starter(
-        provider_command_rx,
-        provider_request_rx,
-        unmount_callable,
-        cache_dir.path(),
-        perma_dir,
-    )
-    .await?;
-
-    let program_end_handle = ctrl_c_thread_starter().await?;
-    select! {
-        _= filesystem_handle => {
-            info!("filesystem thread finished first!");
-            let x = provider_command_tx.send(ProviderCommand::Stop).await;
-            info!("send stop to provider: {:?}", x);
-        },
-        _= program_end_handle => {
-            info!("filesystem thread finished first!");
-            let x = provider_command_tx.send(ProviderCommand::Stop).await;
-            info!("send stop to provider: {:?}", x);
-        },
-    }
-    provider_handle.await?;
-    info!("everything finished! Exiting...");
-    Ok(())
+/// Everything [run] needs to mount a drive.
+///
+/// [RunConfig::new] fills in the defaults, the fields can be changed after that.
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    pub mountpoint: PathBuf,
+    /// where the opened files are downloaded to
+    pub cache_dir: PathBuf,
+    /// where the files that are always kept locally are stored
+    pub perma_dir: PathBuf,
+    /// the oauth client secret of the app, can be shared between accounts
+    pub client_secret_path: PathBuf,
+    /// where the tokens of the account are kept between runs
+    pub tokens_path: PathBuf,
+    pub settings: SyncSettings,
+    /// the folder that gets pinned after the mount, see [Mount::set_prefetch]
+    pub prefetch: Option<PathBuf>,
+    /// see [Mount::set_verify_cache]
+    pub verify_cache: bool,
+    /// starts without the network, see [Mount::set_offline]
+    pub offline: bool,
+    /// see [Mount::set_path_filter]
+    pub path_filter: PathFilter,
+    /// `None` keeps the default, see [Mount::set_quota_cache_time]
+    pub quota_cache_time: Option<Duration>,
+    /// see [Mount::set_refresh_folders_on_read_dir]
+    pub refresh_folders_on_read_dir: bool,
+    /// `None` only polls for changes, see [Mount::set_push_settings]
+    pub push_settings: Option<PushSettings>,
+    pub open_cache_mode: OpenCacheMode,
+    pub deletion_policy: DeletionPolicy,
+    /// see [Mount::set_conflict_name_template]
+    pub conflict_name_template: ConflictNameTemplate,
+    /// see [Mount::set_ttl_overrides]
+    pub ttl_overrides: TtlOverrides,
+    /// see [Mount::set_include_trashed]
+    pub include_trashed: bool,
+    /// see [Mount::set_include_computers]
+    pub include_computers: bool,
+    /// `None` mounts the whole drive, see [Mount::set_root_folder]
+    pub root_folder: Option<DriveId>,
+    /// see [Mount::set_root_label]
+    pub root_label: Option<String>,
+    /// see [Mount::set_dedupe_cache]
+    pub dedupe_cache: bool,
+    /// see [Mount::set_label]
+    pub label: Option<String>,
 }
 
-async fn filesystem_thread_starter(
-    provider_request_tx: Sender<ProviderRequest>,
-    mountpoint: impl Into<&Path>,
-) -> Result<(JoinHandle<()>, SessionUnmounter)> {
-    let filesystem = drive2::DriveFilesystem::new(provider_request_tx);
-    let mount_options = vec![
-        MountOption::RW, /*TODO: make a start parameter that can change the mount to read only*/
-    ];
-    let mut mount = Session::new(filesystem, mountpoint.into(), &mount_options)?;
-    let session_unmounter = mount.unmount_callable();
-    let join_handle = tokio::spawn(async move {
-        let mount_res = mount.run();
-        debug!("mount finished with result: {:?}", mount_res);
-        if let Err(e) = mount_res {
-            error!("mount finished with error: {:?}", e);
+impl RunConfig {
+    /// reads the auth files from `auth/` and checks for changes every 10 seconds
+    pub fn new(
+        mountpoint: impl Into<PathBuf>,
+        cache_dir: impl Into<PathBuf>,
+        perma_dir: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            mountpoint: mountpoint.into(),
+            cache_dir: cache_dir.into(),
+            perma_dir: perma_dir.into(),
+            client_secret_path: PathBuf::from(DEFAULT_CLIENT_SECRET_PATH),
+            tokens_path: PathBuf::from(DEFAULT_TOKENS_PATH),
+            settings: SyncSettings::new(Duration::from_secs(2), Duration::from_secs(10)),
+            prefetch: None,
+            verify_cache: false,
+            offline: false,
+            path_filter: PathFilter::default(),
+            quota_cache_time: None,
+            refresh_folders_on_read_dir: false,
+            push_settings: None,
+            open_cache_mode: OpenCacheMode::default(),
+            deletion_policy: DeletionPolicy::default(),
+            conflict_name_template: ConflictNameTemplate::default(),
+            ttl_overrides: TtlOverrides::default(),
+            include_trashed: false,
+            include_computers: false,
+            root_folder: None,
+            root_label: None,
+            dedupe_cache: false,
+            label: None,
         }
-    });
-    Ok((join_handle, session_unmounter))
+    }
 }
 
-async fn provider_thread_starter(
-    provider_command_rx: Receiver<ProviderCommand>,
-    provider_request_rx: Receiver<ProviderRequest>,
-    mut unmount_callable: SessionUnmounter,
-    cache_dir: &Path,
-    perma_dir: &Path,
-) -> Result<JoinHandle<()>> {
-    let drive = GoogleDrive::new().await?;
-
-    let changes_start_token = drive
-        .get_start_page_token()
-        .await
-        .expect("could not initialize the changes api start page token");
-    let mut provider = drive_file_provider::DriveFileProvider::new(
+/// Mounts the drive of the account and runs until ctrl+c is pressed or the
+/// filesystem gets unmounted from the outside, then everything is stopped and
+/// unmounted.
+///
+/// If the account was never authorized, the browser flow starts and the tokens
+/// get saved to [RunConfig::tokens_path]. See [run_mounts] for the signals that
+/// are handled while it runs.
+///
+/// Before anything else [preflight](preflight::preflight) checks that the mount can work.
+pub async fn run(config: RunConfig) -> Result<()> {
+    preflight::preflight(&config).await?;
+    let drive = if config.offline {
+        GoogleDrive::from_auth_files_without_login(config.client_secret_path, config.tokens_path)
+            .await?
+    } else {
+        GoogleDrive::from_auth_files(config.client_secret_path, config.tokens_path).await?
+    };
+    let mut mount = Mount::new(
         drive,
-        cache_dir.to_path_buf(),
-        perma_dir.to_path_buf(),
-        changes_start_token,
+        config.mountpoint,
+        config.cache_dir,
+        config.perma_dir,
+        config.settings,
     );
-
-    Ok(tokio::spawn(async move {
-        provider
-            .listen(provider_request_rx, provider_command_rx)
-            .await;
-        unmount_callable.unmount().expect("failed to unmount");
-    }))
+    if let Some(folder) = config.prefetch {
+        mount.set_prefetch(folder);
+    }
+    mount.set_verify_cache(config.verify_cache);
+    mount.set_offline(config.offline);
+    mount.set_path_filter(config.path_filter);
+    if let Some(quota_cache_time) = config.quota_cache_time {
+        mount.set_quota_cache_time(quota_cache_time);
+    }
+    mount.set_refresh_folders_on_read_dir(config.refresh_folders_on_read_dir);
+    if let Some(push_settings) = config.push_settings {
+        mount.set_push_settings(push_settings);
+    }
+    mount.set_open_cache_mode(config.open_cache_mode);
+    mount.set_deletion_policy(config.deletion_policy);
+    mount.set_conflict_name_template(config.conflict_name_template);
+    mount.set_ttl_overrides(config.ttl_overrides);
+    mount.set_include_trashed(config.include_trashed);
+    mount.set_include_computers(config.include_computers);
+    if let Some(root_folder) = config.root_folder {
+        mount.set_root_folder(root_folder);
+    }
+    if let Some(root_label) = config.root_label {
+        mount.set_root_label(root_label);
+    }
+    mount.set_dedupe_cache(config.dedupe_cache);
+    if let Some(label) = config.label {
+        mount.set_label(label);
+    }
+    run_mounts(vec![mount]).await?;
+    info!("everything finished! Exiting...");
+    Ok(())
 }
-async fn ctrl_c_thread_starter() -> Result<JoinHandle<()>> {
-    Ok(tokio::spawn(async move {
-        tokio::signal::ctrl_c()
-            .await
-            .expect("failed to listen for ctrl_c event");
+//endregion
 
-        info!("got signal to end program");
-    }))
+//region drive2 full example
+pub async fn sample_drive2() -> Result<()> {
+    let cache_dir = get_cache_dir()?;
+    run(RunConfig::new(
+        "/tmp/fuse/3",
+        cache_dir.path(),
+        "/tmp/fuse/2",
+    ))
+    .await
 }
 //endregion
 
 //region old examples
+/// the drive2 filesystem is what [run] mounts, so this is [sample_drive2] now
 pub async fn sample_drive2_fs() -> Result<()> {
-    // let mountpoint = "/tmp/fuse/3";
-    let mountpoint = Path::new("/tmp/fuse/3");
-    let perma_dir = "/tmp/fuse/2";
-
-    let cache_dir = get_cache_dir()?;
-
-    let drive = GoogleDrive::new().await?;
-    let test = drive.list_all_files().await;
-    debug!("test!");
-    for entry in test.unwrap() {
-        debug!("entry: {:?}", entry);
-    }
-    debug!("test!");
-    let (provider_tx, provider_rx) = channel(1);
-    let filesystem = drive2::DriveFilesystem::new(provider_tx);
-    let mount_options = vec![MountOption::RW];
-    let mut mount = Session::new(filesystem, &mountpoint, &mount_options)?;
-    let mut session_unmounter = mount.unmount_callable();
-
-    let (command_tx, command_rx) = channel(1);
-    let provider_join_handle: JoinHandle<()> = tokio::spawn(drive2_provider(
-        drive,
-        cache_dir.path().to_path_buf(),
-        PathBuf::from(perma_dir),
-        provider_rx,
-        command_rx,
-    ));
-    debug!("running mount and listener");
-    select!(
-        _= async move {mount.run()} => {
-            debug!("mount.run finished first!");
-            let _ = command_tx.send(ProviderCommand::Stop);
-            let _ = session_unmounter.unmount();
-        },
-        _=provider_join_handle => {
-            debug!("provider finished first!");
-            let _ = session_unmounter.unmount();
-        }
-    );
-
-    Ok(())
+    sample_drive2().await
 }
 pub async fn sample_drive_fs() -> Result<()> {
     let mountpoint = "/tmp/fuse/3";
@@ -199,7 +230,11 @@ pub async fn sample_drive_fs() -> Result<()> {
     )
     .await?;
 
-    let mount_options = vec![MountOption::RW];
+    let mount_options = vec![
+        MountOption::RW,
+        MountOption::FSName(mount::FS_NAME.to_string()),
+        MountOption::Subtype(mount::FS_NAME.to_string()),
+    ];
 
     let uploader_handle: JoinHandle<()> = tokio::spawn(async move {
         file_uploader.listen().await;
@@ -236,7 +271,8 @@ async fn mount(
         let _ = end_program_signal_awaiter(sender, session_ender).await;
     });
     debug!("Mounting fuse filesystem");
-    let _ = session.run();
+    // the filesystem blocks its thread and calls into async code from there
+    let _ = tokio::task::spawn_blocking(move || session.run()).await?;
     debug!("Stopped with mounting");
     // Ok(session_ender)
     Ok(end_program_signal_handle)
@@ -258,25 +294,6 @@ async fn end_program_signal_awaiter(
     info!("unmounted");
     Ok(())
 }
-async fn drive2_provider(
-    drive: GoogleDrive,
-    cache_dir: PathBuf,
-    perma_dir: PathBuf,
-    provider_rx: Receiver<ProviderRequest>,
-    command_rx: Receiver<ProviderCommand>,
-) {
-    let changes_start_token = drive
-        .get_start_page_token()
-        .await
-        .expect("could not initialize the changes api start page token");
-    let mut provider = drive_file_provider::DriveFileProvider::new(
-        drive,
-        cache_dir,
-        perma_dir,
-        changes_start_token,
-    );
-    provider.listen(provider_rx, command_rx).await;
-}
 //endregion
 
 #[cfg(test)]
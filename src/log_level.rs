@@ -0,0 +1,70 @@
+//! Changing how much gets logged while the program runs, so diagnosing an
+//! issue does not need a restart that loses the state.
+//!
+//! Whoever sets up the logging registers how to change its level with
+//! [set_reloader], after that [set_log_level] changes it for the whole process.
+
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::anyhow;
+use tracing::level_filters::LevelFilter;
+
+use crate::prelude::*;
+
+type Reloader = Box<dyn Fn(LevelFilter) -> Result<()> + Send + Sync>;
+
+static RELOADER: OnceLock<Reloader> = OnceLock::new();
+static CURRENT_LEVEL: Mutex<LevelFilter> = Mutex::new(LevelFilter::INFO);
+
+/// registers how the level of the subscriber is changed, this only works once
+pub fn set_reloader(
+    initial_level: LevelFilter,
+    reloader: impl Fn(LevelFilter) -> Result<()> + Send + Sync + 'static,
+) -> Result<()> {
+    RELOADER
+        .set(Box::new(reloader))
+        .map_err(|_| anyhow!("the log level reloader was already set"))?;
+    *lock_current_level() = initial_level;
+    Ok(())
+}
+
+pub fn set_log_level(level: LevelFilter) -> Result<()> {
+    let reloader = RELOADER
+        .get()
+        .ok_or_else(|| anyhow!("the logging does not support changing the level"))?;
+    reloader(level)?;
+    *lock_current_level() = level;
+    Ok(())
+}
+
+/// the level that was set last
+pub fn log_level() -> LevelFilter {
+    *lock_current_level()
+}
+
+/// the next more verbose level, after `TRACE` it starts over with `INFO`
+pub fn more_verbose(level: LevelFilter) -> LevelFilter {
+    match level {
+        LevelFilter::DEBUG => LevelFilter::TRACE,
+        LevelFilter::TRACE => LevelFilter::INFO,
+        _ => LevelFilter::DEBUG,
+    }
+}
+
+fn lock_current_level() -> std::sync::MutexGuard<'static, LevelFilter> {
+    CURRENT_LEVEL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn more_verbose_cycles_back_to_info() {
+        assert_eq!(more_verbose(LevelFilter::INFO), LevelFilter::DEBUG);
+        assert_eq!(more_verbose(LevelFilter::DEBUG), LevelFilter::TRACE);
+        assert_eq!(more_verbose(LevelFilter::TRACE), LevelFilter::INFO);
+        assert_eq!(more_verbose(LevelFilter::WARN), LevelFilter::DEBUG);
+        assert_eq!(more_verbose(LevelFilter::OFF), LevelFilter::DEBUG);
+    }
+}
@@ -21,10 +21,7 @@ macro_rules! match_provider_response {
 macro_rules! receive_response {
     ($rx: ident, $response: ident, $reply: ident) => {
         tracing::trace!("receiving response");
-        // let $response = run_async_blocking($rx.recv());
-
-        let sync_code = std::thread::spawn(move || $rx.blocking_recv());
-        let $response = sync_code.join().unwrap();
+        let $response = $crate::async_helper::run_async_blocking($rx.recv());
         tracing::trace!("received response");
         // $rx.close();
         // tracing::info!("closed receiver");
@@ -43,9 +40,7 @@ macro_rules! send_request {
     ($tx: expr, $data:ident, $reply: ident) => {
         tracing::trace!("sending request");
         {
-            let sender = $tx.clone();
-            let send_res = std::thread::spawn(move || sender.blocking_send($data));
-            let send_res = send_res.join().unwrap();
+            let send_res = $crate::async_helper::run_async_blocking($tx.send($data));
             reply_error_e_consuming!(
                 send_res,
                 $reply,
@@ -14,6 +14,11 @@ macro_rules! send_error_response {
         }
         Ok(())
     }};
+    ($request:ident, $e:expr) => {{
+        let provider_error: $crate::fs::drive_file_provider::ProviderError = $e;
+        let code = provider_error.errno();
+        send_error_response!($request, anyhow::Error::from(provider_error), code)
+    }};
 }
 #[macro_export]
 macro_rules! send_response {
@@ -1,8 +1,33 @@
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use drive_syncer::{
+    config::{
+        conflict_name::ConflictNameTemplate, path_filter::PathFilter, ttl_overrides::TtlOverrides,
+    },
+    fs::drive_file_provider::PushSettings,
+    google_drive::DriveId,
+    RunConfig,
+};
+
 #[tokio::main]
 async fn main() {
     // drive_syncer::init_logger();
-    init_tracing();
-    sample_logging().await;
+    let log_options = match parse_log_options(std::env::args().skip(1)) {
+        Ok(log_options) => log_options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    init_tracing(log_options);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(download) = drive_syncer::download::parse_download_args(&args) {
+        if let Err(e) = run_download(download).await {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
     // drive_syncer::sample().await.unwrap();
     // drive_syncer::google_drive::sample().await.unwrap();
     // drive_syncer::watch_file_reading().await.unwrap();
@@ -12,47 +37,401 @@ async fn main() {
 
     // drive_syncer::sample_drive_fs().await.unwrap();
     // drive_syncer::sample_drive2_fs().await.unwrap();
-    drive_syncer::sample_drive2().await.unwrap();
-}
-
-fn init_tracing() {
-    // use tracing::Level;
-    // use tracing_subscriber::fmt;
-    // use tracing_subscriber::EnvFilter;
-    // // Create a new subscriber with the default configuration
-    // let subscriber = fmt::Subscriber::builder()
-    //
-    //     // .with_thread_ids(true)
-    //     .with_env_filter(EnvFilter::from_default_env())
-    //     .with_max_level(Level::DEBUG)
-    //     .with_line_number(true)
-    //     .with_target(true)
-    //     .with_file(true)
-    //     // .with_span_events(fmt::format::FmtSpan::NONE)
-    //     .finish();
-    //
-    // // Install the subscriber as the default for this thread
-    // tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
-    console_subscriber::init();
+    // drive_syncer::sample_drive2().await.unwrap();
+    let config = match parse_run_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = drive_syncer::run(config).await {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    }
+}
+
+const RUN_USAGE: &str = "usage: drive_syncer <mountpoint> <cache dir> <perma dir> [options]
+       drive_syncer download <path or id> <target>";
+
+/// reads `<mountpoint> <cache dir> <perma dir>` and the options after them.
+/// The cache dir should stay the same between runs, so the files do not have
+/// to be downloaded again and --verify-cache has something to check
+fn parse_run_config(args: &[String]) -> Result<RunConfig, String> {
+    let mut args = args.iter().cloned();
+    let mut paths = Vec::new();
+    // the log options can come before the paths, like before download
+    while paths.len() < 3 {
+        let arg = args.next().ok_or(RUN_USAGE)?;
+        if arg == "--log-format" {
+            args.next();
+        } else if arg.starts_with("--log-format=") || arg == "--tokio-console" {
+            continue;
+        } else if arg.starts_with("--") {
+            return Err(RUN_USAGE.to_string());
+        } else {
+            paths.push(arg);
+        }
+    }
+    let mut config = RunConfig::new(&paths[0], &paths[1], &paths[2]);
+    parse_run_options(args, &mut config)?;
+    Ok(config)
+}
+
+/// downloads one file with the default auth files
+async fn run_download(
+    download: anyhow::Result<(String, std::path::PathBuf)>,
+) -> anyhow::Result<()> {
+    let (source, target) = download?;
+    let drive = drive_syncer::google_drive::GoogleDrive::from_auth_files(
+        drive_syncer::DEFAULT_CLIENT_SECRET_PATH,
+        drive_syncer::DEFAULT_TOKENS_PATH,
+    )
+    .await?;
+    drive_syncer::download::download(&drive, &source, &target).await?;
+    println!("downloaded {} to {}", source, target.display());
+    Ok(())
+}
+
+/// reads the options of the mount into `config`, each one is `--name value` or
+/// `--name=value`. The options of the logging are skipped, they are read by
+/// [parse_log_options]
+fn parse_run_options(
+    mut args: impl Iterator<Item = String>,
+    config: &mut RunConfig,
+) -> Result<(), String> {
+    let (mut include, mut exclude, mut ttl_rules) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut push_url, mut push_listen) = (None, None);
+    while let Some(arg) = args.next() {
+        let (name, mut inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                (name.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .take()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match name.as_str() {
+            // the folder gets pinned after the mount
+            "--prefetch" => config.prefetch = Some(PathBuf::from(value()?)),
+            "--verify-cache" => config.verify_cache = true,
+            "--offline" => config.offline = true,
+            "--include" => include.push(value()?),
+            "--exclude" => exclude.push(value()?),
+            "--quota-cache-time" => config.quota_cache_time = Some(parse_secs(&name, &value()?)?),
+            "--refresh-folders" => config.refresh_folders_on_read_dir = true,
+            "--push-url" => push_url = Some(value()?),
+            "--push-listen" => {
+                let address = value()?;
+                let address = address
+                    .parse::<SocketAddr>()
+                    .map_err(|e| format!("invalid --push-listen address '{}': {}", address, e))?;
+                push_listen = Some(address);
+            }
+            "--open-cache-mode" => config.open_cache_mode = value()?.parse()?,
+            "--deletion-policy" => config.deletion_policy = value()?.parse()?,
+            // like `{stem} (conflict {date}){ext}`, checked before anything gets mounted
+            "--conflict-name" => {
+                config.conflict_name_template = ConflictNameTemplate::new(&value()?)
+                    .map_err(|e| format!("invalid --conflict-name: {:#}", e))?;
+            }
+            // `<glob>=<seconds>`, the first matching rule wins
+            "--ttl" => {
+                let rule = value()?;
+                let (pattern, secs) = rule
+                    .rsplit_once('=')
+                    .ok_or_else(|| format!("--ttl needs '<glob>=<seconds>', got '{}'", rule))?;
+                ttl_rules.push((pattern.to_string(), parse_secs(&name, secs)?));
+            }
+            "--include-trashed" => config.include_trashed = true,
+            "--include-computers" => config.include_computers = true,
+            "--root-folder" => config.root_folder = Some(DriveId::from(value()?)),
+            "--root-label" => config.root_label = Some(value()?),
+            "--dedupe-cache" => config.dedupe_cache = true,
+            "--label" => config.label = Some(value()?),
+            "--log-format" => {
+                value()?;
+            }
+            "--tokio-console" => {}
+            _ => return Err(format!("unknown argument '{}'\n{}", name, RUN_USAGE)),
+        }
+    }
+    if !include.is_empty() || !exclude.is_empty() {
+        let include: Vec<&str> = include.iter().map(String::as_str).collect();
+        let exclude: Vec<&str> = exclude.iter().map(String::as_str).collect();
+        config.path_filter = PathFilter::new(&include, &exclude)
+            .map_err(|e| format!("invalid --include or --exclude: {:#}", e))?;
+    }
+    if !ttl_rules.is_empty() {
+        let rules: Vec<(&str, Duration)> = ttl_rules
+            .iter()
+            .map(|(pattern, ttl)| (pattern.as_str(), *ttl))
+            .collect();
+        config.ttl_overrides =
+            TtlOverrides::new(&rules).map_err(|e| format!("invalid --ttl: {:#}", e))?;
+    }
+    match (push_url, push_listen) {
+        (Some(url), Some(address)) => config.push_settings = Some(PushSettings::new(url, address)),
+        (None, None) => {}
+        _ => return Err("--push-url and --push-listen have to be used together".to_string()),
+    }
+    Ok(())
+}
+
+fn parse_secs(name: &str, value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .map(Duration::from_secs)
+        .map_err(|_| format!("{} needs a number of seconds, got '{}'", name, value))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum LogFormat {
+    /// the human readable format
+    #[default]
+    Human,
+    /// one json object per line, for log aggregators
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(LogFormat::Human),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "invalid log format '{}', expected 'human' or 'json'",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct LogOptions {
+    format: LogFormat,
+    /// use the tokio-console subscriber instead of the normal one
+    tokio_console: bool,
+}
+
+/// reads the `--log-format <human|json>` and `--tokio-console` options from the arguments
+fn parse_log_options(mut args: impl Iterator<Item = String>) -> Result<LogOptions, String> {
+    let mut options = LogOptions::default();
+    while let Some(arg) = args.next() {
+        if arg == "--log-format" {
+            let value = args.next().ok_or("--log-format needs a value")?;
+            options.format = value.parse()?;
+        } else if let Some(value) = arg.strip_prefix("--log-format=") {
+            options.format = value.parse()?;
+        } else if arg == "--tokio-console" {
+            options.tokio_console = true;
+        }
+    }
+    Ok(options)
+}
+
+fn init_tracing(options: LogOptions) {
+    use tracing::metadata::LevelFilter;
+    use tracing_subscriber::EnvFilter;
+
+    if options.tokio_console {
+        #[cfg(feature = "tokio-console")]
+        {
+            console_subscriber::init();
+            tracing::info!("tracing initialized with tokio-console");
+            return;
+        }
+        #[cfg(not(feature = "tokio-console"))]
+        eprintln!("--tokio-console needs the 'tokio-console' feature, using the normal logging");
+    }
+    use tracing_subscriber::{fmt, prelude::*, reload};
+
+    // RUST_LOG decides the verbosity, without it everything from info up gets logged
+    let env_filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    let initial_level = env_filter.max_level_hint().unwrap_or(LevelFilter::INFO);
+    let (env_filter, reload_handle) = reload::Layer::new(env_filter);
+    let layer = fmt::layer()
+        .with_line_number(true)
+        .with_target(true)
+        .with_file(true);
+    let layer = match options.format {
+        LogFormat::Human => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(layer)
+        .init();
+    // a new level replaces what RUST_LOG set
+    let reloaded = drive_syncer::log_level::set_reloader(initial_level, move |level| {
+        let filter = EnvFilter::builder()
+            .with_default_directive(level.into())
+            .parse_lossy("");
+        Ok(reload_handle.reload(filter)?)
+    });
+    if let Err(e) = reloaded {
+        tracing::warn!("the log level can not be changed at runtime: {:?}", e);
+    }
     tracing::info!("tracing initialized");
 }
 
-#[tracing::instrument]
-async fn sample_logging() {
-    use tracing::{debug, error, info, trace, warn};
-    info!("info");
-    debug!("debug");
-    let s = tracing::span!(
-        tracing::Level::TRACE,
-        "span around trace and warn with stdin read"
-    );
-    {
-        let _x = s.enter();
-        trace!("trace");
-        // let mut string = [0u8; 1];
-        // info!("press any key to continue");
-        // tokio::io::stdin().read(&mut string).await.expect("failed to read stdin");
-        warn!("warn");
-    }
-    error!("error");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn log_format(arguments: &[&str]) -> Result<LogFormat, String> {
+        parse_log_options(args(arguments)).map(|o| o.format)
+    }
+
+    #[test]
+    fn log_format_parsing() {
+        assert_eq!(log_format(&[]), Ok(LogFormat::Human));
+        assert_eq!(log_format(&["--log-format", "json"]), Ok(LogFormat::Json));
+        assert_eq!(log_format(&["--log-format=human"]), Ok(LogFormat::Human));
+        assert!(log_format(&["--log-format", "xml"]).is_err());
+        assert!(log_format(&["--log-format"]).is_err());
+    }
+
+    fn run_config(arguments: &[&str]) -> Result<RunConfig, String> {
+        let mut config = RunConfig::new("mnt", "cache", "perma");
+        parse_run_options(args(arguments), &mut config).map(|_| config)
+    }
+
+    #[test]
+    fn run_options_parsing() {
+        let config = run_config(&[]).unwrap();
+        assert!(config.prefetch.is_none() && !config.offline && !config.dedupe_cache);
+        assert!(config.push_settings.is_none());
+
+        let config = run_config(&[
+            "--log-format",
+            "json",
+            "--prefetch",
+            "docs",
+            "--offline",
+            "--verify-cache",
+            "--quota-cache-time=60",
+            "--refresh-folders",
+            "--open-cache-mode",
+            "direct-io",
+            "--deletion-policy=hard",
+            "--conflict-name={stem}.{host}{ext}",
+            "--include-trashed",
+            "--include-computers",
+            "--root-folder",
+            "abc",
+            "--root-label",
+            "work",
+            "--dedupe-cache",
+            "--label=main",
+            "--push-url",
+            "https://example.com/push",
+            "--push-listen",
+            "127.0.0.1:8080",
+        ])
+        .unwrap();
+        assert_eq!(config.prefetch, Some(PathBuf::from("docs")));
+        assert!(config.offline && config.verify_cache && config.refresh_folders_on_read_dir);
+        assert_eq!(config.quota_cache_time, Some(Duration::from_secs(60)));
+        assert_eq!(
+            config.open_cache_mode,
+            drive_syncer::fs::drive_file_provider::OpenCacheMode::DirectIo
+        );
+        assert_eq!(
+            config.deletion_policy,
+            drive_syncer::fs::drive_file_provider::DeletionPolicy::Hard
+        );
+        assert_eq!(
+            config.conflict_name_template,
+            ConflictNameTemplate::new("{stem}.{host}{ext}").unwrap()
+        );
+        assert!(config.include_trashed && config.include_computers && config.dedupe_cache);
+        assert_eq!(config.root_folder, Some(DriveId::from("abc")));
+        assert_eq!(config.root_label.as_deref(), Some("work"));
+        assert_eq!(config.label.as_deref(), Some("main"));
+        let push = config.push_settings.unwrap();
+        assert_eq!(push.callback_url, "https://example.com/push");
+        assert_eq!(push.listen_address, "127.0.0.1:8080".parse().unwrap());
+    }
+
+    #[test]
+    fn path_options_parsing() {
+        let config = run_config(&[
+            "--include",
+            "docs/**",
+            "--exclude=docs/tmp",
+            "--ttl",
+            "docs/**=3600",
+        ])
+        .unwrap();
+        assert!(config
+            .path_filter
+            .is_included(std::path::Path::new("docs/a.txt"), false));
+        assert!(!config
+            .path_filter
+            .is_included(std::path::Path::new("docs/tmp"), true));
+        assert!(!config
+            .path_filter
+            .is_included(std::path::Path::new("music/a.mp3"), false));
+        assert_eq!(
+            config
+                .ttl_overrides
+                .ttl_for(std::path::Path::new("docs/a.txt")),
+            Some(Duration::from_secs(3600))
+        );
+    }
+
+    #[test]
+    fn run_paths_parsing() {
+        let arguments: Vec<String> =
+            args(&["--log-format", "json", "mnt", "cache", "perma", "--offline"]).collect();
+        let config = parse_run_config(&arguments).unwrap();
+        assert_eq!(config.mountpoint, PathBuf::from("mnt"));
+        assert_eq!(config.cache_dir, PathBuf::from("cache"));
+        assert_eq!(config.perma_dir, PathBuf::from("perma"));
+        assert!(config.offline);
+
+        let parse = |arguments: &[&str]| parse_run_config(&args(arguments).collect::<Vec<_>>());
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["mnt", "cache"]).is_err());
+        assert!(parse(&["--offline", "mnt", "cache", "perma"]).is_err());
+        assert!(parse(&["mnt", "cache", "perma", "extra"]).is_err());
+    }
+
+    #[test]
+    fn invalid_run_options_are_rejected() {
+        assert!(run_config(&["--quota-cache-time", "soon"]).is_err());
+        assert!(run_config(&["--open-cache-mode", "never"]).is_err());
+        assert!(run_config(&["--deletion-policy"]).is_err());
+        assert!(run_config(&["--conflict-name", "{date}{ext}"]).is_err());
+        assert!(run_config(&["--ttl", "docs"]).is_err());
+        assert!(run_config(&["--include", "["]).is_err());
+        assert!(run_config(&["--push-url", "https://example.com/push"]).is_err());
+        assert!(run_config(&["--push-listen", "nowhere"]).is_err());
+        assert!(run_config(&["--unknown"]).is_err());
+    }
+
+    #[test]
+    fn tokio_console_flag_parsing() {
+        let options = parse_log_options(args(&[])).unwrap();
+        assert!(!options.tokio_console);
+        let options = parse_log_options(args(&["--tokio-console", "--log-format=json"])).unwrap();
+        assert!(options.tokio_console);
+        assert_eq!(options.format, LogFormat::Json);
+    }
 }
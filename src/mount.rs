@@ -0,0 +1,585 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use fuser::{MountOption, Session};
+use futures::future::select_all;
+use google_drive3::api::StartPageToken;
+use tokio::{
+    select,
+    signal::unix::{signal, SignalKind},
+    sync::{
+        mpsc::{channel, Sender},
+        oneshot,
+    },
+    task::JoinHandle,
+};
+use tracing::{debug, error, info, instrument, level_filters::LevelFilter};
+
+use crate::{
+    config::conflict_name::ConflictNameTemplate,
+    config::no_cache::NoCachePaths,
+    config::path_filter::PathFilter,
+    config::ttl_overrides::TtlOverrides,
+    config::upload_windows::UploadWindows,
+    fs::drive::SyncSettings,
+    fs::drive2,
+    fs::drive_file_provider::{
+        run_push_watcher, DeletionPolicy, DriveFileProvider, HealthStatus, NativeFilePolicy,
+        OpenCacheMode, ProviderCommand, ProviderStats, PushSettings, TransferInfo,
+    },
+    fs::next_mount_generation,
+    google_drive::{DriveId, GoogleDrive},
+    log_level,
+    prelude::*,
+};
+
+/// the subtype of the mounts and the start of their names in `/proc/mounts`
+pub const FS_NAME: &str = "drive_syncer";
+
+/// One drive mounted at one mountpoint.
+///
+/// Every mount gets its own provider, filesystem and channels, so multiple
+/// mounts (for example for different accounts) can run in the same process.
+#[derive(Debug)]
+pub struct Mount {
+    drive: GoogleDrive,
+    mountpoint: PathBuf,
+    cache_dir: PathBuf,
+    perma_dir: PathBuf,
+    settings: SyncSettings,
+    /// `None` if the changes should only be polled
+    push_settings: Option<PushSettings>,
+    open_cache_mode: OpenCacheMode,
+    deletion_policy: DeletionPolicy,
+    conflict_name_template: ConflictNameTemplate,
+    native_file_policy: NativeFilePolicy,
+    ttl_overrides: TtlOverrides,
+    path_filter: PathFilter,
+    /// `None` keeps the default of the provider
+    quota_cache_time: Option<Duration>,
+    refresh_folders_on_read_dir: bool,
+    /// `None` keeps the default of the provider
+    max_concurrent_reads: Option<usize>,
+    include_trashed: bool,
+    include_computers: bool,
+    /// `None` shows the whole drive
+    root_folder: Option<DriveId>,
+    root_label: Option<String>,
+    /// tells the mounts apart in `/proc/mounts`, see [Mount::set_label]
+    label: Option<String>,
+    partial_downloads: bool,
+    no_cache: NoCachePaths,
+    dedupe_cache: bool,
+    follow_changes_of_others: bool,
+    show_revisions: bool,
+    upload_windows: UploadWindows,
+    /// the folder that gets pinned after the mount, see [Mount::set_prefetch]
+    prefetch: Option<PathBuf>,
+    hashed_inos: bool,
+    verify_cache: bool,
+    offline: bool,
+}
+
+/// A [Mount] whose filesystem and provider are running.
+#[derive(Debug)]
+pub struct RunningMount {
+    mountpoint: PathBuf,
+    filesystem_handle: JoinHandle<()>,
+    /// the filesystem handle can only be awaited once
+    filesystem_finished: bool,
+    provider_handle: JoinHandle<()>,
+    provider_command_tx: Sender<ProviderCommand>,
+    /// the push watcher and the sender that tells it to stop
+    push_watcher: Option<(JoinHandle<()>, oneshot::Sender<()>)>,
+}
+
+impl Mount {
+    pub fn new(
+        drive: GoogleDrive,
+        mountpoint: impl Into<PathBuf>,
+        cache_dir: impl Into<PathBuf>,
+        perma_dir: impl Into<PathBuf>,
+        settings: SyncSettings,
+    ) -> Self {
+        Self {
+            drive,
+            mountpoint: mountpoint.into(),
+            cache_dir: cache_dir.into(),
+            perma_dir: perma_dir.into(),
+            settings,
+            push_settings: None,
+            open_cache_mode: OpenCacheMode::default(),
+            deletion_policy: DeletionPolicy::default(),
+            conflict_name_template: ConflictNameTemplate::default(),
+            native_file_policy: NativeFilePolicy::default(),
+            ttl_overrides: TtlOverrides::default(),
+            path_filter: PathFilter::default(),
+            quota_cache_time: None,
+            refresh_folders_on_read_dir: false,
+            max_concurrent_reads: None,
+            include_trashed: false,
+            include_computers: false,
+            root_folder: None,
+            root_label: None,
+            label: None,
+            partial_downloads: false,
+            no_cache: NoCachePaths::default(),
+            dedupe_cache: false,
+            follow_changes_of_others: false,
+            show_revisions: false,
+            upload_windows: UploadWindows::default(),
+            prefetch: None,
+            hashed_inos: false,
+            verify_cache: false,
+            offline: false,
+        }
+    }
+    /// lets google drive notify this mount about changes, see [PushSettings]
+    pub fn set_push_settings(&mut self, push_settings: PushSettings) {
+        self.push_settings = Some(push_settings);
+    }
+    /// sets how the kernel caches the content of opened files, see [OpenCacheMode]
+    pub fn set_open_cache_mode(&mut self, open_cache_mode: OpenCacheMode) {
+        self.open_cache_mode = open_cache_mode;
+    }
+    /// sets if removed files and folders go to the trash or get deleted, see [DeletionPolicy]
+    pub fn set_deletion_policy(&mut self, deletion_policy: DeletionPolicy) {
+        self.deletion_policy = deletion_policy;
+    }
+    /// sets how the copy of the local content is named when a file changed
+    /// locally and on the remote, see [ConflictNameTemplate]
+    pub fn set_conflict_name_template(&mut self, conflict_name_template: ConflictNameTemplate) {
+        self.conflict_name_template = conflict_name_template;
+    }
+    /// sets how the google docs, sheets etc. show up, see [NativeFilePolicy]
+    pub fn set_native_file_policy(&mut self, native_file_policy: NativeFilePolicy) {
+        self.native_file_policy = native_file_policy;
+    }
+    /// makes the files in the trash show up in their folders
+    pub fn set_include_trashed(&mut self, include_trashed: bool) {
+        self.include_trashed = include_trashed;
+    }
+    /// makes the backed up computers show up as read only top level folders
+    pub fn set_include_computers(&mut self, include_computers: bool) {
+        self.include_computers = include_computers;
+    }
+    /// applies the changes to files other users own, by default those are skipped
+    pub fn set_follow_changes_of_others(&mut self, follow_changes_of_others: bool) {
+        self.follow_changes_of_others = follow_changes_of_others;
+    }
+    /// makes the revisions of a file readable in the folder `<name>@revisions`,
+    /// each listing of one is an extra request to the drive
+    pub fn set_show_revisions(&mut self, show_revisions: bool) {
+        self.show_revisions = show_revisions;
+    }
+    /// lets the uploads of larger files wait for more changes, see [UploadWindows]
+    pub fn set_upload_windows(&mut self, upload_windows: UploadWindows) {
+        self.upload_windows = upload_windows;
+    }
+    /// pins every file in the folder after the mount, so it is available offline.
+    /// `folder` is the path from the root of the mount, see [DriveFileProvider::prefetch]
+    pub fn set_prefetch(&mut self, folder: impl Into<PathBuf>) {
+        self.prefetch = Some(folder.into());
+    }
+    /// derives the inos from the ids of the files, so they stay the same across
+    /// mounts, see [DriveFilesystem::set_hashed_inos](crate::fs::drive2::DriveFilesystem::set_hashed_inos)
+    pub fn set_hashed_inos(&mut self, hashed_inos: bool) {
+        self.hashed_inos = hashed_inos;
+    }
+    /// only downloads the parts of files that get read, instead of whole files on open
+    pub fn set_partial_downloads(&mut self, partial_downloads: bool) {
+        self.partial_downloads = partial_downloads;
+    }
+    /// reads the matching files straight from the drive, they never get cached.
+    /// `**` matches every file, see [NoCachePaths]
+    pub fn set_no_cache(&mut self, no_cache: NoCachePaths) {
+        self.no_cache = no_cache;
+    }
+    /// checks the files a previous run left in the cache on startup, the
+    /// corrupt ones get downloaded again. See [DriveFileProvider::verify_cache]
+    pub fn set_verify_cache(&mut self, verify_cache: bool) {
+        self.verify_cache = verify_cache;
+    }
+    /// starts without the network, with the entries the last run saved. See
+    /// [DriveFileProvider::set_offline] and [RunningMount::set_offline]
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+    /// lets cached files with the same content share one copy on the disk
+    pub fn set_dedupe_cache(&mut self, dedupe_cache: bool) {
+        self.dedupe_cache = dedupe_cache;
+    }
+    /// mounts only this folder, the mountpoint gets its times
+    pub fn set_root_folder(&mut self, root_folder: DriveId) {
+        self.root_folder = Some(root_folder);
+    }
+    /// the name the root of the mount is shown with, instead of the name of the folder
+    pub fn set_root_label(&mut self, root_label: impl Into<String>) {
+        self.root_label = Some(root_label.into());
+    }
+    /// the mount shows up as `drive_syncer:<label>` in `mount` and `/proc/mounts`,
+    /// without one the root label is used
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = Some(label.into());
+    }
+    fn fs_name(&self) -> String {
+        match self.label.as_ref().or(self.root_label.as_ref()) {
+            // the options are passed on separated by commas
+            Some(label) => format!(
+                "{}:{}",
+                FS_NAME,
+                label.replace(|c: char| c == ',' || c.is_whitespace(), "_")
+            ),
+            None => FS_NAME.to_string(),
+        }
+    }
+    /// sets how long the kernel may cache the attributes of matching entries, see [TtlOverrides]
+    pub fn set_ttl_overrides(&mut self, ttl_overrides: TtlOverrides) {
+        self.ttl_overrides = ttl_overrides;
+    }
+    /// only shows and uploads the entries the filter includes, see [PathFilter]
+    pub fn set_path_filter(&mut self, path_filter: PathFilter) {
+        self.path_filter = path_filter;
+    }
+    /// sets how long the fetched storage quota is used before it gets fetched again
+    pub fn set_quota_cache_time(&mut self, quota_cache_time: Duration) {
+        self.quota_cache_time = Some(quota_cache_time);
+    }
+    /// lists a folder on the remote again every time it is read, instead of
+    /// waiting for the changes to come in
+    pub fn set_refresh_folders_on_read_dir(&mut self, refresh: bool) {
+        self.refresh_folders_on_read_dir = refresh;
+    }
+    /// sets how long a call to google drive may wait for an answer, see
+    /// [GoogleDrive::set_request_timeout]
+    pub fn set_request_timeout(&mut self, request_timeout: Duration) {
+        self.drive.set_request_timeout(request_timeout);
+    }
+    /// sets how many reads of different files can run at the same time
+    pub fn set_max_concurrent_reads(&mut self, max_concurrent_reads: usize) {
+        self.max_concurrent_reads = Some(max_concurrent_reads);
+    }
+
+    /// mounts the filesystem and starts the provider that serves it
+    #[instrument(skip(self), fields(mountpoint = % self.mountpoint.display()))]
+    pub async fn start(self) -> Result<RunningMount> {
+        let (provider_command_tx, provider_command_rx) = channel(1);
+        let (provider_request_tx, provider_request_rx) = channel(1);
+        let (changed_ids_tx, changed_ids_rx) = std::sync::mpsc::channel();
+        let changes_start_token = if self.offline {
+            if !DriveFileProvider::can_start_offline(&self.perma_dir) {
+                return Err(anyhow!(
+                    "starting offline needs the entries of an earlier run in {}",
+                    self.perma_dir.display()
+                ));
+            }
+            // the provider continues with the one the entries were saved with
+            StartPageToken::default()
+        } else {
+            self.drive
+                .get_start_page_token()
+                .await
+                .context("could not initialize the changes api start page token")?
+        };
+
+        let mut filesystem = drive2::DriveFilesystem::new(provider_request_tx, changed_ids_rx);
+        filesystem.set_mount_generation(next_mount_generation(&self.cache_dir));
+        filesystem.set_hashed_inos(self.hashed_inos);
+        let provider_closed = filesystem.provider_closed();
+        let mount_options = vec![
+            MountOption::RW, /*TODO: make a start parameter that can change the mount to read only*/
+            MountOption::FSName(self.fs_name()),
+            MountOption::Subtype(FS_NAME.to_string()),
+        ];
+        let mut session = Session::new(filesystem, &self.mountpoint, &mount_options)?;
+        let mut unmount_callable = session.unmount_callable();
+        let (session_ended_tx, session_ended_rx) = oneshot::channel::<()>();
+        // the session blocks the thread it runs on until it gets unmounted
+        let filesystem_handle = tokio::task::spawn_blocking(move || {
+            let mount_res = session.run();
+            debug!("mount finished with result: {:?}", mount_res);
+            if let Err(e) = mount_res {
+                error!("mount finished with error: {:?}", e);
+            }
+            drop(session_ended_tx);
+        });
+
+        let push_watcher = self.push_settings.map(|push_settings| {
+            let (shutdown_tx, shutdown_rx) = oneshot::channel();
+            let handle = tokio::spawn(run_push_watcher(
+                self.drive.clone(),
+                push_settings,
+                provider_command_tx.clone(),
+                shutdown_rx,
+            ));
+            (handle, shutdown_tx)
+        });
+        let mut provider = DriveFileProvider::new(
+            self.drive,
+            self.cache_dir,
+            self.perma_dir,
+            changes_start_token,
+            self.settings.cache_time(),
+            Some(changed_ids_tx),
+        );
+        provider.set_open_cache_mode(self.open_cache_mode);
+        provider.set_deletion_policy(self.deletion_policy);
+        provider.set_conflict_name_template(self.conflict_name_template);
+        provider.set_native_file_policy(self.native_file_policy);
+        provider.set_ttl_overrides(self.ttl_overrides);
+        provider.set_path_filter(self.path_filter);
+        if let Some(quota_cache_time) = self.quota_cache_time {
+            provider.set_quota_cache_time(quota_cache_time);
+        }
+        provider.set_refresh_folders_on_read_dir(self.refresh_folders_on_read_dir);
+        provider.set_include_trashed(self.include_trashed);
+        provider.set_include_computers(self.include_computers);
+        provider.set_follow_changes_of_others(self.follow_changes_of_others);
+        provider.set_show_revisions(self.show_revisions);
+        provider.set_upload_windows(self.upload_windows);
+        provider.set_partial_downloads(self.partial_downloads);
+        provider.set_blksize(self.settings.blksize());
+        provider.set_no_cache(self.no_cache);
+        provider.set_dedupe_cache(self.dedupe_cache);
+        provider.set_verify_cache(self.verify_cache);
+        if self.offline {
+            provider.set_offline(true).await;
+        }
+        if let Some(root_folder) = self.root_folder {
+            provider.set_root_folder(root_folder);
+        }
+        provider.set_root_label(self.root_label);
+        if let Some(max_concurrent_reads) = self.max_concurrent_reads {
+            provider.set_max_concurrent_reads(max_concurrent_reads);
+        }
+        let provider_handle = tokio::spawn(async move {
+            provider
+                .listen(provider_request_rx, provider_command_rx)
+                .await;
+        });
+        if let Some(folder) = self.prefetch {
+            // the provider knows the entries before it handles the command
+            provider_command_tx
+                .send(ProviderCommand::Prefetch { folder })
+                .await
+                .map_err(|_| anyhow!("the provider is not listening for commands anymore"))?;
+        }
+        // also unmounts if the provider panicked, instead of leaving a mount
+        // behind that fails every request
+        tokio::spawn(drive2::unmount_when_provider_closed(
+            provider_closed,
+            async {
+                let _ = session_ended_rx.await;
+            },
+            move || {
+                if let Err(e) = unmount_callable.unmount() {
+                    error!("failed to unmount: {:?}", e);
+                }
+            },
+        ));
+
+        Ok(RunningMount {
+            mountpoint: self.mountpoint,
+            filesystem_handle,
+            filesystem_finished: false,
+            provider_handle,
+            provider_command_tx,
+            push_watcher,
+        })
+    }
+}
+
+impl RunningMount {
+    /// asks the provider of this mount how it is doing
+    pub async fn health_check(&self) -> Result<HealthStatus> {
+        let (reply, status) = tokio::sync::oneshot::channel();
+        self.provider_command_tx
+            .send(ProviderCommand::HealthCheck { reply })
+            .await
+            .map_err(|_| anyhow!("the provider is not listening for commands anymore"))?;
+        Ok(status.await?)
+    }
+    /// asks the provider of this mount how many entries and handles it holds
+    pub async fn stats(&self) -> Result<ProviderStats> {
+        let (reply, stats) = tokio::sync::oneshot::channel();
+        self.provider_command_tx
+            .send(ProviderCommand::Stats { reply })
+            .await
+            .map_err(|_| anyhow!("the provider is not listening for commands anymore"))?;
+        Ok(stats.await?)
+    }
+    /// asks the provider of this mount for a summary of its internal state
+    pub async fn dump_state(&self, redact_ids: bool) -> Result<serde_json::Value> {
+        let (reply, state) = tokio::sync::oneshot::channel();
+        self.provider_command_tx
+            .send(ProviderCommand::DumpState { redact_ids, reply })
+            .await
+            .map_err(|_| anyhow!("the provider is not listening for commands anymore"))?;
+        Ok(state.await?)
+    }
+    /// changes how much gets logged, this is the same for all mounts of the process
+    pub async fn set_log_level(&self, level: LevelFilter) -> Result<()> {
+        self.provider_command_tx
+            .send(ProviderCommand::SetLogLevel(level))
+            .await
+            .map_err(|_| anyhow!("the provider is not listening for commands anymore"))
+    }
+    /// turns the offline mode of the provider on or off, going online uploads
+    /// what was changed while offline
+    pub async fn set_offline(&self, offline: bool) -> Result<()> {
+        self.send_command(ProviderCommand::SetOffline(offline))
+            .await
+    }
+    /// keeps the file available offline, it gets downloaded if it is not local yet
+    pub async fn pin(&self, file_id: DriveId) -> Result<()> {
+        self.send_command(ProviderCommand::Pin { file_id }).await
+    }
+    pub async fn unpin(&self, file_id: DriveId) -> Result<()> {
+        self.send_command(ProviderCommand::Unpin { file_id }).await
+    }
+    /// pins every file in the folder, see [DriveFileProvider::prefetch]
+    pub async fn prefetch(&self, folder: impl Into<PathBuf>) -> Result<()> {
+        let folder = folder.into();
+        self.send_command(ProviderCommand::Prefetch { folder })
+            .await
+    }
+    /// removes the cached content of the files that are not pinned or open
+    pub async fn evict_cache(&self) -> Result<()> {
+        self.send_command(ProviderCommand::EvictCache).await
+    }
+    /// the downloads and uploads that are running right now
+    pub async fn list_transfers(&self) -> Result<Vec<TransferInfo>> {
+        let (reply, transfers) = tokio::sync::oneshot::channel();
+        self.send_command(ProviderCommand::ListTransfers { reply })
+            .await?;
+        Ok(transfers.await?)
+    }
+    /// stops the download or upload of the file, the reads that wait for a
+    /// download fail
+    pub async fn cancel_transfer(&self, file_id: DriveId) -> Result<()> {
+        self.send_command(ProviderCommand::CancelTransfer { file_id })
+            .await
+    }
+    /// lets the provider use the drive from now on, for example after the user
+    /// logged in again. See [DriveFileProvider::reload_auth]
+    pub async fn reload_auth(&self, drive: GoogleDrive) -> Result<()> {
+        self.send_command(ProviderCommand::ReloadAuth {
+            drive: Box::new(drive),
+        })
+        .await
+    }
+    async fn send_command(&self, command: ProviderCommand) -> Result<()> {
+        self.provider_command_tx
+            .send(command)
+            .await
+            .map_err(|_| anyhow!("the provider is not listening for commands anymore"))
+    }
+    /// stops the provider, which unmounts the filesystem, and waits for both to finish
+    #[instrument(skip(self), fields(mountpoint = % self.mountpoint.display()))]
+    pub async fn stop(self) -> Result<()> {
+        if let Some((handle, shutdown)) = self.push_watcher {
+            let _ = shutdown.send(());
+            handle.await?;
+        }
+        let x = self.provider_command_tx.send(ProviderCommand::Stop).await;
+        info!("send stop to provider: {:?}", x);
+        self.provider_handle.await?;
+        if !self.filesystem_finished {
+            self.filesystem_handle.await?;
+        }
+        Ok(())
+    }
+}
+
+/// starts all mounts and runs them until the program gets a ctrl-c or any of
+/// the filesystems finishes (for example because it got unmounted from the outside).
+///
+/// After that all mounts are stopped and unmounted.
+///
+/// On SIGUSR1 the state of every provider is written to a json file in the
+/// temp dir, with the ids redacted, see [dump_states].
+///
+/// SIGUSR2 makes the logging more verbose, from `TRACE` it goes back to `INFO`.
+pub async fn run_mounts(mounts: Vec<Mount>) -> Result<()> {
+    let mut running_mounts = Vec::with_capacity(mounts.len());
+    for mount in mounts {
+        match mount.start().await {
+            Ok(running_mount) => running_mounts.push(running_mount),
+            Err(e) => {
+                error!("could not start mount: {:?}", e);
+                stop_all(running_mounts).await;
+                return Err(e);
+            }
+        }
+    }
+    if running_mounts.is_empty() {
+        return Ok(());
+    }
+
+    let mut dump_state_signal = signal(SignalKind::user_defined1())?;
+    let mut log_level_signal = signal(SignalKind::user_defined2())?;
+    loop {
+        let filesystem_handles = running_mounts.iter_mut().map(|m| &mut m.filesystem_handle);
+        select! {
+            (_, index, _) = select_all(filesystem_handles) => {
+                info!("filesystem of mount {} finished first!", index);
+                running_mounts[index].filesystem_finished = true;
+                break;
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("got signal to end program");
+                break;
+            },
+            _ = dump_state_signal.recv() => dump_states(&running_mounts).await,
+            _ = log_level_signal.recv() => {
+                let level = log_level::more_verbose(log_level::log_level());
+                // the level is the same for the whole process, one mount is enough
+                if let Err(e) = running_mounts[0].set_log_level(level).await {
+                    error!("could not change the log level: {:?}", e);
+                }
+            },
+        }
+    }
+    stop_all(running_mounts).await;
+    info!("all mounts finished");
+    Ok(())
+}
+
+/// writes the state of each mount to `drive_syncer_state_<pid>_<index>.json` in the temp dir
+async fn dump_states(running_mounts: &[RunningMount]) {
+    for (index, running_mount) in running_mounts.iter().enumerate() {
+        let path = std::env::temp_dir().join(format!(
+            "drive_syncer_state_{}_{}.json",
+            std::process::id(),
+            index
+        ));
+        let result = match running_mount.dump_state(true).await {
+            Ok(state) => tokio::fs::write(&path, format!("{:#}", state))
+                .await
+                .map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => info!(
+                "dumped the state of mount at {} to {}",
+                running_mount.mountpoint.display(),
+                path.display()
+            ),
+            Err(e) => error!(
+                "could not dump the state of mount at {}: {:?}",
+                running_mount.mountpoint.display(),
+                e
+            ),
+        }
+    }
+}
+
+async fn stop_all(running_mounts: Vec<RunningMount>) {
+    for running_mount in running_mounts {
+        let mountpoint = running_mount.mountpoint.clone();
+        if let Err(e) = running_mount.stop().await {
+            error!("could not stop mount at {}: {:?}", mountpoint.display(), e);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Checks that everything a mount needs is there before anything gets mounted.
+//!
+//! Without these the first run fails deep inside the fuse session or the
+//! login, with errors that don't say what is missing.
+
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tracing::{debug, instrument};
+
+use crate::google_drive::read_client_secret;
+use crate::prelude::*;
+use crate::RunConfig;
+
+const FUSE_DEVICE: &str = "/dev/fuse";
+/// the helpers that let users other than root mount, one of them has to be installed
+const FUSERMOUNT_BINARIES: &[&str] = &["fusermount3", "fusermount"];
+
+/// a prerequisite for mounting that is not met, each with what to do about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreflightError {
+    /// the kernel module is not loaded or the system has no fuse support
+    FuseMissing,
+    /// `/dev/fuse` exists, but the user is not allowed to open it
+    FuseNotAccessible,
+    /// the fuse package (with `fusermount`) is not installed
+    FusermountMissing,
+    MountpointMissing(PathBuf),
+    MountpointNotDirectory(PathBuf),
+    /// an earlier mount at the path ended without being unmounted
+    StaleMount(PathBuf),
+}
+
+impl Display for PreflightError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreflightError::FuseMissing => write!(
+                f,
+                "{} does not exist, load the fuse kernel module with `modprobe fuse`",
+                FUSE_DEVICE
+            ),
+            PreflightError::FuseNotAccessible => write!(
+                f,
+                "{} can not be opened, add the user to the group that owns it \
+                 (usually `fuse`) and log in again",
+                FUSE_DEVICE
+            ),
+            PreflightError::FusermountMissing => write!(
+                f,
+                "fusermount was not found, install the fuse3 (or fuse) package"
+            ),
+            PreflightError::MountpointMissing(path) => write!(
+                f,
+                "the mountpoint {} does not exist, create the folder first",
+                path.display()
+            ),
+            PreflightError::MountpointNotDirectory(path) => {
+                write!(f, "the mountpoint {} is not a folder", path.display())
+            }
+            PreflightError::StaleMount(path) => write!(
+                f,
+                "an earlier mount at {} was not unmounted, run `fusermount -u {}` first",
+                path.display(),
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PreflightError {}
+
+/// checks the mountpoint, the credentials and the fuse setup, so [crate::run]
+/// can fail right away with an error that says what to do
+#[instrument(skip(config))]
+pub async fn preflight(config: &RunConfig) -> Result<()> {
+    check_mountpoint(&config.mountpoint)?;
+    read_client_secret(&config.client_secret_path).await?;
+    check_fuse()?;
+    debug!("everything needed for the mount is there");
+    Ok(())
+}
+
+fn check_mountpoint(mountpoint: &Path) -> Result<(), PreflightError> {
+    match std::fs::metadata(mountpoint) {
+        Ok(metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Err(PreflightError::MountpointNotDirectory(
+            mountpoint.to_path_buf(),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(PreflightError::MountpointMissing(mountpoint.to_path_buf()))
+        }
+        // the kernel answers like this for a mount whose filesystem is gone
+        Err(e) if e.raw_os_error() == Some(libc::ENOTCONN) => {
+            Err(PreflightError::StaleMount(mountpoint.to_path_buf()))
+        }
+        // anything else shows up again when mounting, with a better error
+        Err(_) => Ok(()),
+    }
+}
+
+fn check_fuse() -> Result<(), PreflightError> {
+    if let Err(e) = OpenOptions::new().read(true).write(true).open(FUSE_DEVICE) {
+        return Err(match e.kind() {
+            io::ErrorKind::NotFound => PreflightError::FuseMissing,
+            _ => PreflightError::FuseNotAccessible,
+        });
+    }
+    // root mounts directly, everybody else goes through fusermount
+    let is_root = unsafe { libc::geteuid() } == 0;
+    if !is_root && !FUSERMOUNT_BINARIES.iter().any(|name| is_in_path(name)) {
+        return Err(PreflightError::FusermountMissing);
+    }
+    Ok(())
+}
+
+fn is_in_path(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(name).is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::google_drive::CredentialsError;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn missing_mountpoint_and_credentials_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("mnt");
+        let mut config = RunConfig::new(&missing, dir.path(), dir.path());
+        config.client_secret_path = dir.path().join("client_secret.json");
+
+        let e = preflight(&config).await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<PreflightError>(),
+            Some(&PreflightError::MountpointMissing(missing.clone()))
+        );
+
+        std::fs::create_dir(&missing).unwrap();
+        let e = preflight(&config).await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<CredentialsError>(),
+            Some(&CredentialsError::MissingClientSecret(
+                config.client_secret_path.clone()
+            ))
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Mounts a drive backed by a [MockDrive] in a temp dir and uses it through
+//! real syscalls, so the whole way from the kernel to the drive api gets tested.
+//!
+//! These need `/dev/fuse` and permission to mount, run them with
+//! `cargo test --features fuse-tests --test fuse`.
+#![cfg(feature = "fuse-tests")]
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use drive_syncer::fs::drive::SyncSettings;
+use drive_syncer::google_drive::mock::MockDrive;
+use drive_syncer::mount::{Mount, RunningMount};
+use tempfile::TempDir;
+
+/// how long the uploads get to show up on the mock drive
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Harness {
+    mock: MockDrive,
+    running: RunningMount,
+    mountpoint: PathBuf,
+    // has to outlive the mount
+    _dir: TempDir,
+}
+
+impl Harness {
+    async fn mount(mock: MockDrive) -> Self {
+        Self::mount_with(mock, |_| {}).await
+    }
+    async fn mount_with(mock: MockDrive, configure: impl FnOnce(&mut Mount)) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let mountpoint = dir.path().join("mnt");
+        let cache_dir = dir.path().join("cache");
+        let perma_dir = dir.path().join("perma");
+        for path in [&mountpoint, &cache_dir, &perma_dir] {
+            std::fs::create_dir(path).unwrap();
+        }
+        let settings = SyncSettings::new(Duration::from_secs(2), Duration::from_secs(2));
+        let mut mount = Mount::new(mock.drive(), &mountpoint, cache_dir, perma_dir, settings);
+        configure(&mut mount);
+        let running = mount
+            .start()
+            .await
+            .expect("could not mount, is /dev/fuse available?");
+        // the provider only answers once it has loaded all entries
+        running.stats().await.unwrap();
+        Self {
+            mock,
+            running,
+            mountpoint,
+            _dir: dir,
+        }
+    }
+    fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.mountpoint.join(path)
+    }
+    async fn unmount(self) {
+        self.running.stop().await.unwrap();
+    }
+}
+
+/// the filesystem calls block until the filesystem answers, which needs the runtime
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f).await.unwrap()
+}
+
+async fn wait_until(what: &str, mut condition: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(
+            start.elapsed() < UPLOAD_TIMEOUT,
+            "timed out waiting for {}",
+            what
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_files_and_lists_folders() {
+    let mock = MockDrive::start().await.unwrap();
+    let folder = mock.add_folder("folder", &mock.root_id());
+    mock.add_file("a.txt", &mock.root_id(), b"hello");
+    mock.add_file("b.txt", &folder, b"world");
+    let harness = Harness::mount(mock).await;
+
+    let root = harness.path("");
+    let mut names = blocking(move || {
+        std::fs::read_dir(root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>()
+    })
+    .await;
+    names.sort();
+    assert_eq!(names, ["a.txt", "folder"]);
+
+    let (a, b) = (harness.path("a.txt"), harness.path("folder/b.txt"));
+    let (a, b) = blocking(move || (std::fs::read(a).unwrap(), std::fs::read(b).unwrap())).await;
+    assert_eq!(a, b"hello");
+    assert_eq!(b, b"world");
+
+    harness.unmount().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_are_uploaded() {
+    let mock = MockDrive::start().await.unwrap();
+    let id = mock.add_file("a.txt", &mock.root_id(), b"hello");
+    let harness = Harness::mount(mock).await;
+
+    let path = harness.path("a.txt");
+    let content = blocking(move || {
+        std::fs::write(&path, b"hello world").unwrap();
+        std::fs::read(&path).unwrap()
+    })
+    .await;
+    assert_eq!(content, b"hello world");
+    wait_until("the upload", || {
+        harness.mock.content(&id).as_deref() == Some(b"hello world")
+    })
+    .await;
+
+    harness.unmount().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mkdir_p_creates_every_level() {
+    let mock = MockDrive::start().await.unwrap();
+    let harness = Harness::mount(mock).await;
+
+    let path = harness.path("a/b/c");
+    let is_dir = blocking(move || {
+        std::fs::create_dir_all(&path).unwrap();
+        path.is_dir()
+    })
+    .await;
+    assert!(is_dir);
+    let a = harness.mock.find("a", &harness.mock.root_id()).unwrap();
+    let b = harness.mock.find("b", &a).unwrap();
+    assert!(harness.mock.find("c", &b).is_some());
+
+    harness.unmount().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn renames_move_the_file_on_the_drive() {
+    let mock = MockDrive::start().await.unwrap();
+    let folder = mock.add_folder("folder", &mock.root_id());
+    let id = mock.add_file("a.txt", &mock.root_id(), b"hello");
+    let harness = Harness::mount(mock).await;
+
+    let (from, to) = (harness.path("a.txt"), harness.path("folder/b.txt"));
+    let (old_exists, content) = blocking(move || {
+        std::fs::rename(&from, &to).unwrap();
+        (from.exists(), std::fs::read(&to).unwrap())
+    })
+    .await;
+    assert!(!old_exists);
+    assert_eq!(content, b"hello");
+    wait_until("the move", || {
+        let metadata = harness.mock.metadata(&id).unwrap();
+        metadata.name.as_deref() == Some("b.txt")
+            && metadata.parents == Some(vec![folder.to_string()])
+    })
+    .await;
+
+    harness.unmount().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mounts_show_their_label() {
+    let mock = MockDrive::start().await.unwrap();
+    let harness = Harness::mount_with(mock, |mount| mount.set_label("work drive")).await;
+
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap();
+    let mountpoint = harness.mountpoint.to_str().unwrap().to_string();
+    let line = mounts
+        .lines()
+        .find(|line| line.split(' ').nth(1) == Some(mountpoint.as_str()))
+        .expect("the mount is not in /proc/mounts");
+    assert!(
+        line.starts_with("drive_syncer:work_drive fuse.drive_syncer "),
+        "{}",
+        line
+    );
+
+    harness.unmount().await;
+}